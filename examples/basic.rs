@@ -3,11 +3,12 @@ use std::time::Duration;
 use axum::{
     extract::State,
     http::{request::Parts, HeaderMap, StatusCode},
+    Extension,
     response::{IntoResponse, Json},
     routing::{get, post},
     Router,
 };
-use barnacle_rs::{BarnacleConfig, BarnacleContext, BarnacleError, BarnacleKey, BarnacleLayer, BarnacleStore, KeyExtractable, RedisBarnacleStore, ResetOnSuccess};
+use barnacle_rs::{BarnacleConfig, BarnacleContext, BarnacleError, BarnacleKey, BarnacleLayer, BarnacleStore, KeyExtractable, RateLimitSnapshot, RedisBarnacleStore, ResetOnSuccess};
 use serde::{Deserialize, Serialize};
 
 impl KeyExtractable for LoginRequest {
@@ -88,8 +89,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-async fn strict_endpoint(headers: HeaderMap) -> Json<ApiResponse> {
-    let rate_limit_info = extract_rate_limit_info(&headers);
+async fn strict_endpoint(snapshot: Option<Extension<RateLimitSnapshot>>) -> Json<ApiResponse> {
+    let rate_limit_info = snapshot.map(|Extension(snapshot)| snapshot_info(snapshot));
 
     Json(ApiResponse {
         message: "This endpoint has strict rate limiting (5 requests per minute)".to_string(),
@@ -98,8 +99,8 @@ async fn strict_endpoint(headers: HeaderMap) -> Json<ApiResponse> {
     })
 }
 
-async fn moderate_endpoint(headers: HeaderMap) -> Json<ApiResponse> {
-    let rate_limit_info = extract_rate_limit_info(&headers);
+async fn moderate_endpoint(snapshot: Option<Extension<RateLimitSnapshot>>) -> Json<ApiResponse> {
+    let rate_limit_info = snapshot.map(|Extension(snapshot)| snapshot_info(snapshot));
 
     Json(ApiResponse {
         message: "This endpoint has moderate rate limiting (10 requests per minute)".to_string(),
@@ -166,8 +167,8 @@ async fn reset_rate_limit(
     }
 }
 
-async fn status_endpoint(headers: HeaderMap) -> Json<ApiResponse> {
-    let rate_limit_info = extract_rate_limit_info(&headers);
+async fn status_endpoint(snapshot: Option<Extension<RateLimitSnapshot>>) -> Json<ApiResponse> {
+    let rate_limit_info = snapshot.map(|Extension(snapshot)| snapshot_info(snapshot));
 
     Json(ApiResponse {
         message: "Rate limiter is working! Check the response headers for rate limit info."
@@ -211,26 +212,11 @@ struct AppState {
     store: RedisBarnacleStore,
 }
 
-// Helper function to extract rate limit info from headers
-fn extract_rate_limit_info(headers: &HeaderMap) -> Option<RateLimitInfo> {
-    let remaining = headers
-        .get("X-RateLimit-Remaining")
-        .and_then(|h| h.to_str().ok())
-        .and_then(|s| s.parse::<u32>().ok())?;
-
-    let limit = headers
-        .get("X-RateLimit-Limit")
-        .and_then(|h| h.to_str().ok())
-        .and_then(|s| s.parse::<u32>().ok())?;
-
-    let reset_after = headers
-        .get("X-RateLimit-Reset")
-        .and_then(|h| h.to_str().ok())
-        .and_then(|s| s.parse::<u64>().ok());
-
-    Some(RateLimitInfo {
-        remaining,
-        limit,
-        reset_after,
-    })
+// The middleware hands the handler what it counted as a request extension
+fn snapshot_info(snapshot: RateLimitSnapshot) -> RateLimitInfo {
+    RateLimitInfo {
+        remaining: snapshot.remaining,
+        limit: snapshot.limit,
+        reset_after: snapshot.reset_after.map(|reset| reset.as_secs()),
+    }
 }
@@ -3,6 +3,8 @@ use async_trait::async_trait;
 use deadpool_redis::redis::AsyncCommands;
 #[cfg(feature = "redis")]
 use deadpool_redis::{Connection, Pool};
+#[cfg(feature = "redis")]
+use sha2::{Digest, Sha256};
 
 use crate::error::BarnacleError;
 use crate::types::{ApiKeyValidationResult, BarnacleConfig, StaticApiKeyConfig};
@@ -22,7 +24,9 @@ pub trait ApiKeyStore: Send + Sync {
     }
 
     /// Optional: Cache a validated API key for future requests
-    /// Default implementation does nothing - stores can override if they support caching
+    /// Default implementation does nothing - stores can override if they support caching.
+    /// Validators run by the middleware should pass `Some(api_key_config.cache_ttl_seconds)`;
+    /// `None` leaves the TTL to the store.
     async fn try_cache_key(
         &self,
         api_key: &str,
@@ -40,8 +44,14 @@ pub struct RedisApiKeyStore {
     pool: Pool,
     default_config: BarnacleConfig,
     key_prefix: String,
+    default_ttl_seconds: u64,
+    hash_keys: bool,
 }
 
+/// How long `RedisApiKeyStore` keeps a saved key when no TTL is given
+#[cfg(feature = "redis")]
+const DEFAULT_KEY_TTL_SECONDS: u64 = 24 * 60 * 60;
+
 #[cfg(feature = "redis")]
 impl RedisApiKeyStore {
     pub fn new(pool: Pool) -> Self {
@@ -49,6 +59,8 @@ impl RedisApiKeyStore {
             pool,
             default_config: BarnacleConfig::default(),
             key_prefix: "barnacle:api_keys".to_string(),
+            default_ttl_seconds: DEFAULT_KEY_TTL_SECONDS,
+            hash_keys: false,
         }
     }
 
@@ -57,6 +69,8 @@ impl RedisApiKeyStore {
             pool,
             default_config: config,
             key_prefix: "barnacle:api_keys".to_string(),
+            default_ttl_seconds: DEFAULT_KEY_TTL_SECONDS,
+            hash_keys: false,
         }
     }
 
@@ -77,16 +91,40 @@ impl RedisApiKeyStore {
         self
     }
 
+    /// TTL of keys saved without an explicit one (defaults to 24 hours), e.g. the
+    /// middleware's `ApiKeyConfig::cache_ttl_seconds`
+    pub fn with_default_ttl(mut self, ttl_seconds: u64) -> Self {
+        self.default_ttl_seconds = ttl_seconds;
+        self
+    }
+
+    /// Store API keys as SHA-256 digests in Redis key names, so plaintext keys aren't
+    /// visible to anyone with Redis access. Keys saved with hashing off aren't found
+    /// with it on, so switching needs the keys saved again.
+    pub fn with_key_hashing(mut self, enabled: bool) -> Self {
+        self.hash_keys = enabled;
+        self
+    }
+
     async fn get_connection(&self) -> Result<Connection, deadpool_redis::PoolError> {
         self.pool.get().await
     }
 
+    fn key_id(&self, api_key: &str) -> String {
+        if self.hash_keys {
+            let digest = Sha256::digest(api_key.as_bytes());
+            digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+        } else {
+            api_key.to_string()
+        }
+    }
+
     fn get_redis_key(&self, api_key: &str) -> String {
-        format!("{}:{}", self.key_prefix, api_key)
+        format!("{}:{}", self.key_prefix, self.key_id(api_key))
     }
 
     fn get_config_key(&self, api_key: &str) -> String {
-        format!("{}:config:{}", self.key_prefix, api_key)
+        format!("{}:config:{}", self.key_prefix, self.key_id(api_key))
     }
 
     pub async fn save_key(
@@ -97,8 +135,7 @@ impl RedisApiKeyStore {
     ) -> Result<(), BarnacleError> {
         let redis_key = self.get_redis_key(api_key);
         let config_key = self.get_config_key(api_key);
-        let default_ttl: u64 = 24 * 60 * 60; // 24 hours
-        let ttl_api_key_secs: u64 = ttl_seconds.unwrap_or(default_ttl);
+        let ttl_api_key_secs: u64 = ttl_seconds.unwrap_or(self.default_ttl_seconds);
 
         tracing::debug!("Saving API key: {}", api_key);
 
@@ -319,7 +356,8 @@ impl ApiKeyStore for StaticApiKeyStore {
     async fn validate_key(&self, api_key: &str) -> ApiKeyValidationResult {
         if self.config.key_configs.contains_key(api_key) {
             let config = self.config.get_config_for_key(api_key);
-            ApiKeyValidationResult::valid_with_config(api_key.to_string(), config.clone())
+            let scopes = self.config.key_scopes.get(api_key).cloned().unwrap_or_default();
+            ApiKeyValidationResult::valid_with_config(api_key.to_string(), config.clone()).with_scopes(scopes)
         } else {
             ApiKeyValidationResult::invalid()
         }
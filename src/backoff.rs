@@ -0,0 +1,92 @@
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Exponential backoff for clients retrying after a 429, e.g. when no `Retry-After` was sent.
+///
+/// Attempt `n` (from 0) waits `initial * multiplier^n`, capped at `max`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f64,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            multiplier: 2.0,
+        }
+    }
+
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), Duration::from_secs(30))
+    }
+}
+
+/// Jitter fraction drawing the wait anywhere up to the base delay
+pub const FULL_JITTER: f64 = 1.0;
+
+/// Jitter fraction keeping at least half of the base delay
+pub const EQUAL_JITTER: f64 = 0.5;
+
+/// Delay before retry `attempt`, the same for every client
+pub fn next_backoff(attempt: u32, backoff: &Backoff) -> Duration {
+    let factor = backoff.multiplier.max(1.0).powi(attempt.min(i32::MAX as u32) as i32);
+    let secs = backoff.initial.as_secs_f64() * factor;
+    if !secs.is_finite() || secs >= backoff.max.as_secs_f64() {
+        backoff.max
+    } else {
+        Duration::from_secs_f64(secs)
+    }
+}
+
+/// Delay before retry `attempt`, drawn from `[base * (1 - jitter_fraction), base]` so clients
+/// limited at the same moment don't all retry together. `FULL_JITTER` and `EQUAL_JITTER` are
+/// the usual choices; the fraction is clamped to `0.0..=1.0`. Pass a seeded RNG for
+/// reproducible delays.
+pub fn next_backoff_jittered<R: Rng + ?Sized>(
+    attempt: u32,
+    backoff: &Backoff,
+    jitter_fraction: f64,
+    rng: &mut R,
+) -> Duration {
+    let base = next_backoff(attempt, backoff);
+    let fraction = if jitter_fraction.is_nan() { 0.0 } else { jitter_fraction.clamp(0.0, 1.0) };
+    if fraction == 0.0 || base.is_zero() {
+        return base;
+    }
+    let low = base.mul_f64(1.0 - fraction);
+    low + (base - low).mul_f64(rng.random::<f64>())
+}
+
+/// Runs `operation`, retrying up to `retries` more times after a failure and sleeping
+/// `next_backoff` of the attempt in between. Returns the last error when all attempts fail.
+pub async fn retry_with_backoff<F, Fut, T, E>(retries: u32, backoff: &Backoff, mut operation: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 0;
+    loop {
+        match operation().await {
+            Err(_) if attempt < retries => {
+                let delay = next_backoff(attempt, backoff);
+                tracing::debug!("Attempt {} failed, retrying in {:?}", attempt + 1, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            outcome => return outcome,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use std::time::SystemTime;
+
+/// Source of the current time for time-dependent features (maintenance windows,
+/// reset timestamps). Swap it out to make them testable without sleeping.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// Wall clock, used unless another clock is configured
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Clock frozen at a given instant
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock(pub SystemTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{
+    error::BarnacleError,
+    types::{BarnacleContext, BarnacleResult, ConcurrencyConfig},
+    BarnacleStore,
+};
+
+/// RAII guard for an in-flight slot taken with `BarnacleStore::acquire_in_flight`.
+///
+/// The slot is released when the guard is dropped, which also covers handler
+/// panics, early returns and futures dropped because the client disconnected.
+/// Release happens on a spawned task, so it must be dropped inside a Tokio runtime;
+/// otherwise the slot is left to expire with the configured safety TTL.
+pub struct InFlightGuard<S>
+where
+    S: BarnacleStore + 'static,
+{
+    store: S,
+    context: Option<BarnacleContext>,
+}
+
+impl<S> InFlightGuard<S>
+where
+    S: BarnacleStore + 'static,
+{
+    /// Try to take an in-flight slot for the context.
+    /// Returns `None` when all `max_in_flight` slots are taken.
+    pub async fn acquire(
+        store: &S,
+        context: &BarnacleContext,
+        config: &ConcurrencyConfig,
+    ) -> Result<Option<Self>, BarnacleError> {
+        let acquired = store
+            .acquire_in_flight(context, config.max_in_flight, config.safety_ttl)
+            .await?;
+        if !acquired {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            store: store.clone(),
+            context: Some(context.clone()),
+        }))
+    }
+
+    /// Release the slot now and wait for the store to confirm it
+    pub async fn release(mut self) -> Result<(), BarnacleError> {
+        match self.context.take() {
+            Some(context) => self.store.release_in_flight(&context).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S> Drop for InFlightGuard<S>
+where
+    S: BarnacleStore + 'static,
+{
+    fn drop(&mut self) {
+        let Some(context) = self.context.take() else {
+            return;
+        };
+        let store = self.store.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(e) = store.release_in_flight(&context).await {
+                        tracing::warn!(
+                            "Failed to release in-flight slot for key {:?}: {}",
+                            context.key,
+                            e
+                        );
+                    }
+                });
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "No Tokio runtime to release in-flight slot for key {:?}; it will expire with the safety TTL",
+                    context.key
+                );
+            }
+        }
+    }
+}
+
+/// Permit for a rate-limited section, returned by `BarnacleStore::acquire`.
+///
+/// The shipped stores count the request on acquire and return a permit with nothing to
+/// release: dropping it gives nothing back to the window. A permit made with `in_flight`
+/// holds an in-flight slot instead, released when it is dropped, so the slot is given
+/// back on early returns, errors and panics alike. Stores limiting concurrency can
+/// return such permits from `acquire`; otherwise take the slot with `InFlightGuard`.
+///
+/// ```rust
+/// use barnacle_rs::{
+///     BarnacleConfig, BarnacleContext, BarnacleError, BarnacleKey, BarnacleStore, ConcurrencyConfig,
+///     InFlightGuard, InMemoryBarnacleStore, RateLimitPermit,
+/// };
+///
+/// # async fn run_job() -> Result<(), BarnacleError> { Ok(()) }
+/// # async fn example() -> Result<(), BarnacleError> {
+/// let store = InMemoryBarnacleStore::new();
+/// let context = BarnacleContext {
+///     key: BarnacleKey::Custom("tenant-7".to_string()),
+///     path: "export".to_string(),
+///     method: "JOB".to_string(),
+/// };
+///
+/// // Counted in the window; dropping this permit releases nothing
+/// let counted = store.acquire(&context, &BarnacleConfig::default()).await?;
+/// assert!(!counted.holds_slot());
+///
+/// // One of at most 3 concurrent jobs, released when `_slot` is dropped
+/// let guard = InFlightGuard::acquire(&store, &context, &ConcurrencyConfig::new(3))
+///     .await?
+///     .ok_or(BarnacleError::concurrency_limit_exceeded(3))?;
+/// let _slot = RateLimitPermit::in_flight(guard);
+/// run_job().await?; // the slot is released on both paths
+/// # Ok(())
+/// # }
+/// ```
+#[must_use = "dropping a permit ends the rate-limited section"]
+pub struct RateLimitPermit<S>
+where
+    S: BarnacleStore + 'static,
+{
+    result: Option<BarnacleResult>,
+    guard: Option<InFlightGuard<S>>,
+}
+
+impl<S> RateLimitPermit<S>
+where
+    S: BarnacleStore + 'static,
+{
+    /// Permit for a request counted in a window, with nothing to release
+    pub fn counted(result: BarnacleResult) -> Self {
+        Self {
+            result: Some(result),
+            guard: None,
+        }
+    }
+
+    /// Permit holding an in-flight slot until it is dropped or released
+    pub fn in_flight(guard: InFlightGuard<S>) -> Self {
+        Self {
+            result: None,
+            guard: Some(guard),
+        }
+    }
+
+    /// The counting result, for permits from a fixed-window count
+    pub fn result(&self) -> Option<&BarnacleResult> {
+        self.result.as_ref()
+    }
+
+    /// Whether dropping the permit releases an in-flight slot
+    pub fn holds_slot(&self) -> bool {
+        self.guard.is_some()
+    }
+
+    /// Release the slot now, if any, and wait for the store to confirm it
+    pub async fn release(mut self) -> Result<(), BarnacleError> {
+        match self.guard.take() {
+            Some(guard) => guard.release().await,
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use crate::types::{BarnacleConfig, BarnacleContext, BarnacleKey};
+
+/// Picks the limit for a request once its key, path and method are known, so one layer
+/// covering many routes can apply different limits to each, see
+/// `BarnacleLayerBuilder::with_config_resolver`.
+///
+/// Closures `Fn(&BarnacleContext) -> BarnacleConfig` implement it too. Resolving runs on
+/// every request, so keep it cheap.
+pub trait ConfigResolver: Send + Sync {
+    fn resolve(&self, context: &BarnacleContext) -> BarnacleConfig;
+}
+
+/// Resolver returning the same config for every request, what `with_config` sets up
+#[derive(Clone, Debug)]
+pub struct StaticConfigResolver {
+    config: BarnacleConfig,
+}
+
+impl StaticConfigResolver {
+    pub fn new(config: BarnacleConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl ConfigResolver for StaticConfigResolver {
+    fn resolve(&self, _context: &BarnacleContext) -> BarnacleConfig {
+        self.config.clone()
+    }
+}
+
+impl<F> ConfigResolver for F
+where
+    F: Fn(&BarnacleContext) -> BarnacleConfig + Send + Sync,
+{
+    fn resolve(&self, context: &BarnacleContext) -> BarnacleConfig {
+        self(context)
+    }
+}
+
+type KeyConfigLookup = Arc<dyn Fn(&BarnacleKey) -> Option<BarnacleConfig> + Send + Sync>;
+
+/// Looks the limit up by key, deferring to another resolver for keys without their own,
+/// see `BarnacleLayerBuilder::with_per_key_config`
+pub(crate) struct PerKeyConfigResolver {
+    lookup: KeyConfigLookup,
+    fallback: Arc<dyn ConfigResolver>,
+}
+
+impl PerKeyConfigResolver {
+    pub(crate) fn new(
+        lookup: KeyConfigLookup,
+        fallback: Arc<dyn ConfigResolver>,
+    ) -> Self {
+        Self { lookup, fallback }
+    }
+}
+
+impl ConfigResolver for PerKeyConfigResolver {
+    fn resolve(&self, context: &BarnacleContext) -> BarnacleConfig {
+        (self.lookup)(&context.key).unwrap_or_else(|| self.fallback.resolve(context))
+    }
+}
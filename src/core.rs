@@ -0,0 +1,55 @@
+use crate::{
+    error::BarnacleError,
+    types::{BarnacleConfig, BarnacleContext, BarnacleResult},
+    BarnacleStore,
+};
+
+/// Counts one request for `context` against `config`, without any HTTP types, e.g. from a
+/// gRPC service or a job queue. The Axum middleware counts requests the same way.
+///
+/// A request over the limit is returned as `BarnacleError::RateLimitExceeded`, also from
+/// stores reporting it as a result with `allowed: false`.
+///
+/// ```rust
+/// use barnacle_rs::core::check_rate_limit;
+/// use barnacle_rs::{BarnacleConfig, BarnacleContext, BarnacleError, BarnacleKey, InMemoryBarnacleStore};
+///
+/// # async fn example() -> Result<(), BarnacleError> {
+/// let store = InMemoryBarnacleStore::new();
+/// let context = BarnacleContext {
+///     key: BarnacleKey::ApiKey("client-42".to_string()),
+///     path: "/orders.OrderService/Create".to_string(),
+///     method: "POST".to_string(),
+/// };
+///
+/// match check_rate_limit(&store, &context, &BarnacleConfig::default()).await {
+///     Ok(result) => println!("allowed, {} left", result.remaining),
+///     Err(BarnacleError::RateLimitExceeded { retry_after, .. }) => println!("retry in {}s", retry_after),
+///     Err(e) => return Err(e),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn check_rate_limit<S: BarnacleStore>(
+    store: &S,
+    context: &BarnacleContext,
+    config: &BarnacleConfig,
+) -> Result<BarnacleResult, BarnacleError> {
+    store
+        .increment(context, config)
+        .await
+        .and_then(|result| result.reject_if_disallowed(config))
+}
+
+/// Like `check_rate_limit` for a request costing `cost` units, see `BarnacleStore::increment_by`
+pub async fn check_rate_limit_by<S: BarnacleStore>(
+    store: &S,
+    context: &BarnacleContext,
+    config: &BarnacleConfig,
+    cost: u32,
+) -> Result<BarnacleResult, BarnacleError> {
+    store
+        .increment_by(context, config, cost)
+        .await
+        .and_then(|result| result.reject_if_disallowed(config))
+}
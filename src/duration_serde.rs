@@ -0,0 +1,75 @@
+//! Human-friendly (de)serialization of `Duration` config fields, for use with
+//! `#[serde(with = "crate::duration_serde")]`.
+//!
+//! Durations are written as `"250ms"`, `"90s"`, `"5m"`, `"1h"` or `"1d"`. Reading also
+//! accepts compound values (`"1h30m"`), bare seconds (`60`) and serde's default
+//! `{ "secs": .., "nanos": .. }` form, so previously serialized configs still load.
+
+use std::time::Duration;
+
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DurationRepr {
+    Seconds(u64),
+    Text(String),
+    Struct { secs: u64, nanos: u32 },
+}
+
+pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format_duration(*duration))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    match DurationRepr::deserialize(deserializer)? {
+        DurationRepr::Seconds(secs) => Ok(Duration::from_secs(secs)),
+        DurationRepr::Text(text) => parse_duration(&text).map_err(de::Error::custom),
+        DurationRepr::Struct { secs, nanos } => Ok(Duration::new(secs, nanos)),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration.subsec_nanos() != 0 {
+        return format!("{}ms", duration.as_millis());
+    }
+    let secs = duration.as_secs();
+    match secs {
+        0 => "0s".to_string(),
+        _ if secs % 86_400 == 0 => format!("{}d", secs / 86_400),
+        _ if secs % 3_600 == 0 => format!("{}h", secs / 3_600),
+        _ if secs % 60 == 0 => format!("{}m", secs / 60),
+        _ => format!("{}s", secs),
+    }
+}
+
+pub(crate) fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    if let Ok(secs) = text.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let invalid = || format!("invalid duration {:?}, expected e.g. \"500ms\", \"30s\", \"5m\" or \"1h30m\"", text);
+    if text.is_empty() {
+        return Err(invalid());
+    }
+    let mut total = Duration::ZERO;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let value: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = &rest[digits..];
+        let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let unit = match &rest[..unit_len] {
+            "ms" => Duration::from_millis(value),
+            "s" => Duration::from_secs(value),
+            "m" => Duration::from_secs(value.saturating_mul(60)),
+            "h" => Duration::from_secs(value.saturating_mul(3_600)),
+            "d" => Duration::from_secs(value.saturating_mul(86_400)),
+            _ => return Err(invalid()),
+        };
+        total = total.saturating_add(unit);
+        rest = &rest[unit_len..];
+    }
+    Ok(total)
+}
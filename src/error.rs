@@ -6,6 +6,8 @@ use axum::{
 use serde_json::json;
 use thiserror::Error;
 
+use crate::types::ErrorFormat;
+
 /// Main error type for the Barnacle library
 #[derive(Error, Debug)]
 pub enum BarnacleError {
@@ -17,6 +19,14 @@ pub enum BarnacleError {
         limit: u32,
     },
 
+    /// Too many concurrent requests for the key
+    #[error("Concurrency limit exceeded: at most {max_in_flight} requests may be in flight")]
+    ConcurrencyLimitExceeded { max_in_flight: u32 },
+
+    /// Service is in planned maintenance
+    #[error("Service under maintenance, retry after {retry_after}s")]
+    Maintenance { retry_after: u64 },
+
     /// API key validation errors
     #[error("API key validation failed: {reason}")]
     ApiKeyValidation { reason: String },
@@ -29,6 +39,10 @@ pub enum BarnacleError {
     #[error("Invalid API key: {key_hint}")]
     InvalidApiKey { key_hint: String },
 
+    /// Key without the scope the route requires, or denylisted when there is no scope
+    #[error("{}", permission_denied_message(.scope.as_deref()))]
+    PermissionDenied { scope: Option<String> },
+
     /// Store/backend related errors
     #[error("Backend store error: {message}")]
     StoreError {
@@ -70,6 +84,10 @@ pub enum BarnacleError {
     #[error("Request parsing error: {message}")]
     RequestParsing { message: String },
 
+    /// Request body over the configured size limit
+    #[error("Request body exceeds the limit of {max_bytes} bytes")]
+    PayloadTooLarge { max_bytes: usize },
+
     /// Internal server errors
     #[error("Internal server error: {message}")]
     Internal { message: String },
@@ -92,6 +110,16 @@ impl BarnacleError {
         }
     }
 
+    /// Create a concurrency limit exceeded error
+    pub fn concurrency_limit_exceeded(max_in_flight: u32) -> Self {
+        Self::ConcurrencyLimitExceeded { max_in_flight }
+    }
+
+    /// Create a maintenance error
+    pub fn maintenance(retry_after: u64) -> Self {
+        Self::Maintenance { retry_after }
+    }
+
     /// Create an API key validation error
     pub fn api_key_validation<S: Into<String>>(reason: S) -> Self {
         Self::ApiKeyValidation {
@@ -110,6 +138,16 @@ impl BarnacleError {
         Self::InvalidApiKey { key_hint }
     }
 
+    /// Create a permission denied error for a missing scope
+    pub fn permission_denied<S: Into<String>>(scope: S) -> Self {
+        Self::PermissionDenied { scope: Some(scope.into()) }
+    }
+
+    /// Create a permission denied error for a denylisted key
+    pub fn key_denied() -> Self {
+        Self::PermissionDenied { scope: None }
+    }
+
     /// Create a store error
     pub fn store_error<S: Into<String>>(message: S) -> Self {
         Self::StoreError {
@@ -171,6 +209,11 @@ impl BarnacleError {
         }
     }
 
+    /// Create a payload too large error
+    pub fn payload_too_large(max_bytes: usize) -> Self {
+        Self::PayloadTooLarge { max_bytes }
+    }
+
     /// Create an internal server error
     pub fn internal_error<S: Into<String>>(message: S) -> Self {
         Self::Internal {
@@ -190,9 +233,12 @@ impl BarnacleError {
     pub fn status_code(&self) -> StatusCode {
         match self {
             BarnacleError::RateLimitExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            BarnacleError::ConcurrencyLimitExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            BarnacleError::Maintenance { .. } => StatusCode::SERVICE_UNAVAILABLE,
             BarnacleError::ApiKeyValidation { .. } => StatusCode::UNAUTHORIZED,
             BarnacleError::ApiKeyMissing => StatusCode::UNAUTHORIZED,
             BarnacleError::InvalidApiKey { .. } => StatusCode::UNAUTHORIZED,
+            BarnacleError::PermissionDenied { .. } => StatusCode::FORBIDDEN,
             BarnacleError::StoreError { .. } => StatusCode::SERVICE_UNAVAILABLE,
             #[cfg(feature = "redis")]
             BarnacleError::Redis { .. } => StatusCode::SERVICE_UNAVAILABLE,
@@ -200,6 +246,7 @@ impl BarnacleError {
             BarnacleError::Configuration { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             BarnacleError::JsonError { .. } => StatusCode::BAD_REQUEST,
             BarnacleError::RequestParsing { .. } => StatusCode::BAD_REQUEST,
+            BarnacleError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
             BarnacleError::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             BarnacleError::Custom { status_code, .. } => {
                 status_code.unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
@@ -212,15 +259,28 @@ impl BarnacleError {
         matches!(
             self,
             BarnacleError::RateLimitExceeded { .. }
+                | BarnacleError::ConcurrencyLimitExceeded { .. }
+                | BarnacleError::Maintenance { .. }
                 | BarnacleError::StoreError { .. }
                 | BarnacleError::ConnectionPool { .. }
         )
     }
 
+    /// Whether the store itself failed, as opposed to the request being rejected
+    pub fn is_store_failure(&self) -> bool {
+        match self {
+            BarnacleError::StoreError { .. } | BarnacleError::ConnectionPool { .. } => true,
+            #[cfg(feature = "redis")]
+            BarnacleError::Redis { .. } => true,
+            _ => false,
+        }
+    }
+
     /// Get retry-after value in seconds if applicable
     pub fn retry_after(&self) -> Option<u64> {
         match self {
-            BarnacleError::RateLimitExceeded { retry_after, .. } => Some(*retry_after),
+            BarnacleError::RateLimitExceeded { retry_after, .. }
+            | BarnacleError::Maintenance { retry_after } => Some(*retry_after),
             _ => None,
         }
     }
@@ -248,6 +308,26 @@ impl BarnacleError {
                     "limit": limit
                 });
             }
+            BarnacleError::ConcurrencyLimitExceeded { max_in_flight } => {
+                json["error"]["details"] = json!({
+                    "max_in_flight": max_in_flight
+                });
+            }
+            BarnacleError::Maintenance { retry_after } => {
+                json["error"]["details"] = json!({
+                    "retry_after": retry_after
+                });
+            }
+            BarnacleError::PayloadTooLarge { max_bytes } => {
+                json["error"]["details"] = json!({
+                    "max_bytes": max_bytes
+                });
+            }
+            BarnacleError::PermissionDenied { scope: Some(scope) } => {
+                json["error"]["details"] = json!({
+                    "required_scope": scope
+                });
+            }
             BarnacleError::Custom { .. } => {
                 // Allow custom errors to provide additional context
                 json["error"]["details"] = json!({});
@@ -258,13 +338,55 @@ impl BarnacleError {
         json
     }
 
+    /// Convert this error into an RFC 7807 problem details object
+    pub fn to_problem_json(&self) -> serde_json::Value {
+        let status = self.status_code();
+        let mut json = json!({
+            "type": self.error_code(),
+            "title": status.canonical_reason().unwrap_or("Error"),
+            "status": status.as_u16(),
+            "detail": self.to_string(),
+            "category": self.error_type(),
+        });
+
+        // Details become extension members
+        let value = self.to_json_value();
+        if let Some(details) = value["error"]["details"].as_object() {
+            for (name, detail) in details {
+                json[name] = detail.clone();
+            }
+        }
+
+        json
+    }
+
+    /// Like `into_response`, with the body rendered in `format`
+    pub fn into_response_with_format(self, format: ErrorFormat) -> Response {
+        let status = self.status_code();
+        let mut response = match format {
+            ErrorFormat::Json => return self.into_response(),
+            ErrorFormat::ProblemJson => (
+                status,
+                [(axum::http::header::CONTENT_TYPE, "application/problem+json")],
+                self.to_problem_json().to_string(),
+            )
+                .into_response(),
+            ErrorFormat::PlainText => (status, self.to_string()).into_response(),
+        };
+        self.insert_headers(response.headers_mut());
+        response
+    }
+
     /// Get a unique error code for this error type
     pub fn error_code(&self) -> &'static str {
         match self {
             BarnacleError::RateLimitExceeded { .. } => "RATE_LIMIT_EXCEEDED",
+            BarnacleError::ConcurrencyLimitExceeded { .. } => "CONCURRENCY_LIMIT_EXCEEDED",
+            BarnacleError::Maintenance { .. } => "MAINTENANCE",
             BarnacleError::ApiKeyValidation { .. } => "API_KEY_VALIDATION_FAILED",
             BarnacleError::ApiKeyMissing => "API_KEY_MISSING",
             BarnacleError::InvalidApiKey { .. } => "INVALID_API_KEY",
+            BarnacleError::PermissionDenied { .. } => "PERMISSION_DENIED",
             BarnacleError::StoreError { .. } => "STORE_ERROR",
             #[cfg(feature = "redis")]
             BarnacleError::Redis { .. } => "REDIS_ERROR",
@@ -272,6 +394,7 @@ impl BarnacleError {
             BarnacleError::Configuration { .. } => "CONFIGURATION_ERROR",
             BarnacleError::JsonError { .. } => "JSON_ERROR",
             BarnacleError::RequestParsing { .. } => "REQUEST_PARSING_ERROR",
+            BarnacleError::PayloadTooLarge { .. } => "PAYLOAD_TOO_LARGE",
             BarnacleError::Internal { .. } => "INTERNAL_ERROR",
             BarnacleError::Custom { .. } => "CUSTOM_ERROR",
         }
@@ -280,21 +403,34 @@ impl BarnacleError {
     /// Get the error type category
     pub fn error_type(&self) -> &'static str {
         match self {
-            BarnacleError::RateLimitExceeded { .. } => "rate_limit",
+            BarnacleError::RateLimitExceeded { .. }
+            | BarnacleError::ConcurrencyLimitExceeded { .. } => "rate_limit",
             BarnacleError::ApiKeyValidation { .. }
             | BarnacleError::ApiKeyMissing
             | BarnacleError::InvalidApiKey { .. } => "authentication",
+            BarnacleError::PermissionDenied { .. } => "authorization",
             BarnacleError::StoreError { .. } | BarnacleError::ConnectionPool { .. } => "backend",
             #[cfg(feature = "redis")]
             BarnacleError::Redis { .. } => "backend",
-            BarnacleError::Configuration { .. } | BarnacleError::Internal { .. } => "server",
-            BarnacleError::JsonError { .. } | BarnacleError::RequestParsing { .. } => "client",
+            BarnacleError::Configuration { .. }
+            | BarnacleError::Internal { .. }
+            | BarnacleError::Maintenance { .. } => "server",
+            BarnacleError::JsonError { .. }
+            | BarnacleError::RequestParsing { .. }
+            | BarnacleError::PayloadTooLarge { .. } => "client",
             BarnacleError::Custom { .. } => "custom",
         }
     }
 }
 
 /// Helper function to safely convert values to HeaderValue
+fn permission_denied_message(scope: Option<&str>) -> String {
+    match scope {
+        Some(scope) => format!("API key lacks the required scope: {}", scope),
+        None => "Key is not allowed to access this resource".to_string(),
+    }
+}
+
 fn to_header_value<T: ToString>(value: T) -> axum::http::HeaderValue {
     value
         .to_string()
@@ -310,26 +446,35 @@ impl IntoResponse for BarnacleError {
 
         // Set status code
         *response.status_mut() = status;
+        self.insert_headers(response.headers_mut());
+
+        response
+    }
+}
 
+impl BarnacleError {
+    /// Adds the headers every error response carries, e.g. `Retry-After` and the
+    /// `X-RateLimit-*` headers for a rate limit rejection
+    pub(crate) fn insert_headers(&self, headers: &mut axum::http::HeaderMap) {
         // Add rate limit headers for rate limit errors
         if let BarnacleError::RateLimitExceeded {
             remaining,
             retry_after,
             limit,
-        } = &self
+        } = self
         {
-            let headers = response.headers_mut();
             headers.insert("X-RateLimit-Remaining", to_header_value(remaining));
             headers.insert("X-RateLimit-Limit", to_header_value(limit));
             // X-RateLimit-Reset follows Barnacle's convention: seconds until reset (same as Retry-After)
             headers.insert("X-RateLimit-Reset", to_header_value(retry_after));
+            headers.insert(axum::http::header::RETRY_AFTER, to_header_value(retry_after));
         }
 
-        response
-            .headers_mut()
-            .insert("X-Barnacle-Error", to_header_value("true"));
+        if let BarnacleError::Maintenance { retry_after } = self {
+            headers.insert(axum::http::header::RETRY_AFTER, to_header_value(retry_after));
+        }
 
-        response
+        headers.insert("X-Barnacle-Error", to_header_value("true"));
     }
 }
 
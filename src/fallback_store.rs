@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{
+    error::BarnacleError,
+    types::{BarnacleConfig, BarnacleContext, BarnacleResult, KeyUsage},
+    BarnacleStore,
+};
+
+/// Store wrapper that keeps rate limiting running while the primary backend is down, e.g.
+/// Redis with an `InMemoryBarnacleStore` behind it.
+///
+/// Calls go to the primary; when it fails with a backend error (`BarnacleError::is_store_failure`)
+/// the call is retried on the secondary. Rejections and other errors are returned as is.
+/// Limits are only approximate during an outage, as each instance counts in its own secondary,
+/// and counts taken there don't carry over once the primary is back.
+#[derive(Clone)]
+pub struct FallbackStore<P, S> {
+    primary: P,
+    secondary: S,
+}
+
+impl<P: BarnacleStore, S: BarnacleStore> FallbackStore<P, S> {
+    pub fn new(primary: P, secondary: S) -> Self {
+        Self { primary, secondary }
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    pub fn secondary(&self) -> &S {
+        &self.secondary
+    }
+}
+
+/// Whether a primary outcome should be retried on the secondary, logging the switch
+fn falls_back<T>(outcome: &Result<T, BarnacleError>, operation: &str, context: &BarnacleContext) -> bool {
+    match outcome {
+        Err(e) if e.is_store_failure() => {
+            tracing::warn!("Primary store {} failed for key {:?}, using secondary: {}", operation, context.key, e);
+            true
+        }
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl<P: BarnacleStore + 'static, S: BarnacleStore + 'static> BarnacleStore for FallbackStore<P, S> {
+    async fn increment(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let outcome = self.primary.increment(context, config).await;
+        if falls_back(&outcome, "increment", context) {
+            return self.secondary.increment(context, config).await;
+        }
+        outcome
+    }
+
+    async fn increment_by(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        cost: u32,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let outcome = self.primary.increment_by(context, config, cost).await;
+        if falls_back(&outcome, "increment", context) {
+            return self.secondary.increment_by(context, config, cost).await;
+        }
+        outcome
+    }
+
+    async fn increment_with_metadata(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        metadata: &HashMap<String, String>,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let outcome = self.primary.increment_with_metadata(context, config, metadata).await;
+        if falls_back(&outcome, "increment", context) {
+            return self.secondary.increment_with_metadata(context, config, metadata).await;
+        }
+        outcome
+    }
+
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let outcome = self.primary.reset(context).await;
+        if falls_back(&outcome, "reset", context) {
+            return self.secondary.reset(context).await;
+        }
+        outcome
+    }
+
+    async fn reset_if_below(&self, context: &BarnacleContext, threshold: u32) -> Result<bool, BarnacleError> {
+        let outcome = self.primary.reset_if_below(context, threshold).await;
+        if falls_back(&outcome, "reset", context) {
+            return self.secondary.reset_if_below(context, threshold).await;
+        }
+        outcome
+    }
+
+    async fn peek(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let outcome = self.primary.peek(context, config).await;
+        if falls_back(&outcome, "peek", context) {
+            return self.secondary.peek(context, config).await;
+        }
+        outcome
+    }
+
+    async fn usage_for_key(&self, context: &BarnacleContext) -> Result<KeyUsage, BarnacleError> {
+        let outcome = self.primary.usage_for_key(context).await;
+        if falls_back(&outcome, "usage lookup", context) {
+            return self.secondary.usage_for_key(context).await;
+        }
+        outcome
+    }
+
+    async fn decrement(&self, context: &BarnacleContext, n: u32) -> Result<(), BarnacleError> {
+        let outcome = self.primary.decrement(context, n).await;
+        if falls_back(&outcome, "decrement", context) {
+            return self.secondary.decrement(context, n).await;
+        }
+        outcome
+    }
+
+    /// Healthy while either store is
+    fn health(&self) -> Result<(), BarnacleError> {
+        self.primary.health().or_else(|_| self.secondary.health())
+    }
+
+    async fn idempotency_key_seen(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<bool, BarnacleError> {
+        let outcome = self.primary.idempotency_key_seen(context, idempotency_key).await;
+        if falls_back(&outcome, "idempotency lookup", context) {
+            return self.secondary.idempotency_key_seen(context, idempotency_key).await;
+        }
+        outcome
+    }
+
+    async fn record_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<(), BarnacleError> {
+        let outcome = self.primary.record_idempotency_key(context, idempotency_key, ttl).await;
+        if falls_back(&outcome, "idempotency record", context) {
+            return self.secondary.record_idempotency_key(context, idempotency_key, ttl).await;
+        }
+        outcome
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        let outcome = self.primary.claim_idempotency_key(context, idempotency_key, ttl).await;
+        if falls_back(&outcome, "idempotency claim", context) {
+            return self.secondary.claim_idempotency_key(context, idempotency_key, ttl).await;
+        }
+        outcome
+    }
+
+    async fn release_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<(), BarnacleError> {
+        let outcome = self.primary.release_idempotency_key(context, idempotency_key).await;
+        if falls_back(&outcome, "idempotency release", context) {
+            return self.secondary.release_idempotency_key(context, idempotency_key).await;
+        }
+        outcome
+    }
+
+    async fn acquire_in_flight(
+        &self,
+        context: &BarnacleContext,
+        max_in_flight: u32,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        let outcome = self.primary.acquire_in_flight(context, max_in_flight, ttl).await;
+        if falls_back(&outcome, "in-flight acquire", context) {
+            return self.secondary.acquire_in_flight(context, max_in_flight, ttl).await;
+        }
+        outcome
+    }
+
+    async fn release_in_flight(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let outcome = self.primary.release_in_flight(context).await;
+        if falls_back(&outcome, "in-flight release", context) {
+            return self.secondary.release_in_flight(context).await;
+        }
+        outcome
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::api_key_store::ApiKeyStore;
+use crate::error::BarnacleError;
+use crate::types::{ApiKeyValidationResult, BarnacleConfig};
+
+type Keys = Arc<RwLock<HashMap<String, BarnacleConfig>>>;
+
+/// API key store backed by a JSON file that is reloaded whenever it changes, so keys can
+/// be added during local development without a restart.
+///
+/// The file maps each key to its limit, e.g. `{"dev-key": {"max_requests": 100, "window": "1m"}}`.
+/// Keys not in the file are invalid. If a changed file can't be read or parsed (e.g. while
+/// it is being saved), the previous keys stay in effect. Watching stops when the store is dropped.
+pub struct FileWatchApiKeyStore {
+    path: PathBuf,
+    keys: Keys,
+    _watcher: RecommendedWatcher,
+}
+
+impl FileWatchApiKeyStore {
+    /// Loads the keys from `path` and starts watching it
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, BarnacleError> {
+        let path = path.into();
+        let keys: Keys = Arc::new(RwLock::new(load_keys(&path)?));
+
+        let watched_path = path.clone();
+        let watched_keys = keys.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) if event.paths.iter().any(|changed| same_file(changed, &watched_path)) => {
+                reload_into(&watched_path, &watched_keys);
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Watching API key file {} failed: {}", watched_path.display(), e),
+        })
+        .map_err(|e| BarnacleError::store_error_with_source("Failed to create API key file watcher", Box::new(e)))?;
+
+        // Watch the directory, as editors often replace the file rather than writing to it
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(|e| BarnacleError::store_error_with_source("Failed to watch API key file", Box::new(e)))?;
+
+        Ok(Self {
+            path,
+            keys,
+            _watcher: watcher,
+        })
+    }
+
+    /// Reads the file again now, without waiting for a change notification
+    pub fn reload(&self) -> Result<(), BarnacleError> {
+        let keys = load_keys(&self.path)?;
+        *self.keys.write().unwrap() = keys;
+        Ok(())
+    }
+
+    /// Number of keys currently loaded
+    pub fn len(&self) -> usize {
+        self.keys.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn load_keys(path: &Path) -> Result<HashMap<String, BarnacleConfig>, BarnacleError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        BarnacleError::store_error_with_source(format!("Failed to read API key file {}", path.display()), Box::new(e))
+    })?;
+    serde_json::from_str(&contents)
+        .map_err(|e| BarnacleError::json_error(format!("Invalid API key file {}", path.display()), e))
+}
+
+fn reload_into(path: &Path, keys: &Keys) {
+    match load_keys(path) {
+        Ok(loaded) => {
+            tracing::info!("Reloaded {} API keys from {}", loaded.len(), path.display());
+            *keys.write().unwrap() = loaded;
+        }
+        Err(e) => tracing::warn!("Keeping previous API keys: {}", e),
+    }
+}
+
+/// Whether a path from a watch event is the watched file; event paths are absolute, the
+/// configured one may not be
+fn same_file(changed: &Path, watched: &Path) -> bool {
+    changed == watched || watched.file_name().is_some_and(|name| changed.file_name() == Some(name))
+}
+
+#[async_trait]
+impl ApiKeyStore for FileWatchApiKeyStore {
+    async fn validate_key(&self, api_key: &str) -> ApiKeyValidationResult {
+        match self.keys.read().unwrap().get(api_key) {
+            Some(config) => ApiKeyValidationResult::valid_with_config(api_key.to_string(), config.clone()),
+            None => ApiKeyValidationResult::invalid(),
+        }
+    }
+
+    async fn get_rate_limit_config(&self, api_key: &str) -> Option<BarnacleConfig> {
+        self.keys.read().unwrap().get(api_key).cloned()
+    }
+}
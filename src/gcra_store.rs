@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use deadpool_redis::redis::{cmd, AsyncCommands};
+use deadpool_redis::Pool;
+
+use crate::{
+    error::BarnacleError,
+    redis_store::RedisBarnacleStore,
+    types::{BarnacleConfig, BarnacleContext, BarnacleResult},
+    BarnacleStore,
+};
+
+/// Generic cell rate algorithm on a single stored theoretical arrival time (TAT), in ms
+/// of the Redis server clock. A request is conformant unless the TAT is further ahead of
+/// now than the burst tolerance.
+/// KEYS[1] = TAT key, ARGV[1] = emission interval in ms, ARGV[2] = burst tolerance in ms.
+/// Returns {allowed, remaining, ms until the next request is conformant, ms until the TAT is reached}.
+const GCRA_SCRIPT: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local interval = tonumber(ARGV[1])
+local tolerance = tonumber(ARGV[2])
+local tat = math.max(tonumber(redis.call('GET', KEYS[1]) or now), now)
+local allow_at = tat - tolerance
+if now < allow_at then
+    return {0, 0, allow_at - now, tat - now}
+end
+local new_tat = tat + interval
+redis.call('SET', KEYS[1], new_tat, 'PX', new_tat - now)
+local remaining = math.max(0, math.floor((now + tolerance - new_tat) / interval) + 1)
+local wait = 0
+if remaining == 0 then
+    wait = new_tat - tolerance - now
+end
+return {1, remaining, wait, new_tat - now}
+"#;
+
+/// Redis store using the generic cell rate algorithm (GCRA).
+///
+/// Requests are spaced by an emission interval of `window / max_requests`, and up to
+/// `burst_tolerance` extra requests may arrive back to back on top of the steady rate.
+/// The default tolerance is `max_requests - 1`, so a fresh key can burst `max_requests`
+/// requests like with a fixed window, then continues at the smoothed rate instead of
+/// waiting for a window boundary. Only a single timestamp is stored per key.
+/// `retry_after` is the delay before the next request would be conformant and `reset_after`
+/// the time until the full burst is available again.
+#[derive(Clone)]
+pub struct GcraStore {
+    store: RedisBarnacleStore,
+    burst_tolerance: Option<u32>,
+}
+
+impl GcraStore {
+    /// Create a new GCRA store with connection pooling
+    pub fn new(pool: Pool) -> Self {
+        Self {
+            store: RedisBarnacleStore::new(pool),
+            burst_tolerance: None,
+        }
+    }
+
+    /// Create a new GCRA store from a Redis URL
+    pub fn from_url(url: &str) -> Result<Self, deadpool_redis::PoolError> {
+        Ok(Self {
+            store: RedisBarnacleStore::from_url(url)?,
+            burst_tolerance: None,
+        })
+    }
+
+    /// Number of requests allowed back to back on top of the steady rate; `0` enforces
+    /// strict spacing. Defaults to `max_requests - 1`.
+    pub fn with_burst_tolerance(mut self, burst_tolerance: u32) -> Self {
+        self.burst_tolerance = Some(burst_tolerance);
+        self
+    }
+
+    /// The Redis key holding the theoretical arrival time for a context
+    pub fn key_for(&self, context: &BarnacleContext) -> String {
+        format!("{}:tat", self.store.key_for(context))
+    }
+}
+
+#[async_trait]
+impl BarnacleStore for GcraStore {
+    async fn increment(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        if config.max_requests == 0 || config.window.is_zero() {
+            return Err(BarnacleError::configuration_error(format!(
+                "GCRA needs a positive max_requests and window, got {} per {:?}",
+                config.max_requests, config.window
+            )));
+        }
+        let interval_ms = (config.window.as_millis() / u128::from(config.max_requests)).max(1) as i64;
+        let burst_tolerance = self.burst_tolerance.unwrap_or(config.max_requests - 1);
+        let tolerance_ms = interval_ms.saturating_mul(i64::from(burst_tolerance));
+        let redis_key = self.key_for(context);
+
+        let mut conn = self.store.connection().await?;
+
+        let (allowed, remaining, wait_ms, full_ms): (i32, u32, i64, i64) = cmd("EVAL")
+            .arg(GCRA_SCRIPT)
+            .arg(1)
+            .arg(&redis_key)
+            .arg(interval_ms)
+            .arg(tolerance_ms)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis GCRA operation failed", Box::new(e))
+            })?;
+        let wait = Duration::from_millis(wait_ms.max(0) as u64);
+
+        if allowed == 0 {
+            // Round up so clients never retry before the request is conformant
+            let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            tracing::debug!(
+                "GCRA limit exceeded for key: {}, retry_after: {}ms",
+                redis_key,
+                wait_ms
+            );
+            return Err(BarnacleError::rate_limit_exceeded(0, retry_after, config.max_requests));
+        }
+
+        tracing::debug!("GCRA check passed for key: {}, remaining: {}", redis_key, remaining);
+
+        Ok(BarnacleResult {
+            allowed: true,
+            remaining,
+            retry_after: (remaining == 0).then_some(wait),
+            // Back to the full burst once the TAT is reached
+            reset_after: Some(Duration::from_millis(full_ms.max(0) as u64)),
+            first_seen: None,
+            window_reset: None,
+        })
+    }
+
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let redis_key = self.key_for(context);
+
+        let mut conn = self.store.connection().await?;
+
+        let _: () = conn.del(&redis_key).await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to delete key from Redis", Box::new(e))
+        })?;
+
+        Ok(())
+    }
+
+    fn health(&self) -> Result<(), BarnacleError> {
+        self.store.health()
+    }
+
+    async fn idempotency_key_seen(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<bool, BarnacleError> {
+        self.store.idempotency_key_seen(context, idempotency_key).await
+    }
+
+    async fn record_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<(), BarnacleError> {
+        self.store.record_idempotency_key(context, idempotency_key, ttl).await
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        self.store.claim_idempotency_key(context, idempotency_key, ttl).await
+    }
+
+    async fn release_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<(), BarnacleError> {
+        self.store.release_idempotency_key(context, idempotency_key).await
+    }
+
+    async fn acquire_in_flight(
+        &self,
+        context: &BarnacleContext,
+        max_in_flight: u32,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        self.store.acquire_in_flight(context, max_in_flight, ttl).await
+    }
+
+    async fn release_in_flight(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        self.store.release_in_flight(context).await
+    }
+}
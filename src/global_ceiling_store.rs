@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{
+    error::BarnacleError,
+    types::{BarnacleConfig, BarnacleContext, BarnacleKey, BarnacleResult, KeyUsage, ReservationToken},
+    BarnacleStore,
+};
+
+/// Key, path and method of the counter shared by every request
+const GLOBAL_KEY: &str = "__barnacle_global__";
+
+/// Store wrapper adding a global ceiling across all keys, e.g. to protect a downstream
+/// service: once `ceiling.max_requests` requests were allowed in the ceiling's window,
+/// every further request is rejected whatever its own budget.
+///
+/// The per-key limit is checked first, so requests rejected by it don't use the global
+/// budget; a request rejected by the ceiling is refunded to its key with `decrement`.
+/// The global counter lives in the wrapped store under a reserved custom key.
+#[derive(Clone)]
+pub struct GlobalCeilingStore<S> {
+    inner: S,
+    ceiling: BarnacleConfig,
+}
+
+impl<S: BarnacleStore> GlobalCeilingStore<S> {
+    pub fn new(inner: S, ceiling: BarnacleConfig) -> Self {
+        Self { inner, ceiling }
+    }
+
+    /// The context of the global counter
+    pub fn global_context() -> BarnacleContext {
+        BarnacleContext {
+            key: BarnacleKey::Custom(GLOBAL_KEY.to_string()),
+            path: GLOBAL_KEY.to_string(),
+            method: GLOBAL_KEY.to_string(),
+        }
+    }
+
+    /// The wrapped store
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Counts the request's `cost` against the ceiling, refunding the per-key count if it is hit
+    async fn check_ceiling(
+        &self,
+        context: &BarnacleContext,
+        mut result: BarnacleResult,
+        cost: u32,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let global = self.inner.increment_by(&Self::global_context(), &self.ceiling, cost).await;
+        match global.and_then(|global| global.reject_if_disallowed(&self.ceiling)) {
+            Ok(global) => {
+                // Report the global budget once it is the tighter one
+                if global.remaining < result.remaining {
+                    result.remaining = global.remaining;
+                    result.reset_after = global.reset_after;
+                }
+                Ok(result)
+            }
+            Err(e) => {
+                tracing::debug!("Global ceiling reached, rejecting key: {:?}", context.key);
+                if let Err(refund_error) = self.inner.decrement(context, cost).await {
+                    tracing::debug!("Failed to refund key {:?}: {}", context.key, refund_error);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S: BarnacleStore + 'static> BarnacleStore for GlobalCeilingStore<S> {
+    async fn increment(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let result = self.inner.increment(context, config).await?.reject_if_disallowed(config)?;
+        self.check_ceiling(context, result, 1).await
+    }
+
+    async fn increment_by(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        cost: u32,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let cost = cost.max(1);
+        let result = self.inner.increment_by(context, config, cost).await?.reject_if_disallowed(config)?;
+        self.check_ceiling(context, result, cost).await
+    }
+
+    async fn increment_with_metadata(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        metadata: &HashMap<String, String>,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let result = self
+            .inner
+            .increment_with_metadata(context, config, metadata)
+            .await?
+            .reject_if_disallowed(config)?;
+        self.check_ceiling(context, result, 1).await
+    }
+
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        self.inner.reset(context).await
+    }
+
+    async fn reset_if_below(&self, context: &BarnacleContext, threshold: u32) -> Result<bool, BarnacleError> {
+        self.inner.reset_if_below(context, threshold).await
+    }
+
+    /// Cancelling also gives the request back to the global budget
+    async fn cancel(&self, token: ReservationToken) -> Result<(), BarnacleError> {
+        self.inner.decrement(token.context(), 1).await?;
+        self.inner.decrement(&Self::global_context(), 1).await
+    }
+
+    async fn peek(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let mut result = self.inner.peek(context, config).await?;
+        let global = self.inner.peek(&Self::global_context(), &self.ceiling).await?;
+        if global.remaining < result.remaining {
+            result.remaining = global.remaining;
+            result.reset_after = global.reset_after;
+        }
+        result.allowed = result.allowed && global.allowed;
+        Ok(result)
+    }
+
+    async fn usage_for_key(&self, context: &BarnacleContext) -> Result<KeyUsage, BarnacleError> {
+        self.inner.usage_for_key(context).await
+    }
+
+    async fn decrement(&self, context: &BarnacleContext, n: u32) -> Result<(), BarnacleError> {
+        self.inner.decrement(context, n).await
+    }
+
+    fn health(&self) -> Result<(), BarnacleError> {
+        self.inner.health()
+    }
+
+    async fn idempotency_key_seen(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<bool, BarnacleError> {
+        self.inner.idempotency_key_seen(context, idempotency_key).await
+    }
+
+    async fn record_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<(), BarnacleError> {
+        self.inner.record_idempotency_key(context, idempotency_key, ttl).await
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        self.inner.claim_idempotency_key(context, idempotency_key, ttl).await
+    }
+
+    async fn release_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<(), BarnacleError> {
+        self.inner.release_idempotency_key(context, idempotency_key).await
+    }
+
+    async fn acquire_in_flight(
+        &self,
+        context: &BarnacleContext,
+        max_in_flight: u32,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        self.inner.acquire_in_flight(context, max_in_flight, ttl).await
+    }
+
+    async fn release_in_flight(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        self.inner.release_in_flight(context).await
+    }
+}
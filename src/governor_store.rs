@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use governor::clock::{Clock, DefaultClock};
+use governor::middleware::StateInformationMiddleware;
+use governor::state::keyed::DefaultKeyedStateStore;
+use governor::{Quota, RateLimiter};
+
+use crate::{
+    error::BarnacleError,
+    types::{BarnacleConfig, BarnacleContext, BarnacleKey, BarnacleResult},
+    BarnacleStore,
+};
+
+/// Key used for the underlying governor limiter: the context plus a generation
+/// number that is bumped on every reset, so a reset key starts with a fresh state.
+type GovernorKey = (BarnacleKey, String, String, u64);
+
+type KeyedLimiter = RateLimiter<
+    GovernorKey,
+    DefaultKeyedStateStore<GovernorKey>,
+    DefaultClock,
+    StateInformationMiddleware,
+>;
+
+/// Generation of a key that was reset, and when the key was last used
+struct Generation {
+    number: u64,
+    last_used: Instant,
+}
+
+struct GovernorStoreInner {
+    limiter: KeyedLimiter,
+    clock: DefaultClock,
+    /// Only keys reset at least once; the others are at generation 0
+    generations: Mutex<HashMap<(BarnacleKey, String, String), Generation>>,
+    /// How long an idle key takes to refill its whole burst
+    replenish_time: Duration,
+}
+
+impl GovernorStoreInner {
+    fn limiter_key(&self, context: &BarnacleContext) -> GovernorKey {
+        let mut generations = self.generations.lock().unwrap();
+        let generation = match generations.get_mut(&(context.key.clone(), context.path.clone(), context.method.clone())) {
+            Some(generation) => {
+                generation.last_used = Instant::now();
+                generation.number
+            }
+            None => 0,
+        };
+        (
+            context.key.clone(),
+            context.path.clone(),
+            context.method.clone(),
+            generation,
+        )
+    }
+}
+
+/// Implementation of BarnacleStore backed by a keyed `governor` rate limiter.
+///
+/// This store keeps all state in process memory, so it is only suitable for
+/// non-distributed deployments. The limits are defined by the governor `Quota`
+/// the store was created with; `BarnacleConfig::max_requests` and `window` are
+/// not consulted on `increment`.
+#[derive(Clone)]
+pub struct GovernorStore {
+    inner: Arc<GovernorStoreInner>,
+}
+
+impl GovernorStore {
+    /// Create a new governor store enforcing the given quota for every context
+    pub fn new(quota: Quota) -> Self {
+        let clock = DefaultClock::default();
+        let limiter = RateLimiter::keyed(quota).with_middleware::<StateInformationMiddleware>();
+        Self {
+            inner: Arc::new(GovernorStoreInner {
+                limiter,
+                clock,
+                generations: Mutex::new(HashMap::new()),
+                replenish_time: quota.burst_size_replenished_in(),
+            }),
+        }
+    }
+
+    /// Create a governor store allowing `max_requests` per `window`, replenished gradually
+    pub fn from_config(config: &BarnacleConfig) -> Result<Self, BarnacleError> {
+        let max_requests = NonZeroU32::new(config.max_requests).ok_or_else(|| {
+            BarnacleError::configuration_error("max_requests must be greater than zero")
+        })?;
+        if config.window.is_zero() {
+            return Err(BarnacleError::configuration_error("window must be greater than zero"));
+        }
+        // Governor replenishes one request per period, which can't be shorter than a nanosecond
+        let quota = Quota::with_period(config.window / config.max_requests)
+            .ok_or_else(|| {
+                BarnacleError::configuration_error(format!(
+                    "window of {:?} is too short for {} requests: it must be at least 1ns per request",
+                    config.window, config.max_requests
+                ))
+            })?
+            .allow_burst(max_requests);
+        Ok(Self::new(quota))
+    }
+
+    /// Drop limiter state for keys that are indistinguishable from a fresh key, and forget
+    /// the generation of reset keys idle long enough to have refilled completely.
+    /// Call this periodically to bound memory usage.
+    pub fn retain_recent(&self) {
+        self.inner.limiter.retain_recent();
+        let replenish_time = self.inner.replenish_time;
+        self.inner
+            .generations
+            .lock()
+            .unwrap()
+            .retain(|_, generation| generation.last_used.elapsed() < replenish_time);
+    }
+}
+
+#[async_trait]
+impl BarnacleStore for GovernorStore {
+    async fn increment(
+        &self,
+        context: &BarnacleContext,
+        _config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let key = self.inner.limiter_key(context);
+
+        match self.inner.limiter.check_key(&key) {
+            Ok(snapshot) => {
+                tracing::debug!(
+                    "Governor check passed for key: {:?}, remaining: {}",
+                    context.key,
+                    snapshot.remaining_burst_capacity()
+                );
+                Ok(BarnacleResult {
+                    allowed: true,
+                    remaining: snapshot.remaining_burst_capacity(),
+                    retry_after: None,
+                    reset_after: None,
+                    // GCRA has no windows and does not track key history
+                    first_seen: None,
+                    window_reset: None,
+                })
+            }
+            Err(not_until) => {
+                let wait = not_until.wait_time_from(self.inner.clock.now());
+                // Round up so clients never retry before the cell is available
+                let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                tracing::debug!(
+                    "Governor rate limit exceeded for key: {:?}, retry_after: {}s",
+                    context.key,
+                    retry_after
+                );
+                Err(BarnacleError::rate_limit_exceeded(
+                    0,
+                    retry_after.max(1),
+                    not_until.quota().burst_size().get(),
+                ))
+            }
+        }
+    }
+
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let mut generations = self.inner.generations.lock().unwrap();
+        let generation = generations
+            .entry((context.key.clone(), context.path.clone(), context.method.clone()))
+            .or_insert(Generation {
+                number: 0,
+                last_used: Instant::now(),
+            });
+        generation.number += 1;
+        generation.last_used = Instant::now();
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+
+use crate::api_key_store::ApiKeyStore;
+use crate::error::BarnacleError;
+use crate::types::{ApiKeyValidationResult, BarnacleConfig};
+
+/// Above this many cached keys, expired entries are dropped before inserting a new one
+const MAX_CACHED_KEYS: usize = 10_000;
+
+struct CachedResult {
+    result: ApiKeyValidationResult,
+    fetched_at: Instant,
+}
+
+/// API key store that looks keys up in an external HTTP config service.
+///
+/// Each key is sent as a GET to `endpoint` in the `x-api-key` header (see `with_key_header`).
+/// A 2xx response carries an `ApiKeyValidationResult` as JSON, while 401, 403 and 404 mean the
+/// key is unknown. Both outcomes are cached for `cache_ttl`. When the service is unreachable
+/// or answers with anything else, a stale cached result is reused if there is one; otherwise
+/// the key is rejected, unless `with_fail_open(true)` lets it through with the default config.
+pub struct HttpApiKeyStore {
+    endpoint: String,
+    client: reqwest::Client,
+    key_header: String,
+    cache_ttl: Duration,
+    default_config: BarnacleConfig,
+    fail_open: bool,
+    cache: RwLock<HashMap<String, CachedResult>>,
+}
+
+impl HttpApiKeyStore {
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            client: reqwest::Client::new(),
+            key_header: "x-api-key".to_string(),
+            cache_ttl: Duration::from_secs(60),
+            default_config: BarnacleConfig::default(),
+            fail_open: false,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Use a preconfigured client, e.g. with timeouts or auth headers for the config service
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Request header carrying the API key to the config service
+    pub fn with_key_header(mut self, header: impl Into<String>) -> Self {
+        self.key_header = header.into();
+        self
+    }
+
+    pub fn with_cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    /// Config for valid keys whose response has no `rate_limit_config`, and for keys let
+    /// through while the service is down
+    pub fn with_default_config(mut self, config: BarnacleConfig) -> Self {
+        self.default_config = config;
+        self
+    }
+
+    /// Accept keys that can't be checked because the service is down (defaults to rejecting them)
+    pub fn with_fail_open(mut self, fail_open: bool) -> Self {
+        self.fail_open = fail_open;
+        self
+    }
+
+    /// Drop all cached results, so every key is fetched again on its next use
+    pub fn clear_cache(&self) {
+        self.cache.write().unwrap().clear();
+    }
+
+    /// Ask the config service about a key, bypassing the cache
+    pub async fn fetch(&self, api_key: &str) -> Result<ApiKeyValidationResult, BarnacleError> {
+        let response = self
+            .client
+            .get(&self.endpoint)
+            .header(self.key_header.as_str(), api_key)
+            .send()
+            .await
+            .map_err(|e| BarnacleError::store_error_with_source("Failed to reach config service", Box::new(e)))?;
+
+        if matches!(
+            response.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND
+        ) {
+            return Ok(ApiKeyValidationResult::invalid());
+        }
+
+        let mut result: ApiKeyValidationResult = response
+            .error_for_status()
+            .map_err(|e| BarnacleError::store_error_with_source("Config service returned an error", Box::new(e)))?
+            .json()
+            .await
+            .map_err(|e| BarnacleError::store_error_with_source("Failed to parse key config", Box::new(e)))?;
+
+        if result.valid {
+            result.key_id.get_or_insert_with(|| api_key.to_string());
+            result.rate_limit_config.get_or_insert_with(|| self.default_config.clone());
+        }
+        Ok(result)
+    }
+
+    fn cached(&self, api_key: &str) -> Option<(ApiKeyValidationResult, bool)> {
+        let cache = self.cache.read().unwrap();
+        cache
+            .get(api_key)
+            .map(|cached| (cached.result.clone(), cached.fetched_at.elapsed() < self.cache_ttl))
+    }
+
+    fn store(&self, api_key: &str, result: ApiKeyValidationResult) {
+        let mut cache = self.cache.write().unwrap();
+        if cache.len() >= MAX_CACHED_KEYS {
+            let ttl = self.cache_ttl;
+            cache.retain(|_, cached| cached.fetched_at.elapsed() < ttl);
+        }
+        cache.insert(
+            api_key.to_string(),
+            CachedResult {
+                result,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for HttpApiKeyStore {
+    async fn validate_key(&self, api_key: &str) -> ApiKeyValidationResult {
+        let cached = self.cached(api_key);
+        if let Some((result, true)) = &cached {
+            return result.clone();
+        }
+
+        match self.fetch(api_key).await {
+            Ok(result) => {
+                self.store(api_key, result.clone());
+                result
+            }
+            Err(e) => {
+                // Keep serving the last known answer rather than applying the fail policy
+                if let Some((result, _)) = cached {
+                    tracing::warn!("Config service unavailable, using cached key config: {}", e);
+                    return result;
+                }
+                if self.fail_open {
+                    tracing::warn!("Config service unavailable, accepting API key: {}", e);
+                    ApiKeyValidationResult::valid_with_config(api_key.to_string(), self.default_config.clone())
+                } else {
+                    tracing::error!("Config service unavailable, rejecting API key: {}", e);
+                    ApiKeyValidationResult::invalid()
+                }
+            }
+        }
+    }
+
+    async fn get_rate_limit_config(&self, api_key: &str) -> Option<BarnacleConfig> {
+        self.validate_key(api_key).await.rate_limit_config
+    }
+}
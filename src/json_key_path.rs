@@ -0,0 +1,92 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::error::BarnacleError;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Field(String),
+    Index(usize),
+}
+
+/// A simple JSONPath selecting one value of a JSON body, for keying requests without a
+/// typed payload, see `BarnacleLayerBuilder::with_json_key_path`.
+///
+/// Supports field access (`$.user.id`, `$['user id']`) and array indices (`$.items[0].sku`).
+/// Strings, numbers and booleans can be selected; anything else counts as absent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonKeyPath {
+    path: String,
+    segments: Vec<Segment>,
+}
+
+impl JsonKeyPath {
+    /// The selected value as a key, if the body has one
+    pub fn extract(&self, body: &Value) -> Option<String> {
+        let mut current = body;
+        for segment in &self.segments {
+            current = match segment {
+                Segment::Field(name) => current.get(name.as_str())?,
+                Segment::Index(index) => current.get(*index)?,
+            };
+        }
+        match current {
+            Value::String(text) if !text.is_empty() => Some(text.clone()),
+            Value::Number(number) => Some(number.to_string()),
+            Value::Bool(flag) => Some(flag.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for JsonKeyPath {
+    type Err = BarnacleError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let path = text.trim();
+        let invalid = |reason: &str| BarnacleError::configuration_error(format!("Invalid JSON path {:?}: {}", path, reason));
+
+        let mut rest = path.strip_prefix('$').ok_or_else(|| invalid("must start with `$`"))?;
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(invalid("empty field name"));
+                }
+                segments.push(Segment::Field(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(|| invalid("unclosed `[`"))?;
+                let inner = after[..end].trim();
+                let quoted = ['\'', '"']
+                    .iter()
+                    .find_map(|quote| inner.strip_prefix(*quote)?.strip_suffix(*quote));
+                let segment = match quoted {
+                    Some(name) => Segment::Field(name.to_string()),
+                    None => Segment::Index(inner.parse().map_err(|_| invalid("expected an index or a quoted name"))?),
+                };
+                segments.push(segment);
+                rest = &after[end + 1..];
+            } else {
+                return Err(invalid("expected `.` or `[`"));
+            }
+        }
+        if segments.is_empty() {
+            return Err(invalid("selects the whole body"));
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            segments,
+        })
+    }
+}
+
+impl fmt::Display for JsonKeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
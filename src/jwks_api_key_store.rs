@@ -0,0 +1,202 @@
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+use serde::Deserialize;
+
+use crate::api_key_store::ApiKeyStore;
+use crate::error::BarnacleError;
+use crate::types::{ApiKeyValidationResult, BarnacleConfig};
+
+/// Optional per-token rate limit carried in the `rate_limit` claim
+#[derive(Debug, Deserialize)]
+struct RateLimitClaim {
+    max_requests: Option<u32>,
+    window_seconds: Option<u64>,
+}
+
+/// Claims read from a validated token. `exp` is checked by `jsonwebtoken` itself.
+#[derive(Debug, Deserialize)]
+struct JwksClaims {
+    sub: Option<String>,
+    rate_limit: Option<RateLimitClaim>,
+}
+
+struct CachedJwks {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+/// API key store for keys that are signed JWTs, validated against a JWKS endpoint.
+///
+/// The key set is fetched lazily and cached for `refresh_interval`, so rotated keys are
+/// picked up on the next refresh. A valid token must carry a known `kid` (or the set must
+/// hold a single key) and an unexpired `exp`. The rate limit comes from the optional
+/// `rate_limit` claim (`max_requests`, `window_seconds`), falling back to the default config.
+pub struct JwksApiKeyStore {
+    jwks_url: String,
+    client: reqwest::Client,
+    refresh_interval: Duration,
+    default_config: BarnacleConfig,
+    audience: Option<Vec<String>>,
+    issuer: Option<Vec<String>>,
+    cache: RwLock<Option<CachedJwks>>,
+}
+
+impl JwksApiKeyStore {
+    pub fn new(jwks_url: impl Into<String>) -> Self {
+        Self {
+            jwks_url: jwks_url.into(),
+            client: reqwest::Client::new(),
+            refresh_interval: Duration::from_secs(5 * 60), // 5 minutes
+            default_config: BarnacleConfig::default(),
+            audience: None,
+            issuer: None,
+            cache: RwLock::new(None),
+        }
+    }
+
+    pub fn with_refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.refresh_interval = refresh_interval;
+        self
+    }
+
+    pub fn with_default_config(mut self, config: BarnacleConfig) -> Self {
+        self.default_config = config;
+        self
+    }
+
+    /// Only accept tokens whose `aud` matches one of the given audiences
+    pub fn with_audience(mut self, audience: &[&str]) -> Self {
+        self.audience = Some(audience.iter().map(|a| a.to_string()).collect());
+        self
+    }
+
+    /// Only accept tokens whose `iss` matches one of the given issuers
+    pub fn with_issuer(mut self, issuer: &[&str]) -> Self {
+        self.issuer = Some(issuer.iter().map(|i| i.to_string()).collect());
+        self
+    }
+
+    /// Fetch the key set now, replacing the cached one
+    pub async fn refresh(&self) -> Result<(), BarnacleError> {
+        tracing::debug!("Fetching JWKS from {}", self.jwks_url);
+
+        let keys: JwkSet = self
+            .client
+            .get(&self.jwks_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| BarnacleError::store_error_with_source("Failed to fetch JWKS", Box::new(e)))?
+            .json()
+            .await
+            .map_err(|e| BarnacleError::store_error_with_source("Failed to parse JWKS", Box::new(e)))?;
+
+        tracing::debug!("Fetched {} JWKS keys", keys.keys.len());
+        *self.cache.write().unwrap() = Some(CachedJwks {
+            keys,
+            fetched_at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Returns the cached key set, fetching it first if missing or older than the refresh interval
+    async fn key_set(&self) -> Result<JwkSet, BarnacleError> {
+        if let Some(cached) = self.cache.read().unwrap().as_ref() {
+            if cached.fetched_at.elapsed() < self.refresh_interval {
+                return Ok(cached.keys.clone());
+            }
+        }
+        if let Err(e) = self.refresh().await {
+            // Keep serving the stale key set rather than rejecting every token
+            if let Some(cached) = self.cache.read().unwrap().as_ref() {
+                tracing::warn!("JWKS refresh failed, using cached keys: {}", e);
+                return Ok(cached.keys.clone());
+            }
+            return Err(e);
+        }
+        let cache = self.cache.read().unwrap();
+        Ok(cache.as_ref().map(|cached| cached.keys.clone()).unwrap_or(JwkSet { keys: Vec::new() }))
+    }
+
+    fn find_key<'a>(keys: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+        match kid {
+            Some(kid) => keys.find(kid),
+            None if keys.keys.len() == 1 => keys.keys.first(),
+            None => None,
+        }
+    }
+
+    fn config_from_claims(&self, claims: &JwksClaims) -> BarnacleConfig {
+        let mut config = self.default_config.clone();
+        if let Some(rate_limit) = &claims.rate_limit {
+            if let Some(max_requests) = rate_limit.max_requests {
+                config.max_requests = max_requests;
+            }
+            if let Some(window_seconds) = rate_limit.window_seconds {
+                config.window = Duration::from_secs(window_seconds);
+            }
+        }
+        config
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for JwksApiKeyStore {
+    async fn validate_key(&self, api_key: &str) -> ApiKeyValidationResult {
+        let header = match decode_header(api_key) {
+            Ok(header) => header,
+            Err(e) => {
+                tracing::debug!("API key is not a JWT: {}", e);
+                return ApiKeyValidationResult::invalid();
+            }
+        };
+
+        let keys = match self.key_set().await {
+            Ok(keys) => keys,
+            Err(e) => {
+                tracing::error!("JWKS unavailable during API key validation: {}", e);
+                return ApiKeyValidationResult::invalid();
+            }
+        };
+
+        let Some(jwk) = Self::find_key(&keys, header.kid.as_deref()) else {
+            tracing::debug!("No JWKS key found for kid {:?}", header.kid);
+            return ApiKeyValidationResult::invalid();
+        };
+
+        let decoding_key = match DecodingKey::from_jwk(jwk) {
+            Ok(key) => key,
+            Err(e) => {
+                tracing::warn!("Unusable JWKS key {:?}: {}", header.kid, e);
+                return ApiKeyValidationResult::invalid();
+            }
+        };
+
+        // The algorithm must also match the key family, which jsonwebtoken enforces
+        let mut validation = Validation::new(header.alg);
+        if let Some(audience) = &self.audience {
+            validation.set_audience(audience.as_slice());
+        } else {
+            validation.validate_aud = false;
+        }
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(issuer.as_slice());
+        }
+
+        match decode::<JwksClaims>(api_key, &decoding_key, &validation) {
+            Ok(token) => {
+                let config = self.config_from_claims(&token.claims);
+                let key_id = token.claims.sub.unwrap_or_else(|| api_key.to_string());
+                ApiKeyValidationResult::valid_with_config(key_id, config)
+            }
+            Err(e) => {
+                tracing::debug!("JWT API key rejected: {}", e);
+                ApiKeyValidationResult::invalid()
+            }
+        }
+    }
+}
@@ -9,6 +9,14 @@
 //! - **Per-Key Rate Limits**: Different rate limits per API key
 //! - **Extensible Design**: Custom key stores and rate limiting strategies
 //! - **Redis Integration**: Default Redis-based storage for keys and rate limits
+//! - **In-Memory Store**: Built-in store for running without Redis
+//! - **Global Ceiling**: Store wrapper capping total traffic across all keys
+//! - **Store Timeouts**: Store wrapper bounding slow backends, failing open or closed
+//! - **Prometheus Metrics**: Optional observer exporting rate limiting metrics (`metrics` feature)
+//! - **Governor Integration**: Optional in-process store backed by the `governor` crate
+//! - **JWKS Validation**: Optional API key store for signed JWT keys (`jwks` feature)
+//! - **HTTP Key Configs**: Optional API key store backed by a config service (`http-config` feature)
+//! - **File Key Configs**: Optional API key store reloaded when its JSON file changes (`file-watch` feature)
 //! - **Axum Middleware**: Ready-to-use middleware for Axum web framework
 //!
 //! ## Basic Usage
@@ -46,42 +54,179 @@
 //! ```
 
 mod api_key_store;
+mod backoff;
+mod clock;
+mod concurrency;
+mod config_resolver;
+pub mod core;
+mod duration_serde;
 mod error;
+mod fallback_store;
+#[cfg(feature = "file-watch")]
+mod file_watch_api_key_store;
+mod global_ceiling_store;
+#[cfg(feature = "redis")]
+mod gcra_store;
+#[cfg(feature = "governor")]
+mod governor_store;
+#[cfg(feature = "http-config")]
+mod http_api_key_store;
+mod json_key_path;
+#[cfg(feature = "jwks")]
+mod jwks_api_key_store;
+mod memory_store;
 mod middleware;
+mod observe_only;
+mod observer;
+#[cfg(feature = "metrics")]
+mod prometheus_observer;
+mod rate_limiter;
+#[cfg(feature = "cluster")]
+mod redis_cluster_store;
 mod redis_store;
+mod reset_queue;
+#[cfg(feature = "redis")]
+mod sliding_window_store;
+mod store_health;
+mod timeout_store;
+mod token_bucket_store;
+mod trusted_proxy;
 mod types;
 
 // Re-export key items for easier access
 pub use api_key_store::{ApiKeyStore, StaticApiKeyStore};
+pub use backoff::{next_backoff, next_backoff_jittered, retry_with_backoff, Backoff, EQUAL_JITTER, FULL_JITTER};
+pub use clock::{Clock, FixedClock, SystemClock};
+pub use concurrency::{InFlightGuard, RateLimitPermit};
+pub use config_resolver::{ConfigResolver, StaticConfigResolver};
 pub use error::BarnacleError;
+pub use fallback_store::FallbackStore;
+pub use global_ceiling_store::GlobalCeilingStore;
+pub use json_key_path::JsonKeyPath;
+pub use memory_store::InMemoryBarnacleStore;
 pub use middleware::{
-    BarnacleLayer, KeyExtractable, BarnacleLayerBuilderError
+    rate_limit_response, ApiKeyRateLimitLayer, ApiKeyStoreValidator, BarnacleLayer, KeyExtractable, BarnacleLayerBuilderError,
 };
+pub use observe_only::ObserveOnlyLayer;
+pub use observer::{CountingObserver, NoopObserver, RateLimitObserver};
+pub use rate_limiter::{RateLimitDecision, RateLimiter};
+pub use store_health::{StoreHealthError, StoreHealthLayer, StoreHealthMiddleware};
+pub use timeout_store::TimeoutStore;
+pub use token_bucket_store::InMemoryTokenBucketStore;
+pub use trusted_proxy::{IpCidr, IpKeyPrefix, TrustedProxyConfig};
 pub use tracing;
 pub use types::{
-    BarnacleConfig, BarnacleContext, BarnacleKey, BarnacleResult,
-    ResetOnSuccess, StaticApiKeyConfig, ApiKeyConfig,
+    BarnacleConfig, BarnacleContext, BarnacleKey, BarnacleLayerConfig, BarnacleResult,
+    ResetOnSuccess, StaticApiKeyConfig, ApiKeyConfig, RequestIdConfig, ConcurrencyConfig, ResponseCost,
+    IdempotencyConfig, ApiKeyValidationResult, ResetOnSuccessHeader, KeyUsage, TokenBucketConfig,
+    ReservationToken, HeaderStyle, ErrorFormat, AccessControl, StoreErrorPolicy, RequestCost, RateLimitSnapshot,
 };
 
 // Redis-specific exports (only available with "redis" feature)
 #[cfg(feature = "redis")]
 pub use api_key_store::RedisApiKeyStore;
 #[cfg(feature = "redis")]
-pub use redis_store::RedisBarnacleStore;
+pub use redis_store::{RedisBarnacleStore, RedisBarnacleStoreBuilder};
+#[cfg(feature = "cluster")]
+pub use redis_cluster_store::RedisClusterBarnacleStore;
+#[cfg(feature = "redis")]
+pub use gcra_store::GcraStore;
+#[cfg(feature = "redis")]
+pub use sliding_window_store::SlidingWindowStore;
+#[cfg(feature = "redis")]
+pub use token_bucket_store::TokenBucketStore;
 // Re-export commonly used external dependencies (only with redis feature)
 #[cfg(feature = "redis")]
 pub use deadpool_redis;
 
+// Governor-backed local store (only available with "governor" feature)
+#[cfg(feature = "governor")]
+pub use governor;
+#[cfg(feature = "governor")]
+pub use governor_store::GovernorStore;
+
+// Hot-reloaded file API key store (only available with "file-watch" feature)
+#[cfg(feature = "file-watch")]
+pub use file_watch_api_key_store::FileWatchApiKeyStore;
+
+// Prometheus metrics observer (only available with "metrics" feature)
+#[cfg(feature = "metrics")]
+pub use prometheus;
+#[cfg(feature = "metrics")]
+pub use prometheus_observer::PrometheusObserver;
+
+// HTTP config service API key store (only available with "http-config" feature)
+#[cfg(feature = "http-config")]
+pub use http_api_key_store::HttpApiKeyStore;
+
+// JWKS-backed API key store (only available with "jwks" feature)
+#[cfg(feature = "jwks")]
+pub use jwks_api_key_store::JwksApiKeyStore;
+
 use async_trait::async_trait;
+use std::collections::HashMap;
+use std::time::Duration;
 
 pub const BARNACLE_EMAIL_KEY_PREFIX: &str = "barnacle:email";
 pub const BARNACLE_API_KEY_PREFIX: &str = "barnacle:api_keys";
 pub const BARNACLE_IP_PREFIX: &str = "barnacle:ip";
 pub const BARNACLE_CUSTOM_PREFIX: &str = "barnacle:custom";
+pub const BARNACLE_COMPOSITE_PREFIX: &str = "barnacle:composite";
+/// Response header a handler can set to report the cost of the request
+pub const BARNACLE_COST_HEADER: &str = "x-barnacle-cost";
 
 /// Trait to abstract the rate limiter storage backend (e.g., Redis)
+///
+/// The middleware clones the store into every request and holds it across `.await`s on
+/// any runtime thread, so a store must be `Clone + Send + Sync + 'static`: own its data
+/// (no borrowed fields) and keep shared state behind `Arc`, with a `Mutex` or atomics for
+/// anything mutable. Only `increment` and `reset` are required.
+///
+/// Counting methods (`increment`, `increment_by`, `increment_with_metadata`, `reserve`)
+/// return `Ok` only for requests that were counted and may proceed. A request over the
+/// limit is an `Err(BarnacleError::RateLimitExceeded)` carrying `retry_after`, and any other
+/// error means the backend failed (a 503, or a pass under `StoreErrorPolicy::FailOpen`).
+/// Results with `allowed: false` are only meaningful from `peek`; the middleware and the
+/// shipped wrappers treat one returned from a counting method as a rejection, see
+/// `BarnacleResult::reject_if_disallowed`.
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::sync::{Arc, Mutex};
+/// use barnacle_rs::{BarnacleConfig, BarnacleContext, BarnacleError, BarnacleResult, BarnacleStore};
+///
+/// #[derive(Clone, Default)]
+/// struct CountingStore {
+///     counts: Arc<Mutex<HashMap<BarnacleContext, u32>>>,
+/// }
+///
+/// #[async_trait::async_trait]
+/// impl BarnacleStore for CountingStore {
+///     async fn increment(&self, context: &BarnacleContext, config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+///         let mut counts = self.counts.lock().unwrap();
+///         let count = counts.entry(context.clone()).or_insert(0);
+///         if *count >= config.max_requests {
+///             return Err(BarnacleError::rate_limit_exceeded(0, config.window.as_secs(), config.max_requests));
+///         }
+///         *count += 1;
+///         Ok(BarnacleResult {
+///             allowed: true,
+///             remaining: config.max_requests - *count,
+///             retry_after: None,
+///             reset_after: None,
+///             first_seen: None,
+///             window_reset: None,
+///         })
+///     }
+///
+///     async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+///         self.counts.lock().unwrap().remove(context);
+///         Ok(())
+///     }
+/// }
+/// ```
 #[async_trait]
-pub trait BarnacleStore: Clone + Send + Sync {
+pub trait BarnacleStore: Clone + Send + Sync + 'static {
     /// Increments the counter for the key and returns the current number of requests and remaining time until reset.
     async fn increment(
         &self,
@@ -90,6 +235,187 @@ pub trait BarnacleStore: Clone + Send + Sync {
     ) -> Result<types::BarnacleResult, BarnacleError>;
     /// Resets the counter for the key (e.g., after successful login).
     async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError>;
+    /// Counts a request costing `cost` units of quota. It is rejected, and nothing is
+    /// counted, when the cost doesn't fit in what is left of the window. Stores without
+    /// weighted counting only support a cost of 1.
+    async fn increment_by(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        cost: u32,
+    ) -> Result<types::BarnacleResult, BarnacleError> {
+        if cost > 1 {
+            return Err(BarnacleError::store_error(
+                "Weighted increments are not supported by this store",
+            ));
+        }
+        self.increment(context, config).await
+    }
+    /// Resets the counters of all `contexts`, e.g. for `ResetOnSuccess::Multiple`. Stores
+    /// that can should do it in one round trip; by default each context is reset in turn
+    /// and the first error is returned once all were tried.
+    async fn reset_many(&self, contexts: &[BarnacleContext]) -> Result<(), BarnacleError> {
+        let mut first_error = None;
+        for context in contexts {
+            if let Err(e) = self.reset(context).await {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+    /// Atomically resets the counter only if it is at or below `threshold`, so a late
+    /// reset can't wipe counts that arrived since. Returns whether the counter was reset.
+    async fn reset_if_below(&self, context: &BarnacleContext, threshold: u32) -> Result<bool, BarnacleError> {
+        let _ = (context, threshold);
+        Err(BarnacleError::store_error(
+            "Conditional reset is not supported by this store",
+        ))
+    }
+    /// Like `increment`, but also stores `metadata` (e.g. the plan name) alongside the
+    /// counter for the current window. Stores without metadata support just increment.
+    async fn increment_with_metadata(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        metadata: &HashMap<String, String>,
+    ) -> Result<types::BarnacleResult, BarnacleError> {
+        let _ = metadata;
+        self.increment(context, config).await
+    }
+    /// Counts a request provisionally: the quota is taken now and kept by `commit`, or
+    /// given back by `cancel` (e.g. when the guarded operation fails). Rejects like `increment`.
+    async fn reserve(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<ReservationToken, BarnacleError> {
+        let result = self.increment(context, config).await?;
+        Ok(ReservationToken::new(context.clone(), result))
+    }
+    /// Confirms a reservation; the quota stays taken.
+    async fn commit(&self, token: ReservationToken) -> Result<(), BarnacleError> {
+        let _ = token;
+        Ok(())
+    }
+    /// Cancels a reservation, returning its quota with `decrement`. If the window rolled
+    /// over in between, the new window's count is decremented instead.
+    async fn cancel(&self, token: ReservationToken) -> Result<(), BarnacleError> {
+        self.decrement(token.context(), 1).await
+    }
+    /// Counts a request and returns a permit held for the rate-limited section. The default,
+    /// used by every shipped store, counts it with `increment` and returns a permit with
+    /// nothing to release. A store limiting concurrency can override it to return
+    /// `RateLimitPermit::in_flight`, holding a slot released on drop. Rejects like `increment`.
+    async fn acquire(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<RateLimitPermit<Self>, BarnacleError> {
+        let result = self.increment(context, config).await?.reject_if_disallowed(config)?;
+        Ok(RateLimitPermit::counted(result))
+    }
+    /// Reports the remaining quota and time until reset without counting a request,
+    /// e.g. for a rate limit status endpoint. `allowed` tells whether the next request would pass.
+    async fn peek(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<types::BarnacleResult, BarnacleError> {
+        let _ = (context, config);
+        Err(BarnacleError::store_error(
+            "Peeking is not supported by this store",
+        ))
+    }
+    /// Returns the counter and metadata for the key without counting a request.
+    async fn usage_for_key(&self, context: &BarnacleContext) -> Result<KeyUsage, BarnacleError> {
+        let _ = context;
+        Err(BarnacleError::store_error(
+            "Usage lookup is not supported by this store",
+        ))
+    }
+    /// Gives back `n` units of quota (e.g. a refunded no-op request). The counter is
+    /// floored at zero and the window's expiry is left untouched.
+    async fn decrement(&self, context: &BarnacleContext, n: u32) -> Result<(), BarnacleError> {
+        let _ = (context, n);
+        Err(BarnacleError::store_error(
+            "Decrement is not supported by this store",
+        ))
+    }
+    /// Reports whether the store can currently serve requests. Must not block;
+    /// checked in `poll_ready` by `StoreHealthLayer`.
+    fn health(&self) -> Result<(), BarnacleError> {
+        Ok(())
+    }
+    /// Returns whether the idempotency key was already recorded for this context.
+    async fn idempotency_key_seen(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<bool, BarnacleError> {
+        let _ = (context, idempotency_key);
+        Err(BarnacleError::store_error(
+            "Idempotency keys are not supported by this store",
+        ))
+    }
+    /// Records an idempotency key for this context, remembered for `ttl`.
+    async fn record_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<(), BarnacleError> {
+        let _ = (context, idempotency_key, ttl);
+        Err(BarnacleError::store_error(
+            "Idempotency keys are not supported by this store",
+        ))
+    }
+    /// Records the idempotency key unless it is already recorded, returning `true` when
+    /// this call recorded it. The middleware counts a request only after claiming its key,
+    /// so concurrent retries can't both be counted. The default checks then records, which
+    /// is not atomic; stores that can should override it with a single set-if-absent.
+    async fn claim_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        if self.idempotency_key_seen(context, idempotency_key).await? {
+            return Ok(false);
+        }
+        self.record_idempotency_key(context, idempotency_key, ttl).await?;
+        Ok(true)
+    }
+    /// Forgets a claimed idempotency key, so a retry of a rejected request is counted.
+    async fn release_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<(), BarnacleError> {
+        let _ = (context, idempotency_key);
+        Err(BarnacleError::store_error(
+            "Idempotency keys are not supported by this store",
+        ))
+    }
+    /// Takes an in-flight slot for the key if fewer than `max_in_flight` are taken.
+    /// Returns `false` when no slot is available. `ttl` bounds how long a leaked slot can survive.
+    async fn acquire_in_flight(
+        &self,
+        context: &BarnacleContext,
+        max_in_flight: u32,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        let _ = (context, max_in_flight, ttl);
+        Err(BarnacleError::store_error(
+            "In-flight tracking is not supported by this store",
+        ))
+    }
+    /// Releases an in-flight slot taken with `acquire_in_flight`. The counter never goes below zero.
+    async fn release_in_flight(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let _ = context;
+        Err(BarnacleError::store_error(
+            "In-flight tracking is not supported by this store",
+        ))
+    }
 }
 
 
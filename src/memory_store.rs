@@ -0,0 +1,313 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    error::BarnacleError,
+    types::{BarnacleConfig, BarnacleContext, BarnacleResult, KeyUsage},
+    BarnacleStore,
+};
+
+/// Number of independently locked shards, so concurrent requests for different keys rarely contend
+const SHARDS: usize = 16;
+
+struct Counter {
+    count: u32,
+    expires_at: SystemTime,
+}
+
+type Shard = Mutex<HashMap<BarnacleContext, Counter>>;
+
+/// In-flight slots taken per key, expiring with the safety TTL like the Redis counter
+type InFlight = Mutex<HashMap<BarnacleContext, Counter>>;
+
+/// Claimed idempotency keys per context, with the time each one expires
+type IdempotencyKeys = Mutex<HashMap<(BarnacleContext, String), SystemTime>>;
+
+/// Fixed-window store keeping counters in process memory, for running without Redis
+/// (tests, development, single-instance deployments).
+///
+/// Counters expire with their window but stay in memory until swept, so call `gc`
+/// periodically or start `spawn_gc` to bound memory use. `first_seen` and
+/// `window_reset` are reported, with keys swept by `gc` counting as new again.
+/// In-flight slots for `ConcurrencyConfig` and idempotency keys are supported too.
+#[derive(Clone)]
+pub struct InMemoryBarnacleStore {
+    shards: Arc<Vec<Shard>>,
+    in_flight: Arc<InFlight>,
+    idempotency_keys: Arc<IdempotencyKeys>,
+    clock: Arc<dyn Clock>,
+}
+
+impl InMemoryBarnacleStore {
+    pub fn new() -> Self {
+        Self {
+            shards: Arc::new((0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect()),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            idempotency_keys: Arc::new(Mutex::new(HashMap::new())),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Time source used for window expiry (defaults to the system clock)
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    fn shard(&self, context: &BarnacleContext) -> &Shard {
+        let mut hasher = DefaultHasher::new();
+        context.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
+    }
+
+    /// Removes counters whose window has expired and returns how many were removed.
+    /// In-flight slots past their safety TTL and expired idempotency keys are dropped as well.
+    pub fn gc(&self) -> usize {
+        let now = self.clock.now();
+        self.in_flight.lock().unwrap().retain(|_, slots| slots.expires_at > now);
+        self.idempotency_keys.lock().unwrap().retain(|_, expires_at| *expires_at > now);
+        self.shards
+            .iter()
+            .map(|shard| {
+                let mut counters = shard.lock().unwrap();
+                let before = counters.len();
+                counters.retain(|_, counter| counter.expires_at > now);
+                before - counters.len()
+            })
+            .sum()
+    }
+
+    /// Runs `gc` every `every` on the Tokio runtime until the returned handle is aborted
+    pub fn spawn_gc(&self, every: Duration) -> tokio::task::JoinHandle<()> {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                let removed = store.gc();
+                tracing::debug!("In-memory store gc removed {} expired counters", removed);
+            }
+        })
+    }
+
+    /// Number of counters currently held, including expired ones not yet swept
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for InMemoryBarnacleStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Time left until `expires_at`, rounded up to whole seconds
+fn seconds_until(expires_at: SystemTime, now: SystemTime) -> u64 {
+    let left = expires_at.duration_since(now).unwrap_or_default();
+    left.as_secs() + u64::from(left.subsec_nanos() > 0)
+}
+
+#[async_trait]
+impl BarnacleStore for InMemoryBarnacleStore {
+    async fn increment(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        self.increment_by(context, config, 1).await
+    }
+
+    async fn increment_by(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        cost: u32,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let now = self.clock.now();
+        let mut counters = self.shard(context).lock().unwrap();
+
+        let first_seen = !counters.contains_key(context);
+        let counter = counters.entry(context.clone()).or_insert(Counter {
+            count: 0,
+            expires_at: now + config.window,
+        });
+        let window_reset = !first_seen && counter.expires_at <= now;
+        if window_reset {
+            counter.count = 0;
+            counter.expires_at = now + config.window;
+        }
+
+        let retry_after = seconds_until(counter.expires_at, now);
+        if counter.count.saturating_add(cost.max(1)) > config.max_requests {
+            tracing::debug!(
+                "Rate limit exceeded for key: {:?}, current: {}, cost: {}, max: {}, retry_after: {}s",
+                context.key,
+                counter.count,
+                cost,
+                config.max_requests,
+                retry_after
+            );
+            let remaining = config.max_requests.saturating_sub(counter.count);
+            return Err(BarnacleError::rate_limit_exceeded(remaining, retry_after, config.max_requests));
+        }
+        counter.count += cost;
+
+        Ok(BarnacleResult {
+            allowed: true,
+            remaining: config.max_requests - counter.count,
+            retry_after: None,
+            reset_after: Some(Duration::from_secs(retry_after)),
+            first_seen: Some(first_seen),
+            window_reset: Some(window_reset),
+        })
+    }
+
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        self.shard(context).lock().unwrap().remove(context);
+        Ok(())
+    }
+
+    async fn reset_if_below(&self, context: &BarnacleContext, threshold: u32) -> Result<bool, BarnacleError> {
+        let mut counters = self.shard(context).lock().unwrap();
+        let count = counters.get(context).map_or(0, |counter| counter.count);
+        if count > threshold {
+            return Ok(false);
+        }
+        counters.remove(context);
+        Ok(true)
+    }
+
+    async fn peek(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let usage = self.usage_for_key(context).await?;
+        Ok(BarnacleResult {
+            allowed: usage.count < config.max_requests,
+            remaining: config.max_requests.saturating_sub(usage.count),
+            retry_after: None,
+            reset_after: usage.retry_after,
+            first_seen: None,
+            window_reset: None,
+        })
+    }
+
+    async fn usage_for_key(&self, context: &BarnacleContext) -> Result<KeyUsage, BarnacleError> {
+        let now = self.clock.now();
+        let counters = self.shard(context).lock().unwrap();
+        let active = counters.get(context).filter(|counter| counter.expires_at > now);
+        Ok(KeyUsage {
+            count: active.map_or(0, |counter| counter.count),
+            retry_after: active.map(|counter| Duration::from_secs(seconds_until(counter.expires_at, now))),
+            metadata: HashMap::new(),
+        })
+    }
+
+    async fn decrement(&self, context: &BarnacleContext, n: u32) -> Result<(), BarnacleError> {
+        if let Some(counter) = self.shard(context).lock().unwrap().get_mut(context) {
+            counter.count = counter.count.saturating_sub(n);
+        }
+        Ok(())
+    }
+
+    async fn idempotency_key_seen(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<bool, BarnacleError> {
+        let now = self.clock.now();
+        let keys = self.idempotency_keys.lock().unwrap();
+        let expires_at = keys.get(&(context.clone(), idempotency_key.to_string()));
+        Ok(expires_at.is_some_and(|expires_at| *expires_at > now))
+    }
+
+    async fn record_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<(), BarnacleError> {
+        let expires_at = self.clock.now() + ttl;
+        self.idempotency_keys
+            .lock()
+            .unwrap()
+            .insert((context.clone(), idempotency_key.to_string()), expires_at);
+        Ok(())
+    }
+
+    /// Checked and recorded under one lock, so concurrent claims can't both succeed
+    async fn claim_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        let now = self.clock.now();
+        let mut keys = self.idempotency_keys.lock().unwrap();
+        let expires_at = keys.entry((context.clone(), idempotency_key.to_string())).or_insert(now);
+        if *expires_at > now {
+            return Ok(false);
+        }
+        *expires_at = now + ttl;
+        Ok(true)
+    }
+
+    async fn release_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<(), BarnacleError> {
+        self.idempotency_keys
+            .lock()
+            .unwrap()
+            .remove(&(context.clone(), idempotency_key.to_string()));
+        Ok(())
+    }
+
+    async fn acquire_in_flight(
+        &self,
+        context: &BarnacleContext,
+        max_in_flight: u32,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        let now = self.clock.now();
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let slots = in_flight.entry(context.clone()).or_insert(Counter {
+            count: 0,
+            expires_at: now + ttl,
+        });
+        // Slots never released (e.g. a lost guard) are dropped with the safety TTL
+        if slots.expires_at <= now {
+            slots.count = 0;
+        }
+        if slots.count >= max_in_flight {
+            return Ok(false);
+        }
+        slots.count += 1;
+        slots.expires_at = now + ttl;
+        Ok(true)
+    }
+
+    async fn release_in_flight(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(slots) = in_flight.get_mut(context) {
+            slots.count = slots.count.saturating_sub(1);
+            if slots.count == 0 {
+                in_flight.remove(context);
+            }
+        }
+        Ok(())
+    }
+}
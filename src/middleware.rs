@@ -1,19 +1,34 @@
 use axum::body::Body;
-use axum::extract::{OriginalUri, Request};
+use axum::extract::{MatchedPath, OriginalUri, Request};
 use axum::http::request::Parts;
 use axum::http::Response;
 use axum::response::IntoResponse;
-use http_body_util::BodyExt;
+use futures::FutureExt;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
 use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
 use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::task::{Context, Poll};
 use tower::{Layer, Service};
 use std::future::Future;
-use tracing::debug;
+use tracing::{debug, Instrument};
 use std::pin::Pin;
 
-use crate::types::{ApiKeyConfig, ResetOnSuccess, NO_KEY};
+use crate::api_key_store::ApiKeyStore;
+use crate::clock::{Clock, SystemClock};
+use crate::concurrency::InFlightGuard;
+use crate::config_resolver::{ConfigResolver, PerKeyConfigResolver, StaticConfigResolver};
+use crate::core::check_rate_limit_by;
+use crate::json_key_path::JsonKeyPath;
+use crate::observer::RateLimitObserver;
+use crate::reset_queue::ResetQueue;
+use crate::trusted_proxy::{IpKeyPrefix, TrustedProxyConfig};
+use crate::types::{AccessControl, ApiKeyConfig, ApiKeyValidationResult, BarnacleLayerConfig, BarnacleResult, ConcurrencyConfig, ErrorFormat, HeaderStyle, IdempotencyConfig, RateLimitSnapshot, RequestCost, RequestIdConfig, ResetOnSuccess, ResetOnSuccessHeader, ResponseCost, StoreErrorPolicy, NO_KEY};
+use crate::BARNACLE_COST_HEADER;
 use crate::RedisBarnacleStore;
 use crate::{
     types::{BarnacleConfig, BarnacleContext, BarnacleKey},
@@ -21,6 +36,74 @@ use crate::{
 };
 use crate::error::BarnacleError;
 
+/// Path and method used for counters that span every endpoint
+const ALL_ENDPOINTS: &str = "*";
+
+/// Path suffix of the counter tracking failed requests, see `BarnacleLayerBuilder::with_failure_config`
+const FAILURES_SUFFIX: &str = "#failures";
+
+/// Scope reported in `X-RateLimit-Scope` when the endpoint's global limit rejects a request
+const GLOBAL_SCOPE: &str = "global";
+
+/// Async check run before a request is counted, see `BarnacleLayerBuilder::with_pre_check`
+type PreCheck = Arc<dyn Fn(&BarnacleContext) -> Pin<Box<dyn Future<Output = Result<(), BarnacleError>> + Send>> + Send + Sync>;
+
+/// Predicate selecting clients whose rejections omit reset timing, see `BarnacleLayerBuilder::with_hidden_retry_after`
+type HideRetryAfter = Arc<dyn Fn(&Parts) -> bool + Send + Sync>;
+
+/// Picks the rate limit key from the request head, see `BarnacleLayerBuilder::with_key_extractor`
+type KeyExtractor = Arc<dyn Fn(&Parts) -> Option<BarnacleKey> + Send + Sync>;
+
+/// Prices a request from its head, see `BarnacleLayerBuilder::with_request_cost`
+type RequestCostFn = Arc<dyn Fn(&Parts) -> u32 + Send + Sync>;
+
+/// Judges a buffered response a success for reset-on-success, see `BarnacleLayerBuilder::with_success_predicate`
+type SuccessPredicate = Arc<dyn Fn(&axum::http::response::Parts, &axum::body::Bytes) -> bool + Send + Sync>;
+
+/// Renders an error response, see `BarnacleLayerBuilder::with_response_builder`
+type ResponseBuilderFn = Arc<dyn Fn(&BarnacleError) -> Response<Body> + Send + Sync>;
+
+/// Limit for a specific key, see `BarnacleLayerBuilder::with_per_key_config`
+type PerKeyConfigFn = Arc<dyn Fn(&BarnacleKey) -> Option<BarnacleConfig> + Send + Sync>;
+
+/// Request extension recording the config of a `BarnacleLayer` the request already passed
+/// through, so a layer stacked inside it can spot a conflicting setup
+#[derive(Clone)]
+struct AppliedLayerConfig(BarnacleConfig);
+
+/// Describes why two stacked layers conflict, or `None` if they are compatible.
+/// Stacked layers count the same key, so their windows and reset rules must agree.
+fn layer_conflict(outer: &BarnacleConfig, inner: &BarnacleConfig) -> Option<&'static str> {
+    if outer.reset_on_success != inner.reset_on_success {
+        Some("different reset_on_success: one layer resets counters the other is still counting")
+    } else if outer.window != inner.window {
+        Some("different windows: the counter expires with whichever layer counted first")
+    } else {
+        None
+    }
+}
+
+/// Shared across clones of a layer so sampling spans all connections
+#[derive(Debug)]
+struct LogSampler {
+    every: u64,
+    allowed: AtomicU64,
+}
+
+impl LogSampler {
+    fn new(every: u32) -> Self {
+        Self {
+            every: u64::from(every.max(1)),
+            allowed: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether this allowed decision should be logged
+    fn sample(&self) -> bool {
+        self.allowed.fetch_add(1, Ordering::Relaxed) % self.every == 0
+    }
+}
+
 /// Trait to extract the key from any payload type
 pub trait KeyExtractable {
     fn extract_key(&self, request_parts: &Parts) -> BarnacleKey;
@@ -39,12 +122,68 @@ pub enum BarnacleLayerBuilderError {
 pub struct BarnacleLayerBuilder<T = (), S = RedisBarnacleStore, State = (), E = BarnacleError, V = ()> {
     store: Option<S>,
     config: Option<BarnacleConfig>,
+    config_resolver: Option<Arc<dyn ConfigResolver>>,
+    per_key_config: Option<PerKeyConfigFn>,
     state: Option<State>,
     api_key_validator: Option<V>,
     api_key_middleware_config: Option<ApiKeyConfig>,
+    api_key_global_config: Option<BarnacleConfig>,
+    request_id_config: Option<RequestIdConfig>,
+    concurrency_config: Option<ConcurrencyConfig>,
+    response_cost_enabled: Option<bool>,
+    maintenance_until: Option<SystemTime>,
+    idempotency_config: Option<IdempotencyConfig>,
+    body_hash_limit: Option<usize>,
+    retry_after_jitter: Option<f64>,
+    log_sampler: Option<Arc<LogSampler>>,
+    clock: Option<Arc<dyn Clock>>,
+    reset_on_success_header: Option<ResetOnSuccessHeader>,
+    refund_on_panic: Option<bool>,
+    pre_check: Option<PreCheck>,
+    hide_retry_after: Option<HideRetryAfter>,
+    grace_requests: u32,
+    failure_config: Option<BarnacleConfig>,
+    scope_header: Option<bool>,
+    header_style: Option<HeaderStyle>,
+    retry_after_on_success: Option<bool>,
+    key_extractor: Option<KeyExtractor>,
+    reset_queue_capacity: Option<usize>,
+    trusted_proxies: Option<Arc<TrustedProxyConfig>>,
+    ip_key_prefix: Option<IpKeyPrefix>,
+    trusted_identity_header: Option<String>,
+    store_error_policy: Option<StoreErrorPolicy>,
+    observer: Option<Arc<dyn RateLimitObserver>>,
+    json_key_path: Option<(Arc<JsonKeyPath>, usize)>,
+    payload_key_required: Option<bool>,
+    no_count_statuses: Option<Arc<[u16]>>,
+    request_cost: Option<RequestCostFn>,
+    max_body_bytes: Option<usize>,
+    required_scope: Option<String>,
+    reset_if_unchanged: Option<bool>,
+    success_predicate: Option<SuccessPredicate>,
+    response_builder: Option<ResponseBuilderFn>,
+    error_format: Option<ErrorFormat>,
+    access_control: Option<Arc<AccessControl>>,
+    global_config: Option<BarnacleConfig>,
+    use_matched_path: Option<bool>,
+    api_key_query_param: Option<String>,
     _phantom: PhantomData<(T, E)>,
 }
 
+impl<T, S, E, K> BarnacleLayerBuilder<T, S, (), E, ApiKeyStoreValidator<K>>
+where
+    K: ApiKeyStore + 'static,
+{
+    /// Validate the `x-api-key` header (see `with_api_key_middleware_config`) against `store`,
+    /// making this an `ApiKeyRateLimitLayer`. Requests without a key get a 401
+    /// `ApiKeyMissing`, unknown keys a 401 `InvalidApiKey`.
+    pub fn with_api_key_store(mut self, store: K) -> Self {
+        self.api_key_validator = Some(ApiKeyStoreValidator::new(store));
+        self.state = Some(());
+        self
+    }
+}
+
 impl<T, S, State, E, V> BarnacleLayerBuilder<T, S, State, E, V>
 where
     S: BarnacleStore + 'static,
@@ -59,10 +198,99 @@ where
         self.config = Some(config);
         self
     }
+    /// Pick the config per request from its key, path and method instead of applying one
+    /// config to every route under the layer. Takes precedence over `with_config`.
+    pub fn with_config_resolver(mut self, resolver: impl ConfigResolver + 'static) -> Self {
+        self.config_resolver = Some(Arc::new(resolver));
+        self
+    }
+    /// Let a key select its own limit, e.g. an email extracted from the payload. Keys for
+    /// which `lookup` returns `None` get the config from `with_config_resolver` or `with_config`.
+    pub fn with_per_key_config<F>(mut self, lookup: F) -> Self
+    where
+        F: Fn(&BarnacleKey) -> Option<BarnacleConfig> + Send + Sync + 'static,
+    {
+        self.per_key_config = Some(Arc::new(lookup));
+        self
+    }
+    /// Apply a whole `BarnacleLayerConfig`, e.g. loaded from a config file. The limit and
+    /// switches always apply; optional sections only replace earlier builder calls when set.
+    pub fn with_layer_config(mut self, layer_config: BarnacleLayerConfig) -> Self {
+        self = self
+            .with_config(layer_config.limit)
+            .with_grace_requests(layer_config.grace_requests)
+            .with_response_cost(layer_config.response_cost)
+            .with_refund_on_panic(layer_config.refund_on_panic)
+            .with_reset_if_unchanged(layer_config.reset_if_unchanged)
+            .with_no_count_statuses(layer_config.no_count_statuses)
+            .with_scope_header(layer_config.scope_header)
+            .with_header_style(layer_config.header_style)
+            .with_error_format(layer_config.error_format)
+            .with_retry_after_on_success(layer_config.retry_after_on_success)
+            .with_store_error_policy(layer_config.store_error_policy)
+            .with_payload_key_required(layer_config.payload_key_required);
+        if let Some(config) = layer_config.api_key {
+            self = self.with_api_key_middleware_config(config);
+        }
+        if let Some(max_bytes) = layer_config.max_body_bytes {
+            self = self.with_max_body_bytes(max_bytes);
+        }
+        if let Some(config) = layer_config.api_key_global_limit {
+            self = self.with_api_key_global_config(config);
+        }
+        if let Some(config) = layer_config.global_limit {
+            self = self.with_global_config(config);
+        }
+        if let Some(config) = layer_config.failure_limit {
+            self = self.with_failure_config(config);
+        }
+        if let Some(config) = layer_config.request_id {
+            self = self.with_request_id_config(config);
+        }
+        if let Some(config) = layer_config.concurrency {
+            self = self.with_concurrency_config(config);
+        }
+        if let Some(config) = layer_config.idempotency {
+            self = self.with_idempotency_config(config);
+        }
+        if let Some(header) = layer_config.reset_on_success_header {
+            self = self.with_reset_on_success_header(header);
+        }
+        if let Some(config) = layer_config.trusted_proxies {
+            self = self.with_trusted_proxies(config);
+        }
+        if let Some(prefix) = layer_config.ip_key_prefix {
+            self = self.with_ip_key_prefix(prefix);
+        }
+        if let Some(header) = layer_config.trusted_identity_header {
+            self = self.with_trusted_identity_header(header);
+        }
+        if let Some(scope) = layer_config.required_scope {
+            self = self.with_required_scope(scope);
+        }
+        if let Some(access_control) = layer_config.access_control {
+            self = self.with_access_control(access_control);
+        }
+        if let Some(enabled) = layer_config.use_matched_path {
+            self = self.with_use_matched_path(enabled);
+        }
+        if let Some(name) = layer_config.api_key_query_param {
+            self = self.with_api_key_query_param(name);
+        }
+        if let Some(capacity) = layer_config.background_reset {
+            self = self.with_background_reset(capacity);
+        }
+        if let Some(ratio) = layer_config.retry_after_jitter {
+            self = self.with_retry_after_jitter(ratio);
+        }
+        self
+    }
     pub fn with_state(mut self, state: State) -> Self {
         self.state = Some(state);
         self
     }
+    /// Requests with a valid key are limited per key. If the validator also accepts
+    /// requests without a key, those are limited by client IP instead of the payload key.
     pub fn with_api_key_validator(mut self, validator: V) -> Self {
         self.api_key_validator = Some(validator);
         self
@@ -71,25 +299,423 @@ where
         self.api_key_middleware_config = Some(config);
         self
     }
+    /// Read the API key from the query parameter `name` (e.g. `api_key`) when the header
+    /// is absent, for legacy clients. Query strings end up in access logs and browser
+    /// history, so each request keyed this way logs a warning.
+    pub fn with_api_key_query_param(mut self, name: impl Into<String>) -> Self {
+        self.api_key_query_param = Some(name.into());
+        self
+    }
+    /// Limit applied per API key across all endpoints, enforced together with the
+    /// per-endpoint `config`. A request is rejected if either limit is exceeded.
+    pub fn with_api_key_global_config(mut self, config: BarnacleConfig) -> Self {
+        self.api_key_global_config = Some(config);
+        self
+    }
+    /// Limit on all requests to an endpoint whatever their key, e.g. to protect a downstream
+    /// service, enforced together with the per-key `config`. Counted under
+    /// `BarnacleKey::Custom("global:{path}")`; a request it rejects is refunded to its key and
+    /// the 429 carries `X-RateLimit-Scope: global`.
+    pub fn with_global_config(mut self, config: BarnacleConfig) -> Self {
+        self.global_config = Some(config);
+        self
+    }
+    /// Separate, usually tighter, limit on failed (non-2xx) responses per key and endpoint,
+    /// enforced together with `config` which then bounds all attempts. Failures are attributed
+    /// after the response, so once the failure budget is spent further requests are rejected
+    /// before reaching the handler, while successful requests never count against it.
+    pub fn with_failure_config(mut self, config: BarnacleConfig) -> Self {
+        self.failure_config = Some(config);
+        self
+    }
+    /// Configure which header carries the request id used to correlate logs and error bodies.
+    /// Defaults to `x-request-id`, without echoing the id in error bodies.
+    pub fn with_request_id_config(mut self, config: RequestIdConfig) -> Self {
+        self.request_id_config = Some(config);
+        self
+    }
+    /// Limit how many requests per key may be in flight at once. The slot is
+    /// released when the response completes, the handler panics or the request is dropped.
+    pub fn with_concurrency_config(mut self, config: ConcurrencyConfig) -> Self {
+        self.concurrency_config = Some(config);
+        self
+    }
+    /// What to do when the store fails while counting a request. `FailOpen` logs the error
+    /// and passes the request on without rate limit headers; the default `FailClosed`
+    /// rejects it with a 503.
+    pub fn with_store_error_policy(mut self, policy: StoreErrorPolicy) -> Self {
+        self.store_error_policy = Some(policy);
+        self
+    }
+    /// Report allowed and blocked requests, store errors and store latency to `observer`,
+    /// e.g. to export metrics
+    pub fn with_observer(mut self, observer: Arc<dyn RateLimitObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+    /// Let handlers report the cost of a request after processing it, through a
+    /// `ResponseCost` response extension or the `x-barnacle-cost` response header.
+    /// The request is charged `cost` in total; the header is stripped from the response.
+    pub fn with_response_cost(mut self, enabled: bool) -> Self {
+        self.response_cost_enabled = Some(enabled);
+        self
+    }
+    /// Reject every request with 503 and `Retry-After` until the given time,
+    /// without consulting the store
+    pub fn with_maintenance_until(mut self, until: SystemTime) -> Self {
+        self.maintenance_until = Some(until);
+        self
+    }
+    /// Count a request only once per idempotency key: retries carrying an
+    /// idempotency key already seen for the same rate limit key are not counted.
+    /// Repeated requests are sent without rate limit headers.
+    pub fn with_idempotency_config(mut self, config: IdempotencyConfig) -> Self {
+        self.idempotency_config = Some(config);
+        self
+    }
+    /// Rate limit by a SHA-256 hash of the request body, so identical payloads share
+    /// a bucket whatever their source. Only the first `max_bytes` of the body are
+    /// hashed. Takes precedence over payload and fallback keys, but not over API keys.
+    pub fn with_body_hash_key(mut self, max_bytes: usize) -> Self {
+        self.body_hash_limit = Some(max_bytes);
+        self
+    }
+    /// Rate limit by the value at `path` in the JSON body (e.g. `$.user.id`), without a
+    /// typed payload. Bodies over `max_bytes` aren't parsed; requests whose body lacks the
+    /// value are keyed by IP. Takes precedence over body hash and payload keys.
+    pub fn with_json_key_path(mut self, path: JsonKeyPath, max_bytes: usize) -> Self {
+        self.json_key_path = Some((Arc::new(path), max_bytes));
+        self
+    }
+    /// Reject bodies over `max_bytes` with a 413 before they are fully buffered. Checked
+    /// before the request is counted, so oversized requests use no quota.
+    pub fn with_max_body_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_body_bytes = Some(max_bytes);
+        self
+    }
+    /// Reject requests whose body can't be read or doesn't deserialize into the payload type
+    /// `T` with a 400, instead of falling back to an IP key. The error tells apart a body that
+    /// isn't JSON from JSON lacking the key field. Only meaningful with a typed payload.
+    pub fn with_payload_key_required(mut self, required: bool) -> Self {
+        self.payload_key_required = Some(required);
+        self
+    }
+    /// Randomize the advertised `Retry-After`/`X-RateLimit-Reset` of rejected requests
+    /// within `±ratio` of the real value (e.g. `0.1` for ±10%), so clients don't retry
+    /// in lockstep. Never advertises less than one second. The stored window is unchanged.
+    pub fn with_retry_after_jitter(mut self, ratio: f64) -> Self {
+        self.retry_after_jitter = Some(ratio.clamp(0.0, 1.0));
+        self
+    }
+    /// Log only one in `every` allowed rate limit decisions; rejections are always logged.
+    /// Keeps debug logging usable under heavy legitimate traffic.
+    pub fn with_log_sampling(mut self, every: u32) -> Self {
+        self.log_sampler = Some(Arc::new(LogSampler::new(every)));
+        self
+    }
+    /// Clock used for maintenance windows and `X-RateLimit-Reset-At`. Defaults to `SystemClock`.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+    /// Let a response header decide whether a request counts as a success for
+    /// `reset_on_success`, overriding the status code when the header is present
+    pub fn with_reset_on_success_header(mut self, header: ResetOnSuccessHeader) -> Self {
+        self.reset_on_success_header = Some(header);
+        self
+    }
+    /// Also require `predicate` to accept the response before resetting on success, e.g. for
+    /// APIs reporting failures as `{"ok": false}` in a 200. Responses that pass the status
+    /// (or header) check are buffered to run it, so they no longer stream; only enable it
+    /// on routes with small bodies.
+    pub fn with_success_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&axum::http::response::Parts, &axum::body::Bytes) -> bool + Send + Sync + 'static,
+    {
+        self.success_predicate = Some(Arc::new(predicate));
+        self
+    }
+    /// Render the middleware's own error responses (429, 401, 403, ...) with `builder`
+    /// instead of `E`, e.g. to match an API's error envelope. `Retry-After` and the
+    /// `X-RateLimit-*` headers are still added on top of what it returns.
+    pub fn with_response_builder<F>(mut self, builder: F) -> Self
+    where
+        F: Fn(&BarnacleError) -> Response<Body> + Send + Sync + 'static,
+    {
+        self.response_builder = Some(Arc::new(builder));
+        self
+    }
+    /// On reset-on-success, only reset the request's own counter if no other request was
+    /// counted since (via `BarnacleStore::reset_if_below`), so a concurrent request's count
+    /// isn't wiped. Extra reset contexts and the background reset queue still reset
+    /// unconditionally, as do stores without conditional reset support.
+    pub fn with_reset_if_unchanged(mut self, enabled: bool) -> Self {
+        self.reset_if_unchanged = Some(enabled);
+        self
+    }
+    /// Give back the unit counted for a request whose inner service panics. The panic
+    /// still propagates; response-based work (cost, headers, reset) is always skipped.
+    pub fn with_refund_on_panic(mut self, enabled: bool) -> Self {
+        self.refund_on_panic = Some(enabled);
+        self
+    }
+    /// Give back the unit counted for a request whose response has one of these statuses
+    /// (e.g. `304 Not Modified`), so it consumes no quota. Unlike reset on success, the
+    /// rest of the counter is kept. Such responses also skip response cost and failure counting.
+    pub fn with_no_count_statuses(mut self, statuses: Vec<u16>) -> Self {
+        self.no_count_statuses = (!statuses.is_empty()).then(|| Arc::from(statuses));
+        self
+    }
+    /// Run an async check (e.g. "is this key suspended?") before the request is counted.
+    /// An `Err` is returned as the response without consuming quota.
+    pub fn with_pre_check<F, Fut>(mut self, pre_check: F) -> Self
+    where
+        F: Fn(&BarnacleContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), BarnacleError>> + Send + 'static,
+    {
+        let pre_check: PreCheck = Arc::new(move |context: &BarnacleContext| {
+            Box::pin(pre_check(context)) as Pin<Box<dyn Future<Output = Result<(), BarnacleError>> + Send>>
+        });
+        self.pre_check = Some(pre_check);
+        self
+    }
+    /// Omit `Retry-After` and the `X-RateLimit-Reset*` headers from rate limit rejections
+    /// for requests matching `predicate` (e.g. known bots), so they don't learn the optimal
+    /// retry timing. Other clients keep the headers. The error body is left untouched.
+    pub fn with_hidden_retry_after<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Parts) -> bool + Send + Sync + 'static,
+    {
+        self.hide_retry_after = Some(Arc::new(predicate));
+        self
+    }
+    /// Soft start: once the per-endpoint limit is hit, let up to `grace_requests` more
+    /// requests through with an `X-RateLimit-Warning: over-limit` header before rejecting.
+    pub fn with_grace_requests(mut self, grace_requests: u32) -> Self {
+        self.grace_requests = grace_requests;
+        self
+    }
+    /// Send an `X-RateLimit-Scope` header (`ip`, `api-key`, `email` or `custom`) on counted
+    /// and rejected responses, telling clients what their limit is keyed on.
+    pub fn with_scope_header(mut self, enabled: bool) -> Self {
+        self.scope_header = Some(enabled);
+        self
+    }
+    /// Key requests by something in the request head (e.g. a tenant id header) without
+    /// needing a body. Runs after API key extraction; when it returns `None` the request
+    /// falls back to the usual body-based or IP key.
+    pub fn with_key_extractor<F>(mut self, extractor: F) -> Self
+    where
+        F: Fn(&Parts) -> Option<BarnacleKey> + Send + Sync + 'static,
+    {
+        self.key_extractor = Some(Arc::new(extractor));
+        self
+    }
+    /// Charge each request `cost(parts)` units of quota instead of one, e.g. more for an
+    /// expensive export route. A `RequestCost` request extension takes precedence. The
+    /// store must support `increment_by`; a cost of 0 counts as 1.
+    pub fn with_request_cost<F>(mut self, cost: F) -> Self
+    where
+        F: Fn(&Parts) -> u32 + Send + Sync + 'static,
+    {
+        self.request_cost = Some(Arc::new(cost));
+        self
+    }
+    /// Pick the client IP from `X-Forwarded-For` according to `config` when falling back to
+    /// IP keys, instead of trusting the connection's peer first and the header's first entry.
+    pub fn with_trusted_proxies(mut self, config: TrustedProxyConfig) -> Self {
+        self.trusted_proxies = Some(Arc::new(config));
+        self
+    }
+    /// Mask client IPs to a network before using them as keys, e.g. `IpKeyPrefix::new(24, 64)`
+    /// limits each IPv4 /24 and IPv6 /64 as a whole. Header values that aren't IP addresses
+    /// are used as they are.
+    pub fn with_ip_key_prefix(mut self, prefix: IpKeyPrefix) -> Self {
+        self.ip_key_prefix = Some(prefix);
+        self
+    }
+    /// Count requests under the route they matched (`/users/{id}`) instead of their raw
+    /// path (`/users/1`), so every request to a parameterized route shares one bucket.
+    /// Defaults to `true`; requests outside a router with matched paths use the raw path.
+    pub fn with_use_matched_path(mut self, enabled: bool) -> Self {
+        self.use_matched_path = Some(enabled);
+        self
+    }
+    /// Key requests by an identity an upstream auth gateway has already verified, e.g.
+    /// `X-Authenticated-User`, as `BarnacleKey::Custom`. Requests carrying the header skip API
+    /// key validation. The header is trusted as is, so the edge proxy must strip it from
+    /// incoming requests; otherwise clients can pick their own key and bypass validation.
+    pub fn with_trusted_identity_header(mut self, header: impl Into<String>) -> Self {
+        self.trusted_identity_header = Some(header.into());
+        self
+    }
+    /// Reject validated API keys without `scope` in `ApiKeyValidationResult::scopes` with a 403
+    /// `PermissionDenied`, before they are counted. Needs `with_api_key_validator`; requests
+    /// keyed by a trusted identity header aren't checked.
+    pub fn with_required_scope(mut self, scope: impl Into<String>) -> Self {
+        self.required_scope = Some(scope.into());
+        self
+    }
+    /// Let allowlisted keys through without counting them and reject denylisted keys with a
+    /// 403 `PermissionDenied`, both before the store is called
+    pub fn with_access_control(mut self, access_control: AccessControl) -> Self {
+        self.access_control = Some(Arc::new(access_control));
+        self
+    }
+    /// Run reset-on-success resets on a background task instead of before the response is
+    /// returned, so a slow store doesn't delay successful requests. Up to `capacity` resets
+    /// are queued; when the queue is full, responses wait for room. Failed resets are retried.
+    pub fn with_background_reset(mut self, capacity: usize) -> Self {
+        self.reset_queue_capacity = Some(capacity);
+        self
+    }
+    /// Choose between the `X-RateLimit-*` headers, the IETF draft `RateLimit*` headers or
+    /// both, on counted and rejected responses. Defaults to `HeaderStyle::Legacy`.
+    pub fn with_header_style(mut self, header_style: HeaderStyle) -> Self {
+        self.header_style = Some(header_style);
+        self
+    }
+    /// Render the middleware's own error responses as JSON, RFC 7807 problem details or
+    /// plain text. Defaults to `ErrorFormat::Json`, which renders through `E`; the other
+    /// formats render the `BarnacleError` directly.
+    pub fn with_error_format(mut self, error_format: ErrorFormat) -> Self {
+        self.error_format = Some(error_format);
+        self
+    }
+    /// Also send `Retry-After` with the seconds until the window resets on allowed responses,
+    /// so clients can slow down before being rejected. Needs a store reporting the reset on
+    /// allowed requests (e.g. `RedisBarnacleStore`); skipped for `with_hidden_retry_after` matches.
+    pub fn with_retry_after_on_success(mut self, enabled: bool) -> Self {
+        self.retry_after_on_success = Some(enabled);
+        self
+    }
     pub fn build(self) -> Result<BarnacleLayer<T, S, State, E, V>, BarnacleLayerBuilderError> {
+        let store = self.store.ok_or(BarnacleLayerBuilderError::MissingStore)?;
+        let reset_queue = self
+            .reset_queue_capacity
+            .map(|capacity| Arc::new(ResetQueue::new(store.clone(), capacity)));
+        let config_resolver: Arc<dyn ConfigResolver> = match (self.config_resolver, self.config) {
+            (Some(resolver), _) => resolver,
+            (None, Some(config)) => Arc::new(StaticConfigResolver::new(config)),
+            (None, None) => return Err(BarnacleLayerBuilderError::MissingConfig),
+        };
+        let config_resolver: Arc<dyn ConfigResolver> = match self.per_key_config {
+            Some(lookup) => Arc::new(PerKeyConfigResolver::new(lookup, config_resolver)),
+            None => config_resolver,
+        };
         Ok(BarnacleLayer {
-            store: self.store.ok_or(BarnacleLayerBuilderError::MissingStore)?,
-            config: self.config.ok_or(BarnacleLayerBuilderError::MissingConfig)?,
+            store,
+            config_resolver,
             state: self.state,
             api_key_validator: self.api_key_validator,
             api_key_middleware_config: self.api_key_middleware_config,
+            api_key_global_config: self.api_key_global_config,
+            request_id_config: self.request_id_config.unwrap_or_default(),
+            concurrency_config: self.concurrency_config,
+            response_cost_enabled: self.response_cost_enabled.unwrap_or(false),
+            maintenance_until: self.maintenance_until,
+            idempotency_config: self.idempotency_config,
+            body_hash_limit: self.body_hash_limit,
+            retry_after_jitter: self.retry_after_jitter,
+            log_sampler: self.log_sampler.clone(),
+            clock: self.clock.unwrap_or_else(|| Arc::new(SystemClock)),
+            reset_on_success_header: self.reset_on_success_header,
+            refund_on_panic: self.refund_on_panic.unwrap_or(false),
+            conflict_warned: Arc::new(AtomicBool::new(false)),
+            pre_check: self.pre_check,
+            hide_retry_after: self.hide_retry_after,
+            grace_requests: self.grace_requests,
+            failure_config: self.failure_config,
+            scope_header: self.scope_header.unwrap_or(false),
+            header_style: self.header_style.unwrap_or_default(),
+            retry_after_on_success: self.retry_after_on_success.unwrap_or(false),
+            key_extractor: self.key_extractor,
+            reset_queue,
+            trusted_proxies: self.trusted_proxies,
+            ip_key_prefix: self.ip_key_prefix,
+            trusted_identity_header: self.trusted_identity_header,
+            store_error_policy: self.store_error_policy.unwrap_or_default(),
+            observer: self.observer,
+            json_key_path: self.json_key_path,
+            payload_key_required: self.payload_key_required.unwrap_or(false),
+            no_count_statuses: self.no_count_statuses,
+            request_cost: self.request_cost,
+            max_body_bytes: self.max_body_bytes,
+            required_scope: self.required_scope,
+            reset_if_unchanged: self.reset_if_unchanged.unwrap_or(false),
+            success_predicate: self.success_predicate,
+            response_builder: self.response_builder,
+            error_format: self.error_format.unwrap_or_default(),
+            access_control: self.access_control,
+            global_config: self.global_config,
+            use_matched_path: self.use_matched_path.unwrap_or(true),
+            api_key_query_param: self.api_key_query_param,
             _phantom: PhantomData,
         })
     }
 }
 
-/// Generic rate limiting and API key layer
+/// `BarnacleLayer` validating API keys against an `ApiKeyStore`, then limiting valid keys
+/// per key (and other requests by payload key or IP) in the same pass, with one set of
+/// rate limit headers. Build it with `BarnacleLayerBuilder::with_api_key_store`.
+pub type ApiKeyRateLimitLayer<T, S, K, E = BarnacleError> = BarnacleLayer<T, S, (), E, ApiKeyStoreValidator<K>>;
+
+/// Generic rate limiting and API key layer.
+///
+/// The middleware accepts requests with any body type and passes `axum::body::Body` on, so
+/// layers stack with each other and with other tower layers. The layer added last runs
+/// first: add an `ApiKeyRateLimitLayer` after payload or IP layers so invalid keys are
+/// rejected before those count the request.
+///
+/// Stacked layers count the same keys; if their windows or reset rules disagree,
+/// a warning is logged the first time a request passes through both.
 pub struct BarnacleLayer<T = (), S = RedisBarnacleStore, State = (), E = BarnacleError, V = ()> {
     store: S,
-    config: BarnacleConfig,
+    config_resolver: Arc<dyn ConfigResolver>,
     state: Option<State>,
     api_key_validator: Option<V>,
     api_key_middleware_config: Option<ApiKeyConfig>,
+    api_key_global_config: Option<BarnacleConfig>,
+    request_id_config: RequestIdConfig,
+    concurrency_config: Option<ConcurrencyConfig>,
+    response_cost_enabled: bool,
+    maintenance_until: Option<SystemTime>,
+    idempotency_config: Option<IdempotencyConfig>,
+    body_hash_limit: Option<usize>,
+    retry_after_jitter: Option<f64>,
+    log_sampler: Option<Arc<LogSampler>>,
+    clock: Arc<dyn Clock>,
+    reset_on_success_header: Option<ResetOnSuccessHeader>,
+    refund_on_panic: bool,
+    conflict_warned: Arc<AtomicBool>,
+    pre_check: Option<PreCheck>,
+    hide_retry_after: Option<HideRetryAfter>,
+    grace_requests: u32,
+    failure_config: Option<BarnacleConfig>,
+    scope_header: bool,
+    header_style: HeaderStyle,
+    retry_after_on_success: bool,
+    key_extractor: Option<KeyExtractor>,
+    reset_queue: Option<Arc<ResetQueue<S>>>,
+    trusted_proxies: Option<Arc<TrustedProxyConfig>>,
+    ip_key_prefix: Option<IpKeyPrefix>,
+    trusted_identity_header: Option<String>,
+    store_error_policy: StoreErrorPolicy,
+    observer: Option<Arc<dyn RateLimitObserver>>,
+    json_key_path: Option<(Arc<JsonKeyPath>, usize)>,
+    payload_key_required: bool,
+    no_count_statuses: Option<Arc<[u16]>>,
+    request_cost: Option<RequestCostFn>,
+    max_body_bytes: Option<usize>,
+    required_scope: Option<String>,
+    reset_if_unchanged: bool,
+    success_predicate: Option<SuccessPredicate>,
+    response_builder: Option<ResponseBuilderFn>,
+    error_format: ErrorFormat,
+    access_control: Option<Arc<AccessControl>>,
+    global_config: Option<BarnacleConfig>,
+    use_matched_path: bool,
+    api_key_query_param: Option<String>,
     _phantom: PhantomData<(T, E)>,
 }
 
@@ -102,10 +728,51 @@ where
     fn clone(&self) -> Self {
         Self {
             store: self.store.clone(),
-            config: self.config.clone(),
+            config_resolver: self.config_resolver.clone(),
             state: self.state.clone(),
             api_key_validator: self.api_key_validator.clone(),
             api_key_middleware_config: self.api_key_middleware_config.clone(),
+            api_key_global_config: self.api_key_global_config.clone(),
+            request_id_config: self.request_id_config.clone(),
+            concurrency_config: self.concurrency_config.clone(),
+            response_cost_enabled: self.response_cost_enabled,
+            maintenance_until: self.maintenance_until,
+            idempotency_config: self.idempotency_config.clone(),
+            body_hash_limit: self.body_hash_limit,
+            retry_after_jitter: self.retry_after_jitter,
+            log_sampler: self.log_sampler.clone(),
+            clock: self.clock.clone(),
+            reset_on_success_header: self.reset_on_success_header.clone(),
+            refund_on_panic: self.refund_on_panic,
+            conflict_warned: self.conflict_warned.clone(),
+            pre_check: self.pre_check.clone(),
+            hide_retry_after: self.hide_retry_after.clone(),
+            grace_requests: self.grace_requests,
+            failure_config: self.failure_config.clone(),
+            scope_header: self.scope_header,
+            header_style: self.header_style,
+            retry_after_on_success: self.retry_after_on_success,
+            key_extractor: self.key_extractor.clone(),
+            reset_queue: self.reset_queue.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
+            ip_key_prefix: self.ip_key_prefix,
+            trusted_identity_header: self.trusted_identity_header.clone(),
+            store_error_policy: self.store_error_policy,
+            observer: self.observer.clone(),
+            json_key_path: self.json_key_path.clone(),
+            payload_key_required: self.payload_key_required,
+            no_count_statuses: self.no_count_statuses.clone(),
+            request_cost: self.request_cost.clone(),
+            max_body_bytes: self.max_body_bytes,
+            required_scope: self.required_scope.clone(),
+            reset_if_unchanged: self.reset_if_unchanged,
+            success_predicate: self.success_predicate.clone(),
+            response_builder: self.response_builder.clone(),
+            error_format: self.error_format,
+            access_control: self.access_control.clone(),
+            global_config: self.global_config.clone(),
+            use_matched_path: self.use_matched_path,
+            api_key_query_param: self.api_key_query_param.clone(),
             _phantom: PhantomData,
         }
     }
@@ -121,9 +788,51 @@ where
         BarnacleLayerBuilder {
             store: None,
             config: None,
+            config_resolver: None,
+            per_key_config: None,
             state: None,
             api_key_validator: None,
             api_key_middleware_config: None,
+            api_key_global_config: None,
+            request_id_config: None,
+            concurrency_config: None,
+            response_cost_enabled: None,
+            maintenance_until: None,
+            idempotency_config: None,
+            body_hash_limit: None,
+            retry_after_jitter: None,
+            log_sampler: None,
+            clock: None,
+            reset_on_success_header: None,
+            refund_on_panic: None,
+            pre_check: None,
+            hide_retry_after: None,
+            grace_requests: 0,
+            failure_config: None,
+            scope_header: None,
+            header_style: None,
+            retry_after_on_success: None,
+            key_extractor: None,
+            reset_queue_capacity: None,
+            trusted_proxies: None,
+            ip_key_prefix: None,
+            trusted_identity_header: None,
+            store_error_policy: None,
+            observer: None,
+            json_key_path: None,
+            payload_key_required: None,
+            no_count_statuses: None,
+            request_cost: None,
+            max_body_bytes: None,
+            required_scope: None,
+            reset_if_unchanged: None,
+            success_predicate: None,
+            response_builder: None,
+            error_format: None,
+            access_control: None,
+            global_config: None,
+            use_matched_path: None,
+            api_key_query_param: None,
             _phantom: PhantomData,
         }
     }
@@ -143,22 +852,68 @@ where
         BarnacleMiddleware {
             inner,
             store: self.store.clone(),
-            config: self.config.clone(),
+            config_resolver: self.config_resolver.clone(),
             state: self.state.clone(),
             api_key_validator: self.api_key_validator.clone(),
             api_key_config: self.api_key_middleware_config.clone(),
+            api_key_global_config: self.api_key_global_config.clone(),
+            request_id_config: self.request_id_config.clone(),
+            concurrency_config: self.concurrency_config.clone(),
+            response_cost_enabled: self.response_cost_enabled,
+            maintenance_until: self.maintenance_until,
+            idempotency_config: self.idempotency_config.clone(),
+            body_hash_limit: self.body_hash_limit,
+            retry_after_jitter: self.retry_after_jitter,
+            log_sampler: self.log_sampler.clone(),
+            clock: self.clock.clone(),
+            reset_on_success_header: self.reset_on_success_header.clone(),
+            refund_on_panic: self.refund_on_panic,
+            conflict_warned: self.conflict_warned.clone(),
+            pre_check: self.pre_check.clone(),
+            hide_retry_after: self.hide_retry_after.clone(),
+            grace_requests: self.grace_requests,
+            failure_config: self.failure_config.clone(),
+            scope_header: self.scope_header,
+            header_style: self.header_style,
+            retry_after_on_success: self.retry_after_on_success,
+            key_extractor: self.key_extractor.clone(),
+            reset_queue: self.reset_queue.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
+            ip_key_prefix: self.ip_key_prefix,
+            trusted_identity_header: self.trusted_identity_header.clone(),
+            store_error_policy: self.store_error_policy,
+            observer: self.observer.clone(),
+            json_key_path: self.json_key_path.clone(),
+            payload_key_required: self.payload_key_required,
+            no_count_statuses: self.no_count_statuses.clone(),
+            request_cost: self.request_cost.clone(),
+            max_body_bytes: self.max_body_bytes,
+            required_scope: self.required_scope.clone(),
+            reset_if_unchanged: self.reset_if_unchanged,
+            success_predicate: self.success_predicate.clone(),
+            response_builder: self.response_builder.clone(),
+            error_format: self.error_format,
+            access_control: self.access_control.clone(),
+            global_config: self.global_config.clone(),
+            use_matched_path: self.use_matched_path,
+            api_key_query_param: self.api_key_query_param.clone(),
             _phantom: PhantomData,
         }
     }
 }
 
 /// Helper function to handle rate limit reset logic
+#[allow(clippy::too_many_arguments)]
 async fn handle_rate_limit_reset<S>(
     store: &S,
     config: &BarnacleConfig,
     context: &BarnacleContext,
     status_code: u16,
+    headers: &axum::http::HeaderMap,
+    success_header: Option<&ResetOnSuccessHeader>,
     is_fallback: bool,
+    reset_queue: Option<&ResetQueue<S>>,
+    unchanged_count: Option<u32>,
 ) where
     S: BarnacleStore + 'static,
 {
@@ -167,7 +922,7 @@ async fn handle_rate_limit_reset<S>(
     }
 
     let key_type = if is_fallback { "fallback key" } else { "key" };
-    if !config.is_success_status(status_code) {
+    if !config.is_success_response(status_code, headers, success_header) {
         debug!(
             "Not resetting rate limit for {} {:?} due to error status: {}",
             key_type,
@@ -187,35 +942,457 @@ async fn handle_rate_limit_reset<S>(
         if ctx.key == BarnacleKey::Custom(NO_KEY.to_string()) {
             ctx.key = context.key.clone();
         }
-        match store.reset(ctx).await {
-            Ok(_) => debug!(
-                "Rate limit reset for {} {:?} after successful request (status: {}) path: {}",
-                key_type,
-                ctx.key,
-                status_code,
-                ctx.path
-            ),
-            Err(e) => debug!(
-                "Failed to reset rate limit for {} {:?}: {} path: {}",
-                key_type,
-                ctx.key,
-                e,
-                ctx.path
-            ),
+    }
+
+    // Compare-and-delete the request's own counter; it drops out of the plain reset below
+    if let Some(count) = unchanged_count {
+        match store.reset_if_below(context, count).await {
+            Ok(reset) => {
+                debug!("Conditional reset for {} {:?} at count {}: {}", key_type, context.key, count, reset);
+                contexts.remove(0);
+            }
+            Err(e) => debug!("Conditional reset failed for {} {:?}, resetting unconditionally: {}", key_type, context.key, e),
+        }
+        if contexts.is_empty() {
+            return;
+        }
+    }
+
+    if let Some(reset_queue) = reset_queue {
+        for ctx in contexts {
+            debug!("Queueing rate limit reset for {} {:?} path: {}", key_type, ctx.key, ctx.path);
+            reset_queue.enqueue(ctx).await;
+        }
+        return;
+    }
+    match store.reset_many(&contexts).await {
+        Ok(_) => debug!(
+            "Rate limit reset for {} {:?} after successful request (status: {}) paths: {:?}",
+            key_type,
+            context.key,
+            status_code,
+            contexts.iter().map(|ctx| ctx.path.as_str()).collect::<Vec<_>>()
+        ),
+        Err(e) => debug!(
+            "Failed to reset rate limit for {} {:?}: {}",
+            key_type,
+            context.key,
+            e
+        ),
+    }
+}
+
+/// Helper function to spread the advertised retry time of a rate limit error
+fn jitter_retry_after(error: BarnacleError, ratio: Option<f64>) -> BarnacleError {
+    match (error, ratio) {
+        (
+            BarnacleError::RateLimitExceeded {
+                remaining,
+                retry_after,
+                limit,
+            },
+            Some(ratio),
+        ) if ratio > 0.0 => {
+            // Rounded so float error can't widen the band, e.g. 100s * 1.1 ceiling to 111s
+            let spread = ((retry_after as f64) * ratio).round() as u64;
+            let low = retry_after.saturating_sub(spread).max(1);
+            let high = retry_after.saturating_add(spread).max(low);
+            let jittered = rand::random_range(low..=high);
+            debug!("Jittered retry_after from {}s to {}s", retry_after, jittered);
+            BarnacleError::rate_limit_exceeded(remaining, jittered, limit)
+        }
+        (error, _) => error,
+    }
+}
+
+/// Helper function to turn a store error into a response, jittering and
+/// timestamping the reset of rate limit errors, or dropping the reset headers if `hide_reset`
+#[allow(clippy::too_many_arguments)]
+fn rate_limit_error_response<E>(
+    error: BarnacleError,
+    jitter: Option<f64>,
+    now: SystemTime,
+    hide_reset: bool,
+    scope: Option<&'static str>,
+    header_style: HeaderStyle,
+    error_format: ErrorFormat,
+    response_builder: Option<&ResponseBuilderFn>,
+) -> Response<Body>
+where
+    E: IntoResponse + From<BarnacleError>,
+{
+    let error = jitter_retry_after(error, jitter);
+    let reset_after = match &error {
+        BarnacleError::RateLimitExceeded { retry_after, .. } => Some(*retry_after),
+        _ => None,
+    };
+    let mut response = render_error::<E>(error, error_format, response_builder);
+    if hide_reset {
+        let headers = response.headers_mut();
+        headers.remove(axum::http::header::RETRY_AFTER);
+        headers.remove("X-RateLimit-Reset");
+        headers.remove("X-RateLimit-Reset-At");
+    } else if let Some(reset_after) = reset_after {
+        insert_reset_at_header(response.headers_mut(), now, reset_after);
+    }
+    if let Some(scope) = scope {
+        response.headers_mut().insert("X-RateLimit-Scope", axum::http::HeaderValue::from_static(scope));
+    }
+    apply_header_style(response.headers_mut(), header_style);
+    response
+}
+
+/// Helper function to render an error with the layer's response builder, falling back to
+/// `E` for JSON and to the error itself for the other formats
+fn render_error<E>(
+    error: BarnacleError,
+    error_format: ErrorFormat,
+    response_builder: Option<&ResponseBuilderFn>,
+) -> Response<Body>
+where
+    E: IntoResponse + From<BarnacleError>,
+{
+    match response_builder {
+        Some(builder) => {
+            let mut response = builder(&error);
+            error.insert_headers(response.headers_mut());
+            response
+        }
+        None if error_format == ErrorFormat::Json => E::from(error).into_response(),
+        None => error.into_response_with_format(error_format),
+    }
+}
+
+/// Helper function to render a validator error. A `BarnacleError` goes through the layer's
+/// error format and response builder like the middleware's own errors; other types render themselves.
+fn render_validator_error<E>(
+    error: E,
+    error_format: ErrorFormat,
+    response_builder: Option<&ResponseBuilderFn>,
+) -> Response<Body>
+where
+    E: IntoResponse + From<BarnacleError> + 'static,
+{
+    let mut error = Some(error);
+    if let Some(barnacle_error) = (&mut error as &mut dyn std::any::Any).downcast_mut::<Option<BarnacleError>>() {
+        if let Some(barnacle_error) = barnacle_error.take() {
+            return render_error::<E>(barnacle_error, error_format, response_builder);
+        }
+    }
+    error.map(IntoResponse::into_response).unwrap_or_default()
+}
+
+/// Helper function to report a rate limit rejection against `limit` rather than the store's limit
+fn with_reported_limit(error: BarnacleError, limit: u32) -> BarnacleError {
+    match error {
+        BarnacleError::RateLimitExceeded { remaining, retry_after, .. } => {
+            BarnacleError::rate_limit_exceeded(remaining, retry_after, limit)
+        }
+        error => error,
+    }
+}
+
+/// Helper function to add `X-RateLimit-Reset-At`: the Unix time (seconds) the window resets
+pub(crate) fn insert_reset_at_header(headers: &mut axum::http::HeaderMap, now: SystemTime, reset_after: u64) {
+    let reset_at = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs().saturating_add(reset_after);
+    if let Ok(reset_at_header) = reset_at.to_string().parse() {
+        headers.insert("X-RateLimit-Reset-At", reset_at_header);
+        debug!("[middleware.rs] Added X-RateLimit-Reset-At: {}", reset_at);
+    }
+}
+
+/// Helper function to add the `X-RateLimit-*` headers for a counted request
+pub(crate) fn insert_rate_limit_headers(
+    headers: &mut axum::http::HeaderMap,
+    result: &BarnacleResult,
+    limit: u32,
+    now: SystemTime,
+) {
+    if let Ok(remaining_header) = result.remaining.to_string().parse() {
+        headers.insert("X-RateLimit-Remaining", remaining_header);
+        debug!("[middleware.rs] Added X-RateLimit-Remaining: {}", result.remaining);
+    }
+    if let Ok(limit_header) = limit.to_string().parse() {
+        headers.insert("X-RateLimit-Limit", limit_header);
+        debug!("[middleware.rs] Added X-RateLimit-Limit: {}", limit);
+    }
+    if let Some(reset_after) = result.reset_after.or(result.retry_after) {
+        if let Ok(reset_header) = reset_after.as_secs().to_string().parse() {
+            headers.insert("X-RateLimit-Reset", reset_header);
+            debug!("[middleware.rs] Added X-RateLimit-Reset: {}", reset_after.as_secs());
+        }
+        insert_reset_at_header(headers, now, reset_after.as_secs());
+    }
+}
+
+/// Builds the response the middleware would send for a rate limit decision made by hand,
+/// e.g. a handler calling `BarnacleStore::increment` itself. An allowed `outcome` adds the
+/// `X-RateLimit-*` headers for `config` to the caller's `response`; an error (such as a
+/// rejection) replaces it with the standard headered error response.
+pub fn rate_limit_response(
+    outcome: Result<BarnacleResult, BarnacleError>,
+    config: &BarnacleConfig,
+    mut response: Response<Body>,
+) -> Response<Body> {
+    let now = SystemTime::now();
+    match outcome {
+        Ok(result) => {
+            insert_rate_limit_headers(response.headers_mut(), &result, config.max_requests, now);
+            response
+        }
+        Err(e) => rate_limit_error_response::<BarnacleError>(e, None, now, false, None, HeaderStyle::Legacy, ErrorFormat::default(), None),
+    }
+}
+
+/// Helper function to rewrite the `X-RateLimit-*` headers into the chosen style. The IETF draft
+/// headers mirror the legacy ones (its `RateLimit-Reset` is also seconds until reset), plus
+/// the combined `RateLimit: limit=.., remaining=.., reset=..` header.
+fn apply_header_style(headers: &mut axum::http::HeaderMap, style: HeaderStyle) {
+    if style == HeaderStyle::Legacy {
+        return;
+    }
+    let mut fields = Vec::new();
+    for (legacy, ietf, field) in [
+        ("X-RateLimit-Limit", "RateLimit-Limit", "limit"),
+        ("X-RateLimit-Remaining", "RateLimit-Remaining", "remaining"),
+        ("X-RateLimit-Reset", "RateLimit-Reset", "reset"),
+    ] {
+        let value = if style == HeaderStyle::IetfDraft {
+            headers.remove(legacy)
+        } else {
+            headers.get(legacy).cloned()
+        };
+        if let Some(value) = value {
+            if let Ok(text) = value.to_str() {
+                fields.push(format!("{}={}", field, text));
+            }
+            headers.insert(ietf, value);
+        }
+    }
+    if style == HeaderStyle::IetfDraft {
+        headers.remove("X-RateLimit-Reset-At");
+    }
+    if fields.is_empty() {
+        return;
+    }
+    if let Ok(combined) = fields.join(", ").parse() {
+        headers.insert("RateLimit", combined);
+    }
+}
+
+/// Helper function to build a key from the hash of the (size-capped) request body
+fn body_hash_key(body: &[u8], max_bytes: usize) -> BarnacleKey {
+    let digest = Sha256::digest(&body[..body.len().min(max_bytes)]);
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    BarnacleKey::Custom(format!("body:{}", hex))
+}
+
+/// Helper function to explain why a body didn't yield a payload key: not JSON at all, or
+/// JSON without the expected fields
+fn payload_parse_error(body: &[u8], error: serde_json::Error) -> BarnacleError {
+    if serde_json::from_slice::<serde_json::Value>(body).is_err() {
+        BarnacleError::request_parsing_error("Request body is not valid JSON")
+    } else {
+        BarnacleError::request_parsing_error(format!("Request body is missing the rate limit key: {}", error))
+    }
+}
+
+/// Helper function to key a request by the value at `path` in its JSON body
+fn json_path_key(body: &[u8], path: &JsonKeyPath, max_bytes: usize) -> Option<BarnacleKey> {
+    if body.len() > max_bytes {
+        debug!("Body of {} bytes is over the {} byte limit for JSON path keys", body.len(), max_bytes);
+        return None;
+    }
+    let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+    path.extract(&value).map(BarnacleKey::Custom)
+}
+
+/// Helper function returning the seconds left in a maintenance window, rounded up,
+/// or `None` once it is over
+fn maintenance_retry_after(until: SystemTime, now: SystemTime) -> Option<u64> {
+    let remaining = until.duration_since(now).ok()?;
+    if remaining.is_zero() {
+        return None;
+    }
+    Some(remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0))
+}
+
+/// Helper function to give back what was counted for a request: its cost per endpoint
+/// and against each global limit it was counted by
+async fn refund_request<S>(store: &S, context: &BarnacleContext, cost: u32, global_contexts: &[&BarnacleContext])
+where
+    S: BarnacleStore + 'static,
+{
+    for context in std::iter::once(context).chain(global_contexts.iter().copied()) {
+        if let Err(e) = store.decrement(context, cost).await {
+            debug!("[middleware.rs] Failed to refund request for key: {:?}: {}", context.key, e);
+        }
+    }
+}
+
+/// Helper function to forget a claimed idempotency key after its request was rejected,
+/// so a retry is counted rather than let through for free
+async fn release_idempotency_key<S>(store: &S, context: &BarnacleContext, idempotency_key: Option<&str>)
+where
+    S: BarnacleStore + 'static,
+{
+    if let Some(idempotency_key) = idempotency_key {
+        if let Err(e) = store.release_idempotency_key(context, idempotency_key).await {
+            debug!("[middleware.rs] Failed to release idempotency key for key: {:?}: {}", context.key, e);
+        }
+    }
+}
+
+/// Helper function to read and strip the cost reported by the handler.
+/// The extension takes precedence over the header; a missing or invalid cost counts as 1.
+fn take_response_cost(response: &mut Response<Body>) -> u32 {
+    let header_cost = response
+        .headers_mut()
+        .remove(BARNACLE_COST_HEADER)
+        .and_then(|value| value.to_str().ok()?.trim().parse::<u32>().ok());
+    response
+        .extensions_mut()
+        .remove::<ResponseCost>()
+        .map(|cost| cost.0)
+        .or(header_cost)
+        .unwrap_or(1)
+}
+
+/// Helper function to report a counting call and its outcome to the observer
+fn observe_increment(
+    observer: Option<&dyn RateLimitObserver>,
+    context: &BarnacleContext,
+    latency: std::time::Duration,
+    outcome: &Result<BarnacleResult, BarnacleError>,
+) {
+    let Some(observer) = observer else {
+        return;
+    };
+    observer.on_store_latency(context, latency);
+    match outcome {
+        Ok(_) => {}
+        Err(e @ BarnacleError::RateLimitExceeded { .. }) => observer.on_blocked(context, e),
+        Err(e) => observer.on_store_error(context, e),
+    }
+}
+
+/// Helper function to charge the cost above the one unit counted before the handler ran,
+/// in one `increment_by`. A cost that doesn't fit charges what is left of the window instead.
+/// Returns the latest counter state, or `None` if nothing could be charged.
+async fn charge_extra_cost<S>(
+    store: &S,
+    context: &BarnacleContext,
+    config: &BarnacleConfig,
+    extra: u32,
+) -> Option<BarnacleResult>
+where
+    S: BarnacleStore + 'static,
+{
+    let outcome = match check_rate_limit_by(store, context, config, extra).await {
+        Err(BarnacleError::RateLimitExceeded { remaining, .. }) if remaining > 0 => {
+            check_rate_limit_by(store, context, config, remaining).await
+        }
+        outcome => outcome,
+    };
+    match outcome {
+        Ok(result) => Some(result),
+        Err(BarnacleError::RateLimitExceeded { retry_after, .. }) => {
+            // Quota is used up; the remaining cost cannot be charged
+            Some(BarnacleResult {
+                allowed: false,
+                remaining: 0,
+                retry_after: Some(std::time::Duration::from_secs(retry_after)),
+                reset_after: Some(std::time::Duration::from_secs(retry_after)),
+                first_seen: None,
+                window_reset: None,
+            })
+        }
+        Err(e) => {
+            debug!("Failed to charge response cost for key {:?}: {}", context.key, e);
+            None
         }
     }
 }
 
-fn get_fallback_key_common(
+/// Helper function to finish an error response, echoing the request id in
+/// Barnacle's JSON error body when configured
+async fn error_response(
+    response: Response<Body>,
+    request_id: Option<&str>,
+    request_id_config: &RequestIdConfig,
+) -> Response<Body> {
+    let Some(request_id) = request_id.filter(|_| request_id_config.include_in_error_body) else {
+        return response;
+    };
+    if !response.headers().contains_key("X-Barnacle-Error") {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(_) => return Response::from_parts(parts, Body::empty()),
+    };
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(mut json) if json["error"].is_object() => {
+            json["error"]["request_id"] = serde_json::Value::String(request_id.to_string());
+            parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+            Body::from(json.to_string())
+        }
+        // Problem details carry it as an extension member
+        Ok(mut json) if json["type"].is_string() && json["status"].is_number() => {
+            json["request_id"] = serde_json::Value::String(request_id.to_string());
+            parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+            Body::from(json.to_string())
+        }
+        _ => Body::from(bytes),
+    };
+    Response::from_parts(parts, body)
+}
+
+/// Helper function to read a non-empty query parameter, percent-decoded
+fn query_param(uri: &axum::http::Uri, name: &str) -> Option<String> {
+    let axum::extract::Query(params) =
+        axum::extract::Query::<std::collections::HashMap<String, String>>::try_from_uri(uri).ok()?;
+    params.get(name).filter(|value| !value.is_empty()).cloned()
+}
+
+pub(crate) fn get_fallback_key_common(
     extensions: &axum::http::Extensions,
     headers: &axum::http::HeaderMap,
     path: &str,
     method: &axum::http::Method,
 ) -> BarnacleKey {
+    let peer = extensions
+        .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
+        .map(|addr| addr.ip());
+    let ip_key_prefix = extensions.get::<IpKeyPrefix>();
+    let ip_key = |ip: std::net::IpAddr| {
+        BarnacleKey::Ip(ip_key_prefix.map_or(ip, |prefix| prefix.mask(ip)).to_string())
+    };
+    // Header values are only masked when they parse as an address
+    let header_key = |value: &str| match (ip_key_prefix, value.parse()) {
+        (Some(prefix), Ok(ip)) => BarnacleKey::Ip(prefix.mask(ip).to_string()),
+        _ => BarnacleKey::Ip(value.to_string()),
+    };
+
+    // 0. Behind configured proxies, only trust X-Forwarded-For as far as the config allows
+    if let Some(trusted_proxies) = extensions.get::<Arc<TrustedProxyConfig>>() {
+        let client = headers
+            .get("x-forwarded-for")
+            .and_then(|forwarded| forwarded.to_str().ok())
+            .and_then(|forwarded| trusted_proxies.client_ip(forwarded, peer));
+        if let Some(ip) = client.or(peer) {
+            debug!("IP via trusted proxies: {}", ip);
+            return ip_key(ip);
+        }
+        return local_key(path, method);
+    }
+
     // 1. Try ConnectInfo<SocketAddr> (only available in full Request)
-    if let Some(addr) = extensions.get::<axum::extract::ConnectInfo<std::net::SocketAddr>>() {
-        debug!("IP via ConnectInfo: {}", addr.ip());
-        return BarnacleKey::Ip(addr.ip().to_string());
+    if let Some(ip) = peer {
+        debug!("IP via ConnectInfo: {}", ip);
+        return ip_key(ip);
     }
 
     // 2. Try X-Forwarded-For header
@@ -223,7 +1400,7 @@ fn get_fallback_key_common(
         if let Ok(forwarded) = forwarded.to_str() {
             let ip = forwarded.split(',').next().unwrap_or("").trim();
             if !ip.is_empty() && ip != "unknown" {
-                return BarnacleKey::Ip(ip.to_string());
+                return header_key(ip);
             }
         }
     }
@@ -232,14 +1409,18 @@ fn get_fallback_key_common(
     if let Some(real_ip) = headers.get("x-real-ip") {
         if let Ok(real_ip) = real_ip.to_str() {
             if !real_ip.is_empty() && real_ip != "unknown" {
-                return BarnacleKey::Ip(real_ip.to_string());
+                return header_key(real_ip);
             }
         }
     }
 
     // 4. For local requests, use a unique identifier based on route + method
-    let method_str = method.as_str();
-    let local_key = format!("local:{}:{}", method_str, path);
+    local_key(path, method)
+}
+
+/// Helper function to key requests without a known client address by route + method
+fn local_key(path: &str, method: &axum::http::Method) -> BarnacleKey {
+    let local_key = format!("local:{}:{}", method.as_str(), path);
     debug!("Local key: {}", local_key);
     BarnacleKey::Ip(local_key)
 }
@@ -250,10 +1431,51 @@ fn get_fallback_key_common(
 pub struct BarnacleMiddleware<Inner, T, S, State = (), E = BarnacleError, V = ()> {
     inner: Inner,
     store: S,
-    config: BarnacleConfig,
+    config_resolver: Arc<dyn ConfigResolver>,
     state: Option<State>,
     api_key_validator: Option<V>,
     api_key_config: Option<ApiKeyConfig>,
+    api_key_global_config: Option<BarnacleConfig>,
+    request_id_config: RequestIdConfig,
+    concurrency_config: Option<ConcurrencyConfig>,
+    response_cost_enabled: bool,
+    maintenance_until: Option<SystemTime>,
+    idempotency_config: Option<IdempotencyConfig>,
+    body_hash_limit: Option<usize>,
+    retry_after_jitter: Option<f64>,
+    log_sampler: Option<Arc<LogSampler>>,
+    clock: Arc<dyn Clock>,
+    reset_on_success_header: Option<ResetOnSuccessHeader>,
+    refund_on_panic: bool,
+    conflict_warned: Arc<AtomicBool>,
+    pre_check: Option<PreCheck>,
+    hide_retry_after: Option<HideRetryAfter>,
+    grace_requests: u32,
+    failure_config: Option<BarnacleConfig>,
+    scope_header: bool,
+    header_style: HeaderStyle,
+    retry_after_on_success: bool,
+    key_extractor: Option<KeyExtractor>,
+    reset_queue: Option<Arc<ResetQueue<S>>>,
+    trusted_proxies: Option<Arc<TrustedProxyConfig>>,
+    ip_key_prefix: Option<IpKeyPrefix>,
+    trusted_identity_header: Option<String>,
+    store_error_policy: StoreErrorPolicy,
+    observer: Option<Arc<dyn RateLimitObserver>>,
+    json_key_path: Option<(Arc<JsonKeyPath>, usize)>,
+    payload_key_required: bool,
+    no_count_statuses: Option<Arc<[u16]>>,
+    request_cost: Option<RequestCostFn>,
+    max_body_bytes: Option<usize>,
+    required_scope: Option<String>,
+    reset_if_unchanged: bool,
+    success_predicate: Option<SuccessPredicate>,
+    response_builder: Option<ResponseBuilderFn>,
+    error_format: ErrorFormat,
+    access_control: Option<Arc<AccessControl>>,
+    global_config: Option<BarnacleConfig>,
+    use_matched_path: bool,
+    api_key_query_param: Option<String>,
     _phantom: PhantomData<(T, E)>,
 }
 
@@ -268,10 +1490,51 @@ where
         Self {
             inner: self.inner.clone(),
             store: self.store.clone(),
-            config: self.config.clone(),
+            config_resolver: self.config_resolver.clone(),
             state: self.state.clone(),
             api_key_validator: self.api_key_validator.clone(),
             api_key_config: self.api_key_config.clone(),
+            api_key_global_config: self.api_key_global_config.clone(),
+            request_id_config: self.request_id_config.clone(),
+            concurrency_config: self.concurrency_config.clone(),
+            response_cost_enabled: self.response_cost_enabled,
+            maintenance_until: self.maintenance_until,
+            idempotency_config: self.idempotency_config.clone(),
+            body_hash_limit: self.body_hash_limit,
+            retry_after_jitter: self.retry_after_jitter,
+            log_sampler: self.log_sampler.clone(),
+            clock: self.clock.clone(),
+            reset_on_success_header: self.reset_on_success_header.clone(),
+            refund_on_panic: self.refund_on_panic,
+            conflict_warned: self.conflict_warned.clone(),
+            pre_check: self.pre_check.clone(),
+            hide_retry_after: self.hide_retry_after.clone(),
+            grace_requests: self.grace_requests,
+            failure_config: self.failure_config.clone(),
+            scope_header: self.scope_header,
+            header_style: self.header_style,
+            retry_after_on_success: self.retry_after_on_success,
+            key_extractor: self.key_extractor.clone(),
+            reset_queue: self.reset_queue.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
+            ip_key_prefix: self.ip_key_prefix,
+            trusted_identity_header: self.trusted_identity_header.clone(),
+            store_error_policy: self.store_error_policy,
+            observer: self.observer.clone(),
+            json_key_path: self.json_key_path.clone(),
+            payload_key_required: self.payload_key_required,
+            no_count_statuses: self.no_count_statuses.clone(),
+            request_cost: self.request_cost.clone(),
+            max_body_bytes: self.max_body_bytes,
+            required_scope: self.required_scope.clone(),
+            reset_if_unchanged: self.reset_if_unchanged,
+            success_predicate: self.success_predicate.clone(),
+            response_builder: self.response_builder.clone(),
+            error_format: self.error_format,
+            access_control: self.access_control.clone(),
+            global_config: self.global_config.clone(),
+            use_matched_path: self.use_matched_path,
+            api_key_query_param: self.api_key_query_param.clone(),
             _phantom: PhantomData,
         }
     }
@@ -285,14 +1548,15 @@ pub trait ValidatorCall<T, S, State, E> {
         api_key_config: S,
         parts: Arc<Parts>,
         state: State,
-    ) -> Pin<Box<dyn Future<Output = Result<(), E>> + Send>>;
+    ) -> Pin<Box<dyn Future<Output = Result<ApiKeyValidationResult, E>> + Send>>;
 }
 
-// Implementation for closures
-impl<F, Fut, T, S, State, E> ValidatorCall<T, S, State, E> for F
+// Implementation for closures returning `Ok(())` or `Ok(ApiKeyValidationResult)`
+impl<F, Fut, R, T, S, State, E> ValidatorCall<T, S, State, E> for F
 where
     F: Fn(T, S, Arc<Parts>, State) -> Fut + Send + Sync,
-    Fut: Future<Output = Result<(), E>> + Send + 'static,
+    Fut: Future<Output = Result<R, E>> + Send + 'static,
+    R: Into<ApiKeyValidationResult>,
     T: Send + 'static,
     S: Send + 'static,
     State: Send + 'static,
@@ -304,8 +1568,49 @@ where
         api_key_config: S,
         parts: Arc<Parts>,
         state: State,
-    ) -> Pin<Box<dyn Future<Output = Result<(), E>> + Send>> {
-        Box::pin((self)(api_key, api_key_config, parts, state))
+    ) -> Pin<Box<dyn Future<Output = Result<ApiKeyValidationResult, E>> + Send>> {
+        let fut = (self)(api_key, api_key_config, parts, state);
+        Box::pin(async move { fut.await.map(Into::into) })
+    }
+}
+
+/// Validator checking keys against an `ApiKeyStore`, see `ApiKeyRateLimitLayer`
+pub struct ApiKeyStoreValidator<K> {
+    store: Arc<K>,
+}
+
+impl<K> ApiKeyStoreValidator<K> {
+    pub fn new(store: K) -> Self {
+        Self { store: Arc::new(store) }
+    }
+}
+
+impl<K> Clone for ApiKeyStoreValidator<K> {
+    fn clone(&self) -> Self {
+        Self { store: self.store.clone() }
+    }
+}
+
+impl<K, State, E> ValidatorCall<String, ApiKeyConfig, State, E> for ApiKeyStoreValidator<K>
+where
+    K: ApiKeyStore + 'static,
+    State: Send + 'static,
+    E: From<BarnacleError> + Send + 'static,
+{
+    fn call(
+        &self,
+        api_key: String,
+        _api_key_config: ApiKeyConfig,
+        _parts: Arc<Parts>,
+        _state: State,
+    ) -> Pin<Box<dyn Future<Output = Result<ApiKeyValidationResult, E>> + Send>> {
+        let store = self.store.clone();
+        Box::pin(async move {
+            if api_key.is_empty() {
+                return Err(E::from(BarnacleError::ApiKeyMissing));
+            }
+            Ok(store.validate_key(&api_key).await)
+        })
     }
 }
 
@@ -317,8 +1622,8 @@ impl<T, S, State, E> ValidatorCall<T, S, State, E> for () {
         _api_key_config: S,
         _parts: Arc<Parts>,
         _state: State,
-    ) -> Pin<Box<dyn Future<Output = Result<(), E>> + Send>> {
-        Box::pin(async { Ok(()) })
+    ) -> Pin<Box<dyn Future<Output = Result<ApiKeyValidationResult, E>> + Send>> {
+        Box::pin(async { Ok(().into()) })
     }
 }
 
@@ -361,29 +1666,138 @@ where
         debug!("[middleware.rs] Unified BarnacleMiddleware::call invoked");
         let mut inner = self.inner.clone();
         let store = self.store.clone();
-        let config = self.config.clone();
+        let config_resolver = self.config_resolver.clone();
         let state = self.state.clone();
         let api_key_validator = self.api_key_validator.clone();
         let api_key_config = self.api_key_config.clone();
+        let api_key_global_config = self.api_key_global_config.clone();
+        let request_id_config = self.request_id_config.clone();
+        let concurrency_config = self.concurrency_config.clone();
+        let response_cost_enabled = self.response_cost_enabled;
+        let maintenance_until = self.maintenance_until;
+        let idempotency_config = self.idempotency_config.clone();
+        let body_hash_limit = self.body_hash_limit;
+        let retry_after_jitter = self.retry_after_jitter;
+        let log_sampler = self.log_sampler.clone();
+        let clock = self.clock.clone();
+        let reset_on_success_header = self.reset_on_success_header.clone();
+        let refund_on_panic = self.refund_on_panic;
+        let reset_if_unchanged = self.reset_if_unchanged;
+        let success_predicate = self.success_predicate.clone();
+        let response_builder = self.response_builder.clone();
+        let error_format = self.error_format;
+        let request_cost = self.request_cost.clone();
+        let no_count_statuses = self.no_count_statuses.clone();
+        let conflict_warned = self.conflict_warned.clone();
+        let pre_check = self.pre_check.clone();
+        let hide_retry_after = self.hide_retry_after.clone();
+        let grace_requests = self.grace_requests;
+        let failure_config = self.failure_config.clone();
+        let scope_header = self.scope_header;
+        let header_style = self.header_style;
+        let retry_after_on_success = self.retry_after_on_success;
+        let key_extractor = self.key_extractor.clone();
+        let reset_queue = self.reset_queue.clone();
+        let trusted_proxies = self.trusted_proxies.clone();
+        let ip_key_prefix = self.ip_key_prefix;
+        let trusted_identity_header = self.trusted_identity_header.clone();
+        let api_key_query_param = self.api_key_query_param.clone();
+        let required_scope = self.required_scope.clone();
+        let access_control = self.access_control.clone();
+        let global_config = self.global_config.clone();
+        let use_matched_path = self.use_matched_path;
+        let store_error_policy = self.store_error_policy;
+        let observer = self.observer.clone();
+        let json_key_path = self.json_key_path.clone();
+        let payload_key_required = self.payload_key_required;
+        let max_body_bytes = self.max_body_bytes;
+        // One span per decision, filled in as the key, the outcome and the store latency are known
+        let span = tracing::info_span!(
+            "barnacle.ratelimit",
+            key = tracing::field::Empty,
+            path = tracing::field::Empty,
+            method = %req.method(),
+            allowed = tracing::field::Empty,
+            remaining = tracing::field::Empty,
+            store_latency_ms = tracing::field::Empty,
+        );
         Box::pin(async move {
             debug!("[middleware.rs] Entered async block in call");
-            let current_path = req
+            if let Some(retry_after) = maintenance_until.and_then(|until| maintenance_retry_after(until, clock.now())) {
+                debug!("[middleware.rs] Maintenance mode active, retry_after: {}s", retry_after);
+                let request_id = req
+                    .headers()
+                    .get(request_id_config.header_name.as_str())
+                    .and_then(|h| h.to_str().ok());
+                let response =
+                    render_error::<E>(BarnacleError::maintenance(retry_after), error_format, response_builder.as_ref());
+                return Ok(error_response(response, request_id, &request_id_config).await);
+            }
+            let matched_path = req
                 .extensions()
-                .get::<OriginalUri>()
-                .map(|original_url| original_url.path().to_owned())
-                .unwrap_or(req.uri().path().to_owned());
+                .get::<MatchedPath>()
+                .filter(|_| use_matched_path)
+                .map(|matched| matched.as_str().to_owned());
+            let current_path = matched_path.unwrap_or_else(|| {
+                req.extensions()
+                    .get::<OriginalUri>()
+                    .map(|original_url| original_url.path().to_owned())
+                    .unwrap_or(req.uri().path().to_owned())
+            });
             
             debug!("[middleware.rs] current_path: {}", current_path);
-            let (parts, body) = req.into_parts();
+            tracing::Span::current().record("path", current_path.as_str());
+            let (mut parts, body) = req.into_parts();
+            if let Some(trusted_proxies) = trusted_proxies {
+                parts.extensions.insert(trusted_proxies);
+            }
+            if let Some(ip_key_prefix) = ip_key_prefix {
+                parts.extensions.insert(ip_key_prefix);
+            }
             debug!("[middleware.rs] Request parts and body split");
+            let request_id = parts
+                .headers
+                .get(request_id_config.header_name.as_str())
+                .and_then(|h| h.to_str().ok())
+                .map(str::to_owned);
+
+            // An identity verified upstream replaces API key validation
+            let trusted_identity = trusted_identity_header
+                .as_deref()
+                .and_then(|name| parts.headers.get(name))
+                .and_then(|h| h.to_str().ok())
+                .filter(|identity| !identity.is_empty())
+                .map(str::to_owned);
 
             // API key validation (if configured)
             let mut api_key_used: Option<String> = None;
+            let forward_headers;
             let api_key_config = api_key_config.unwrap_or_default();
-            let api_key = parts.headers.get(api_key_config.header_name.as_str()).and_then(|h| h.to_str().ok()).unwrap_or("");
+            let header_api_key = parts
+                .headers
+                .get(api_key_config.header_name.as_str())
+                .and_then(|h| h.to_str().ok())
+                .filter(|key| !key.is_empty());
+            let query_api_key = match (header_api_key, api_key_query_param.as_deref()) {
+                (None, Some(name)) if trusted_identity.is_none() => {
+                    let key = query_param(&parts.uri, name);
+                    if key.is_some() {
+                        tracing::warn!("API key read from the `{}` query parameter; query strings are often logged, send the `{}` header instead", name, api_key_config.header_name);
+                    }
+                    key
+                }
+                _ => None,
+            };
+            let api_key = match trusted_identity {
+                Some(_) => "",
+                None => header_api_key.or(query_api_key.as_deref()).unwrap_or(""),
+            };
             debug!("[middleware.rs] About to call validator with key: '{}'", api_key);
 
-            let validation_result = if let Some(validator) = api_key_validator.as_ref() {
+            let validation_result = if trusted_identity.is_some() {
+                debug!("[middleware.rs] Trusted identity header present, skipping validator");
+                Ok(().into())
+            } else if let Some(validator) = api_key_validator.as_ref() {
                 let is_stateless_validator = std::any::TypeId::of::<V>() == std::any::TypeId::of::<()>();
                 let is_unit_state = std::any::TypeId::of::<State>() == std::any::TypeId::of::<()>();
                 if is_stateless_validator && is_unit_state {
@@ -401,118 +1815,487 @@ where
                     }
                 }
             } else {
-                Ok(())
+                Ok(().into())
             };
             match validation_result {
-                Ok(_) => {
+                Ok(outcome) if !outcome.valid => {
+                    debug!("[middleware.rs] Validator marked key invalid, request_id={:?}", request_id);
+                    return Ok(error_response(render_error::<E>(BarnacleError::invalid_api_key(api_key), error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                }
+                Ok(outcome) => {
                     debug!("[middleware.rs] Validator returned Ok for: '{}'", api_key);
+                    if let Some(scope) = required_scope.as_deref() {
+                        if trusted_identity.is_none() && !outcome.has_scope(scope) {
+                            debug!("[middleware.rs] Key lacks scope '{}', request_id={:?}", scope, request_id);
+                            let e = BarnacleError::permission_denied(scope);
+                            return Ok(error_response(render_error::<E>(e, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                        }
+                    }
                     if !api_key.is_empty() {
                         api_key_used = Some(api_key.to_string());
                     }
+                    forward_headers = outcome.forward_headers;
                 },
                 Err(e) => {
-                    debug!("[middleware.rs] Validator returned Err");
-                    return Ok(e.into_response());
+                    debug!("[middleware.rs] Validator returned Err, request_id={:?}", request_id);
+                    let response = render_validator_error::<E>(e, error_format, response_builder.as_ref());
+                    return Ok(error_response(response, request_id.as_deref(), &request_id_config).await);
                 }
             }
 
-            // Unified logic: always try to extract key from body (for T=(), uses fallback)
-            let (rate_limit_context, body_bytes) = match body.collect().await {
-                Ok(collected) => {
-                    let bytes = collected.to_bytes();
-                    let (key, used_fallback) = if let Some(ref api_key) = api_key_used {
-                        // Use API key as the rate limiting key
-                        (BarnacleKey::ApiKey(api_key.clone()), false)
-                    } else {
-                        match serde_json::from_slice::<T>(&bytes) {
-                            Ok(payload) => (payload.extract_key(&parts), false),
-                            Err(_) => (
-                                get_fallback_key_common(
-                                    &parts.extensions,
-                                    &parts.headers,
-                                    &current_path,
-                                    &parts.method,
-                                ),
-                                true,
-                            ),
-                        }
-                    };
-                    let context = BarnacleContext {
-                        key,
-                        path: current_path.clone(),
-                        method: parts.method.as_str().to_string(),
-                    };
-                    if used_fallback {
-                        debug!("[middleware.rs] (unified) Using fallback key for rate limiting");
-                    } else if api_key_used.is_some() {
-                        debug!("[middleware.rs] (unified) Using API key for rate limiting");
-                    } else {
-                        debug!("[middleware.rs] (unified) Extracted key from payload for rate limiting");
+            let extracted_key = key_extractor.as_ref().and_then(|extract| extract(&parts));
+            // Unified logic: always try to extract key from body (for T=(), uses fallback).
+            // A body that can't be read is treated like one that doesn't parse: body-based
+            // keys fall back to the IP key, or the request is rejected if a payload key is required.
+            let collected = match max_body_bytes {
+                Some(max_bytes) => match Limited::new(body, max_bytes).collect().await {
+                    Err(e) if e.is::<LengthLimitError>() => {
+                        debug!("[middleware.rs] (unified) Body exceeds {} bytes, request_id={:?}", max_bytes, request_id);
+                        let e = BarnacleError::payload_too_large(max_bytes);
+                        return Ok(error_response(render_error::<E>(e, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
                     }
-                    (context, Some(bytes))
+                    collected => collected.map_err(|e| e.to_string()),
+                },
+                None => body.collect().await.map_err(|e| e.to_string()),
+            };
+            let body_bytes = match collected {
+                Ok(collected) => Some(collected.to_bytes()),
+                Err(e) => {
+                    debug!("[middleware.rs] (unified) Failed to collect body: {}, request_id={:?}", e, request_id);
+                    None
                 }
-                Err(_) => {
-                    debug!("[middleware.rs] (unified) Failed to collect body, using fallback key");
-                    let fallback_key = get_fallback_key_common(
-                        &parts.extensions,
-                        &parts.headers,
-                        &current_path,
-                        &parts.method,
-                    );
-                    let context = BarnacleContext {
-                        key: fallback_key,
-                        path: current_path.clone(),
-                        method: parts.method.as_str().to_string(),
-                    };
-                    (context, None)
+            };
+            let fallback_key = || get_fallback_key_common(&parts.extensions, &parts.headers, &current_path, &parts.method);
+            let (key, used_fallback) = if let Some(ref identity) = trusted_identity {
+                (BarnacleKey::Custom(identity.clone()), false)
+            } else if let Some(ref api_key) = api_key_used {
+                // Use API key as the rate limiting key
+                (BarnacleKey::ApiKey(api_key.clone()), false)
+            } else if let Some(key) = extracted_key {
+                (key, false)
+            } else if let Some((path, max_bytes)) = json_key_path.as_ref() {
+                match body_bytes.as_deref().and_then(|bytes| json_path_key(bytes, path, *max_bytes)) {
+                    Some(key) => (key, false),
+                    None => (fallback_key(), true),
+                }
+            } else if let Some(body_hash_limit) = body_hash_limit {
+                // Identical bodies share a bucket regardless of who sends them
+                match body_bytes.as_deref() {
+                    Some(bytes) => (body_hash_key(bytes, body_hash_limit), false),
+                    None => (fallback_key(), true),
+                }
+            } else if api_key_validator.is_some() {
+                // Optional-key route called without a key: limit anonymous traffic by IP
+                (fallback_key(), true)
+            } else {
+                let parsed = body_bytes.as_deref().map(|bytes| (bytes, serde_json::from_slice::<T>(bytes)));
+                match parsed {
+                    Some((_, Ok(payload))) => (payload.extract_key(&parts), false),
+                    Some((bytes, Err(e))) if payload_key_required => {
+                        debug!("[middleware.rs] (unified) Payload key required but body did not parse: {}, request_id={:?}", e, request_id);
+                        let e = payload_parse_error(bytes, e);
+                        return Ok(error_response(render_error::<E>(e, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                    }
+                    None if payload_key_required => {
+                        let e = BarnacleError::request_parsing_error("Failed to read request body");
+                        return Ok(error_response(render_error::<E>(e, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                    }
+                    _ => (fallback_key(), true),
                 }
             };
+            if used_fallback {
+                debug!("[middleware.rs] (unified) Using fallback key for rate limiting");
+            } else if api_key_used.is_some() {
+                debug!("[middleware.rs] (unified) Using API key for rate limiting");
+            } else {
+                debug!("[middleware.rs] (unified) Extracted key from payload for rate limiting");
+            }
+            let rate_limit_context = BarnacleContext {
+                key,
+                path: current_path.clone(),
+                method: parts.method.as_str().to_string(),
+            };
+            tracing::Span::current().record("key", tracing::field::debug(&rate_limit_context.key));
+            if let Some(access_control) = access_control.as_deref() {
+                if access_control.is_denylisted(&rate_limit_context.key) {
+                    debug!("[middleware.rs] (unified) Denylisted key: {:?}, request_id={:?}", rate_limit_context.key, request_id);
+                    tracing::Span::current().record("allowed", false);
+                    let e = BarnacleError::key_denied();
+                    return Ok(error_response(render_error::<E>(e, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                }
+                if access_control.is_allowlisted(&rate_limit_context.key) {
+                    debug!("[middleware.rs] (unified) Allowlisted key, skipping rate limiting: {:?}, request_id={:?}", rate_limit_context.key, request_id);
+                    tracing::Span::current().record("allowed", true);
+                    let body = body_bytes.map(Body::from).unwrap_or_else(Body::empty);
+                    return inner.call(Request::from_parts(parts, body)).await;
+                }
+            }
             debug!("[middleware.rs] (unified) About to increment rate limit for context: {:?}", rate_limit_context);
-            tracing::debug!("[middleware.rs] Rate limit increment: api_key={:?}, path={}, method={}", rate_limit_context.key, rate_limit_context.path, rate_limit_context.method);
-            let result = match store.increment(&rate_limit_context, &config).await {
-                Ok(result) => result,
-                Err(e) => {
-                    debug!("[middleware.rs] (unified) Rate limit store error: {}", e);
-                    return Ok(E::from(e).into_response());
+            tracing::debug!("[middleware.rs] Rate limit increment: api_key={:?}, path={}, method={}, request_id={:?}", rate_limit_context.key, rate_limit_context.path, rate_limit_context.method, request_id);
+            let config = config_resolver.resolve(&rate_limit_context);
+            if let Some(AppliedLayerConfig(outer)) = parts.extensions.get::<AppliedLayerConfig>() {
+                if let Some(conflict) = layer_conflict(outer, &config) {
+                    if !conflict_warned.swap(true, Ordering::Relaxed) {
+                        tracing::warn!("Stacked BarnacleLayers have conflicting configs ({}); outer: {:?}, inner: {:?}", conflict, outer, config);
+                    }
+                }
+            }
+            parts.extensions.insert(AppliedLayerConfig(config.clone()));
+            if let Some(pre_check) = pre_check.as_ref() {
+                if let Err(e) = pre_check(&rate_limit_context).await {
+                    debug!("[middleware.rs] (unified) Pre-check rejected key: {:?}: {}, request_id={:?}", rate_limit_context.key, e, request_id);
+                    return Ok(error_response(render_error::<E>(e, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                }
+            }
+            let hide_reset = hide_retry_after.as_ref().is_some_and(|hide| hide(&parts));
+            let scope = scope_header.then(|| rate_limit_context.key.scope());
+            let failure_limit = failure_config.as_ref().map(|failure_config| {
+                let failure_context = BarnacleContext {
+                    key: rate_limit_context.key.clone(),
+                    path: format!("{}{}", rate_limit_context.path, FAILURES_SUFFIX),
+                    method: rate_limit_context.method.clone(),
+                };
+                (failure_config, failure_context)
+            });
+            // Reject before counting the attempt once the failure budget is spent
+            if let Some((failure_config, failure_context)) = failure_limit.as_ref() {
+                match store.usage_for_key(failure_context).await {
+                    Ok(usage) if usage.count >= failure_config.max_requests => {
+                        debug!("[middleware.rs] (unified) Failure limit reached for key: {:?}, request_id={:?}", rate_limit_context.key, request_id);
+                        let retry_after = usage.retry_after.unwrap_or(failure_config.window).as_secs();
+                        let e = BarnacleError::rate_limit_exceeded(0, retry_after, failure_config.max_requests);
+                        if let Some(observer) = observer.as_deref() {
+                            observer.on_blocked(&rate_limit_context, &e);
+                        }
+                        return Ok(error_response(rate_limit_error_response::<E>(e, retry_after_jitter, clock.now(), hide_reset, scope, header_style, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        debug!("[middleware.rs] (unified) Failure count lookup failed, allowing request: {}, request_id={:?}", e, request_id);
+                    }
+                }
+            }
+            // Taken before counting, so a request turned away here doesn't use up the window.
+            // Held until the inner service finishes; dropping it releases the slot
+            let _in_flight = match concurrency_config.as_ref() {
+                Some(concurrency_config) => {
+                    match InFlightGuard::acquire(&store, &rate_limit_context, concurrency_config).await {
+                        Ok(Some(guard)) => Some(guard),
+                        Ok(None) => {
+                            debug!("[middleware.rs] (unified) Concurrency limit reached for key: {:?}, request_id={:?}", rate_limit_context.key, request_id);
+                            let e = BarnacleError::concurrency_limit_exceeded(concurrency_config.max_in_flight);
+                            if let Some(observer) = observer.as_deref() {
+                                observer.on_blocked(&rate_limit_context, &e);
+                            }
+                            return Ok(error_response(render_error::<E>(e, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                        }
+                        Err(e) if store_error_policy.fails_open(&e) => {
+                            tracing::warn!("[middleware.rs] (unified) In-flight acquire store error, failing open: {}, request_id={:?}", e, request_id);
+                            None
+                        }
+                        Err(e) => {
+                            debug!("[middleware.rs] (unified) In-flight acquire error: {}, request_id={:?}", e, request_id);
+                            return Ok(error_response(render_error::<E>(e, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                        }
+                    }
+                }
+                None => None,
+            };
+            // A retried request carrying an already-claimed idempotency key is not counted again.
+            // Claiming is a single set-if-absent, so concurrent retries can't both be counted;
+            // it comes after the gates above so their rejections leave the key unclaimed
+            let idempotency = idempotency_config.as_ref().and_then(|idempotency_config| {
+                parts
+                    .headers
+                    .get(idempotency_config.header_name.as_str())
+                    .and_then(|h| h.to_str().ok())
+                    .filter(|key| !key.is_empty())
+                    .map(|key| (idempotency_config, key.to_string()))
+            });
+            let is_repeat = match idempotency.as_ref() {
+                Some((idempotency_config, idempotency_key)) => {
+                    match store.claim_idempotency_key(&rate_limit_context, idempotency_key, idempotency_config.ttl).await {
+                        Ok(claimed) => !claimed,
+                        Err(e) => {
+                            debug!("[middleware.rs] (unified) Idempotency claim failed, counting request: {}, request_id={:?}", e, request_id);
+                            false
+                        }
+                    }
                 }
+                None => false,
             };
-            debug!("[middleware.rs] (unified) Rate limit check passed for key: {:?}, remaining: {}, retry_after: {:?}", rate_limit_context.key, result.remaining, result.retry_after);
+            let claimed_key = idempotency.as_ref().filter(|_| !is_repeat).map(|(_, key)| key.as_str());
+            let mut limit = config.max_requests;
+            let global_limit = api_key_global_config.as_ref().zip(api_key_used.as_ref()).map(|(global_config, api_key)| {
+                let global_context = BarnacleContext {
+                    key: BarnacleKey::ApiKey(api_key.clone()),
+                    path: ALL_ENDPOINTS.to_string(),
+                    method: ALL_ENDPOINTS.to_string(),
+                };
+                (global_config, global_context)
+            });
+            let endpoint_limit = global_config.as_ref().map(|global_config| {
+                let endpoint_context = BarnacleContext {
+                    key: BarnacleKey::Custom(format!("global:{}", rate_limit_context.path)),
+                    path: rate_limit_context.path.clone(),
+                    method: rate_limit_context.method.clone(),
+                };
+                (global_config, endpoint_context)
+            });
+            // Every global counter a counted request was charged, for refunds
+            let global_contexts: Vec<&BarnacleContext> = global_limit
+                .iter()
+                .chain(endpoint_limit.iter())
+                .map(|(_, context)| context)
+                .collect();
+            let units = parts
+                .extensions
+                .get::<RequestCost>()
+                .map(|cost| cost.0)
+                .or_else(|| request_cost.as_ref().map(|request_cost| request_cost(&parts)))
+                .unwrap_or(1)
+                .max(1);
+            let mut result = None;
+            // What the counter held after this request's own units, for `with_reset_if_unchanged`
+            let mut seen_count = None;
+            let mut over_limit = false;
+            if is_repeat {
+                debug!("[middleware.rs] (unified) Repeated idempotency key, skipping increment for key: {:?}, request_id={:?}", rate_limit_context.key, request_id);
+            } else {
+                // Grace requests are counted against the raised limit, but reported against the real one
+                let mut enforced_config = config.clone();
+                enforced_config.max_requests = config.max_requests.saturating_add(grace_requests);
+                let started = Instant::now();
+                let outcome = check_rate_limit_by(&store, &rate_limit_context, &enforced_config, units).await;
+                let latency = started.elapsed();
+                tracing::Span::current().record("store_latency_ms", latency.as_secs_f64() * 1000.0);
+                observe_increment(observer.as_deref(), &rate_limit_context, latency, &outcome);
+                let counted = match outcome {
+                    Ok(result) => Some(result),
+                    Err(e) if store_error_policy.fails_open(&e) => {
+                        tracing::warn!("[middleware.rs] (unified) Rate limit store error, failing open: {}, request_id={:?}", e, request_id);
+                        None
+                    }
+                    Err(e) => {
+                        debug!("[middleware.rs] (unified) Rate limit store error: {}, request_id={:?}", e, request_id);
+                        tracing::Span::current().record("allowed", false);
+                        let e = with_reported_limit(e, config.max_requests);
+                        release_idempotency_key(&store, &rate_limit_context, claimed_key).await;
+                        return Ok(error_response(rate_limit_error_response::<E>(e, retry_after_jitter, clock.now(), hide_reset, scope, header_style, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                    }
+                };
+                if let Some(mut counted) = counted {
+                    seen_count = Some(enforced_config.max_requests.saturating_sub(counted.remaining));
+                    if grace_requests > 0 {
+                        over_limit = counted.remaining < grace_requests;
+                        counted.remaining = counted.remaining.saturating_sub(grace_requests);
+                        if over_limit {
+                            debug!("[middleware.rs] (unified) Over limit within grace for key: {:?}, request_id={:?}", rate_limit_context.key, request_id);
+                        }
+                    }
+                    // Per-key limit across all endpoints, checked after the per-endpoint limit
+                    if let Some((global_config, global_context)) = global_limit.as_ref() {
+                        let started = Instant::now();
+                        let outcome = check_rate_limit_by(&store, global_context, global_config, units).await;
+                        observe_increment(observer.as_deref(), global_context, started.elapsed(), &outcome);
+                        match outcome {
+                            // Report whichever limit is closest to being exhausted
+                            Ok(global_result) => {
+                                if global_result.remaining < counted.remaining {
+                                    limit = global_config.max_requests;
+                                    counted = global_result;
+                                }
+                            }
+                            Err(e) if store_error_policy.fails_open(&e) => {
+                                tracing::warn!("[middleware.rs] (unified) Global API key limit store error, failing open: {}, request_id={:?}", e, request_id);
+                            }
+                            Err(e) => {
+                                debug!("[middleware.rs] (unified) Global API key limit error: {}, request_id={:?}", e, request_id);
+                                tracing::Span::current().record("allowed", false);
+                                // Give back the endpoint units so the rejection doesn't use up its quota
+                                refund_request(&store, &rate_limit_context, units, &[]).await;
+                                release_idempotency_key(&store, &rate_limit_context, claimed_key).await;
+                                return Ok(error_response(rate_limit_error_response::<E>(e, retry_after_jitter, clock.now(), hide_reset, scope, header_style, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                            }
+                        }
+                    }
+                    // Limit on all keys together, checked last
+                    if let Some((global_config, endpoint_context)) = endpoint_limit.as_ref() {
+                        let started = Instant::now();
+                        let outcome = check_rate_limit_by(&store, endpoint_context, global_config, units).await;
+                        observe_increment(observer.as_deref(), endpoint_context, started.elapsed(), &outcome);
+                        match outcome {
+                            Ok(global_result) => {
+                                if global_result.remaining < counted.remaining {
+                                    limit = global_config.max_requests;
+                                    counted = global_result;
+                                }
+                            }
+                            Err(e) if store_error_policy.fails_open(&e) => {
+                                tracing::warn!("[middleware.rs] (unified) Global endpoint limit store error, failing open: {}, request_id={:?}", e, request_id);
+                            }
+                            Err(e) => {
+                                debug!("[middleware.rs] (unified) Global endpoint limit error: {}, request_id={:?}", e, request_id);
+                                tracing::Span::current().record("allowed", false);
+                                // The key itself wasn't over its limit, so it keeps the request
+                                let key_contexts: Vec<&BarnacleContext> = global_limit.iter().map(|(_, context)| context).collect();
+                                refund_request(&store, &rate_limit_context, units, &key_contexts).await;
+                                release_idempotency_key(&store, &rate_limit_context, claimed_key).await;
+                                return Ok(error_response(rate_limit_error_response::<E>(e, retry_after_jitter, clock.now(), hide_reset, Some(GLOBAL_SCOPE), header_style, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                            }
+                        }
+                    }
+                    if log_sampler.as_ref().map_or(true, |sampler| sampler.sample()) {
+                        debug!("[middleware.rs] (unified) Rate limit check passed for key: {:?}, remaining: {}, reset_after: {:?}, request_id={:?}", rate_limit_context.key, counted.remaining, counted.reset_after, request_id);
+                    }
+                    if let Some(observer) = observer.as_deref() {
+                        observer.on_allowed(&rate_limit_context, &counted);
+                    }
+                    result = Some(counted);
+                }
+            }
             let reconstructed_body = match body_bytes {
                 Some(bytes) => axum::body::Body::from(bytes),
                 None => axum::body::Body::empty(),
             };
+            for (name, value) in forward_headers {
+                match (axum::http::HeaderName::try_from(name.as_str()), axum::http::HeaderValue::try_from(value.as_str())) {
+                    (Ok(name), Ok(value)) => {
+                        parts.headers.insert(name, value);
+                    }
+                    _ => debug!("[middleware.rs] Skipping invalid forward header: {}", name),
+                }
+            }
+            if let Some(counted) = result.as_ref() {
+                tracing::Span::current().record("allowed", true).record("remaining", counted.remaining);
+                parts.extensions.insert(RateLimitSnapshot {
+                    remaining: counted.remaining,
+                    limit,
+                    reset_after: counted.reset_after.or(counted.retry_after),
+                });
+            }
             let new_req = Request::from_parts(parts, reconstructed_body);
             debug!("[middleware.rs] (unified) Calling inner service");
-            let response = inner.call(new_req).await?;
+            // Unwrap the inner result before awaiting anything else, so the future stays
+            // `Send` without requiring `Inner::Error: Send`
+            let outcome = match AssertUnwindSafe(async { inner.call(new_req).await }).catch_unwind().await {
+                Ok(response) => Ok(response?),
+                Err(panic) => Err(panic),
+            };
+            let mut response = match outcome {
+                Ok(response) => response,
+                Err(panic) => {
+                    debug!("[middleware.rs] (unified) Inner service panicked for key: {:?}, request_id={:?}", rate_limit_context.key, request_id);
+                    if refund_on_panic && result.is_some() {
+                        refund_request(&store, &rate_limit_context, units, &global_contexts).await;
+                    }
+                    std::panic::resume_unwind(panic);
+                }
+            };
+            let uncounted = no_count_statuses
+                .as_deref()
+                .is_some_and(|statuses| statuses.contains(&response.status().as_u16()));
+            if let Some(result) = result.as_mut().filter(|_| uncounted) {
+                debug!("[middleware.rs] (unified) Refunding request with uncounted status {} for key: {:?}, request_id={:?}", response.status(), rate_limit_context.key, request_id);
+                refund_request(&store, &rate_limit_context, units, &global_contexts).await;
+                result.remaining = result.remaining.saturating_add(units).min(limit);
+                seen_count = seen_count.map(|count: u32| count.saturating_sub(units));
+            }
+            if response_cost_enabled {
+                let cost = take_response_cost(&mut response);
+                if let Some(result) = result.as_mut().filter(|_| cost > 1 && !uncounted) {
+                    debug!("[middleware.rs] (unified) Charging response cost {} for key: {:?}, request_id={:?}", cost, rate_limit_context.key, request_id);
+                    seen_count = seen_count.map(|count: u32| count.saturating_add(cost - 1));
+                    if let Some(charged) = charge_extra_cost(&store, &rate_limit_context, &config, cost - 1).await {
+                        if charged.remaining <= result.remaining {
+                            limit = config.max_requests;
+                            *result = charged;
+                        }
+                    }
+                    for (global_config, global_context) in global_limit.iter().chain(endpoint_limit.iter()) {
+                        if let Some(charged) = charge_extra_cost(&store, global_context, global_config, cost - 1).await {
+                            if charged.remaining < result.remaining {
+                                limit = global_config.max_requests;
+                                *result = charged;
+                            }
+                        }
+                    }
+                }
+            }
             // Add rate limit headers to successful response
             let mut response_with_headers = response;
-            {
-                let headers = response_with_headers.headers_mut();
-                if let Ok(remaining_header) = result.remaining.to_string().parse() {
-                    headers.insert("X-RateLimit-Remaining", remaining_header);
-                    debug!("[middleware.rs] (unified) Added X-RateLimit-Remaining: {}", result.remaining);
+            if let Some(result) = result.as_ref() {
+                insert_rate_limit_headers(response_with_headers.headers_mut(), result, limit, clock.now());
+                apply_header_style(response_with_headers.headers_mut(), header_style);
+                if let Some(reset_after) = result.reset_after.or(result.retry_after).filter(|_| retry_after_on_success && !hide_reset) {
+                    if let Ok(retry_after_header) = reset_after.as_secs().to_string().parse() {
+                        response_with_headers
+                            .headers_mut()
+                            .insert(axum::http::header::RETRY_AFTER, retry_after_header);
+                    }
                 }
-                if let Ok(limit_header) = config.max_requests.to_string().parse() {
-                    headers.insert("X-RateLimit-Limit", limit_header);
-                    debug!("[middleware.rs] (unified) Added X-RateLimit-Limit: {}", config.max_requests);
+                if let Some(scope) = scope {
+                    response_with_headers
+                        .headers_mut()
+                        .insert("X-RateLimit-Scope", axum::http::HeaderValue::from_static(scope));
                 }
-                if let Some(retry_after) = result.retry_after {
-                    if let Ok(reset_header) = retry_after.as_secs().to_string().parse() {
-                        headers.insert("X-RateLimit-Reset", reset_header);
-                        debug!("[middleware.rs] (unified) Added X-RateLimit-Reset: {}", retry_after.as_secs());
+            }
+            if let Some((failure_config, failure_context)) = failure_limit.as_ref().filter(|_| result.is_some() && !uncounted) {
+                if !response_with_headers.status().is_success() {
+                    if let Err(e) = store.increment(failure_context, failure_config).await {
+                        debug!("[middleware.rs] (unified) Failed to count failed request: {}, request_id={:?}", e, request_id);
                     }
                 }
             }
-            handle_rate_limit_reset(
-                &store,
-                &config,
-                &rate_limit_context,
-                response_with_headers.status().as_u16(),
-                false,
-            )
-            .await;
+            if over_limit {
+                response_with_headers
+                    .headers_mut()
+                    .insert("X-RateLimit-Warning", axum::http::HeaderValue::from_static("over-limit"));
+            }
+            // Only buffer responses that would otherwise reset the quota
+            let check_body = success_predicate.as_ref().filter(|_| {
+                config.reset_on_success != ResetOnSuccess::Not
+                    && config.is_success_response(
+                        response_with_headers.status().as_u16(),
+                        response_with_headers.headers(),
+                        reset_on_success_header.as_ref(),
+                    )
+            });
+            let body_success = match check_body {
+                Some(predicate) => {
+                    let (response_parts, body) = response_with_headers.into_parts();
+                    let bytes = match body.collect().await {
+                        Ok(collected) => collected.to_bytes(),
+                        Err(e) => {
+                            debug!("[middleware.rs] (unified) Failed to buffer response body: {}, request_id={:?}", e, request_id);
+                            let e = BarnacleError::internal_error("Failed to read response body");
+                            return Ok(error_response(render_error::<E>(e, error_format, response_builder.as_ref()), request_id.as_deref(), &request_id_config).await);
+                        }
+                    };
+                    let success = predicate(&response_parts, &bytes);
+                    response_with_headers = Response::from_parts(response_parts, Body::from(bytes));
+                    success
+                }
+                None => true,
+            };
+            if body_success {
+                handle_rate_limit_reset(
+                    &store,
+                    &config,
+                    &rate_limit_context,
+                    response_with_headers.status().as_u16(),
+                    response_with_headers.headers(),
+                    reset_on_success_header.as_ref(),
+                    false,
+                    reset_queue.as_deref(),
+                    seen_count.filter(|_| reset_if_unchanged),
+                )
+                .await;
+            } else {
+                debug!("[middleware.rs] (unified) Success predicate rejected response, not resetting key: {:?}, request_id={:?}", rate_limit_context.key, request_id);
+            }
             debug!("[middleware.rs] (unified) Returning final response");
             Ok(response_with_headers)
-        })
+        }.instrument(span))
     }
 }
@@ -0,0 +1,175 @@
+use axum::body::Body;
+use axum::extract::{OriginalUri, Request};
+use axum::http::Response;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::{Layer, Service};
+use tracing::debug;
+
+use crate::clock::{Clock, SystemClock};
+use crate::error::BarnacleError;
+use crate::middleware::{get_fallback_key_common, insert_rate_limit_headers};
+use crate::types::{ApiKeyConfig, BarnacleConfig, BarnacleContext, BarnacleKey, BarnacleResult};
+use crate::BarnacleStore;
+
+/// Metering layer: counts requests and adds the `X-RateLimit-*` headers like
+/// `BarnacleLayer`, but never rejects a request. Requests over the limit get
+/// `X-RateLimit-Remaining: 0`; store errors only skip the headers.
+///
+/// Requests are keyed by the API key header when present, otherwise by client IP.
+pub struct ObserveOnlyLayer<S> {
+    store: S,
+    config: BarnacleConfig,
+    api_key_config: ApiKeyConfig,
+    clock: Arc<dyn Clock>,
+}
+
+impl<S> ObserveOnlyLayer<S>
+where
+    S: BarnacleStore + 'static,
+{
+    pub fn new(store: S, config: BarnacleConfig) -> Self {
+        Self {
+            store,
+            config,
+            api_key_config: ApiKeyConfig::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_api_key_config(mut self, config: ApiKeyConfig) -> Self {
+        self.api_key_config = config;
+        self
+    }
+
+    /// Clock used for `X-RateLimit-Reset-At`. Defaults to `SystemClock`.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+}
+
+impl<S> Clone for ObserveOnlyLayer<S>
+where
+    S: BarnacleStore + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            config: self.config.clone(),
+            api_key_config: self.api_key_config.clone(),
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+impl<Inner, S> Layer<Inner> for ObserveOnlyLayer<S>
+where
+    S: BarnacleStore + 'static,
+{
+    type Service = ObserveOnlyMiddleware<Inner, S>;
+    fn layer(&self, inner: Inner) -> Self::Service {
+        ObserveOnlyMiddleware {
+            inner,
+            store: self.store.clone(),
+            config: self.config.clone(),
+            api_key_config: self.api_key_config.clone(),
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+/// Middleware created by `ObserveOnlyLayer`
+pub struct ObserveOnlyMiddleware<Inner, S> {
+    inner: Inner,
+    store: S,
+    config: BarnacleConfig,
+    api_key_config: ApiKeyConfig,
+    clock: Arc<dyn Clock>,
+}
+
+impl<Inner, S> Clone for ObserveOnlyMiddleware<Inner, S>
+where
+    Inner: Clone,
+    S: BarnacleStore + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            store: self.store.clone(),
+            config: self.config.clone(),
+            api_key_config: self.api_key_config.clone(),
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+impl<Inner, B, S> Service<Request<B>> for ObserveOnlyMiddleware<Inner, S>
+where
+    Inner: Service<Request<B>, Response = Response<Body>> + Clone + Send + 'static,
+    Inner::Future: Send + 'static,
+    B: Send + 'static,
+    S: BarnacleStore + 'static,
+{
+    type Response = Inner::Response;
+    type Error = Inner::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let store = self.store.clone();
+        let config = self.config.clone();
+        let clock = self.clock.clone();
+        let path = req
+            .extensions()
+            .get::<OriginalUri>()
+            .map(|original_url| original_url.path().to_owned())
+            .unwrap_or(req.uri().path().to_owned());
+        let key = match req
+            .headers()
+            .get(self.api_key_config.header_name.as_str())
+            .and_then(|h| h.to_str().ok())
+            .filter(|api_key| !api_key.is_empty())
+        {
+            Some(api_key) => BarnacleKey::ApiKey(api_key.to_string()),
+            None => get_fallback_key_common(req.extensions(), req.headers(), &path, req.method()),
+        };
+        let context = BarnacleContext {
+            key,
+            path,
+            method: req.method().as_str().to_string(),
+        };
+        Box::pin(async move {
+            let result = match store.increment(&context, &config).await {
+                Ok(result) => Some(result),
+                Err(BarnacleError::RateLimitExceeded { retry_after, .. }) => {
+                    debug!("[observe_only.rs] Notional limit exceeded for key: {:?}", context.key);
+                    Some(BarnacleResult {
+                        allowed: false,
+                        remaining: 0,
+                        retry_after: Some(Duration::from_secs(retry_after)),
+                        reset_after: Some(Duration::from_secs(retry_after)),
+                        first_seen: None,
+                        window_reset: None,
+                    })
+                }
+                Err(e) => {
+                    debug!("[observe_only.rs] Store error while metering: {}", e);
+                    None
+                }
+            };
+            let mut response = inner.call(req).await?;
+            if let Some(result) = result.as_ref() {
+                insert_rate_limit_headers(response.headers_mut(), result, config.max_requests, clock.now());
+            }
+            Ok(response)
+        })
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::error::BarnacleError;
+use crate::types::{BarnacleContext, BarnacleResult};
+
+/// Hooks called by the middleware as it rate limits requests, e.g. to feed metrics.
+///
+/// The context tells which key and path a call is about; `context.key.scope()` gives the
+/// key type. Every method does nothing by default. Hooks run inline on the request path,
+/// so keep them cheap.
+pub trait RateLimitObserver: Send + Sync {
+    /// A request was counted and let through
+    fn on_allowed(&self, context: &BarnacleContext, result: &BarnacleResult) {
+        let _ = (context, result);
+    }
+
+    /// A request was rejected for being over a limit
+    fn on_blocked(&self, context: &BarnacleContext, error: &BarnacleError) {
+        let _ = (context, error);
+    }
+
+    /// The store failed while counting a request
+    fn on_store_error(&self, context: &BarnacleContext, error: &BarnacleError) {
+        let _ = (context, error);
+    }
+
+    /// How long the store took to count a request, whatever the outcome
+    fn on_store_latency(&self, context: &BarnacleContext, latency: Duration) {
+        let _ = (context, latency);
+    }
+}
+
+/// Observer that ignores everything
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopObserver;
+
+impl RateLimitObserver for NoopObserver {}
+
+/// Observer counting calls across all keys and paths, for tests and simple health reporting
+#[derive(Debug, Default)]
+pub struct CountingObserver {
+    allowed: AtomicU64,
+    blocked: AtomicU64,
+    store_errors: AtomicU64,
+    store_calls: AtomicU64,
+    store_latency_micros: AtomicU64,
+}
+
+impl CountingObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allowed(&self) -> u64 {
+        self.allowed.load(Ordering::Relaxed)
+    }
+
+    pub fn blocked(&self) -> u64 {
+        self.blocked.load(Ordering::Relaxed)
+    }
+
+    pub fn store_errors(&self) -> u64 {
+        self.store_errors.load(Ordering::Relaxed)
+    }
+
+    /// Number of store calls whose latency was reported
+    pub fn store_calls(&self) -> u64 {
+        self.store_calls.load(Ordering::Relaxed)
+    }
+
+    /// Total time spent in the store over all reported calls
+    pub fn store_latency(&self) -> Duration {
+        Duration::from_micros(self.store_latency_micros.load(Ordering::Relaxed))
+    }
+}
+
+impl RateLimitObserver for CountingObserver {
+    fn on_allowed(&self, _context: &BarnacleContext, _result: &BarnacleResult) {
+        self.allowed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_blocked(&self, _context: &BarnacleContext, _error: &BarnacleError) {
+        self.blocked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_store_error(&self, _context: &BarnacleContext, _error: &BarnacleError) {
+        self.store_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_store_latency(&self, _context: &BarnacleContext, latency: Duration) {
+        self.store_calls.fetch_add(1, Ordering::Relaxed);
+        self.store_latency_micros
+            .fetch_add(latency.as_micros().min(u128::from(u64::MAX)) as u64, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use prometheus::{Histogram, HistogramOpts, IntCounterVec, Opts, Registry};
+
+use crate::error::BarnacleError;
+use crate::observer::RateLimitObserver;
+use crate::types::{BarnacleContext, BarnacleResult};
+
+/// Observer exporting rate limiting metrics to a Prometheus registry:
+///
+/// - `barnacle_requests_total{result}`: requests by outcome, `allowed`, `blocked` or `store_error`
+/// - `barnacle_blocked_total{key_type}`: blocked requests by key type (`ip`, `api-key`, ...)
+/// - `barnacle_store_latency_seconds`: time the store took to count a request
+///
+/// Paths are deliberately not used as labels, to keep cardinality bounded.
+#[derive(Clone)]
+pub struct PrometheusObserver {
+    requests: IntCounterVec,
+    blocked: IntCounterVec,
+    store_latency: Histogram,
+}
+
+impl PrometheusObserver {
+    /// Creates the metrics and registers them with `registry`
+    pub fn new(registry: &Registry) -> Result<Self, prometheus::Error> {
+        let requests = IntCounterVec::new(
+            Opts::new("barnacle_requests_total", "Requests seen by the rate limiter, by result"),
+            &["result"],
+        )?;
+        let blocked = IntCounterVec::new(
+            Opts::new("barnacle_blocked_total", "Requests rejected by the rate limiter, by key type"),
+            &["key_type"],
+        )?;
+        let store_latency = Histogram::with_opts(HistogramOpts::new(
+            "barnacle_store_latency_seconds",
+            "Time the rate limit store took to count a request",
+        ))?;
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(blocked.clone()))?;
+        registry.register(Box::new(store_latency.clone()))?;
+        Ok(Self {
+            requests,
+            blocked,
+            store_latency,
+        })
+    }
+}
+
+impl RateLimitObserver for PrometheusObserver {
+    fn on_allowed(&self, _context: &BarnacleContext, _result: &BarnacleResult) {
+        self.requests.with_label_values(&["allowed"]).inc();
+    }
+
+    fn on_blocked(&self, context: &BarnacleContext, _error: &BarnacleError) {
+        self.requests.with_label_values(&["blocked"]).inc();
+        self.blocked.with_label_values(&[context.key.scope()]).inc();
+    }
+
+    fn on_store_error(&self, _context: &BarnacleContext, _error: &BarnacleError) {
+        self.requests.with_label_values(&["store_error"]).inc();
+    }
+
+    fn on_store_latency(&self, _context: &BarnacleContext, latency: Duration) {
+        self.store_latency.observe(latency.as_secs_f64());
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use crate::{
+    error::BarnacleError,
+    types::{BarnacleConfig, BarnacleContext, BarnacleResult},
+    BarnacleStore,
+};
+
+/// Outcome of counting a request with `RateLimiter::evaluate`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RateLimitDecision {
+    /// The request was counted and may proceed
+    Allowed {
+        remaining: u32,
+        /// Time until the quota is fully restored, when the store knows it
+        reset: Option<Duration>,
+    },
+    /// The request is over the limit and was not counted
+    Throttled { retry_after: Duration, limit: u32 },
+}
+
+impl RateLimitDecision {
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, RateLimitDecision::Allowed { .. })
+    }
+}
+
+/// Rate limiting outside the middleware, e.g. in a job queue or a gRPC handler.
+///
+/// Wraps a store so callers can branch on a `RateLimitDecision` instead of matching
+/// `BarnacleError::RateLimitExceeded`; only store failures are returned as errors.
+#[derive(Clone)]
+pub struct RateLimiter<S> {
+    store: S,
+}
+
+impl<S: BarnacleStore> RateLimiter<S> {
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+
+    /// The wrapped store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Counts a request for `context` against `config`
+    pub async fn evaluate(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<RateLimitDecision, BarnacleError> {
+        match self.store.increment(context, config).await {
+            Ok(result) => Ok(decision(result, config)),
+            Err(BarnacleError::RateLimitExceeded { retry_after, limit, .. }) => Ok(RateLimitDecision::Throttled {
+                retry_after: Duration::from_secs(retry_after),
+                limit,
+            }),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn decision(result: BarnacleResult, config: &BarnacleConfig) -> RateLimitDecision {
+    if result.allowed {
+        RateLimitDecision::Allowed {
+            remaining: result.remaining,
+            reset: result.reset_after.or(result.retry_after),
+        }
+    } else {
+        RateLimitDecision::Throttled {
+            retry_after: result.retry_after.unwrap_or(config.window),
+            limit: config.max_requests,
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::cluster::ClusterClient;
+use redis::cluster_async::ClusterConnection;
+use redis::{cmd, AsyncCommands};
+use tokio::sync::OnceCell;
+
+use crate::{
+    error::BarnacleError,
+    redis_store::{
+        expire_seconds, hex_digest, key_kind_and_id, ACQUIRE_IN_FLIGHT_SCRIPT, DECREMENT_SCRIPT, DEFAULT_KEY_PREFIX,
+        RELEASE_IN_FLIGHT_SCRIPT, RESET_IF_BELOW_SCRIPT,
+    },
+    types::{BarnacleConfig, BarnacleContext, BarnacleResult, KeyUsage},
+    BarnacleStore,
+};
+
+/// Checks and counts `ARGV[1]` units against a limit of `ARGV[2]` in one step, starting a
+/// window of `ARGV[3]` seconds. A value that isn't a number is dropped as no counter.
+/// Returns {allowed, count, ttl}. KEYS[1] = counter key
+const INCREMENT_SCRIPT: &str = r#"
+local raw = redis.call('GET', KEYS[1])
+local current = tonumber(raw)
+if raw and not current then
+    redis.call('DEL', KEYS[1])
+end
+current = current or 0
+local ttl = redis.call('TTL', KEYS[1])
+if current > 0 and ttl < 0 then
+    redis.call('EXPIRE', KEYS[1], ARGV[3])
+    ttl = tonumber(ARGV[3])
+end
+if current + tonumber(ARGV[1]) > tonumber(ARGV[2]) then
+    return {0, current, ttl}
+end
+local count = redis.call('INCRBY', KEYS[1], ARGV[1])
+ttl = redis.call('TTL', KEYS[1])
+if ttl < 0 then
+    redis.call('EXPIRE', KEYS[1], ARGV[3])
+    ttl = tonumber(ARGV[3])
+end
+return {1, count, ttl}
+"#;
+
+/// Fixed-window store for Redis Cluster, counting like `RedisBarnacleStore`.
+///
+/// Every operation touches a single counter, so keys may live on any node. The variable
+/// part of each key is wrapped in a `{...}` hash tag, e.g.
+/// `barnacle:email:{user@example.com:POST:/login}`, so a counter and its metadata hash
+/// share a slot and can be updated together. Multi-key commands over different counters
+/// (such as a batch reset) would cross slots; `reset_many` therefore resets one key at a
+/// time. Anything else writing several keys in one command must use a shared hash tag.
+#[derive(Clone)]
+pub struct RedisClusterBarnacleStore {
+    client: ClusterClient,
+    connection: std::sync::Arc<OnceCell<ClusterConnection>>,
+    key_prefix: String,
+}
+
+impl RedisClusterBarnacleStore {
+    /// Creates a store for the cluster reachable at any of `urls`. Connects on first use.
+    pub fn from_urls(urls: &[&str]) -> Result<Self, BarnacleError> {
+        let client = ClusterClient::new(urls.to_vec()).map_err(|e| {
+            BarnacleError::store_error_with_source("Invalid Redis Cluster configuration", Box::new(e))
+        })?;
+        Ok(Self {
+            client,
+            connection: Default::default(),
+            key_prefix: DEFAULT_KEY_PREFIX.to_string(),
+        })
+    }
+
+    /// Namespace all keys under `prefix` instead of `barnacle`
+    pub fn with_key_prefix(mut self, prefix: String) -> Self {
+        self.key_prefix = prefix;
+        self
+    }
+
+    /// The Redis key holding the counter for a context
+    pub fn key_for(&self, context: &BarnacleContext) -> String {
+        let (kind, id) = key_kind_and_id(&context.key);
+        format!("{}:{}:{{{}:{}:{}}}", self.key_prefix, kind, id, context.method, context.path)
+    }
+
+    fn metadata_key(&self, context: &BarnacleContext) -> String {
+        format!("{}:meta", self.key_for(context))
+    }
+
+    fn in_flight_key(&self, context: &BarnacleContext) -> String {
+        format!("{}:in_flight", self.key_for(context))
+    }
+
+    fn idempotency_key(&self, context: &BarnacleContext, idempotency_key: &str) -> String {
+        format!("{}:idempotency:{}", self.key_for(context), hex_digest(idempotency_key))
+    }
+
+    async fn connection(&self) -> Result<ClusterConnection, BarnacleError> {
+        self.connection
+            .get_or_try_init(|| self.client.get_async_connection())
+            .await
+            .cloned()
+            .map_err(|e| BarnacleError::connection_pool_error("Failed to connect to Redis Cluster", Box::new(e)))
+    }
+}
+
+#[async_trait]
+impl BarnacleStore for RedisClusterBarnacleStore {
+    async fn increment(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        self.increment_by(context, config, 1).await
+    }
+
+    async fn increment_by(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        cost: u32,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let redis_key = self.key_for(context);
+        let window_seconds = expire_seconds(config.window)?;
+        let mut conn = self.connection().await?;
+
+        let (allowed, count, ttl): (i32, u32, i64) = cmd("EVAL")
+            .arg(INCREMENT_SCRIPT)
+            .arg(1)
+            .arg(&redis_key)
+            .arg(cost.max(1))
+            .arg(config.max_requests)
+            .arg(window_seconds)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis Cluster increment operation failed", Box::new(e))
+            })?;
+
+        let ttl = if ttl > 0 {
+            Duration::from_secs(ttl as u64)
+        } else {
+            config.window
+        };
+        tracing::debug!("Cluster rate limit increment for key: {}, count: {}, allowed: {}", redis_key, count, allowed == 1);
+
+        if allowed != 1 {
+            return Err(BarnacleError::rate_limit_exceeded(
+                config.max_requests.saturating_sub(count),
+                ttl.as_secs(),
+                config.max_requests,
+            ));
+        }
+
+        Ok(BarnacleResult {
+            allowed: true,
+            remaining: config.max_requests.saturating_sub(count),
+            retry_after: None,
+            reset_after: Some(ttl),
+            first_seen: None,
+            window_reset: None,
+        })
+    }
+
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let mut conn = self.connection().await?;
+        // Same hash tag, same slot
+        let _: () = conn
+            .del(&[self.key_for(context), self.metadata_key(context)])
+            .await
+            .map_err(|e| BarnacleError::store_error_with_source("Failed to delete key from Redis Cluster", Box::new(e)))?;
+        Ok(())
+    }
+
+    async fn reset_if_below(&self, context: &BarnacleContext, threshold: u32) -> Result<bool, BarnacleError> {
+        let mut conn = self.connection().await?;
+        let reset: i32 = cmd("EVAL")
+            .arg(RESET_IF_BELOW_SCRIPT)
+            .arg(2)
+            .arg(self.key_for(context))
+            .arg(self.metadata_key(context))
+            .arg(threshold)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis Cluster conditional reset failed", Box::new(e))
+            })?;
+        Ok(reset == 1)
+    }
+
+    async fn peek(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let usage = self.usage_for_key(context).await?;
+        Ok(BarnacleResult {
+            allowed: usage.count < config.max_requests,
+            remaining: config.max_requests.saturating_sub(usage.count),
+            retry_after: None,
+            reset_after: usage.retry_after,
+            first_seen: None,
+            window_reset: None,
+        })
+    }
+
+    async fn usage_for_key(&self, context: &BarnacleContext) -> Result<KeyUsage, BarnacleError> {
+        let redis_key = self.key_for(context);
+        let mut conn = self.connection().await?;
+
+        let value: Option<String> = conn.get(&redis_key).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Redis Cluster get operation failed", Box::new(e))
+        })?;
+        let ttl: i64 = conn.ttl(&redis_key).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Redis Cluster TTL operation failed", Box::new(e))
+        })?;
+        let metadata: HashMap<String, String> = conn.hgetall(self.metadata_key(context)).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Redis Cluster HGETALL operation failed", Box::new(e))
+        })?;
+
+        Ok(KeyUsage {
+            count: value.and_then(|value| value.trim().parse().ok()).unwrap_or(0),
+            retry_after: (ttl > 0).then(|| Duration::from_secs(ttl as u64)),
+            metadata,
+        })
+    }
+
+    async fn decrement(&self, context: &BarnacleContext, n: u32) -> Result<(), BarnacleError> {
+        let mut conn = self.connection().await?;
+        let _: i64 = cmd("EVAL")
+            .arg(DECREMENT_SCRIPT)
+            .arg(1)
+            .arg(self.key_for(context))
+            .arg(n)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis Cluster decrement operation failed", Box::new(e))
+            })?;
+        Ok(())
+    }
+
+    async fn idempotency_key_seen(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<bool, BarnacleError> {
+        let mut conn = self.connection().await?;
+        conn.exists(self.idempotency_key(context, idempotency_key)).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Redis Cluster EXISTS operation failed", Box::new(e))
+        })
+    }
+
+    async fn record_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<(), BarnacleError> {
+        let mut conn = self.connection().await?;
+        conn.set_ex::<_, _, ()>(self.idempotency_key(context, idempotency_key), 1, ttl.as_secs().max(1))
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Failed to record idempotency key", Box::new(e))
+            })
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        let mut conn = self.connection().await?;
+        // SET NX replies nil when the key already exists
+        let claimed: Option<String> = cmd("SET")
+            .arg(self.idempotency_key(context, idempotency_key))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl.as_secs().max(1))
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Failed to claim idempotency key", Box::new(e))
+            })?;
+        Ok(claimed.is_some())
+    }
+
+    async fn release_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<(), BarnacleError> {
+        let mut conn = self.connection().await?;
+        conn.del::<_, ()>(self.idempotency_key(context, idempotency_key)).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Failed to release idempotency key", Box::new(e))
+        })
+    }
+
+    async fn acquire_in_flight(
+        &self,
+        context: &BarnacleContext,
+        max_in_flight: u32,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        let mut conn = self.connection().await?;
+        let acquired: i32 = cmd("EVAL")
+            .arg(ACQUIRE_IN_FLIGHT_SCRIPT)
+            .arg(1)
+            .arg(self.in_flight_key(context))
+            .arg(max_in_flight)
+            .arg(ttl.as_secs().max(1))
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis Cluster in-flight acquire failed", Box::new(e))
+            })?;
+        Ok(acquired == 1)
+    }
+
+    async fn release_in_flight(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let mut conn = self.connection().await?;
+        let _: i64 = cmd("EVAL")
+            .arg(RELEASE_IN_FLIGHT_SCRIPT)
+            .arg(1)
+            .arg(self.in_flight_key(context))
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis Cluster in-flight release failed", Box::new(e))
+            })?;
+        Ok(())
+    }
+}
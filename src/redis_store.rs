@@ -1,4 +1,8 @@
 #[cfg(feature = "redis")]
+use std::borrow::Cow;
+#[cfg(feature = "redis")]
+use std::collections::HashMap;
+#[cfg(feature = "redis")]
 use std::sync::Arc;
 #[cfg(feature = "redis")]
 use std::time::Duration;
@@ -8,43 +12,343 @@ use async_trait::async_trait;
 #[cfg(feature = "redis")]
 use deadpool_redis::redis::AsyncCommands;
 #[cfg(feature = "redis")]
+use deadpool_redis::redis::cmd;
+#[cfg(feature = "redis")]
 use deadpool_redis::{Connection, Pool};
+#[cfg(feature = "redis")]
+use sha2::{Digest, Sha256};
 
 use crate::{
     error::BarnacleError,
-    types::{BarnacleConfig, BarnacleContext, BarnacleKey, BarnacleResult},
-    BarnacleStore, BARNACLE_API_KEY_PREFIX, BARNACLE_CUSTOM_PREFIX, BARNACLE_EMAIL_KEY_PREFIX,
-    BARNACLE_IP_PREFIX,
+    types::{BarnacleConfig, BarnacleContext, BarnacleKey, BarnacleResult, KeyUsage},
+    BarnacleStore,
 };
+#[cfg(feature = "redis")]
+use crate::backoff::{next_backoff, retry_with_backoff, Backoff};
+#[cfg(feature = "redis")]
+use crate::types::render_composite_key;
+
+/// Takes an in-flight slot unless `max_in_flight` are already taken, refreshing the safety TTL.
+/// KEYS[1] = in-flight key, ARGV[1] = max_in_flight, ARGV[2] = ttl in seconds
+#[cfg(feature = "redis")]
+pub(crate) const ACQUIRE_IN_FLIGHT_SCRIPT: &str = r#"
+local current = redis.call('INCR', KEYS[1])
+redis.call('EXPIRE', KEYS[1], ARGV[2])
+if current > tonumber(ARGV[1]) then
+    redis.call('DECR', KEYS[1])
+    return 0
+end
+return 1
+"#;
+
+/// Releases an in-flight slot, deleting the counter instead of letting it go below zero.
+/// KEYS[1] = in-flight key
+#[cfg(feature = "redis")]
+pub(crate) const RELEASE_IN_FLIGHT_SCRIPT: &str = r#"
+local current = redis.call('DECR', KEYS[1])
+if current <= 0 then
+    redis.call('DEL', KEYS[1])
+    return 0
+end
+return current
+"#;
+
+/// Decrements a counter by up to ARGV[1] without going below zero. DECRBY keeps the TTL.
+/// KEYS[1] = counter key, ARGV[1] = amount
+#[cfg(feature = "redis")]
+pub(crate) const DECREMENT_SCRIPT: &str = r#"
+local current = tonumber(redis.call('GET', KEYS[1]) or '0')
+if current <= 0 then
+    return 0
+end
+return redis.call('DECRBY', KEYS[1], math.min(tonumber(ARGV[1]), current))
+"#;
+
+/// Deletes the counter and its metadata if the count is at most ARGV[1].
+/// KEYS[1] = counter key, KEYS[2] = metadata key, ARGV[1] = threshold
+#[cfg(feature = "redis")]
+pub(crate) const RESET_IF_BELOW_SCRIPT: &str = r#"
+local current = tonumber(redis.call('GET', KEYS[1]) or '0')
+if current > tonumber(ARGV[1]) then
+    return 0
+end
+redis.call('DEL', KEYS[1], KEYS[2])
+return 1
+"#;
+
+/// Stores metadata fields in a hash that expires together with the counter.
+/// KEYS[1] = counter key, KEYS[2] = metadata key, ARGV = field, value, field, value, ...
+#[cfg(feature = "redis")]
+const STORE_METADATA_SCRIPT: &str = r#"
+redis.call('HSET', KEYS[2], unpack(ARGV))
+local ttl = redis.call('PTTL', KEYS[1])
+if ttl > 0 then
+    redis.call('PEXPIRE', KEYS[2], ttl)
+end
+return 1
+"#;
+
+/// Largest TTL Redis accepts for `EXPIRE`; it converts seconds to milliseconds internally.
+#[cfg(feature = "redis")]
+const MAX_EXPIRE_SECONDS: u64 = (i64::MAX / 1000) as u64;
+
+/// Converts a window into `EXPIRE` seconds, rejecting values Redis would refuse or
+/// that would expire the counter immediately.
+#[cfg(feature = "redis")]
+pub(crate) fn expire_seconds(window: Duration) -> Result<i64, BarnacleError> {
+    let seconds = window.as_secs();
+    if seconds == 0 || seconds > MAX_EXPIRE_SECONDS {
+        return Err(BarnacleError::configuration_error(format!(
+            "Rate limit window of {}s is outside the range Redis accepts for EXPIRE (1..={}s)",
+            seconds, MAX_EXPIRE_SECONDS
+        )));
+    }
+    Ok(seconds as i64)
+}
+
+/// Reads a counter, treating a value that isn't a non-negative integer (e.g. a key seeded
+/// by hand) as no counter: the key is deleted so the next `INCR` starts a fresh window.
+#[cfg(feature = "redis")]
+async fn read_count(conn: &mut Connection, redis_key: &str) -> Result<u32, BarnacleError> {
+    let value: Option<String> = conn.get(redis_key).await.map_err(|e| {
+        BarnacleError::store_error_with_source("Redis get operation failed", Box::new(e))
+    })?;
+    let Some(value) = value else {
+        return Ok(0);
+    };
+    if let Ok(count) = value.trim().parse::<u32>() {
+        return Ok(count);
+    }
+
+    tracing::warn!("Counter {} holds non-numeric value {:?}, re-initializing it", redis_key, value);
+    let _: () = conn.del(redis_key).await.map_err(|e| {
+        BarnacleError::store_error_with_source("Failed to delete invalid counter", Box::new(e))
+    })?;
+    Ok(0)
+}
+
+/// Key type and identifier of a key, as they appear in Redis key names
+#[cfg(feature = "redis")]
+pub(crate) fn key_kind_and_id(key: &BarnacleKey) -> (&'static str, Cow<'_, str>) {
+    match key {
+        BarnacleKey::Email(email) => ("email", Cow::Borrowed(email)),
+        BarnacleKey::ApiKey(api_key) => ("api_keys", Cow::Borrowed(api_key)),
+        BarnacleKey::Ip(ip) => ("ip", Cow::Borrowed(ip)),
+        BarnacleKey::Custom(custom_data) => ("custom", Cow::Borrowed(custom_data)),
+        BarnacleKey::Composite(parts) => ("composite", Cow::Owned(render_composite_key(parts))),
+    }
+}
+
+/// Lowercase hex SHA-256 of `value`, for fixed-length key names
+#[cfg(feature = "redis")]
+pub(crate) fn hex_digest(value: &str) -> String {
+    Sha256::digest(value.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Namespace of the keys written by `RedisBarnacleStore` unless `with_key_prefix` changes it
+#[cfg(feature = "redis")]
+pub(crate) const DEFAULT_KEY_PREFIX: &str = "barnacle";
 
 #[cfg(feature = "redis")]
+#[derive(Clone)]
 struct RedisBarnacleStoreInner {
     pool: Pool,
+    hash_keys: bool,
+    key_prefix: String,
+    violation_backoff: Option<Backoff>,
+    connection_retries: u32,
+    connection_backoff: Backoff,
+    fail_open: bool,
 }
 
 #[cfg(feature = "redis")]
 impl RedisBarnacleStoreInner {
     fn new(pool: Pool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            hash_keys: false,
+            key_prefix: DEFAULT_KEY_PREFIX.to_string(),
+            violation_backoff: None,
+            connection_retries: 0,
+            connection_backoff: Backoff::default(),
+            fail_open: false,
+        }
     }
 
     async fn get_connection(&self) -> Result<Connection, deadpool_redis::PoolError> {
-        self.pool.get().await
+        retry_with_backoff(self.connection_retries, &self.connection_backoff, || self.pool.get()).await
+    }
+
+    /// Counts a rejection and extends the block on the counter to the backoff for the
+    /// violation count if that is longer than `retry_after`, returning the resulting wait
+    async fn record_violation(
+        &self,
+        conn: &mut Connection,
+        context: &BarnacleContext,
+        redis_key: &str,
+        backoff: &Backoff,
+        retry_after: Duration,
+        window: Duration,
+    ) -> Result<Duration, BarnacleError> {
+        let violations_key = self.get_violations_key(context);
+        let violations: u32 = conn.incr(&violations_key, 1).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Redis violation count failed", Box::new(e))
+        })?;
+
+        // Whole seconds, as `retry_after` is reported in seconds
+        let delay = next_backoff(violations.saturating_sub(1), backoff);
+        let delay = Duration::from_secs(delay.as_secs() + u64::from(delay.subsec_nanos() > 0));
+        let blocked_for = retry_after.max(delay).min(Duration::from_secs(MAX_EXPIRE_SECONDS));
+        if blocked_for > retry_after {
+            let _: () = conn.expire(redis_key, blocked_for.as_secs() as i64).await.map_err(|e| {
+                BarnacleError::store_error_with_source("Redis EXPIRE operation failed", Box::new(e))
+            })?;
+        }
+        let forget_after = blocked_for.saturating_add(window).as_secs().min(MAX_EXPIRE_SECONDS);
+        let _: () = conn.expire(&violations_key, forget_after as i64).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Redis EXPIRE operation failed", Box::new(e))
+        })?;
+
+        tracing::debug!("Violation {} for key: {}, blocked for {}s", violations, redis_key, blocked_for.as_secs());
+        Ok(blocked_for)
     }
 
     fn get_redis_key(&self, context: &BarnacleContext) -> String {
-        let base_key = match &context.key {
-            BarnacleKey::Email(email) => format!("{BARNACLE_EMAIL_KEY_PREFIX}:{}", email),
-            BarnacleKey::ApiKey(api_key) => format!("{BARNACLE_API_KEY_PREFIX}:{}", api_key),
-            BarnacleKey::Ip(ip) => format!("{BARNACLE_IP_PREFIX}:{}", ip),
-            BarnacleKey::Custom(custom_data) => format!("{BARNACLE_CUSTOM_PREFIX}:{}", custom_data),
-        };
+        let (kind, id) = key_kind_and_id(&context.key);
+        let prefix = format!("{}:{}", self.key_prefix, kind);
 
         // Include path and method in the Redis key
-        let redis_key = format!("{}:{}:{}", base_key, context.method, context.path);
+        let variable_part = format!("{}:{}:{}", id, context.method, context.path);
+        let redis_key = if self.hash_keys {
+            // Fixed-length key, the prefix stays readable for namespacing
+            format!("{}:{}", prefix, hex_digest(&variable_part))
+        } else {
+            format!("{}:{}", prefix, variable_part)
+        };
         tracing::debug!("[redis_store.rs] get_redis_key: redis_key='{}', key={:?}, method={}, path={}", redis_key, context.key, context.method, context.path);
         redis_key
     }
+
+    fn get_metadata_key(&self, context: &BarnacleContext) -> String {
+        format!("{}:meta", self.get_redis_key(context))
+    }
+
+    fn get_violations_key(&self, context: &BarnacleContext) -> String {
+        format!("{}:violations", self.get_redis_key(context))
+    }
+
+    fn get_in_flight_key(&self, context: &BarnacleContext) -> String {
+        format!("{}:in_flight", self.get_redis_key(context))
+    }
+
+    /// The client-supplied idempotency key is always hashed, bounding the key length
+    fn get_idempotency_key(&self, context: &BarnacleContext, idempotency_key: &str) -> String {
+        format!("{}:idempotency:{}", self.get_redis_key(context), hex_digest(idempotency_key))
+    }
+}
+
+#[cfg(feature = "redis")]
+fn create_pool(url: &str, max_size: Option<usize>) -> Result<Pool, deadpool_redis::PoolError> {
+    let mut cfg = deadpool_redis::Config::from_url(url);
+    if let Some(max_size) = max_size {
+        cfg.pool = Some(deadpool_redis::PoolConfig {
+            max_size,
+            ..Default::default()
+        });
+    }
+    cfg.create_pool(Some(deadpool_redis::Runtime::Tokio1)).map_err(|e| {
+        deadpool_redis::PoolError::Backend(deadpool_redis::redis::RedisError::from(
+            std::io::Error::new(std::io::ErrorKind::Other, e),
+        ))
+    })
+}
+
+/// Builder for `RedisBarnacleStore`, gathering its connection and key options in one place.
+///
+/// Needs either `url` (optionally with `max_size`) or an existing `pool`.
+#[cfg(feature = "redis")]
+#[derive(Default)]
+pub struct RedisBarnacleStoreBuilder {
+    url: Option<String>,
+    pool: Option<Pool>,
+    max_size: Option<usize>,
+    key_prefix: Option<String>,
+    key_hashing: bool,
+    connection_retry: Option<(u32, Backoff)>,
+    fail_open: bool,
+}
+
+#[cfg(feature = "redis")]
+impl RedisBarnacleStoreBuilder {
+    /// Connect to the Redis at `url`
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Use an existing pool instead of a URL
+    pub fn pool(mut self, pool: Pool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Size of the pool created from `url`
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// See `RedisBarnacleStore::with_key_prefix`
+    pub fn key_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.key_prefix = Some(prefix.into());
+        self
+    }
+
+    /// See `RedisBarnacleStore::with_key_hashing`
+    pub fn key_hashing(mut self, enabled: bool) -> Self {
+        self.key_hashing = enabled;
+        self
+    }
+
+    /// See `RedisBarnacleStore::with_connection_retry`
+    pub fn connection_retry(mut self, retries: u32, backoff: Backoff) -> Self {
+        self.connection_retry = Some((retries, backoff));
+        self
+    }
+
+    /// Let requests through when Redis fails while counting, instead of returning the
+    /// error. For use outside the middleware, e.g. with `RateLimiter`; layers should use
+    /// `BarnacleLayerBuilder::with_store_error_policy`.
+    pub fn fail_open(mut self) -> Self {
+        self.fail_open = true;
+        self
+    }
+
+    pub fn build(self) -> Result<RedisBarnacleStore, BarnacleError> {
+        let pool = match (self.pool, self.url) {
+            (Some(_), Some(_)) => {
+                return Err(BarnacleError::configuration_error("Set either a Redis URL or a pool, not both"))
+            }
+            (Some(_), None) if self.max_size.is_some() => {
+                return Err(BarnacleError::configuration_error("max_size only applies to a pool created from a URL"))
+            }
+            (Some(pool), None) => pool,
+            (None, Some(url)) => create_pool(&url, self.max_size)
+                .map_err(|e| BarnacleError::connection_pool_error("Failed to create Redis pool", Box::new(e)))?,
+            (None, None) => return Err(BarnacleError::configuration_error("Set a Redis URL or a pool")),
+        };
+        let mut inner = RedisBarnacleStoreInner::new(pool);
+        if let Some(prefix) = self.key_prefix {
+            inner.key_prefix = prefix;
+        }
+        inner.hash_keys = self.key_hashing;
+        if let Some((retries, backoff)) = self.connection_retry {
+            inner.connection_retries = retries;
+            inner.connection_backoff = backoff;
+        }
+        inner.fail_open = self.fail_open;
+        Ok(RedisBarnacleStore { inner: Arc::new(inner) })
+    }
 }
 
 /// Implementation of BarnacleStore using Redis with connection pooling.
@@ -64,51 +368,89 @@ impl RedisBarnacleStore {
         }
     }
 
+    /// Configure a store step by step, see `RedisBarnacleStoreBuilder`
+    pub fn builder() -> RedisBarnacleStoreBuilder {
+        RedisBarnacleStoreBuilder::default()
+    }
+
     /// Create a new Redis store from a Redis URL
     pub fn from_url(url: &str) -> Result<Self, deadpool_redis::PoolError> {
-        let cfg = deadpool_redis::Config::from_url(url);
-        let pool = cfg
-            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
-            .map_err(|e| {
-                deadpool_redis::PoolError::Backend(deadpool_redis::redis::RedisError::from(
-                    std::io::Error::new(std::io::ErrorKind::Other, e),
-                ))
-            })?;
-        Ok(Self::new(pool))
+        Ok(Self::new(create_pool(url, None)?))
     }
 
     /// Create a new Redis store with custom pool configuration
     pub fn with_pool_config(url: &str, max_size: usize) -> Result<Self, deadpool_redis::PoolError> {
-        let mut cfg = deadpool_redis::Config::from_url(url);
-        cfg.pool = Some(deadpool_redis::PoolConfig {
-            max_size,
-            ..Default::default()
-        });
-        let pool = cfg
-            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
-            .map_err(|e| {
-                deadpool_redis::PoolError::Backend(deadpool_redis::redis::RedisError::from(
-                    std::io::Error::new(std::io::ErrorKind::Other, e),
-                ))
-            })?;
-        Ok(Self::new(pool))
+        Ok(Self::new(create_pool(url, Some(max_size))?))
     }
-}
 
-#[cfg(feature = "redis")]
-#[async_trait]
-impl BarnacleStore for RedisBarnacleStore {
-    async fn increment(
+    /// Hash the variable part of every Redis key (key value, method and path) with
+    /// SHA-256, keeping the `barnacle:*` prefix. Bounds key size for long custom keys
+    /// at the cost of `redis-cli` readability, so it is off by default.
+    pub fn with_key_hashing(self, enabled: bool) -> Self {
+        Self {
+            inner: Arc::new(RedisBarnacleStoreInner {
+                hash_keys: enabled,
+                ..(*self.inner).clone()
+            }),
+        }
+    }
+
+    /// Namespace all keys under `prefix` instead of `barnacle`, e.g. `tenant-a` writes
+    /// `tenant-a:email:...`, so several deployments can share one Redis
+    pub fn with_key_prefix(self, prefix: String) -> Self {
+        Self {
+            inner: Arc::new(RedisBarnacleStoreInner {
+                key_prefix: prefix,
+                ..(*self.inner).clone()
+            }),
+        }
+    }
+
+    /// Retry getting a connection from the pool up to `retries` times, waiting `backoff`
+    /// between attempts, so a brief Redis blip doesn't fail every request in flight.
+    /// Retries add latency to each request while Redis is down; off by default.
+    pub fn with_connection_retry(self, retries: u32, backoff: Backoff) -> Self {
+        Self {
+            inner: Arc::new(RedisBarnacleStoreInner {
+                connection_retries: retries,
+                connection_backoff: backoff,
+                ..(*self.inner).clone()
+            }),
+        }
+    }
+
+    /// Count consecutive rejections per key and keep a key blocked for `next_backoff` of
+    /// its violation count when that outlasts the window, so clients that keep retrying
+    /// get an escalating `retry_after`. Violations are forgotten a window after the block
+    /// ends, or on reset.
+    pub fn with_violation_backoff(self, backoff: Backoff) -> Self {
+        Self {
+            inner: Arc::new(RedisBarnacleStoreInner {
+                violation_backoff: Some(backoff),
+                ..(*self.inner).clone()
+            }),
+        }
+    }
+
+    /// The Redis key holding the counter for a context
+    pub fn key_for(&self, context: &BarnacleContext) -> String {
+        self.inner.get_redis_key(context)
+    }
+
+    /// Counts `cost` units, see `BarnacleStore::increment_by`
+    async fn count(
         &self,
         context: &BarnacleContext,
         config: &BarnacleConfig,
+        cost: u32,
     ) -> Result<BarnacleResult, BarnacleError> {
         let redis_key = self.inner.get_redis_key(context);
-        let window_seconds = config.window.as_secs() as usize;
+        let window_seconds = expire_seconds(config.window)?;
 
         tracing::debug!(
-            "Rate limit increment for key: {}, max_requests: {}, window: {}s",
+            "Rate limit increment for key: {}, cost: {}, max_requests: {}, window: {}s",
             redis_key,
+            cost,
             config.max_requests,
             window_seconds
         );
@@ -119,15 +461,18 @@ impl BarnacleStore for RedisBarnacleStore {
         })?;
 
         // Get current count and TTL using individual commands
-        let current_count: Option<u32> = conn.get(&redis_key).await.map_err(|e| {
-            BarnacleError::store_error_with_source("Redis get operation failed", Box::new(e))
-        })?;
+        let current_count = read_count(&mut conn, &redis_key).await?;
 
         let ttl: i32 = conn.ttl(&redis_key).await.map_err(|e| {
             BarnacleError::store_error_with_source("Redis TTL operation failed", Box::new(e))
         })?;
 
-        let current_count = current_count.unwrap_or(0);
+        // A counter without TTL (-1) would never reset, e.g. when a connection died between
+        // INCR and EXPIRE; give it a fresh window instead of blocking the key forever
+        if current_count > 0 && ttl < 0 {
+            tracing::debug!("Counter {} has no TTL, restoring expiry of {}s", redis_key, window_seconds);
+            let _: Result<(), _> = conn.expire(&redis_key, window_seconds).await;
+        }
         let ttl = ttl.max(0) as u32;
 
         tracing::debug!(
@@ -137,14 +482,20 @@ impl BarnacleStore for RedisBarnacleStore {
             config.max_requests
         );
 
-        // Check if we're within the rate limit
-        if current_count >= config.max_requests {
+        // Check if the cost fits within the rate limit
+        if current_count.saturating_add(cost.max(1)) > config.max_requests {
             // Rate limit exceeded
-            let retry_after = if ttl > 0 {
+            let mut retry_after = if ttl > 0 {
                 Duration::from_secs(ttl as u64)
             } else {
                 config.window
             };
+            if let Some(backoff) = self.inner.violation_backoff.as_ref() {
+                retry_after = self
+                    .inner
+                    .record_violation(&mut conn, context, &redis_key, backoff, retry_after, config.window)
+                    .await?;
+            }
 
             tracing::debug!(
                 "Rate limit exceeded for key: {}, current: {}, max: {}, retry_after: {}s",
@@ -155,21 +506,28 @@ impl BarnacleStore for RedisBarnacleStore {
             );
 
             return Err(BarnacleError::rate_limit_exceeded(
-                0,
+                config.max_requests.saturating_sub(current_count),
                 retry_after.as_secs(),
                 config.max_requests,
             ));
         }
 
         // Increment the counter
-        let new_count: u32 = conn.incr(&redis_key, 1).await.map_err(|e| {
+        let new_count: u32 = conn.incr(&redis_key, cost).await.map_err(|e| {
             BarnacleError::store_error_with_source("Redis increment operation failed", Box::new(e))
         })?;
 
-        // Set expiration if this is the first increment
-        if new_count == 1 {
-            let _: Result<(), _> = conn.expire(&redis_key, window_seconds as i64).await;
-        }
+        // Ensure the window has an expiry. Checking the TTL rather than `new_count == 1` keeps
+        // this idempotent when concurrent first requests race between INCR and EXPIRE.
+        let ttl_after: i64 = conn.ttl(&redis_key).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Redis TTL operation failed", Box::new(e))
+        })?;
+        let reset_after = if ttl_after > 0 {
+            Duration::from_secs(ttl_after as u64)
+        } else {
+            let _: Result<(), _> = conn.expire(&redis_key, window_seconds).await;
+            Duration::from_secs(window_seconds as u64)
+        };
 
         let remaining = config.max_requests.saturating_sub(new_count);
 
@@ -184,8 +542,52 @@ impl BarnacleStore for RedisBarnacleStore {
             allowed: true,
             remaining,
             retry_after: None,
+            reset_after: Some(reset_after),
+            // An expired key is indistinguishable from one never seen
+            first_seen: None,
+            window_reset: None,
+        })
+    }
+
+    pub(crate) async fn connection(&self) -> Result<Connection, BarnacleError> {
+        self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
         })
     }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl BarnacleStore for RedisBarnacleStore {
+    async fn increment(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        self.increment_by(context, config, 1).await
+    }
+
+    async fn increment_by(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        cost: u32,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        match self.count(context, config, cost).await {
+            Err(e) if self.inner.fail_open && e.is_store_failure() => {
+                tracing::warn!("Redis unavailable, allowing request (fail open): {}", e);
+                Ok(BarnacleResult {
+                    allowed: true,
+                    remaining: config.max_requests,
+                    retry_after: None,
+                    reset_after: None,
+                    first_seen: None,
+                    window_reset: None,
+                })
+            }
+            outcome => outcome,
+        }
+    }
 
     async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
         let redis_key = self.inner.get_redis_key(context);
@@ -194,10 +596,312 @@ impl BarnacleStore for RedisBarnacleStore {
             BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
         })?;
 
-        let _: () = conn.del(&redis_key).await.map_err(|e| {
+        let metadata_key = self.inner.get_metadata_key(context);
+        let violations_key = self.inner.get_violations_key(context);
+        let _: () = conn.del(&[&redis_key, &metadata_key, &violations_key]).await.map_err(|e| {
             BarnacleError::connection_pool_error("Failed to delete key from Redis", Box::new(e))
         })?;
 
         Ok(())
     }
+
+    async fn reset_many(&self, contexts: &[BarnacleContext]) -> Result<(), BarnacleError> {
+        if contexts.is_empty() {
+            return Ok(());
+        }
+        let keys: Vec<String> = contexts
+            .iter()
+            .flat_map(|context| {
+                [
+                    self.inner.get_redis_key(context),
+                    self.inner.get_metadata_key(context),
+                    self.inner.get_violations_key(context),
+                ]
+            })
+            .collect();
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        let _: () = conn.del(&keys).await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to delete keys from Redis", Box::new(e))
+        })?;
+
+        Ok(())
+    }
+
+    async fn reset_if_below(&self, context: &BarnacleContext, threshold: u32) -> Result<bool, BarnacleError> {
+        let redis_key = self.inner.get_redis_key(context);
+        let metadata_key = self.inner.get_metadata_key(context);
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        let reset: i32 = cmd("EVAL")
+            .arg(RESET_IF_BELOW_SCRIPT)
+            .arg(2)
+            .arg(&redis_key)
+            .arg(&metadata_key)
+            .arg(threshold)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis conditional reset failed", Box::new(e))
+            })?;
+
+        tracing::debug!("Conditional reset for key: {}, threshold: {}, reset: {}", redis_key, threshold, reset == 1);
+
+        Ok(reset == 1)
+    }
+
+    async fn increment_with_metadata(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        metadata: &HashMap<String, String>,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let result = self.increment(context, config).await?;
+        if metadata.is_empty() {
+            return Ok(result);
+        }
+
+        let redis_key = self.inner.get_redis_key(context);
+        let metadata_key = self.inner.get_metadata_key(context);
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        let mut script = cmd("EVAL");
+        script.arg(STORE_METADATA_SCRIPT).arg(2).arg(&redis_key).arg(&metadata_key);
+        for (field, value) in metadata {
+            script.arg(field).arg(value);
+        }
+        // The request was already counted, so a metadata failure is only logged
+        let stored: Result<i32, _> = script.query_async(&mut conn).await;
+        if let Err(e) = stored {
+            tracing::warn!("Failed to store metadata for key {}: {}", redis_key, e);
+        }
+
+        Ok(result)
+    }
+
+    async fn peek(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let redis_key = self.inner.get_redis_key(context);
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        let count = read_count(&mut conn, &redis_key).await?;
+        let ttl: i64 = conn.ttl(&redis_key).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Redis TTL operation failed", Box::new(e))
+        })?;
+
+        Ok(BarnacleResult {
+            allowed: count < config.max_requests,
+            remaining: config.max_requests.saturating_sub(count),
+            retry_after: None,
+            reset_after: (ttl > 0).then(|| Duration::from_secs(ttl as u64)),
+            first_seen: None,
+            window_reset: None,
+        })
+    }
+
+    async fn usage_for_key(&self, context: &BarnacleContext) -> Result<KeyUsage, BarnacleError> {
+        let redis_key = self.inner.get_redis_key(context);
+        let metadata_key = self.inner.get_metadata_key(context);
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        let count = read_count(&mut conn, &redis_key).await?;
+        let ttl: i64 = conn.ttl(&redis_key).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Redis TTL operation failed", Box::new(e))
+        })?;
+        let metadata: HashMap<String, String> = conn.hgetall(&metadata_key).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Redis HGETALL operation failed", Box::new(e))
+        })?;
+
+        Ok(KeyUsage {
+            count,
+            retry_after: (ttl > 0).then(|| Duration::from_secs(ttl as u64)),
+            metadata,
+        })
+    }
+
+    async fn decrement(&self, context: &BarnacleContext, n: u32) -> Result<(), BarnacleError> {
+        let redis_key = self.inner.get_redis_key(context);
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        let remaining: i64 = cmd("EVAL")
+            .arg(DECREMENT_SCRIPT)
+            .arg(1)
+            .arg(&redis_key)
+            .arg(n)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis decrement operation failed", Box::new(e))
+            })?;
+
+        tracing::debug!("Rate limit decrement for key: {}, by: {}, count: {}", redis_key, n, remaining);
+
+        Ok(())
+    }
+
+    async fn idempotency_key_seen(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<bool, BarnacleError> {
+        let redis_key = self.inner.get_idempotency_key(context, idempotency_key);
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        let seen: bool = conn.exists(&redis_key).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Redis EXISTS operation failed", Box::new(e))
+        })?;
+
+        Ok(seen)
+    }
+
+    async fn record_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<(), BarnacleError> {
+        let redis_key = self.inner.get_idempotency_key(context, idempotency_key);
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        conn.set_ex::<_, _, ()>(&redis_key, 1, ttl.as_secs().max(1))
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Failed to record idempotency key", Box::new(e))
+            })?;
+
+        Ok(())
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        let redis_key = self.inner.get_idempotency_key(context, idempotency_key);
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        // SET NX replies nil when the key already exists
+        let claimed: Option<String> = cmd("SET")
+            .arg(&redis_key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl.as_secs().max(1))
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Failed to claim idempotency key", Box::new(e))
+            })?;
+
+        Ok(claimed.is_some())
+    }
+
+    async fn release_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<(), BarnacleError> {
+        let redis_key = self.inner.get_idempotency_key(context, idempotency_key);
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        let _: () = conn.del(&redis_key).await.map_err(|e| {
+            BarnacleError::store_error_with_source("Failed to release idempotency key", Box::new(e))
+        })?;
+
+        Ok(())
+    }
+
+    async fn acquire_in_flight(
+        &self,
+        context: &BarnacleContext,
+        max_in_flight: u32,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        let in_flight_key = self.inner.get_in_flight_key(context);
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        let acquired: i32 = cmd("EVAL")
+            .arg(ACQUIRE_IN_FLIGHT_SCRIPT)
+            .arg(1)
+            .arg(&in_flight_key)
+            .arg(max_in_flight)
+            .arg(ttl.as_secs().max(1))
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis in-flight acquire failed", Box::new(e))
+            })?;
+
+        tracing::debug!(
+            "In-flight acquire for key: {}, max_in_flight: {}, acquired: {}",
+            in_flight_key,
+            max_in_flight,
+            acquired == 1
+        );
+
+        Ok(acquired == 1)
+    }
+
+    async fn release_in_flight(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let in_flight_key = self.inner.get_in_flight_key(context);
+
+        let mut conn = self.inner.get_connection().await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to get Redis connection", Box::new(e))
+        })?;
+
+        let remaining: i64 = cmd("EVAL")
+            .arg(RELEASE_IN_FLIGHT_SCRIPT)
+            .arg(1)
+            .arg(&in_flight_key)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis in-flight release failed", Box::new(e))
+            })?;
+
+        tracing::debug!(
+            "In-flight release for key: {}, in_flight: {}",
+            in_flight_key,
+            remaining
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,75 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use crate::{types::BarnacleContext, BarnacleStore};
+
+/// Attempts per reset before it is given up
+const RESET_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry, doubled for each further attempt
+const RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Bounded queue of resets executed off the response path, see
+/// `BarnacleLayerBuilder::with_background_reset`. A single worker task, started on first
+/// use, runs the resets in order and retries failed ones; it exits when the layer is dropped.
+pub(crate) struct ResetQueue<S> {
+    store: S,
+    sender: mpsc::Sender<BarnacleContext>,
+    receiver: Mutex<Option<mpsc::Receiver<BarnacleContext>>>,
+}
+
+impl<S> ResetQueue<S>
+where
+    S: BarnacleStore + 'static,
+{
+    pub(crate) fn new(store: S, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        Self {
+            store,
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    /// Queues a reset, waiting for room while the queue is full
+    pub(crate) async fn enqueue(&self, context: BarnacleContext) {
+        self.start_worker();
+        if let Err(e) = self.sender.send(context).await {
+            tracing::warn!("Reset queue closed, dropping reset for key {:?}", e.0.key);
+        }
+    }
+
+    fn start_worker(&self) {
+        let Some(mut receiver) = self.receiver.lock().unwrap().take() else {
+            return;
+        };
+        let store = self.store.clone();
+        tokio::spawn(async move {
+            while let Some(context) = receiver.recv().await {
+                reset_with_retry(&store, &context).await;
+            }
+        });
+    }
+}
+
+async fn reset_with_retry<S: BarnacleStore>(store: &S, context: &BarnacleContext) {
+    let mut backoff = RETRY_BACKOFF;
+    for attempt in 1..=RESET_ATTEMPTS {
+        match store.reset(context).await {
+            Ok(()) => {
+                tracing::debug!("Background reset for key {:?} done (attempt {})", context.key, attempt);
+                return;
+            }
+            Err(e) if attempt < RESET_ATTEMPTS => {
+                tracing::debug!("Background reset for key {:?} failed, retrying: {}", context.key, e);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => {
+                tracing::warn!("Background reset for key {:?} failed after {} attempts: {}", context.key, attempt, e);
+            }
+        }
+    }
+}
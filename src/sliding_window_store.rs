@@ -0,0 +1,282 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use deadpool_redis::redis::{cmd, AsyncCommands};
+use deadpool_redis::Pool;
+
+use crate::{
+    error::BarnacleError,
+    redis_store::RedisBarnacleStore,
+    types::{BarnacleConfig, BarnacleContext, BarnacleResult},
+    BarnacleStore,
+};
+
+/// Drops entries older than the window, then records the request if there is room.
+/// Uses the Redis server clock so every instance agrees on the window.
+/// KEYS[1] = sorted set, ARGV[1] = window in ms, ARGV[2] = max_requests, ARGV[3] = unique member.
+/// Returns {allowed, count, ms until the oldest in-window entry expires}.
+const SLIDING_WINDOW_SCRIPT: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local window = tonumber(ARGV[1])
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+local count = redis.call('ZCARD', KEYS[1])
+local allowed = 0
+if count < tonumber(ARGV[2]) then
+    redis.call('ZADD', KEYS[1], now, ARGV[3])
+    redis.call('PEXPIRE', KEYS[1], window)
+    count = count + 1
+    allowed = 1
+end
+local oldest = redis.call('ZRANGE', KEYS[1], 0, 0, 'WITHSCORES')
+local reset_after = window
+if oldest[2] then
+    reset_after = tonumber(oldest[2]) + window - now
+end
+return {allowed, count, reset_after}
+"#;
+
+/// Counts the entries inside the trailing window without recording a request.
+/// KEYS[1] = sorted set, ARGV[1] = window in ms.
+/// Returns {count, ms until the oldest in-window entry expires, or 0 if there is none}.
+const SLIDING_WINDOW_PEEK_SCRIPT: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local window = tonumber(ARGV[1])
+local start = '(' .. (now - window)
+local count = redis.call('ZCOUNT', KEYS[1], start, '+inf')
+local oldest = redis.call('ZRANGEBYSCORE', KEYS[1], start, '+inf', 'WITHSCORES', 'LIMIT', 0, 1)
+local reset_after = 0
+if oldest[2] then
+    reset_after = tonumber(oldest[2]) + window - now
+end
+return {count, reset_after}
+"#;
+
+/// Sliding-window log store: keeps the timestamp of every request in a Redis sorted set
+/// per key and counts the ones inside the trailing `window`.
+///
+/// Unlike the fixed-window `RedisBarnacleStore`, a burst straddling a window boundary can't
+/// get `2 * max_requests` through, at the cost of one sorted-set entry per counted request.
+/// `retry_after` is the time until the oldest in-window request expires, i.e. when the next
+/// slot frees up. Keys share the `RedisBarnacleStore` naming with a `:sliding` suffix;
+/// idempotency keys and in-flight slots are handled exactly like the fixed-window store.
+#[derive(Clone)]
+pub struct SlidingWindowStore {
+    store: RedisBarnacleStore,
+}
+
+impl SlidingWindowStore {
+    /// Create a new sliding window store with connection pooling
+    pub fn new(pool: Pool) -> Self {
+        Self {
+            store: RedisBarnacleStore::new(pool),
+        }
+    }
+
+    /// Create a new sliding window store from a Redis URL
+    pub fn from_url(url: &str) -> Result<Self, deadpool_redis::PoolError> {
+        Ok(Self {
+            store: RedisBarnacleStore::from_url(url)?,
+        })
+    }
+
+    /// Hash the variable part of every Redis key, see `RedisBarnacleStore::with_key_hashing`
+    pub fn with_key_hashing(self, enabled: bool) -> Self {
+        Self {
+            store: self.store.with_key_hashing(enabled),
+        }
+    }
+
+    /// The Redis key holding the sorted set of request timestamps for a context
+    pub fn key_for(&self, context: &BarnacleContext) -> String {
+        format!("{}:sliding", self.store.key_for(context))
+    }
+}
+
+/// Rounds milliseconds up to whole seconds, so clients never retry too early
+fn ceil_seconds(ms: i64) -> u64 {
+    ((ms.max(0) as u64 + 999) / 1000).max(1)
+}
+
+/// The window in milliseconds, if Redis can handle it
+fn window_ms(config: &BarnacleConfig) -> Result<i64, BarnacleError> {
+    let window_ms = config.window.as_millis();
+    if window_ms == 0 || window_ms > i64::MAX as u128 {
+        return Err(BarnacleError::configuration_error(format!(
+            "Sliding window of {:?} is outside the range Redis accepts",
+            config.window
+        )));
+    }
+    Ok(window_ms as i64)
+}
+
+#[async_trait]
+impl BarnacleStore for SlidingWindowStore {
+    async fn increment(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let window_ms = window_ms(config)?;
+        let redis_key = self.key_for(context);
+
+        let mut conn = self.store.connection().await?;
+
+        let (allowed, count, reset_after_ms): (i64, u32, i64) = cmd("EVAL")
+            .arg(SLIDING_WINDOW_SCRIPT)
+            .arg(1)
+            .arg(&redis_key)
+            .arg(window_ms)
+            .arg(config.max_requests)
+            .arg(uuid::Uuid::new_v4().to_string())
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis sliding window operation failed", Box::new(e))
+            })?;
+        let retry_after = ceil_seconds(reset_after_ms);
+
+        if allowed == 0 {
+            tracing::debug!(
+                "Sliding window limit exceeded for key: {}, count: {}, max: {}, retry_after: {}s",
+                redis_key,
+                count,
+                config.max_requests,
+                retry_after
+            );
+            return Err(BarnacleError::rate_limit_exceeded(0, retry_after, config.max_requests));
+        }
+
+        // Only requests still inside the trailing window use up the quota
+        let remaining = config.max_requests.saturating_sub(count);
+        tracing::debug!(
+            "Sliding window increment successful for key: {}, count: {}, remaining: {}",
+            redis_key,
+            count,
+            remaining
+        );
+
+        Ok(BarnacleResult {
+            allowed: true,
+            remaining,
+            retry_after: None,
+            reset_after: Some(Duration::from_secs(retry_after)),
+            first_seen: None,
+            window_reset: None,
+        })
+    }
+
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let redis_key = self.key_for(context);
+
+        let mut conn = self.store.connection().await?;
+
+        let _: () = conn.del(&redis_key).await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to delete key from Redis", Box::new(e))
+        })?;
+
+        Ok(())
+    }
+
+    /// `remaining` is based on the requests inside the trailing window right now
+    async fn peek(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let window_ms = window_ms(config)?;
+        let redis_key = self.key_for(context);
+
+        let mut conn = self.store.connection().await?;
+
+        let (count, reset_after_ms): (u32, i64) = cmd("EVAL")
+            .arg(SLIDING_WINDOW_PEEK_SCRIPT)
+            .arg(1)
+            .arg(&redis_key)
+            .arg(window_ms)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| BarnacleError::store_error_with_source("Redis sliding window peek failed", Box::new(e)))?;
+
+        let allowed = count < config.max_requests;
+        let reset_after = (count > 0).then(|| Duration::from_secs(ceil_seconds(reset_after_ms)));
+        Ok(BarnacleResult {
+            allowed,
+            remaining: config.max_requests.saturating_sub(count),
+            retry_after: reset_after.filter(|_| !allowed),
+            reset_after,
+            first_seen: None,
+            window_reset: None,
+        })
+    }
+
+    /// Removes the `n` most recent entries, so the refunded requests stop counting
+    async fn decrement(&self, context: &BarnacleContext, n: u32) -> Result<(), BarnacleError> {
+        let redis_key = self.key_for(context);
+
+        let mut conn = self.store.connection().await?;
+
+        let _: Vec<String> = cmd("ZPOPMAX")
+            .arg(&redis_key)
+            .arg(n)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis sliding window decrement failed", Box::new(e))
+            })?;
+
+        Ok(())
+    }
+
+    fn health(&self) -> Result<(), BarnacleError> {
+        self.store.health()
+    }
+
+    async fn idempotency_key_seen(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<bool, BarnacleError> {
+        self.store.idempotency_key_seen(context, idempotency_key).await
+    }
+
+    async fn record_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<(), BarnacleError> {
+        self.store.record_idempotency_key(context, idempotency_key, ttl).await
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        self.store.claim_idempotency_key(context, idempotency_key, ttl).await
+    }
+
+    async fn release_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<(), BarnacleError> {
+        self.store.release_idempotency_key(context, idempotency_key).await
+    }
+
+    async fn acquire_in_flight(
+        &self,
+        context: &BarnacleContext,
+        max_in_flight: u32,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        self.store.acquire_in_flight(context, max_in_flight, ttl).await
+    }
+
+    async fn release_in_flight(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        self.store.release_in_flight(context).await
+    }
+}
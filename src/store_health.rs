@@ -0,0 +1,97 @@
+use std::task::{Context, Poll};
+
+use tower::{Layer, Service};
+use tracing::debug;
+
+use crate::error::BarnacleError;
+use crate::BarnacleStore;
+
+/// Maps a store health error into a service error type so `poll_ready` can report it.
+/// Return `None` when the error type cannot represent it; readiness then falls
+/// through to the inner service.
+pub trait StoreHealthError: Sized {
+    fn from_store_health_error(error: BarnacleError) -> Option<Self>;
+}
+
+impl StoreHealthError for std::convert::Infallible {
+    fn from_store_health_error(_error: BarnacleError) -> Option<Self> {
+        None
+    }
+}
+
+impl StoreHealthError for BarnacleError {
+    fn from_store_health_error(error: BarnacleError) -> Option<Self> {
+        Some(error)
+    }
+}
+
+impl StoreHealthError for tower::BoxError {
+    fn from_store_health_error(error: BarnacleError) -> Option<Self> {
+        Some(Box::new(error))
+    }
+}
+
+/// Backpressure layer: fails `poll_ready` while `BarnacleStore::health` reports the store
+/// unhealthy, so a load balancer or `tower::buffer` stops sending requests.
+///
+/// Kept apart from `BarnacleLayer` so only services opting in need an error type
+/// implementing `StoreHealthError` (e.g. `BarnacleError` or `tower::BoxError`). Wrap it
+/// around the `BarnacleLayer` sharing the same store.
+#[derive(Clone)]
+pub struct StoreHealthLayer<S> {
+    store: S,
+}
+
+impl<S> StoreHealthLayer<S>
+where
+    S: BarnacleStore + 'static,
+{
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+}
+
+impl<Inner, S> Layer<Inner> for StoreHealthLayer<S>
+where
+    S: BarnacleStore + 'static,
+{
+    type Service = StoreHealthMiddleware<Inner, S>;
+    fn layer(&self, inner: Inner) -> Self::Service {
+        StoreHealthMiddleware {
+            inner,
+            store: self.store.clone(),
+        }
+    }
+}
+
+/// Middleware created by `StoreHealthLayer`
+#[derive(Clone)]
+pub struct StoreHealthMiddleware<Inner, S> {
+    inner: Inner,
+    store: S,
+}
+
+impl<Inner, Request, S> Service<Request> for StoreHealthMiddleware<Inner, S>
+where
+    Inner: Service<Request>,
+    Inner::Error: StoreHealthError,
+    S: BarnacleStore + 'static,
+{
+    type Response = Inner::Response;
+    type Error = Inner::Error;
+    type Future = Inner::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Err(e) = self.store.health() {
+            debug!("[store_health.rs] Store health check failed: {}", e);
+            if let Some(error) = Inner::Error::from_store_health_error(e) {
+                return Poll::Ready(Err(error));
+            }
+        }
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        self.inner.call(req)
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{
+    error::BarnacleError,
+    types::{BarnacleConfig, BarnacleContext, BarnacleResult, KeyUsage, ReservationToken},
+    BarnacleStore,
+};
+
+/// Store wrapper bounding how long `increment` and `reset` may take, so a slow backend
+/// (e.g. an overloaded Redis) can't stall every request behind it.
+///
+/// When the timeout elapses the inner call is dropped and a `StoreError` is returned. With
+/// `with_fail_open(true)` a timed out `increment` lets the request through instead, and a
+/// timed out `reset` is only logged. Errors returned by the inner store are passed on as is.
+#[derive(Clone)]
+pub struct TimeoutStore<S> {
+    inner: S,
+    timeout: Duration,
+    fail_open: bool,
+}
+
+impl<S: BarnacleStore> TimeoutStore<S> {
+    pub fn new(inner: S, timeout: Duration) -> Self {
+        Self {
+            inner,
+            timeout,
+            fail_open: false,
+        }
+    }
+
+    /// Allow requests whose count timed out (defaults to rejecting them)
+    pub fn with_fail_open(mut self, fail_open: bool) -> Self {
+        self.fail_open = fail_open;
+        self
+    }
+
+    /// The wrapped store
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    fn timed_out(&self, operation: &str) -> BarnacleError {
+        BarnacleError::store_error(format!("Store {} timed out after {:?}", operation, self.timeout))
+    }
+
+    /// Applies the fail policy to a count that timed out
+    fn count_timed_out(&self, context: &BarnacleContext, config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+        let e = self.timed_out("increment");
+        if !self.fail_open {
+            return Err(e);
+        }
+        tracing::warn!("{}, allowing request for key: {:?}", e, context.key);
+        Ok(BarnacleResult {
+            allowed: true,
+            remaining: config.max_requests,
+            retry_after: None,
+            reset_after: None,
+            first_seen: None,
+            window_reset: None,
+        })
+    }
+}
+
+#[async_trait]
+impl<S: BarnacleStore + 'static> BarnacleStore for TimeoutStore<S> {
+    async fn increment(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        match tokio::time::timeout(self.timeout, self.inner.increment(context, config)).await {
+            Ok(result) => result,
+            Err(_) => self.count_timed_out(context, config),
+        }
+    }
+
+    async fn increment_by(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        cost: u32,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        match tokio::time::timeout(self.timeout, self.inner.increment_by(context, config, cost)).await {
+            Ok(result) => result,
+            Err(_) => self.count_timed_out(context, config),
+        }
+    }
+
+    async fn increment_with_metadata(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+        metadata: &HashMap<String, String>,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let increment = self.inner.increment_with_metadata(context, config, metadata);
+        match tokio::time::timeout(self.timeout, increment).await {
+            Ok(result) => result,
+            Err(_) => self.count_timed_out(context, config),
+        }
+    }
+
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        match tokio::time::timeout(self.timeout, self.inner.reset(context)).await {
+            Ok(result) => result,
+            Err(_) if self.fail_open => {
+                tracing::warn!("{}, keeping count for key: {:?}", self.timed_out("reset"), context.key);
+                Ok(())
+            }
+            Err(_) => Err(self.timed_out("reset")),
+        }
+    }
+
+    async fn reset_if_below(&self, context: &BarnacleContext, threshold: u32) -> Result<bool, BarnacleError> {
+        self.inner.reset_if_below(context, threshold).await
+    }
+
+    async fn cancel(&self, token: ReservationToken) -> Result<(), BarnacleError> {
+        self.inner.cancel(token).await
+    }
+
+    async fn peek(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        self.inner.peek(context, config).await
+    }
+
+    async fn usage_for_key(&self, context: &BarnacleContext) -> Result<KeyUsage, BarnacleError> {
+        self.inner.usage_for_key(context).await
+    }
+
+    async fn decrement(&self, context: &BarnacleContext, n: u32) -> Result<(), BarnacleError> {
+        self.inner.decrement(context, n).await
+    }
+
+    fn health(&self) -> Result<(), BarnacleError> {
+        self.inner.health()
+    }
+
+    async fn idempotency_key_seen(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<bool, BarnacleError> {
+        self.inner.idempotency_key_seen(context, idempotency_key).await
+    }
+
+    async fn record_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<(), BarnacleError> {
+        self.inner.record_idempotency_key(context, idempotency_key, ttl).await
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        self.inner.claim_idempotency_key(context, idempotency_key, ttl).await
+    }
+
+    async fn release_idempotency_key(
+        &self,
+        context: &BarnacleContext,
+        idempotency_key: &str,
+    ) -> Result<(), BarnacleError> {
+        self.inner.release_idempotency_key(context, idempotency_key).await
+    }
+
+    async fn acquire_in_flight(
+        &self,
+        context: &BarnacleContext,
+        max_in_flight: u32,
+        ttl: Duration,
+    ) -> Result<bool, BarnacleError> {
+        self.inner.acquire_in_flight(context, max_in_flight, ttl).await
+    }
+
+    async fn release_in_flight(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        self.inner.release_in_flight(context).await
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+#[cfg(feature = "redis")]
+use deadpool_redis::redis::{cmd, AsyncCommands};
+#[cfg(feature = "redis")]
+use deadpool_redis::Pool;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    error::BarnacleError,
+    types::{BarnacleConfig, BarnacleContext, BarnacleResult, TokenBucketConfig},
+    BarnacleStore,
+};
+#[cfg(feature = "redis")]
+use crate::redis_store::RedisBarnacleStore;
+
+/// Refills the bucket for the time elapsed since the last update and takes a token if one is
+/// available. A missing bucket starts full. Stores the fractional token count as a string.
+/// KEYS[1] = bucket hash, ARGV[1] = refill rate (tokens/s), ARGV[2] = burst capacity.
+/// Returns {allowed, tokens left}.
+#[cfg(feature = "redis")]
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local rate = tonumber(ARGV[1])
+local burst = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) + tonumber(time[2]) / 1000000
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(state[1]) or burst
+local updated_at = tonumber(state[2]) or now
+tokens = math.min(burst, tokens + math.max(0, now - updated_at) * rate)
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', tostring(now))
+redis.call('PEXPIRE', KEYS[1], math.ceil(burst / rate * 1000))
+return {allowed, tostring(tokens)}
+"#;
+
+/// Reads the tokens a bucket would hold now, without taking one or writing the refill.
+/// KEYS[1] = bucket hash, ARGV[1] = refill rate (tokens/s), ARGV[2] = burst capacity.
+/// Returns the token count as a string.
+#[cfg(feature = "redis")]
+const TOKEN_BUCKET_PEEK_SCRIPT: &str = r#"
+local rate = tonumber(ARGV[1])
+local burst = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) + tonumber(time[2]) / 1000000
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(state[1]) or burst
+local updated_at = tonumber(state[2]) or now
+return tostring(math.min(burst, tokens + math.max(0, now - updated_at) * rate))
+"#;
+
+/// The bucket parameters to use for a request: the store's override, else derived from `config`
+fn bucket_config(override_config: Option<&TokenBucketConfig>, config: &BarnacleConfig) -> Result<TokenBucketConfig, BarnacleError> {
+    let bucket = override_config.cloned().unwrap_or_else(|| TokenBucketConfig::from(config));
+    if !(bucket.refill_rate.is_finite() && bucket.refill_rate > 0.0) || bucket.burst_capacity == 0 {
+        return Err(BarnacleError::configuration_error(format!(
+            "Token bucket needs a positive refill rate and burst capacity, got {:?}",
+            bucket
+        )));
+    }
+    Ok(bucket)
+}
+
+/// Tokens in a bucket that held `tokens` `elapsed` seconds ago
+fn refill(tokens: f64, elapsed: f64, bucket: &TokenBucketConfig) -> f64 {
+    (tokens + elapsed.max(0.0) * bucket.refill_rate).min(f64::from(bucket.burst_capacity))
+}
+
+/// Time until the bucket holds a whole token again
+fn time_until_token(tokens: f64, bucket: &TokenBucketConfig) -> Duration {
+    Duration::from_secs_f64(((1.0 - tokens) / bucket.refill_rate).max(0.0))
+}
+
+/// Time until the bucket is back at its burst capacity
+fn time_until_full(tokens: f64, bucket: &TokenBucketConfig) -> Duration {
+    Duration::from_secs_f64(((f64::from(bucket.burst_capacity) - tokens) / bucket.refill_rate).max(0.0))
+}
+
+/// The state of a bucket holding `tokens`: `remaining` counts whole tokens, `retry_after`
+/// is set once no whole token is left and `reset_after` is the time until the bucket is
+/// full again
+fn bucket_state(tokens: f64, bucket: &TokenBucketConfig) -> BarnacleResult {
+    BarnacleResult {
+        allowed: tokens >= 1.0,
+        remaining: tokens.floor() as u32,
+        retry_after: (tokens < 1.0).then(|| time_until_token(tokens, bucket)),
+        reset_after: Some(time_until_full(tokens, bucket)),
+        first_seen: None,
+        window_reset: None,
+    }
+}
+
+/// Turns the outcome of taking a token into the store's result
+fn bucket_result(allowed: bool, tokens: f64, bucket: &TokenBucketConfig) -> Result<BarnacleResult, BarnacleError> {
+    if !allowed {
+        let wait = time_until_token(tokens, bucket);
+        // Round up so clients never retry before a token is available
+        let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        return Err(BarnacleError::rate_limit_exceeded(0, retry_after, bucket.burst_capacity));
+    }
+    Ok(BarnacleResult {
+        allowed: true,
+        ..bucket_state(tokens, bucket)
+    })
+}
+
+/// In-process token bucket store, for single-instance deployments and tests.
+///
+/// Like `TokenBucketStore`, buckets refill continuously at `refill_rate` tokens per second,
+/// derived from each request's `BarnacleConfig` unless `with_bucket_config` overrides it.
+#[derive(Clone)]
+pub struct InMemoryTokenBucketStore {
+    // context -> (tokens, last update)
+    buckets: Arc<Mutex<HashMap<BarnacleContext, (f64, SystemTime)>>>,
+    bucket_config: Option<TokenBucketConfig>,
+    clock: Arc<dyn Clock>,
+}
+
+impl InMemoryTokenBucketStore {
+    pub fn new() -> Self {
+        Self {
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            bucket_config: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Use these bucket parameters for every key instead of deriving them from `BarnacleConfig`
+    pub fn with_bucket_config(mut self, bucket_config: TokenBucketConfig) -> Self {
+        self.bucket_config = Some(bucket_config);
+        self
+    }
+
+    /// Time source used for refills (defaults to the system clock)
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+}
+
+impl Default for InMemoryTokenBucketStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl BarnacleStore for InMemoryTokenBucketStore {
+    async fn increment(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let bucket = bucket_config(self.bucket_config.as_ref(), config)?;
+        let now = self.clock.now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let (tokens, updated_at) = buckets
+            .entry(context.clone())
+            .or_insert((f64::from(bucket.burst_capacity), now));
+        let elapsed = now.duration_since(*updated_at).unwrap_or_default().as_secs_f64();
+        *tokens = refill(*tokens, elapsed, &bucket);
+        *updated_at = now;
+
+        let allowed = *tokens >= 1.0;
+        if allowed {
+            *tokens -= 1.0;
+        }
+        tracing::debug!("Token bucket for key: {:?}, allowed: {}, tokens: {:.3}", context.key, allowed, *tokens);
+        bucket_result(allowed, *tokens, &bucket)
+    }
+
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        self.buckets.lock().unwrap().remove(context);
+        Ok(())
+    }
+
+    async fn peek(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let bucket = bucket_config(self.bucket_config.as_ref(), config)?;
+        let now = self.clock.now();
+        let tokens = match self.buckets.lock().unwrap().get(context) {
+            Some((tokens, updated_at)) => {
+                let elapsed = now.duration_since(*updated_at).unwrap_or_default().as_secs_f64();
+                refill(*tokens, elapsed, &bucket)
+            }
+            None => f64::from(bucket.burst_capacity),
+        };
+        Ok(bucket_state(tokens, &bucket))
+    }
+
+    /// Puts `n` tokens back; the next refill caps them at the burst capacity
+    async fn decrement(&self, context: &BarnacleContext, n: u32) -> Result<(), BarnacleError> {
+        if let Some((tokens, _)) = self.buckets.lock().unwrap().get_mut(context) {
+            *tokens += f64::from(n);
+        }
+        Ok(())
+    }
+}
+
+/// Redis-backed token bucket store for smooth sustained throughput with a burst allowance.
+///
+/// Each key keeps its token count and last refill time in a Redis hash, refilled atomically
+/// using the Redis server clock. The bucket parameters are derived from each request's
+/// `BarnacleConfig` (`max_requests` per `window`, bursts of `max_requests`) unless
+/// `with_bucket_config` overrides them. `remaining` is the number of whole tokens left and
+/// `retry_after` the time until the next token once the bucket is empty.
+#[cfg(feature = "redis")]
+#[derive(Clone)]
+pub struct TokenBucketStore {
+    store: RedisBarnacleStore,
+    bucket_config: Option<TokenBucketConfig>,
+}
+
+#[cfg(feature = "redis")]
+impl TokenBucketStore {
+    /// Create a new token bucket store with connection pooling
+    pub fn new(pool: Pool) -> Self {
+        Self {
+            store: RedisBarnacleStore::new(pool),
+            bucket_config: None,
+        }
+    }
+
+    /// Create a new token bucket store from a Redis URL
+    pub fn from_url(url: &str) -> Result<Self, deadpool_redis::PoolError> {
+        Ok(Self {
+            store: RedisBarnacleStore::from_url(url)?,
+            bucket_config: None,
+        })
+    }
+
+    /// Use these bucket parameters for every key instead of deriving them from `BarnacleConfig`
+    pub fn with_bucket_config(mut self, bucket_config: TokenBucketConfig) -> Self {
+        self.bucket_config = Some(bucket_config);
+        self
+    }
+
+    /// The Redis key holding the bucket for a context
+    pub fn key_for(&self, context: &BarnacleContext) -> String {
+        format!("{}:bucket", self.store.key_for(context))
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl BarnacleStore for TokenBucketStore {
+    async fn increment(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let bucket = bucket_config(self.bucket_config.as_ref(), config)?;
+        let redis_key = self.key_for(context);
+
+        let mut conn = self.store.connection().await?;
+
+        let (allowed, tokens): (i32, String) = cmd("EVAL")
+            .arg(TOKEN_BUCKET_SCRIPT)
+            .arg(1)
+            .arg(&redis_key)
+            .arg(bucket.refill_rate)
+            .arg(bucket.burst_capacity)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                BarnacleError::store_error_with_source("Redis token bucket operation failed", Box::new(e))
+            })?;
+        let tokens: f64 = tokens.parse().map_err(|_| {
+            BarnacleError::store_error(format!("Invalid token count {:?} for key {}", tokens, redis_key))
+        })?;
+
+        tracing::debug!("Token bucket for key: {}, allowed: {}, tokens: {:.3}", redis_key, allowed == 1, tokens);
+        bucket_result(allowed == 1, tokens, &bucket)
+    }
+
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let redis_key = self.key_for(context);
+
+        let mut conn = self.store.connection().await?;
+
+        let _: () = conn.del(&redis_key).await.map_err(|e| {
+            BarnacleError::connection_pool_error("Failed to delete key from Redis", Box::new(e))
+        })?;
+
+        Ok(())
+    }
+
+    async fn peek(
+        &self,
+        context: &BarnacleContext,
+        config: &BarnacleConfig,
+    ) -> Result<BarnacleResult, BarnacleError> {
+        let bucket = bucket_config(self.bucket_config.as_ref(), config)?;
+        let redis_key = self.key_for(context);
+
+        let mut conn = self.store.connection().await?;
+
+        let tokens: String = cmd("EVAL")
+            .arg(TOKEN_BUCKET_PEEK_SCRIPT)
+            .arg(1)
+            .arg(&redis_key)
+            .arg(bucket.refill_rate)
+            .arg(bucket.burst_capacity)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| BarnacleError::store_error_with_source("Redis token bucket peek failed", Box::new(e)))?;
+        let tokens: f64 = tokens.parse().map_err(|_| {
+            BarnacleError::store_error(format!("Invalid token count {:?} for key {}", tokens, redis_key))
+        })?;
+
+        Ok(bucket_state(tokens, &bucket))
+    }
+
+    fn health(&self) -> Result<(), BarnacleError> {
+        self.store.health()
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::error::BarnacleError;
+
+/// An IP network in CIDR notation, e.g. `10.0.0.0/8` or `fd00::/8`. A bare address is a
+/// single-host network.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, BarnacleError> {
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_len {
+            return Err(BarnacleError::configuration_error(format!(
+                "Prefix length {} is too long for {}",
+                prefix_len, addr
+            )));
+        }
+        Ok(Self { addr, prefix_len })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(u128::from(u32::from(net)) << 96, u128::from(u32::from(ip)) << 96, self.prefix_len)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => prefix_matches(u128::from(net), u128::from(ip), self.prefix_len),
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(net: u128, ip: u128, prefix_len: u8) -> bool {
+    let mask = prefix_mask(prefix_len);
+    net & mask == ip & mask
+}
+
+/// 128-bit mask with the top `prefix_len` bits set
+fn prefix_mask(prefix_len: u8) -> u128 {
+    u128::MAX.checked_shl(128 - u32::from(prefix_len.min(128))).unwrap_or(0)
+}
+
+impl FromStr for IpCidr {
+    type Err = BarnacleError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || BarnacleError::configuration_error(format!("Invalid CIDR {:?}", text));
+        let (addr, prefix_len) = match text.trim().split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (text.trim(), None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse().map_err(|_| invalid())?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(addr, prefix_len)
+    }
+}
+
+impl fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl serde::Serialize for IpCidr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for IpCidr {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Which `X-Forwarded-For` entries to believe when the app runs behind proxies.
+///
+/// Each proxy appends the address it received the request from, so only the rightmost
+/// entries are trustworthy. With `hops` set, the client is the entry `hops` places from
+/// the right (one per proxy in front of the app). With `trusted_cidrs`, the request must
+/// come from a trusted address and the client is the rightmost entry outside them.
+/// When the header can't be trusted the connection's peer address is used instead.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TrustedProxyConfig {
+    pub hops: usize,
+    pub trusted_cidrs: Vec<IpCidr>,
+}
+
+impl TrustedProxyConfig {
+    /// Trust the last `hops` proxies, whatever their addresses
+    pub fn hops(hops: usize) -> Self {
+        Self { hops, trusted_cidrs: Vec::new() }
+    }
+
+    /// Trust proxies whose addresses fall in these networks
+    pub fn trusted_cidrs(trusted_cidrs: Vec<IpCidr>) -> Self {
+        Self { hops: 0, trusted_cidrs }
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_cidrs.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// The client address from an `X-Forwarded-For` value, or `None` if the chain is
+    /// malformed, too short or arrived through an untrusted peer
+    pub fn client_ip(&self, forwarded_for: &str, peer: Option<IpAddr>) -> Option<IpAddr> {
+        let chain: Vec<IpAddr> = forwarded_for
+            .split(',')
+            .map(|entry| entry.trim().parse())
+            .collect::<Result<_, _>>()
+            .ok()?;
+
+        if !self.trusted_cidrs.is_empty() {
+            if peer.is_some_and(|peer| !self.is_trusted(peer)) {
+                return None;
+            }
+            return chain.iter().rev().find(|ip| !self.is_trusted(**ip)).copied();
+        }
+        if self.hops == 0 || chain.len() < self.hops {
+            return None;
+        }
+        chain.get(chain.len() - self.hops).copied()
+    }
+}
+
+/// Prefix lengths IP keys are masked to before limiting, so every address in a network
+/// shares one budget. Without it each IPv6 address is limited separately and a client
+/// holding a /64 can rotate through addresses to dodge the limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct IpKeyPrefix {
+    pub ipv4: u8,
+    pub ipv6: u8,
+}
+
+impl Default for IpKeyPrefix {
+    /// Whole IPv4 addresses and IPv6 /64 networks
+    fn default() -> Self {
+        Self { ipv4: 32, ipv6: 64 }
+    }
+}
+
+impl IpKeyPrefix {
+    pub fn new(ipv4: u8, ipv6: u8) -> Self {
+        Self { ipv4, ipv6 }
+    }
+
+    /// The network address of `ip`. IPv4-mapped IPv6 addresses are masked as IPv4.
+    pub fn mask(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(v4) => {
+                let mask = (prefix_mask(self.ipv4.min(32)) >> 96) as u32;
+                IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+            }
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => self.mask(IpAddr::V4(v4)),
+                None => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & prefix_mask(self.ipv6))),
+            },
+        }
+    }
+}
@@ -1,20 +1,100 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
 /// Special constant to indicate a placeholder key that should be replaced
 pub const NO_KEY: &str = "__BARNACLE_NO_KEY_PLACEHOLDER__";
 
+/// When a successful response resets the key's counter.
+///
+/// The variants accept any `u16`; prefer `on_status_codes` and `success_2xx`, which only
+/// produce valid status codes.
 #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum ResetOnSuccess {
     Not,
+    /// Reset on the listed status codes, or on any 2xx when `None`
     Yes(Option<Vec<u16>>),
+    /// Like `Yes`, also resetting the listed contexts
     Multiple(Option<Vec<u16>>, Vec<BarnacleContext>),
 }
 
-/// Rate limiter configuration
+impl ResetOnSuccess {
+    /// Reset on any of `codes`, which must be HTTP status codes (100–599)
+    pub fn on_status_codes<I>(codes: I) -> Result<Self, crate::error::BarnacleError>
+    where
+        I: IntoIterator<Item = u16>,
+    {
+        let codes: Vec<u16> = codes.into_iter().collect();
+        if codes.is_empty() {
+            return Err(crate::error::BarnacleError::configuration_error(
+                "Reset on success needs at least one status code",
+            ));
+        }
+        if let Some(code) = codes.iter().find(|code| !(100..=599).contains(*code)) {
+            return Err(crate::error::BarnacleError::configuration_error(format!(
+                "Invalid status code for reset on success: {}",
+                code
+            )));
+        }
+        Ok(Self::Yes(Some(codes)))
+    }
+
+    /// Reset on any 2xx response
+    pub fn success_2xx() -> Self {
+        Self::Yes(None)
+    }
+}
+
+/// Which set of rate limit headers the middleware sends
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum HeaderStyle {
+    /// `X-RateLimit-Limit`, `X-RateLimit-Remaining`, `X-RateLimit-Reset` and `X-RateLimit-Reset-At`
+    #[default]
+    Legacy,
+    /// `RateLimit-Limit`, `RateLimit-Remaining`, `RateLimit-Reset` and the combined `RateLimit`
+    /// header from the IETF draft, without the `X-` headers
+    IetfDraft,
+    /// Both sets
+    Both,
+}
+
+/// How the middleware renders its own error responses
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ErrorFormat {
+    /// `{"error": {"code", "message", "type", "details"}}` as `application/json`
+    #[default]
+    Json,
+    /// RFC 7807 `application/problem+json`: the error code as `type`, the error type as
+    /// `category`, and any details as extension members
+    ProblemJson,
+    /// The error message alone as `text/plain`
+    PlainText,
+}
+
+/// How the middleware handles a store that fails while counting a request
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum StoreErrorPolicy {
+    /// Let the request through unlimited, for endpoints where availability matters most
+    FailOpen,
+    /// Reject the request with a 503
+    #[default]
+    FailClosed,
+}
+
+impl StoreErrorPolicy {
+    /// Whether `error` should let the request through under this policy. Only failures of
+    /// the store itself qualify, never a request being over its limit.
+    pub fn fails_open(&self, error: &crate::error::BarnacleError) -> bool {
+        *self == StoreErrorPolicy::FailOpen && error.is_store_failure()
+    }
+}
+
+/// Rate limiter configuration. Missing fields deserialize to their defaults and
+/// `window` accepts human-friendly durations such as `"90s"` or `"1h"`.
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct BarnacleConfig {
     pub max_requests: u32,
+    #[serde(with = "crate::duration_serde")]
     pub window: Duration,
     pub reset_on_success: ResetOnSuccess,
 }
@@ -30,6 +110,40 @@ impl Default for BarnacleConfig {
 }
 
 impl BarnacleConfig {
+    /// Preset for login and other credential-checking endpoints: 5 attempts per
+    /// 15 minutes, reset after a successful (2xx) response. Slows brute forcing
+    /// to a handful of guesses while a legitimate user who mistypes once is
+    /// unblocked as soon as they log in.
+    pub fn login_defaults() -> Self {
+        Self {
+            max_requests: 5,
+            window: Duration::from_secs(15 * 60),
+            reset_on_success: ResetOnSuccess::Yes(None),
+        }
+    }
+
+    /// Preset for general public API traffic: 100 requests per minute, never reset.
+    /// Generous enough for interactive clients and paginated reads, while bounding
+    /// a single client to a predictable share of capacity.
+    pub fn public_api_defaults() -> Self {
+        Self {
+            max_requests: 100,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        }
+    }
+
+    /// Preset for expensive or abuse-prone endpoints (password reset, signup, sending
+    /// email): 3 requests per hour, never reset. Success is not a signal of good
+    /// intent for these endpoints, so it does not restore quota.
+    pub fn strict() -> Self {
+        Self {
+            max_requests: 3,
+            window: Duration::from_secs(60 * 60),
+            reset_on_success: ResetOnSuccess::Not,
+        }
+    }
+
     /// Check if a status code should be considered successful for rate limit reset
     pub fn is_success_status(&self, status_code: u16) -> bool {
         match &self.reset_on_success {
@@ -44,6 +158,87 @@ impl BarnacleConfig {
             }
         }
     }
+
+    /// Like `is_success_status`, but when `success_header` is configured and present on
+    /// the response, its value decides instead of the status code
+    pub fn is_success_response(
+        &self,
+        status_code: u16,
+        headers: &axum::http::HeaderMap,
+        success_header: Option<&ResetOnSuccessHeader>,
+    ) -> bool {
+        let header_value = success_header
+            .and_then(|header| headers.get(header.name.as_str()).map(|value| (header, value)));
+        match header_value {
+            Some((header, value)) => value.as_bytes() == header.success_value.as_bytes(),
+            None => self.is_success_status(status_code),
+        }
+    }
+}
+
+/// Parses a rate string such as `"1000/hour"`, `"10/s"` or `"5/15m"`: a request count, then
+/// a unit (`s`, `m`, `h`, `d` or their spelled-out names) or any duration accepted for
+/// `window`. Parsed configs never reset on success.
+impl std::str::FromStr for BarnacleConfig {
+    type Err = crate::error::BarnacleError;
+
+    fn from_str(rate: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| {
+            crate::error::BarnacleError::configuration_error(format!("Invalid rate {:?}: {}", rate, reason))
+        };
+        let (count, unit) = rate.split_once('/').ok_or_else(|| invalid("expected \"<count>/<unit>\"".to_string()))?;
+        let max_requests = count.trim().parse::<u32>().map_err(|e| invalid(e.to_string()))?;
+        let unit = match unit.trim() {
+            "sec" | "second" | "seconds" => "s",
+            "min" | "minute" | "minutes" => "m",
+            "hour" | "hours" => "h",
+            "day" | "days" => "d",
+            unit => unit,
+        };
+        // A bare unit means one of it
+        let window = if unit.starts_with(|c: char| c.is_ascii_digit()) {
+            crate::duration_serde::parse_duration(unit)
+        } else {
+            crate::duration_serde::parse_duration(&format!("1{}", unit))
+        }
+        .map_err(invalid)?;
+        if window.is_zero() {
+            return Err(invalid("window must be positive".to_string()));
+        }
+        Ok(Self {
+            max_requests,
+            window,
+            reset_on_success: ResetOnSuccess::Not,
+        })
+    }
+}
+
+/// Token bucket parameters: the bucket holds up to `burst_capacity` tokens and gains
+/// `refill_rate` tokens per second; each request takes one.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TokenBucketConfig {
+    /// Tokens added per second, may be fractional
+    pub refill_rate: f64,
+    pub burst_capacity: u32,
+}
+
+impl TokenBucketConfig {
+    pub fn new(refill_rate: f64, burst_capacity: u32) -> Self {
+        Self {
+            refill_rate,
+            burst_capacity,
+        }
+    }
+}
+
+/// `max_requests` per `window`, sustained, with bursts of up to `max_requests`
+impl From<&BarnacleConfig> for TokenBucketConfig {
+    fn from(config: &BarnacleConfig) -> Self {
+        Self {
+            refill_rate: f64::from(config.max_requests) / config.window.as_secs_f64(),
+            burst_capacity: config.max_requests,
+        }
+    }
 }
 
 /// Identification key for rate limiting (e.g., email, api-key, IP)
@@ -53,10 +248,57 @@ pub enum BarnacleKey {
     ApiKey(String),
     Ip(String),
     Custom(String),
+    /// Named parts such as `[("tenant", "123"), ("user", "456")]`, rendered in a fixed
+    /// order and escaped by the stores so callers don't hand-encode composite keys
+    Composite(Vec<(String, String)>),
+}
+
+impl BarnacleKey {
+    /// A `Composite` key from `(name, value)` pairs
+    pub fn composite<N: Into<String>, V: Into<String>>(parts: impl IntoIterator<Item = (N, V)>) -> Self {
+        BarnacleKey::Composite(parts.into_iter().map(|(name, value)| (name.into(), value.into())).collect())
+    }
+
+    /// What the key identifies, as reported in the `X-RateLimit-Scope` header:
+    /// `email`, `api-key`, `ip`, `custom` or `composite`
+    pub fn scope(&self) -> &'static str {
+        match self {
+            BarnacleKey::Email(_) => "email",
+            BarnacleKey::ApiKey(_) => "api-key",
+            BarnacleKey::Ip(_) => "ip",
+            BarnacleKey::Custom(_) => "custom",
+            BarnacleKey::Composite(_) => "composite",
+        }
+    }
 }
 
-/// Rate limiting context that includes route information
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+/// Renders composite key parts as `name=value` pairs sorted by name then value and joined
+/// with `:`, escaping `\`, `:` and `=` so distinct parts never render the same
+#[cfg(feature = "redis")]
+pub(crate) fn render_composite_key(parts: &[(String, String)]) -> String {
+    fn escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            if matches!(c, '\\' | ':' | '=') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+
+    let mut rendered: Vec<(String, String)> = parts.iter().map(|(name, value)| (escape(name), escape(value))).collect();
+    rendered.sort();
+    rendered
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Rate limiting context that includes route information. Hashable, so custom
+/// in-memory stores can key their counters on it directly.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, Hash)]
 pub struct BarnacleContext {
     pub key: BarnacleKey,
     pub path: String,
@@ -79,17 +321,87 @@ impl BarnacleContext {
 /// Result of an increment attempt
 #[derive(Clone, Debug)]
 pub struct BarnacleResult {
+    /// Whether the request may proceed; always `true` from counting methods, which report
+    /// rejections as errors (see `BarnacleStore`)
     pub allowed: bool,
+    /// Requests left as the store's algorithm sees it: the rest of the fixed window, the
+    /// rest of the trailing window for sliding windows, whole tokens for token buckets
     pub remaining: u32,
+    /// Time until a request can succeed again, when the store knows it must wait
     pub retry_after: Option<Duration>,
+    /// Time until the quota is fully restored (e.g. the window's TTL), reported as
+    /// `X-RateLimit-Reset` on allowed requests when the store knows it
+    pub reset_after: Option<Duration>,
+    /// Whether this was the key's first ever request, if the store can tell
+    pub first_seen: Option<bool>,
+    /// Whether this request started a new window for a previously seen key, if the store can tell
+    pub window_reset: Option<bool>,
+}
+
+impl BarnacleResult {
+    /// Applies the `BarnacleStore::increment` contract to a result: one reported with
+    /// `allowed: false` becomes the `RateLimitExceeded` error it should have been
+    pub fn reject_if_disallowed(self, config: &BarnacleConfig) -> Result<Self, crate::error::BarnacleError> {
+        if self.allowed {
+            return Ok(self);
+        }
+        let wait = self.retry_after.or(self.reset_after).unwrap_or(config.window);
+        let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        Err(crate::error::BarnacleError::rate_limit_exceeded(self.remaining, retry_after, config.max_requests))
+    }
+}
+
+/// A request counted provisionally by `BarnacleStore::reserve`, to be settled with
+/// `commit` or given back with `cancel`
+#[derive(Debug)]
+#[must_use = "a reservation holds quota until it is committed or cancelled"]
+pub struct ReservationToken {
+    context: BarnacleContext,
+    result: BarnacleResult,
+}
+
+impl ReservationToken {
+    pub fn new(context: BarnacleContext, result: BarnacleResult) -> Self {
+        Self { context, result }
+    }
+
+    /// The context the quota was reserved for
+    pub fn context(&self) -> &BarnacleContext {
+        &self.context
+    }
+
+    /// The outcome of the provisional increment
+    pub fn result(&self) -> &BarnacleResult {
+        &self.result
+    }
+}
+
+/// Current state of a key's counter, as reported by `BarnacleStore::usage_for_key`
+#[derive(Clone, Debug, Default)]
+pub struct KeyUsage {
+    /// Requests counted in the current window
+    pub count: u32,
+    /// Time until the window resets, if a window is active
+    pub retry_after: Option<Duration>,
+    /// Metadata stored with `increment_with_metadata`
+    pub metadata: HashMap<String, String>,
 }
 
 /// API key validation result
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct ApiKeyValidationResult {
     pub valid: bool,
+    #[serde(default)]
     pub key_id: Option<String>,
+    #[serde(default)]
     pub rate_limit_config: Option<BarnacleConfig>,
+    /// Headers the middleware sets on the request forwarded to the inner service,
+    /// replacing any client-supplied value (e.g. plan name or org id)
+    #[serde(default)]
+    pub forward_headers: HashMap<String, String>,
+    /// Scopes granted to the key, checked against `BarnacleLayerBuilder::with_required_scope`
+    #[serde(default)]
+    pub scopes: Vec<String>,
 }
 
 impl ApiKeyValidationResult {
@@ -98,6 +410,8 @@ impl ApiKeyValidationResult {
             valid: true,
             key_id: Some(key_id),
             rate_limit_config: Some(config),
+            forward_headers: HashMap::new(),
+            scopes: Vec::new(),
         }
     }
 
@@ -106,6 +420,8 @@ impl ApiKeyValidationResult {
             valid: true,
             key_id: Some(key_id),
             rate_limit_config: Some(BarnacleConfig::default()),
+            forward_headers: HashMap::new(),
+            scopes: Vec::new(),
         }
     }
 
@@ -114,15 +430,50 @@ impl ApiKeyValidationResult {
             valid: false,
             key_id: None,
             rate_limit_config: None,
+            forward_headers: HashMap::new(),
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn with_forward_header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.forward_headers.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn with_scopes<I, S>(mut self, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.scopes.extend(scopes.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+}
+
+/// Validators returning `Ok(())` accept the key without forwarding anything
+impl From<()> for ApiKeyValidationResult {
+    fn from(_: ()) -> Self {
+        Self {
+            valid: true,
+            key_id: None,
+            rate_limit_config: None,
+            forward_headers: HashMap::new(),
+            scopes: Vec::new(),
         }
     }
 }
 
 /// Configuration for API key middleware
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct ApiKeyConfig {
     pub header_name: String,
-    /// TTL for caching API keys validated by custom validator (in seconds)
+    /// TTL for caching API keys validated by custom validator (in seconds). Validators get
+    /// this config, so they can pass it on to `ApiKeyStore::try_cache_key`.
     pub cache_ttl_seconds: u64,
 }
 
@@ -151,11 +502,228 @@ impl Default for ApiKeyConfig {
     }
 }
 
+/// Response header a handler sets to report whether a request counts as a success
+/// for `reset_on_success`, e.g. `X-Auth-Result: ok`
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ResetOnSuccessHeader {
+    pub name: String,
+    /// Value meaning success; any other value means failure
+    pub success_value: String,
+}
+
+impl ResetOnSuccessHeader {
+    pub fn new(name: impl Into<String>, success_value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            success_value: success_value.into(),
+        }
+    }
+}
+
+/// Cost of a request in units of quota, set as a request extension by an outer layer.
+/// Takes precedence over `BarnacleLayerBuilder::with_request_cost`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestCost(pub u32);
+
+/// Quota left after counting the current request, set as a request extension so handlers
+/// can read it with `Extension<RateLimitSnapshot>`. Absent when the request wasn't counted,
+/// e.g. a repeated idempotency key or a store error under a fail-open policy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimitSnapshot {
+    pub remaining: u32,
+    pub limit: u32,
+    /// Time until the quota is fully restored, when the store knows it
+    pub reset_after: Option<Duration>,
+}
+
+/// Cost of a request as reported by the handler, set as a response extension.
+/// Only honored when the layer is built with `with_response_cost(true)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResponseCost(pub u32);
+
+/// Configuration for counting retried requests only once
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct IdempotencyConfig {
+    /// Header carrying the client's idempotency key
+    pub header_name: String,
+    /// How long a seen idempotency key is remembered
+    #[serde(with = "crate::duration_serde")]
+    pub ttl: Duration,
+}
+
+impl IdempotencyConfig {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_header_name(mut self, header_name: impl Into<String>) -> Self {
+        self.header_name = header_name.into();
+        self
+    }
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            header_name: "idempotency-key".to_string(),
+            ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Configuration for limiting the number of concurrent in-flight requests per key
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    /// Maximum number of requests that may be in flight at the same time
+    pub max_in_flight: u32,
+    /// Expiry applied to the in-flight counter so a crashed process cannot hold slots forever
+    #[serde(with = "crate::duration_serde")]
+    pub safety_ttl: Duration,
+}
+
+impl ConcurrencyConfig {
+    pub fn new(max_in_flight: u32) -> Self {
+        Self {
+            max_in_flight,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_safety_ttl(mut self, safety_ttl: Duration) -> Self {
+        self.safety_ttl = safety_ttl;
+        self
+    }
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 10,
+            safety_ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Configuration for request-id correlation in logs and error responses
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RequestIdConfig {
+    /// Header carrying the request id
+    pub header_name: String,
+    /// Echo the request id in JSON error bodies as `error.request_id`
+    pub include_in_error_body: bool,
+}
+
+impl RequestIdConfig {
+    pub fn new(header_name: impl Into<String>, include_in_error_body: bool) -> Self {
+        Self {
+            header_name: header_name.into(),
+            include_in_error_body,
+        }
+    }
+}
+
+impl Default for RequestIdConfig {
+    fn default() -> Self {
+        Self {
+            header_name: "x-request-id".to_string(),
+            include_in_error_body: false,
+        }
+    }
+}
+
+/// Complete layer configuration that can be declared in a config file and applied with
+/// `BarnacleLayerBuilder::with_layer_config`. Every section is optional; an absent
+/// section leaves the corresponding feature off (or at its default).
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BarnacleLayerConfig {
+    /// Per-endpoint limit, see `BarnacleLayerBuilder::with_config`
+    pub limit: BarnacleConfig,
+    pub api_key: Option<ApiKeyConfig>,
+    pub api_key_global_limit: Option<BarnacleConfig>,
+    /// Limit on all keys together per endpoint, see `BarnacleLayerBuilder::with_global_config`
+    pub global_limit: Option<BarnacleConfig>,
+    pub failure_limit: Option<BarnacleConfig>,
+    pub request_id: Option<RequestIdConfig>,
+    pub concurrency: Option<ConcurrencyConfig>,
+    pub idempotency: Option<IdempotencyConfig>,
+    pub reset_on_success_header: Option<ResetOnSuccessHeader>,
+    pub grace_requests: u32,
+    pub retry_after_jitter: Option<f64>,
+    pub response_cost: bool,
+    pub refund_on_panic: bool,
+    pub reset_if_unchanged: bool,
+    pub scope_header: bool,
+    pub header_style: HeaderStyle,
+    pub error_format: ErrorFormat,
+    pub retry_after_on_success: bool,
+    pub store_error_policy: StoreErrorPolicy,
+    pub payload_key_required: bool,
+    pub no_count_statuses: Vec<u16>,
+    pub max_body_bytes: Option<usize>,
+    /// Queue capacity for resets run in the background, see `BarnacleLayerBuilder::with_background_reset`
+    pub background_reset: Option<usize>,
+    pub trusted_proxies: Option<crate::trusted_proxy::TrustedProxyConfig>,
+    pub ip_key_prefix: Option<crate::trusted_proxy::IpKeyPrefix>,
+    /// Header set by an upstream auth gateway, see `BarnacleLayerBuilder::with_trusted_identity_header`
+    pub trusted_identity_header: Option<String>,
+    /// Scope validated keys must have, see `BarnacleLayerBuilder::with_required_scope`
+    pub required_scope: Option<String>,
+    pub access_control: Option<AccessControl>,
+    /// Key routes by their pattern rather than the raw path, see `BarnacleLayerBuilder::with_use_matched_path`
+    pub use_matched_path: Option<bool>,
+    /// Query parameter read when the API key header is absent, see `BarnacleLayerBuilder::with_api_key_query_param`
+    pub api_key_query_param: Option<String>,
+}
+
+/// Keys that skip rate limiting or are always rejected, see `BarnacleLayerBuilder::with_access_control`.
+/// A key on both lists is rejected.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AccessControl {
+    /// Keys passed through without being counted, e.g. internal services
+    pub allowlist: HashSet<BarnacleKey>,
+    /// Keys rejected with a 403 before being counted
+    pub denylist: HashSet<BarnacleKey>,
+}
+
+impl AccessControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow(mut self, key: BarnacleKey) -> Self {
+        self.allowlist.insert(key);
+        self
+    }
+
+    pub fn deny(mut self, key: BarnacleKey) -> Self {
+        self.denylist.insert(key);
+        self
+    }
+
+    pub fn is_allowlisted(&self, key: &BarnacleKey) -> bool {
+        self.allowlist.contains(key) && !self.is_denylisted(key)
+    }
+
+    pub fn is_denylisted(&self, key: &BarnacleKey) -> bool {
+        self.denylist.contains(key)
+    }
+}
+
 /// Per-key rate limiting configuration for static configurations
 #[derive(Clone, Debug)]
 pub struct StaticApiKeyConfig {
     pub key_configs: HashMap<String, BarnacleConfig>,
     pub default_config: BarnacleConfig,
+    /// Scopes granted per key, keys without an entry have none
+    pub key_scopes: HashMap<String, Vec<String>>,
 }
 
 impl StaticApiKeyConfig {
@@ -163,6 +731,7 @@ impl StaticApiKeyConfig {
         Self {
             key_configs: HashMap::new(),
             default_config,
+            key_scopes: HashMap::new(),
         }
     }
 
@@ -171,6 +740,25 @@ impl StaticApiKeyConfig {
         self
     }
 
+    /// Like `with_key_config`, with the limit given as a rate string such as `"1000/hour"`
+    pub fn with_key_config_str(self, api_key: impl Into<String>, rate: &str) -> Result<Self, crate::error::BarnacleError> {
+        let config = rate.parse()?;
+        Ok(self.with_key_config(api_key.into(), config))
+    }
+
+    /// Grant scopes to a key, see `BarnacleLayerBuilder::with_required_scope`
+    pub fn with_key_scopes<I, S>(mut self, api_key: impl Into<String>, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.key_scopes
+            .entry(api_key.into())
+            .or_default()
+            .extend(scopes.into_iter().map(Into::into));
+        self
+    }
+
     pub fn get_config_for_key(&self, api_key: &str) -> &BarnacleConfig {
         self.key_configs
             .get(api_key)
@@ -320,3 +320,93 @@ mod api_keys {
         assert!(status.is_client_error() || status.is_server_error());
     }
 }
+
+mod key_cache_ttl {
+    use super::*;
+    use barnacle_rs::{ApiKeyStore, BarnacleConfig, RedisApiKeyStore};
+
+    fn store() -> RedisApiKeyStore {
+        let pool = RedisConfig::from_url("redis://127.0.0.1/")
+            .create_pool(None)
+            .expect("Failed to create Redis pool");
+        RedisApiKeyStore::new(pool).with_key_prefix(format!("barnacle:api_keys:ttl-{}", Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_cached_key_expires_after_ttl() {
+        init_tracing();
+        let store = store();
+        let api_key_config = ApiKeyConfig {
+            cache_ttl_seconds: 1,
+            ..Default::default()
+        };
+
+        store
+            .try_cache_key("cached-key", &BarnacleConfig::default(), Some(api_key_config.cache_ttl_seconds))
+            .await
+            .unwrap();
+        assert!(store.validate_key("cached-key").await.valid);
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!store.validate_key("cached-key").await.valid);
+    }
+
+    #[tokio::test]
+    async fn test_default_ttl_applies_without_explicit_ttl() {
+        init_tracing();
+        let store = store().with_default_ttl(1);
+
+        store
+            .try_cache_key("default-ttl-key", &BarnacleConfig::default(), None)
+            .await
+            .unwrap();
+        assert!(store.validate_key("default-ttl-key").await.valid);
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!store.validate_key("default-ttl-key").await.valid);
+    }
+}
+
+mod key_hashing {
+    use super::*;
+    use barnacle_rs::{ApiKeyStore, BarnacleConfig, RedisApiKeyStore};
+
+    async fn keys_matching(pattern: &str) -> Vec<String> {
+        let pool = RedisConfig::from_url("redis://127.0.0.1/")
+            .create_pool(None)
+            .expect("Failed to create Redis pool");
+        let mut conn = pool.get().await.expect("Failed to get Redis connection");
+        deadpool_redis::redis::cmd("KEYS")
+            .arg(pattern)
+            .query_async(&mut conn)
+            .await
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn test_hashed_key_round_trip() {
+        init_tracing();
+        let prefix = format!("barnacle:api_keys:hashed-{}", Uuid::new_v4());
+        let pool = RedisConfig::from_url("redis://127.0.0.1/")
+            .create_pool(None)
+            .expect("Failed to create Redis pool");
+        let store = RedisApiKeyStore::new(pool)
+            .with_key_prefix(prefix.clone())
+            .with_key_hashing(true);
+        let config = BarnacleConfig {
+            max_requests: 7,
+            ..Default::default()
+        };
+
+        store.save_key("secret-key", Some(&config), Some(60)).await.unwrap();
+
+        let result = store.validate_key("secret-key").await;
+        assert!(result.valid);
+        assert_eq!(result.rate_limit_config.map(|c| c.max_requests), Some(7));
+        assert!(!store.validate_key("other-key").await.valid);
+
+        let keys = keys_matching(&format!("{}:*", prefix)).await;
+        assert_eq!(keys.len(), 2);
+        assert!(keys.iter().all(|key| !key.contains("secret-key")));
+    }
+}
@@ -0,0 +1,13 @@
+// Custom stores only need the bounds documented on `BarnacleStore` to be usable in a layer
+#[test]
+fn test_custom_store_compiles() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/custom_store.rs");
+}
+
+// Layers accept any request body and hand `axum::body::Body` on, so they stack in any order
+#[test]
+fn test_stacked_layers_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/stacked_layers.rs");
+}
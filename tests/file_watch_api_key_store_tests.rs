@@ -0,0 +1,53 @@
+#![cfg(feature = "file-watch")]
+
+use barnacle_rs::{ApiKeyStore, FileWatchApiKeyStore};
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn key_file(contents: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("barnacle-keys-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("keys.json");
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+// Waits for the watcher to pick up a change
+async fn eventually_valid(store: &FileWatchApiKeyStore, api_key: &str, valid: bool) {
+    for _ in 0..50 {
+        if store.validate_key(api_key).await.valid == valid {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("{} never became {}", api_key, if valid { "valid" } else { "invalid" });
+}
+
+#[tokio::test]
+async fn test_file_changes_update_validation() {
+    let path = key_file(r#"{"dev-key": {"max_requests": 5, "window": "1m"}}"#);
+    let store = FileWatchApiKeyStore::new(&path).unwrap();
+
+    let result = store.validate_key("dev-key").await;
+    assert!(result.valid);
+    assert_eq!(result.rate_limit_config.unwrap().max_requests, 5);
+    assert!(!store.validate_key("new-key").await.valid);
+
+    std::fs::write(&path, r#"{"new-key": {"max_requests": 20, "window": "1m"}}"#).unwrap();
+    eventually_valid(&store, "new-key", true).await;
+    eventually_valid(&store, "dev-key", false).await;
+    assert_eq!(store.get_rate_limit_config("new-key").await.unwrap().max_requests, 20);
+
+    // A broken file keeps the last good keys
+    std::fs::write(&path, "{ not json").unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(store.validate_key("new-key").await.valid);
+    assert!(store.reload().is_err());
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_missing_file_is_an_error() {
+    assert!(FileWatchApiKeyStore::new("/nonexistent/barnacle-keys.json").is_err());
+}
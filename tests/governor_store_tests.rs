@@ -0,0 +1,96 @@
+#![cfg(feature = "governor")]
+
+use barnacle_rs::{BarnacleConfig, BarnacleContext, BarnacleError, BarnacleKey, BarnacleStore, GovernorStore, ResetOnSuccess};
+use std::time::Duration;
+
+fn config() -> BarnacleConfig {
+    BarnacleConfig { max_requests: 3, window: Duration::from_secs(60), reset_on_success: ResetOnSuccess::Not }
+}
+
+fn context(key: &str) -> BarnacleContext {
+    BarnacleContext { key: BarnacleKey::ApiKey(key.into()), path: "/governor".into(), method: "GET".into() }
+}
+
+#[cfg(test)]
+mod governor_store_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_allows_then_denies() {
+        let c = config();
+        let store = GovernorStore::from_config(&c).unwrap();
+        let ctx = context("gov-key");
+
+        for expected in [2, 1, 0] {
+            let result = store.increment(&ctx, &c).await.unwrap();
+            assert!(result.allowed);
+            assert_eq!(result.remaining, expected);
+        }
+
+        match store.increment(&ctx, &c).await {
+            Err(BarnacleError::RateLimitExceeded { remaining, retry_after, limit }) => {
+                assert_eq!(remaining, 0);
+                assert_eq!(limit, 3);
+                assert!((1..=20).contains(&retry_after));
+            }
+            other => panic!("Expected rate limit error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_isolated() {
+        let c = config();
+        let store = GovernorStore::from_config(&c).unwrap();
+        for _ in 0..3 { assert!(store.increment(&context("a"), &c).await.is_ok()); }
+        assert!(store.increment(&context("a"), &c).await.is_err());
+        assert!(store.increment(&context("b"), &c).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_reset_restores_quota() {
+        let c = config();
+        let store = GovernorStore::from_config(&c).unwrap();
+        let ctx = context("reset-key");
+        for _ in 0..3 { assert!(store.increment(&ctx, &c).await.is_ok()); }
+        assert!(store.increment(&ctx, &c).await.is_err());
+        store.reset(&ctx).await.unwrap();
+        for _ in 0..3 { assert!(store.increment(&ctx, &c).await.is_ok()); }
+        assert!(store.increment(&ctx, &c).await.is_err());
+    }
+
+    #[test]
+    fn test_from_config_rejects_zero_requests() {
+        let c = BarnacleConfig { max_requests: 0, ..config() };
+        assert!(matches!(GovernorStore::from_config(&c), Err(BarnacleError::Configuration { .. })));
+    }
+
+    #[test]
+    fn test_from_config_names_the_period_constraint() {
+        let c = BarnacleConfig { max_requests: 10, window: Duration::from_nanos(5), ..config() };
+        match GovernorStore::from_config(&c) {
+            Err(BarnacleError::Configuration { message }) => assert!(message.contains("1ns per request"), "{}", message),
+            other => panic!("Expected configuration error, got {:?}", other.err()),
+        }
+
+        let c = BarnacleConfig { window: Duration::ZERO, ..config() };
+        match GovernorStore::from_config(&c) {
+            Err(BarnacleError::Configuration { message }) => assert!(message.contains("greater than zero"), "{}", message),
+            other => panic!("Expected configuration error, got {:?}", other.err()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reset_key_is_fresh_after_pruning() {
+        let c = BarnacleConfig { max_requests: 2, window: Duration::from_millis(100), ..config() };
+        let store = GovernorStore::from_config(&c).unwrap();
+        let ctx = context("pruned-key");
+        for _ in 0..2 { assert!(store.increment(&ctx, &c).await.is_ok()); }
+        store.reset(&ctx).await.unwrap();
+        assert!(store.increment(&ctx, &c).await.is_ok());
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        store.retain_recent();
+        for _ in 0..2 { assert!(store.increment(&ctx, &c).await.is_ok()); }
+        assert!(store.increment(&ctx, &c).await.is_err());
+    }
+}
@@ -0,0 +1,102 @@
+#![cfg(feature = "http-config")]
+
+use axum::{http::HeaderMap, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use barnacle_rs::{ApiKeyStore, BarnacleConfig, HttpApiKeyStore, ResetOnSuccess};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Serves key configs for "live-key" only and counts how often it is asked
+async fn start_config_server() -> (String, Arc<AtomicUsize>) {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let counter = fetches.clone();
+    let app = Router::new().route(
+        "/keys",
+        get(move |headers: HeaderMap| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if headers.get("x-api-key").and_then(|v| v.to_str().ok()) != Some("live-key") {
+                    return StatusCode::NOT_FOUND.into_response();
+                }
+                Json(json!({
+                    "valid": true,
+                    "key_id": "tenant-7",
+                    "rate_limit_config": { "max_requests": 25, "window": "30s", "reset_on_success": "Not" },
+                    "forward_headers": { "x-plan": "pro" },
+                }))
+                .into_response()
+            }
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind config server");
+    let addr = listener.local_addr().expect("Failed to get server address");
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.expect("Config server failed");
+    });
+
+    (format!("http://{}/keys", addr), fetches)
+}
+
+// An endpoint nothing listens on
+async fn unreachable_endpoint() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    format!("http://{}/keys", addr)
+}
+
+#[cfg(test)]
+mod http_api_key_store_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_config_applied_and_cached() {
+        let (url, fetches) = start_config_server().await;
+        let store = HttpApiKeyStore::new(url);
+
+        let result = store.validate_key("live-key").await;
+        assert!(result.valid);
+        assert_eq!(result.key_id.as_deref(), Some("tenant-7"));
+        assert_eq!(result.forward_headers.get("x-plan").map(String::as_str), Some("pro"));
+        let config = result.rate_limit_config.unwrap();
+        assert_eq!(config.max_requests, 25);
+        assert_eq!(config.window, Duration::from_secs(30));
+
+        assert!(store.validate_key("live-key").await.valid);
+        assert!(!store.validate_key("unknown-key").await.valid);
+        assert!(!store.validate_key("unknown-key").await.valid);
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_expires_after_ttl() {
+        let (url, fetches) = start_config_server().await;
+        let store = HttpApiKeyStore::new(url).with_cache_ttl(Duration::ZERO);
+
+        assert!(store.validate_key("live-key").await.valid);
+        assert!(store.validate_key("live-key").await.valid);
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_service_down_uses_fail_policy() {
+        let url = unreachable_endpoint().await;
+        let default_config = BarnacleConfig {
+            max_requests: 3,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+
+        let closed = HttpApiKeyStore::new(url.clone());
+        assert!(!closed.validate_key("live-key").await.valid);
+
+        let open = HttpApiKeyStore::new(url).with_fail_open(true).with_default_config(default_config);
+        let result = open.validate_key("live-key").await;
+        assert!(result.valid);
+        assert_eq!(result.rate_limit_config.unwrap().max_requests, 3);
+    }
+}
@@ -0,0 +1,107 @@
+#![cfg(feature = "jwks")]
+
+use axum::{routing::get, Json, Router};
+use barnacle_rs::{ApiKeyStore, JwksApiKeyStore};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECRET: &[u8] = b"barnacle-jwks-test-secret-key-033";
+// SECRET as unpadded base64url, as it appears in the JWKS
+const SECRET_B64: &str = "YmFybmFjbGUtandrcy10ZXN0LXNlY3JldC1rZXktMDMz";
+
+// Serves a local JWKS with a single HS256 key and counts how often it is fetched
+async fn start_jwks_server() -> (String, Arc<AtomicUsize>) {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let counter = fetches.clone();
+    let app = Router::new().route(
+        "/.well-known/jwks.json",
+        get(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                Json(json!({
+                    "keys": [{ "kty": "oct", "kid": "test-key", "alg": "HS256", "k": SECRET_B64 }]
+                }))
+            }
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind JWKS server");
+    let addr = listener.local_addr().expect("Failed to get server address");
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.expect("JWKS server failed");
+    });
+
+    (format!("http://{}/.well-known/jwks.json", addr), fetches)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn token(exp: u64) -> String {
+    let mut header = Header::new(jsonwebtoken::Algorithm::HS256);
+    header.kid = Some("test-key".to_string());
+    let claims = json!({
+        "sub": "tenant-42",
+        "exp": exp,
+        "rate_limit": { "max_requests": 7, "window_seconds": 30 },
+    });
+    encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+}
+
+#[cfg(test)]
+mod jwks_api_key_store_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_signed_token_validates_with_claims_config() {
+        let (url, fetches) = start_jwks_server().await;
+        let store = JwksApiKeyStore::new(url);
+
+        let result = store.validate_key(&token(now() + 3600)).await;
+        assert!(result.valid);
+        assert_eq!(result.key_id.as_deref(), Some("tenant-42"));
+        let config = result.rate_limit_config.unwrap();
+        assert_eq!(config.max_requests, 7);
+        assert_eq!(config.window, Duration::from_secs(30));
+
+        // The key set is cached between validations
+        assert!(store.validate_key(&token(now() + 3600)).await.valid);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_fails() {
+        let (url, _fetches) = start_jwks_server().await;
+        let store = JwksApiKeyStore::new(url);
+
+        assert!(!store.validate_key(&token(now() - 3600)).await.valid);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_signature_and_garbage_fail() {
+        let (url, _fetches) = start_jwks_server().await;
+        let store = JwksApiKeyStore::new(url);
+
+        let mut header = Header::new(jsonwebtoken::Algorithm::HS256);
+        header.kid = Some("test-key".to_string());
+        let forged = encode(&header, &json!({ "sub": "x", "exp": now() + 3600 }), &EncodingKey::from_secret(b"other")).unwrap();
+        assert!(!store.validate_key(&forged).await.valid);
+        assert!(!store.validate_key("not-a-jwt").await.valid);
+    }
+
+    #[tokio::test]
+    async fn test_key_set_refreshed_after_interval() {
+        let (url, fetches) = start_jwks_server().await;
+        let store = JwksApiKeyStore::new(url).with_refresh_interval(Duration::ZERO);
+
+        assert!(store.validate_key(&token(now() + 3600)).await.valid);
+        assert!(store.validate_key(&token(now() + 3600)).await.valid);
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,271 @@
+use barnacle_rs::{
+    BarnacleConfig, BarnacleContext, BarnacleError, BarnacleKey, BarnacleStore, Clock,
+    InMemoryBarnacleStore, ResetOnSuccess,
+};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+// Clock that only moves when the test advances it
+#[derive(Clone)]
+struct ManualClock(Arc<Mutex<SystemTime>>);
+
+impl ManualClock {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))))
+    }
+
+    fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+fn context(ip: &str) -> BarnacleContext {
+    BarnacleContext {
+        key: BarnacleKey::Ip(ip.to_string()),
+        path: "/api/search".to_string(),
+        method: "GET".to_string(),
+    }
+}
+
+fn config(max_requests: u32) -> BarnacleConfig {
+    BarnacleConfig { max_requests, window: Duration::from_secs(60), reset_on_success: ResetOnSuccess::Not }
+}
+
+#[cfg(test)]
+mod memory_store_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_window_expiry() {
+        let clock = ManualClock::new();
+        let store = InMemoryBarnacleStore::new().with_clock(clock.clone());
+
+        let first = store.increment(&context("10.0.0.1"), &config(2)).await.unwrap();
+        assert_eq!((first.remaining, first.first_seen), (1, Some(true)));
+        store.increment(&context("10.0.0.1"), &config(2)).await.unwrap();
+
+        clock.advance(Duration::from_secs(45));
+        match store.increment(&context("10.0.0.1"), &config(2)).await {
+            Err(BarnacleError::RateLimitExceeded { retry_after, .. }) => assert_eq!(retry_after, 15),
+            other => panic!("expected rate limit error, got {:?}", other.map(|r| r.remaining)),
+        }
+
+        clock.advance(Duration::from_secs(15));
+        let next_window = store.increment(&context("10.0.0.1"), &config(2)).await.unwrap();
+        assert_eq!(next_window.remaining, 1);
+        assert_eq!((next_window.first_seen, next_window.window_reset), (Some(false), Some(true)));
+        assert_eq!(next_window.reset_after, Some(Duration::from_secs(60)));
+    }
+
+    #[tokio::test]
+    async fn test_reset_many_clears_each_context() {
+        let store = InMemoryBarnacleStore::new();
+        let contexts = [context("10.0.0.1"), context("10.0.0.2")];
+        for context in contexts.iter().chain([&context("10.0.0.3")]) {
+            store.increment(context, &config(2)).await.unwrap();
+        }
+
+        store.reset_many(&contexts).await.unwrap();
+        for context in &contexts {
+            assert_eq!(store.increment(context, &config(2)).await.unwrap().remaining, 1);
+        }
+        assert_eq!(store.increment(&context("10.0.0.3"), &config(2)).await.unwrap().remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn test_increment_by_rejects_costs_that_do_not_fit() {
+        let store = InMemoryBarnacleStore::new();
+        assert_eq!(store.increment_by(&context("10.0.0.1"), &config(10), 5).await.unwrap().remaining, 5);
+        assert_eq!(store.increment_by(&context("10.0.0.1"), &config(10), 5).await.unwrap().remaining, 0);
+        assert!(matches!(
+            store.increment_by(&context("10.0.0.1"), &config(10), 5).await,
+            Err(BarnacleError::RateLimitExceeded { .. })
+        ));
+
+        // A rejected cost is not counted
+        store.increment_by(&context("10.0.0.2"), &config(10), 8).await.unwrap();
+        assert!(store.increment_by(&context("10.0.0.2"), &config(10), 3).await.is_err());
+        assert_eq!(store.increment(&context("10.0.0.2"), &config(10)).await.unwrap().remaining, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reset_and_gc() {
+        let clock = ManualClock::new();
+        let store = InMemoryBarnacleStore::new().with_clock(clock.clone());
+
+        for ip in ["10.0.0.1", "10.0.0.2", "10.0.0.3"] {
+            store.increment(&context(ip), &config(1)).await.unwrap();
+        }
+        store.reset(&context("10.0.0.1")).await.unwrap();
+        assert!(store.increment(&context("10.0.0.1"), &config(1)).await.is_ok());
+        assert!(store.increment(&context("10.0.0.2"), &config(1)).await.is_err());
+
+        // Nothing to sweep until the windows expire
+        assert_eq!(store.gc(), 0);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(store.gc(), 3);
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_peek_reports_without_counting() {
+        let store = InMemoryBarnacleStore::new();
+
+        store.increment(&context("10.0.0.1"), &config(2)).await.unwrap();
+        let status = store.peek(&context("10.0.0.1"), &config(2)).await.unwrap();
+        assert_eq!((status.allowed, status.remaining), (true, 1));
+        let status = store.peek(&context("10.0.0.1"), &config(2)).await.unwrap();
+        assert_eq!(status.remaining, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_increments_never_exceed_limit() {
+        let store = InMemoryBarnacleStore::new();
+
+        let tasks: Vec<_> = (0..100)
+            .map(|i| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    // Two keys interleaved, sharing nothing but the store
+                    let ip = if i % 2 == 0 { "10.0.0.1" } else { "10.0.0.2" };
+                    store.increment(&context(ip), &config(20)).await.is_ok()
+                })
+            })
+            .collect();
+
+        let mut allowed = 0;
+        for task in tasks {
+            if task.await.unwrap() {
+                allowed += 1;
+            }
+        }
+        assert_eq!(allowed, 40);
+    }
+}
+
+#[cfg(test)]
+mod in_flight_tests {
+    use super::*;
+    use barnacle_rs::{ConcurrencyConfig, InFlightGuard};
+
+    fn concurrency(max_in_flight: u32) -> ConcurrencyConfig {
+        ConcurrencyConfig { max_in_flight, safety_ttl: Duration::from_secs(30) }
+    }
+
+    #[tokio::test]
+    async fn test_holds_over_the_cap_are_refused() {
+        let store = InMemoryBarnacleStore::new();
+        let config = concurrency(3);
+
+        let mut guards = Vec::new();
+        for _ in 0..3 {
+            guards.push(InFlightGuard::acquire(&store, &context("10.0.0.1"), &config).await.unwrap().unwrap());
+        }
+        assert!(InFlightGuard::acquire(&store, &context("10.0.0.1"), &config).await.unwrap().is_none());
+        // Other keys have their own slots
+        assert!(InFlightGuard::acquire(&store, &context("10.0.0.2"), &config).await.unwrap().is_some());
+
+        guards.pop().unwrap().release().await.unwrap();
+        assert!(InFlightGuard::acquire(&store, &context("10.0.0.1"), &config).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_guard_frees_its_slot() {
+        let store = InMemoryBarnacleStore::new();
+        let config = concurrency(1);
+
+        let guard = InFlightGuard::acquire(&store, &context("10.0.0.1"), &config).await.unwrap();
+        assert!(guard.is_some());
+        drop(guard);
+
+        let mut acquired = None;
+        for _ in 0..50 {
+            acquired = InFlightGuard::acquire(&store, &context("10.0.0.1"), &config).await.unwrap();
+            if acquired.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(acquired.is_some(), "release runs on a spawned task after drop");
+    }
+
+    #[tokio::test]
+    async fn test_leaked_slots_expire_with_safety_ttl() {
+        let clock = ManualClock::new();
+        let store = InMemoryBarnacleStore::new().with_clock(clock.clone());
+
+        assert!(store.acquire_in_flight(&context("10.0.0.1"), 1, Duration::from_secs(30)).await.unwrap());
+        assert!(!store.acquire_in_flight(&context("10.0.0.1"), 1, Duration::from_secs(30)).await.unwrap());
+
+        clock.advance(Duration::from_secs(31));
+        assert!(store.acquire_in_flight(&context("10.0.0.1"), 1, Duration::from_secs(30)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_release_never_goes_below_zero() {
+        let store = InMemoryBarnacleStore::new();
+
+        store.release_in_flight(&context("10.0.0.1")).await.unwrap();
+        assert!(store.acquire_in_flight(&context("10.0.0.1"), 1, Duration::from_secs(30)).await.unwrap());
+        assert!(!store.acquire_in_flight(&context("10.0.0.1"), 1, Duration::from_secs(30)).await.unwrap());
+    }
+}
+
+#[cfg(test)]
+mod idempotency_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_concurrent_claims_succeed_once() {
+        let store = InMemoryBarnacleStore::new();
+
+        let claims: Vec<_> = (0..16)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    store.claim_idempotency_key(&context("10.0.0.1"), "order-1", Duration::from_secs(60)).await.unwrap()
+                })
+            })
+            .collect();
+        let mut claimed = 0;
+        for claim in claims {
+            if claim.await.unwrap() {
+                claimed += 1;
+            }
+        }
+        assert_eq!(claimed, 1);
+        assert!(store.idempotency_key_seen(&context("10.0.0.1"), "order-1").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_released_key_can_be_claimed_again() {
+        let store = InMemoryBarnacleStore::new();
+        let ttl = Duration::from_secs(60);
+
+        assert!(store.claim_idempotency_key(&context("10.0.0.1"), "order-1", ttl).await.unwrap());
+        assert!(!store.claim_idempotency_key(&context("10.0.0.1"), "order-1", ttl).await.unwrap());
+        // Keys are per context
+        assert!(store.claim_idempotency_key(&context("10.0.0.2"), "order-1", ttl).await.unwrap());
+
+        store.release_idempotency_key(&context("10.0.0.1"), "order-1").await.unwrap();
+        assert!(store.claim_idempotency_key(&context("10.0.0.1"), "order-1", ttl).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_claims_expire_with_ttl() {
+        let clock = ManualClock::new();
+        let store = InMemoryBarnacleStore::new().with_clock(clock.clone());
+        let ttl = Duration::from_secs(60);
+
+        assert!(store.claim_idempotency_key(&context("10.0.0.1"), "order-1", ttl).await.unwrap());
+        clock.advance(Duration::from_secs(61));
+        assert!(!store.idempotency_key_seen(&context("10.0.0.1"), "order-1").await.unwrap());
+        assert!(store.claim_idempotency_key(&context("10.0.0.1"), "order-1", ttl).await.unwrap());
+    }
+}
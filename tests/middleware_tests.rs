@@ -0,0 +1,3119 @@
+use axum::{
+    body::Body,
+    http::{request::Parts, Request, StatusCode},
+    response::Response,
+    routing::get,
+    Router,
+};
+use barnacle_rs::{
+    ApiKeyConfig, BarnacleConfig, BarnacleContext, BarnacleError, BarnacleKey, BarnacleLayer,
+    BarnacleResult, BarnacleStore, ConcurrencyConfig, RequestIdConfig, ResetOnSuccess, ResponseCost,
+    IdempotencyConfig, ObserveOnlyLayer, ApiKeyValidationResult, FixedClock,
+    ResetOnSuccessHeader, KeyExtractable, KeyUsage, BarnacleLayerConfig, HeaderStyle,
+    InMemoryBarnacleStore, rate_limit_response, TrustedProxyConfig, IpKeyPrefix, StoreErrorPolicy, CountingObserver, JsonKeyPath,
+    StaticConfigResolver, StoreHealthLayer, ErrorFormat, AccessControl, ApiKeyRateLimitLayer, StaticApiKeyConfig, StaticApiKeyStore,
+};
+use http_body_util::BodyExt;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower::ServiceExt;
+
+// (key, path, method) -> count
+type Counters = Arc<Mutex<HashMap<(BarnacleKey, String, String), u32>>>;
+// (key, path, method, idempotency key)
+type IdempotencyKeys = Arc<Mutex<HashSet<(BarnacleKey, String, String, String)>>>;
+
+// Mock store for in-memory rate limiting that also records how often it is called
+#[derive(Clone, Default)]
+struct MockStore {
+    counters: Counters,
+    in_flight: Counters,
+    idempotency_keys: IdempotencyKeys,
+    calls: Arc<AtomicUsize>,
+}
+
+impl MockStore {
+    fn count(&self, key: BarnacleKey, path: &str, method: &str) -> u32 {
+        let counters = self.counters.lock().unwrap();
+        counters.get(&(key, path.to_string(), method.to_string())).copied().unwrap_or(0)
+    }
+
+    fn in_flight(&self, key: BarnacleKey, path: &str, method: &str) -> u32 {
+        let in_flight = self.in_flight.lock().unwrap();
+        in_flight.get(&(key, path.to_string(), method.to_string())).copied().unwrap_or(0)
+    }
+}
+
+#[async_trait::async_trait]
+impl BarnacleStore for MockStore {
+    async fn increment(&self, context: &BarnacleContext, config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+        self.increment_by(context, config, 1).await
+    }
+    async fn increment_by(&self, context: &BarnacleContext, config: &BarnacleConfig, cost: u32) -> Result<BarnacleResult, BarnacleError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        // Lets concurrent requests interleave between store calls, like a networked store
+        tokio::task::yield_now().await;
+        let mut counters = self.counters.lock().unwrap();
+        let k = (context.key.clone(), context.path.clone(), context.method.clone());
+        let count = counters.entry(k).or_insert(0);
+        if count.saturating_add(cost.max(1)) > config.max_requests {
+            let remaining = config.max_requests.saturating_sub(*count);
+            return Err(BarnacleError::rate_limit_exceeded(remaining, config.window.as_secs(), config.max_requests));
+        }
+        *count += cost.max(1);
+        Ok(BarnacleResult { allowed: true, remaining: config.max_requests - *count, retry_after: None, reset_after: None, first_seen: None, window_reset: None })
+    }
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let mut counters = self.counters.lock().unwrap();
+        let k = (context.key.clone(), context.path.clone(), context.method.clone());
+        counters.remove(&k);
+        Ok(())
+    }
+    async fn usage_for_key(&self, context: &BarnacleContext) -> Result<KeyUsage, BarnacleError> {
+        let counters = self.counters.lock().unwrap();
+        let k = (context.key.clone(), context.path.clone(), context.method.clone());
+        Ok(KeyUsage { count: counters.get(&k).copied().unwrap_or(0), retry_after: None, metadata: HashMap::new() })
+    }
+    async fn decrement(&self, context: &BarnacleContext, n: u32) -> Result<(), BarnacleError> {
+        let mut counters = self.counters.lock().unwrap();
+        let k = (context.key.clone(), context.path.clone(), context.method.clone());
+        if let Some(count) = counters.get_mut(&k) {
+            *count = count.saturating_sub(n);
+        }
+        Ok(())
+    }
+    async fn acquire_in_flight(&self, context: &BarnacleContext, max_in_flight: u32, _ttl: Duration) -> Result<bool, BarnacleError> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let k = (context.key.clone(), context.path.clone(), context.method.clone());
+        let count = in_flight.entry(k).or_insert(0);
+        if *count >= max_in_flight {
+            return Ok(false);
+        }
+        *count += 1;
+        Ok(true)
+    }
+    async fn release_in_flight(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let k = (context.key.clone(), context.path.clone(), context.method.clone());
+        if let Some(count) = in_flight.get_mut(&k) {
+            *count = count.saturating_sub(1);
+        }
+        Ok(())
+    }
+    async fn claim_idempotency_key(&self, context: &BarnacleContext, idempotency_key: &str, _ttl: Duration) -> Result<bool, BarnacleError> {
+        let mut keys = self.idempotency_keys.lock().unwrap();
+        Ok(keys.insert((context.key.clone(), context.path.clone(), context.method.clone(), idempotency_key.to_string())))
+    }
+    async fn release_idempotency_key(&self, context: &BarnacleContext, idempotency_key: &str) -> Result<(), BarnacleError> {
+        let mut keys = self.idempotency_keys.lock().unwrap();
+        keys.remove(&(context.key.clone(), context.path.clone(), context.method.clone(), idempotency_key.to_string()));
+        Ok(())
+    }
+}
+
+fn config(max_requests: u32) -> BarnacleConfig {
+    BarnacleConfig { max_requests, window: Duration::from_secs(60), reset_on_success: ResetOnSuccess::Not }
+}
+
+async fn require_api_key(api_key: String, _config: ApiKeyConfig, _parts: Arc<Parts>, _state: ()) -> Result<(), BarnacleError> {
+    if api_key.is_empty() {
+        Err(BarnacleError::ApiKeyMissing)
+    } else {
+        Ok(())
+    }
+}
+
+fn request(path: &str, api_key: Option<&str>) -> Request<Body> {
+    let mut builder = Request::builder().uri(path).method("GET");
+    if let Some(key) = api_key {
+        builder = builder.header("x-api-key", key);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+async fn send(app: &Router, req: Request<Body>) -> Response {
+    app.clone().oneshot(req).await.unwrap()
+}
+
+async fn body_json(response: Response) -> serde_json::Value {
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+fn header(response: &Response, name: &str) -> Option<String> {
+    response.headers().get(name).map(|v| v.to_str().unwrap().to_string())
+}
+
+async fn ok_handler() -> &'static str {
+    "ok"
+}
+
+// Collects formatted log lines written by the subscriber
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl CapturedLogs {
+    // Routes this thread's logs into the buffer until the guard is dropped
+    fn capture(&self) -> tracing::subscriber::DefaultGuard {
+        let writer = self.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::set_default(subscriber)
+    }
+
+    fn count(&self, needle: &str) -> usize {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).matches(needle).count()
+    }
+}
+
+mod api_key_global_limit {
+    use super::*;
+
+    fn app(store: MockStore, endpoint_limit: u32, global_limit: u32) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(endpoint_limit))
+            .with_api_key_global_config(config(global_limit))
+            .with_api_key_validator(require_api_key)
+            .with_state(())
+            .build()
+            .unwrap();
+        Router::new()
+            .route("/reports", get(ok_handler))
+            .route("/other", get(ok_handler))
+            .layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_limit_trips_before_global() {
+        let store = MockStore::default();
+        let app = app(store.clone(), 2, 10);
+
+        for _ in 0..2 {
+            let response = send(&app, request("/reports", Some("enterprise"))).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = send(&app, request("/reports", Some("enterprise"))).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("2"));
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["details"]["limit"], 2);
+
+        // The rejected request did not consume the global budget
+        assert_eq!(store.count(BarnacleKey::ApiKey("enterprise".into()), "*", "*"), 2);
+        let response = send(&app, request("/other", Some("enterprise"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn test_global_limit_spans_endpoints() {
+        let store = MockStore::default();
+        let app = app(store, 10, 3);
+
+        for path in ["/reports", "/other", "/reports"] {
+            let response = send(&app, request(path, Some("enterprise"))).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = send(&app, request("/other", Some("enterprise"))).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["details"]["limit"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_global_rejection_keeps_endpoint_quota() {
+        let store = MockStore::default();
+        let app = app(store.clone(), 10, 2);
+
+        for _ in 0..2 {
+            assert_eq!(send(&app, request("/reports", Some("enterprise"))).await.status(), StatusCode::OK);
+        }
+        for _ in 0..3 {
+            let response = send(&app, request("/reports", Some("enterprise"))).await;
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+        assert_eq!(store.count(BarnacleKey::ApiKey("enterprise".into()), "/reports", "GET"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_headers_report_binding_limit() {
+        let store = MockStore::default();
+        let app = app(store, 5, 2);
+
+        let response = send(&app, request("/reports", Some("enterprise"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("2"));
+        assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some("1"));
+    }
+}
+
+mod request_id {
+    use super::*;
+
+    fn app(request_id_config: RequestIdConfig) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(1))
+            .with_request_id_config(request_id_config)
+            .build()
+            .unwrap();
+        Router::new().route("/reports", get(ok_handler)).layer(layer)
+    }
+
+    fn request_with_id(header_name: &str, request_id: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/reports")
+            .method("GET")
+            .header(header_name, request_id)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_request_id_echoed_in_error_body() {
+        let app = app(RequestIdConfig::new("x-correlation-id", true));
+
+        let response = send(&app, request_with_id("x-correlation-id", "req-123")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, request_with_id("x-correlation-id", "req-124")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "X-Barnacle-Error").as_deref(), Some("true"));
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["request_id"], "req-124");
+        assert_eq!(body["error"]["code"], "RATE_LIMIT_EXCEEDED");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_not_echoed_by_default() {
+        let app = app(RequestIdConfig::default());
+
+        send(&app, request_with_id("x-request-id", "req-1")).await;
+        let response = send(&app, request_with_id("x-request-id", "req-2")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = body_json(response).await;
+        assert!(body["error"].get("request_id").is_none());
+    }
+}
+
+mod concurrency_limit {
+    use super::*;
+
+    fn app(store: MockStore, max_in_flight: u32) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(100))
+            .with_concurrency_config(ConcurrencyConfig::new(max_in_flight))
+            .build()
+            .unwrap();
+        Router::new()
+            .route("/reports", get(ok_handler))
+            .route("/panic", get(panic_handler))
+            .route("/slow", get(slow_handler))
+            .layer(layer)
+    }
+
+    async fn panic_handler() -> &'static str {
+        panic!("handler failure")
+    }
+
+    async fn slow_handler() -> &'static str {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        "ok"
+    }
+
+    fn fallback_key(path: &str) -> BarnacleKey {
+        BarnacleKey::Ip(format!("local:GET:{path}"))
+    }
+
+    async fn wait_for_release(store: &MockStore, path: &str) {
+        for _ in 0..50 {
+            if store.in_flight(fallback_key(path), path, "GET") == 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slot_released_when_handler_panics() {
+        let store = MockStore::default();
+        let app = app(store.clone(), 1);
+
+        let panicking = tokio::spawn({
+            let app = app.clone();
+            async move { send(&app, request("/panic", None)).await }
+        });
+        assert!(panicking.await.unwrap_err().is_panic());
+
+        wait_for_release(&store, "/panic").await;
+        assert_eq!(store.in_flight(fallback_key("/panic"), "/panic", "GET"), 0);
+        let panicking = tokio::spawn(async move { send(&app, request("/panic", None)).await });
+        assert!(panicking.await.unwrap_err().is_panic(), "slot must be free for the next request");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_requests_over_max_in_flight() {
+        let store = MockStore::default();
+        let app = app(store.clone(), 1);
+
+        let first = tokio::spawn({
+            let app = app.clone();
+            async move { send(&app, request("/slow", None)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let response = send(&app, request("/slow", None)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "CONCURRENCY_LIMIT_EXCEEDED");
+
+        assert_eq!(first.await.unwrap().status(), StatusCode::OK);
+        wait_for_release(&store, "/slow").await;
+        let response = send(&app, request("/slow", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_rejection_is_not_counted() {
+        let store = MockStore::default();
+        let app = app(store.clone(), 1);
+
+        let first = tokio::spawn({
+            let app = app.clone();
+            async move { send(&app, request("/slow", None)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        for _ in 0..3 {
+            assert_eq!(send(&app, request("/slow", None)).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+        assert_eq!(first.await.unwrap().status(), StatusCode::OK);
+        assert_eq!(store.count(fallback_key("/slow"), "/slow", "GET"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_concurrency_rejection_is_counted() {
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(100))
+            .with_concurrency_config(ConcurrencyConfig::new(1))
+            .with_idempotency_config(IdempotencyConfig::new(Duration::from_secs(60)))
+            .build()
+            .unwrap();
+        let app = Router::new().route("/slow", get(slow_handler)).layer(layer);
+        let slow_request = |idempotency_key: &str| {
+            Request::builder()
+                .uri("/slow")
+                .header("idempotency-key", idempotency_key)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let first = tokio::spawn({
+            let app = app.clone();
+            let request = slow_request("job-1");
+            async move { send(&app, request).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let response = send(&app, slow_request("job-2")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(first.await.unwrap().status(), StatusCode::OK);
+        wait_for_release(&store, "/slow").await;
+
+        // The rejected request never claimed its key, so the retry is counted
+        assert_eq!(send(&app, slow_request("job-2")).await.status(), StatusCode::OK);
+        assert_eq!(store.count(fallback_key("/slow"), "/slow", "GET"), 2);
+    }
+}
+
+mod store_health {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use tower::{BoxError, Layer};
+
+    // Store whose health can be toggled from the test
+    #[derive(Clone, Default)]
+    struct HealthStore {
+        inner: MockStore,
+        unhealthy: Arc<AtomicBool>,
+    }
+
+    #[async_trait::async_trait]
+    impl BarnacleStore for HealthStore {
+        async fn increment(&self, context: &BarnacleContext, config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+            self.inner.increment(context, config).await
+        }
+        async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+            self.inner.reset(context).await
+        }
+        fn health(&self) -> Result<(), BarnacleError> {
+            if self.unhealthy.load(Ordering::SeqCst) {
+                Err(BarnacleError::store_error("store offline"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn layer(store: HealthStore) -> BarnacleLayer<(), HealthStore, (), BarnacleError, ()> {
+        BarnacleLayer::builder().with_store(store).with_config(config(10)).build().unwrap()
+    }
+
+    fn inner_service() -> impl tower::Service<Request<Body>, Response = Response, Error = BoxError, Future = impl Send> + Clone + Send {
+        tower::service_fn(|_req: Request<Body>| async { Ok::<_, BoxError>(Response::new(Body::from("ok"))) })
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_store_fails_poll_ready() {
+        let store = HealthStore::default();
+        let mut service = StoreHealthLayer::new(store.clone()).layer(layer(store.clone()).layer(inner_service()));
+        assert!(ServiceExt::<Request<Body>>::ready(&mut service).await.is_ok());
+
+        store.unhealthy.store(true, Ordering::SeqCst);
+        let error = ServiceExt::<Request<Body>>::ready(&mut service).await.err().expect("poll_ready should fail");
+        let error = error.downcast::<BarnacleError>().expect("error should be a BarnacleError");
+        assert!(matches!(*error, BarnacleError::StoreError { .. }));
+
+        store.unhealthy.store(false, Ordering::SeqCst);
+        assert!(ServiceExt::<Request<Body>>::ready(&mut service).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_health_check_is_opt_in() {
+        let store = HealthStore::default();
+        store.unhealthy.store(true, Ordering::SeqCst);
+        let mut service = layer(store).layer(inner_service());
+        assert!(ServiceExt::<Request<Body>>::ready(&mut service).await.is_ok());
+    }
+
+    // Neither `Send` nor `StoreHealthError`
+    #[derive(Debug)]
+    struct LocalError(std::marker::PhantomData<std::rc::Rc<()>>);
+
+    #[tokio::test]
+    async fn test_inner_error_needs_no_health_conversion() {
+        let inner = tower::service_fn(|_req: Request<Body>| async { Ok::<_, LocalError>(Response::new(Body::from("ok"))) });
+        let service = layer(HealthStore::default()).layer(inner);
+        let response = service.oneshot(request("/reports", None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+mod response_cost {
+    use super::*;
+    use axum::{extract::Query, response::IntoResponse};
+
+    #[derive(serde::Deserialize)]
+    struct Cost {
+        cost: u32,
+    }
+
+    fn app(store: MockStore) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(10))
+            .with_response_cost(true)
+            .build()
+            .unwrap();
+        Router::new()
+            .route("/rows", get(header_cost_handler))
+            .route("/batch", get(extension_cost_handler))
+            .layer(layer)
+    }
+
+    async fn header_cost_handler(Query(Cost { cost }): Query<Cost>) -> impl IntoResponse {
+        ([("x-barnacle-cost", cost.to_string())], "ok")
+    }
+
+    async fn extension_cost_handler(Query(Cost { cost }): Query<Cost>) -> impl IntoResponse {
+        let mut response = "ok".into_response();
+        response.extensions_mut().insert(ResponseCost(cost));
+        response
+    }
+
+    #[tokio::test]
+    async fn test_reported_costs_deplete_quota() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+
+        for (cost, remaining) in [(3, "7"), (5, "2"), (1, "1")] {
+            let response = send(&app, request(&format!("/rows?cost={cost}"), None)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some(remaining));
+            assert!(header(&response, "x-barnacle-cost").is_none(), "cost header must not leak to clients");
+        }
+
+        // Cost exceeding the remaining quota exhausts it
+        let response = send(&app, request("/rows?cost=4", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some("0"));
+        let response = send(&app, request("/rows?cost=1", None)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_cost_from_response_extension() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+
+        let response = send(&app, request("/batch?cost=6", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some("4"));
+        assert_eq!(store.count(BarnacleKey::Ip("local:GET:/batch".into()), "/batch", "GET"), 6);
+        // One unit before the handler, the other five in a single call after it
+        assert_eq!(store.calls.load(Ordering::SeqCst), 2);
+    }
+}
+
+mod maintenance_mode {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn app(store: MockStore, until: SystemTime) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(10))
+            .with_maintenance_until(until)
+            .build()
+            .unwrap();
+        Router::new().route("/reports", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_rejects_all_requests_without_store() {
+        let store = MockStore::default();
+        let app = app(store.clone(), SystemTime::now() + Duration::from_secs(120));
+
+        for api_key in [None, Some("enterprise"), Some("free")] {
+            let response = send(&app, request("/reports", api_key)).await;
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(header(&response, "Retry-After").as_deref(), Some("120"));
+            let body = body_json(response).await;
+            assert_eq!(body["error"]["code"], "MAINTENANCE");
+        }
+        assert_eq!(store.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_requests_pass_after_maintenance_ends() {
+        let store = MockStore::default();
+        let app = app(store.clone(), SystemTime::now() - Duration::from_secs(1));
+
+        let response = send(&app, request("/reports", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_response_echoes_request_id() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(10))
+            .with_maintenance_until(SystemTime::now() + Duration::from_secs(60))
+            .with_request_id_config(RequestIdConfig::new("x-request-id", true))
+            .build()
+            .unwrap();
+        let app = Router::new().route("/reports", get(ok_handler)).layer(layer);
+
+        let request = Request::builder()
+            .uri("/reports")
+            .header("x-request-id", "req-503")
+            .body(Body::empty())
+            .unwrap();
+        let response = send(&app, request).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "MAINTENANCE");
+        assert_eq!(body["error"]["request_id"], "req-503");
+    }
+}
+
+mod idempotency {
+    use super::*;
+
+    fn app(store: MockStore, max_requests: u32) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(max_requests))
+            .with_idempotency_config(IdempotencyConfig::new(Duration::from_secs(60)))
+            .build()
+            .unwrap();
+        Router::new().route("/orders", get(ok_handler)).layer(layer)
+    }
+
+    fn request_with_key(idempotency_key: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/orders")
+            .method("GET")
+            .header("idempotency-key", idempotency_key)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn key() -> BarnacleKey {
+        BarnacleKey::Ip("local:GET:/orders".into())
+    }
+
+    #[tokio::test]
+    async fn test_same_idempotency_key_counts_once() {
+        let store = MockStore::default();
+        let app = app(store.clone(), 10);
+
+        for _ in 0..2 {
+            let response = send(&app, request_with_key("order-1")).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert_eq!(store.count(key(), "/orders", "GET"), 1);
+
+        let response = send(&app, request_with_key("order-2")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.count(key(), "/orders", "GET"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_request_key_is_not_remembered() {
+        let store = MockStore::default();
+        let app = app(store.clone(), 1);
+
+        assert_eq!(send(&app, request_with_key("order-1")).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, request_with_key("order-2")).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        // The retry of a rejected request must not slip through
+        assert_eq!(send(&app, request_with_key("order-2")).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        // The retry of an accepted request is still free
+        assert_eq!(send(&app, request_with_key("order-1")).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_retries_count_once() {
+        let store = MockStore::default();
+        let app = app(store.clone(), 10);
+
+        let (first, second) = tokio::join!(
+            send(&app, request_with_key("order-1")),
+            send(&app, request_with_key("order-1")),
+        );
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::OK);
+        assert_eq!(store.count(key(), "/orders", "GET"), 1);
+    }
+}
+
+mod body_hash_key {
+    use super::*;
+    use axum::routing::post;
+
+    fn app(store: MockStore) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(2))
+            .with_body_hash_key(1024)
+            .build()
+            .unwrap();
+        Router::new().route("/comments", post(ok_handler)).layer(layer)
+    }
+
+    fn post_body(body: &str, forwarded_for: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/comments")
+            .method("POST")
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_identical_bodies_share_a_bucket() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+        let spam = r#"{"text":"buy now"}"#;
+
+        // Different sources, same payload
+        assert_eq!(send(&app, post_body(spam, "10.0.0.1")).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, post_body(spam, "10.0.0.2")).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, post_body(spam, "10.0.0.3")).await.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // A different body has its own bucket
+        let response = send(&app, post_body(r#"{"text":"hello"}"#, "10.0.0.1")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some("1"));
+    }
+}
+
+mod observe_only {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_headers_emitted_without_enforcement() {
+        let store = MockStore::default();
+        let app = Router::new()
+            .route("/reports", get(ok_handler))
+            .layer(ObserveOnlyLayer::new(store.clone(), config(2)));
+
+        for remaining in ["1", "0", "0"] {
+            let response = send(&app, request("/reports", Some("metered"))).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("2"));
+            assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some(remaining));
+        }
+        // The request over the notional limit still reports when the window resets
+        let response = send(&app, request("/reports", Some("metered"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "X-RateLimit-Reset").as_deref(), Some("60"));
+        assert_eq!(store.count(BarnacleKey::ApiKey("metered".into()), "/reports", "GET"), 2);
+    }
+}
+
+mod retry_after_jitter {
+    use super::*;
+
+    fn app(store: MockStore, window: Duration, ratio: f64) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(BarnacleConfig { window, ..config(1) })
+            .with_retry_after_jitter(ratio)
+            .build()
+            .unwrap();
+        Router::new().route("/reports", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_advertised_retry_after_varies_within_band() {
+        let app = app(MockStore::default(), Duration::from_secs(100), 0.1);
+        assert_eq!(send(&app, request("/reports", Some("jitter"))).await.status(), StatusCode::OK);
+
+        let mut seen = HashSet::new();
+        for _ in 0..200 {
+            let response = send(&app, request("/reports", Some("jitter"))).await;
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            let retry_after: u64 = header(&response, "Retry-After").unwrap().parse().unwrap();
+            assert!((90..=110).contains(&retry_after), "retry_after {} outside band", retry_after);
+            assert_eq!(header(&response, "X-RateLimit-Reset"), Some(retry_after.to_string()));
+            seen.insert(retry_after);
+        }
+        assert!(seen.len() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_jitter_never_advertises_below_one_second() {
+        let app = app(MockStore::default(), Duration::from_secs(1), 1.0);
+        assert_eq!(send(&app, request("/reports", Some("jitter"))).await.status(), StatusCode::OK);
+
+        for _ in 0..50 {
+            let response = send(&app, request("/reports", Some("jitter"))).await;
+            let retry_after: u64 = header(&response, "Retry-After").unwrap().parse().unwrap();
+            assert!((1..=2).contains(&retry_after));
+        }
+    }
+}
+
+mod forward_headers {
+    use super::*;
+    use axum::http::HeaderMap;
+
+    async fn plan_validator(api_key: String, _config: ApiKeyConfig, _parts: Arc<Parts>, _state: ()) -> Result<ApiKeyValidationResult, BarnacleError> {
+        match api_key.as_str() {
+            "pro-key" => Ok(ApiKeyValidationResult::valid_with_default_config(api_key).with_forward_header("X-Plan", "pro")),
+            _ => Ok(ApiKeyValidationResult::invalid()),
+        }
+    }
+
+    async fn plan_handler(headers: HeaderMap) -> String {
+        headers.get("x-plan").map(|v| v.to_str().unwrap().to_string()).unwrap_or_default()
+    }
+
+    fn app() -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(10))
+            .with_api_key_validator(plan_validator)
+            .with_state(())
+            .build()
+            .unwrap();
+        Router::new().route("/reports", get(plan_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_validated_key_forwards_headers_to_handler() {
+        let app = app();
+        let mut req = request("/reports", Some("pro-key"));
+        // A client-supplied value must not survive validation
+        req.headers_mut().insert("x-plan", "enterprise".parse().unwrap());
+
+        let response = send(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&bytes[..], b"pro");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_result_is_rejected() {
+        let response = send(&app(), request("/reports", Some("unknown"))).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
+mod log_sampling {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rejections_always_logged_allowed_sampled() {
+        let logs = CapturedLogs::default();
+        let _guard = logs.capture();
+
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(10))
+            .with_log_sampling(5)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/reports", get(ok_handler)).layer(layer);
+
+        for _ in 0..10 {
+            assert_eq!(send(&app, request("/reports", Some("sampled"))).await.status(), StatusCode::OK);
+        }
+        for _ in 0..3 {
+            assert_eq!(send(&app, request("/reports", Some("sampled"))).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        assert_eq!(logs.count("Rate limit check passed"), 2);
+        assert_eq!(logs.count("Rate limit store error"), 3);
+    }
+}
+
+mod clock {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn frozen() -> FixedClock {
+        FixedClock(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+    }
+
+    #[tokio::test]
+    async fn test_reset_at_uses_injected_clock() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(1))
+            .with_clock(frozen())
+            .build()
+            .unwrap();
+        let app = Router::new().route("/reports", get(ok_handler)).layer(layer);
+
+        assert_eq!(send(&app, request("/reports", Some("clocked"))).await.status(), StatusCode::OK);
+        let response = send(&app, request("/reports", Some("clocked"))).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "X-RateLimit-Reset-At").as_deref(), Some("1700000060"));
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_window_measured_against_injected_clock() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(10))
+            .with_maintenance_until(UNIX_EPOCH + Duration::from_secs(1_700_000_090))
+            .with_clock(frozen())
+            .build()
+            .unwrap();
+        let app = Router::new().route("/reports", get(ok_handler)).layer(layer);
+
+        // Long over by wall-clock time, but still active for the frozen clock
+        assert!(SystemTime::now() > UNIX_EPOCH + Duration::from_secs(1_700_000_090));
+        let response = send(&app, request("/reports", None)).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(header(&response, "Retry-After").as_deref(), Some("90"));
+    }
+}
+
+mod reset_on_success_header {
+    use super::*;
+    use axum::{extract::Query, response::IntoResponse};
+
+    #[derive(serde::Deserialize)]
+    struct AuthResult {
+        result: String,
+    }
+
+    async fn login_handler(Query(AuthResult { result }): Query<AuthResult>) -> impl IntoResponse {
+        ([("X-Auth-Result", result)], "done")
+    }
+
+    fn app(store: MockStore) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(BarnacleConfig { reset_on_success: ResetOnSuccess::Yes(None), ..config(5) })
+            .with_reset_on_success_header(ResetOnSuccessHeader::new("X-Auth-Result", "ok"))
+            .build()
+            .unwrap();
+        Router::new().route("/login", get(login_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_header_decides_reset_over_status() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+        let key = || BarnacleKey::ApiKey("login".into());
+
+        for _ in 0..2 {
+            let response = send(&app, request("/login?result=fail", Some("login"))).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert_eq!(store.count(key(), "/login", "GET"), 2);
+
+        send(&app, request("/login?result=ok", Some("login"))).await;
+        assert_eq!(store.count(key(), "/login", "GET"), 0);
+    }
+}
+
+mod inner_panic {
+    use super::*;
+
+    async fn panic_handler() -> &'static str {
+        panic!("handler failure")
+    }
+
+    fn app(store: MockStore, refund_on_panic: bool) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(10))
+            .with_api_key_global_config(config(100))
+            .with_api_key_validator(require_api_key)
+            .with_state(())
+            .with_response_cost(true)
+            .with_refund_on_panic(refund_on_panic)
+            .build()
+            .unwrap();
+        Router::new().route("/panic", get(panic_handler)).layer(layer)
+    }
+
+    async fn send_panicking(app: &Router) {
+        let app = app.clone();
+        let panicking = tokio::spawn(async move { send(&app, request("/panic", Some("panicky"))).await });
+        assert!(panicking.await.unwrap_err().is_panic());
+    }
+
+    #[tokio::test]
+    async fn test_panicking_request_is_refunded() {
+        let store = MockStore::default();
+        let app = app(store.clone(), true);
+
+        send_panicking(&app).await;
+        send_panicking(&app).await;
+        assert_eq!(store.count(BarnacleKey::ApiKey("panicky".into()), "/panic", "GET"), 0);
+        assert_eq!(store.count(BarnacleKey::ApiKey("panicky".into()), "*", "*"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_request_counts_once_without_refund() {
+        let store = MockStore::default();
+        let app = app(store.clone(), false);
+
+        send_panicking(&app).await;
+        assert_eq!(store.count(BarnacleKey::ApiKey("panicky".into()), "/panic", "GET"), 1);
+        assert_eq!(store.count(BarnacleKey::ApiKey("panicky".into()), "*", "*"), 1);
+    }
+
+    #[test]
+    fn test_middleware_boxes_as_send_service() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(10))
+            .with_refund_on_panic(true)
+            .build()
+            .unwrap();
+        let inner = tower::service_fn(|_req: Request<Body>| async { Ok::<_, std::convert::Infallible>(Response::new(Body::empty())) });
+        let service = tower::ServiceBuilder::new().layer(layer).service(inner);
+        let _boxed: tower::util::BoxCloneService<Request<Body>, Response, std::convert::Infallible> =
+            tower::util::BoxCloneService::new(service);
+    }
+}
+
+mod stacked_layers {
+    use super::*;
+
+    fn layer(config: BarnacleConfig) -> BarnacleLayer<(), MockStore, (), BarnacleError, ()> {
+        BarnacleLayer::builder().with_store(MockStore::default()).with_config(config).build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_stack_warns_once() {
+        let logs = CapturedLogs::default();
+        let _guard = logs.capture();
+        let resetting = BarnacleConfig { reset_on_success: ResetOnSuccess::Yes(None), ..config(10) };
+        let app = Router::new()
+            .route("/login", get(ok_handler))
+            .layer(layer(config(10)))
+            .layer(layer(resetting));
+
+        for _ in 0..3 {
+            assert_eq!(send(&app, request("/login", None)).await.status(), StatusCode::OK);
+        }
+        assert_eq!(logs.count("Stacked BarnacleLayers have conflicting configs"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_compatible_stack_and_separate_routes_do_not_warn() {
+        let logs = CapturedLogs::default();
+        let _guard = logs.capture();
+        let resetting = BarnacleConfig { reset_on_success: ResetOnSuccess::Yes(None), ..config(10) };
+        // Different limits over the same window and reset rule are fine to stack
+        let app = Router::new()
+            .route("/login", get(ok_handler).layer(layer(resetting)))
+            .route("/reports", get(ok_handler).layer::<_, std::convert::Infallible>(layer(config(10))).layer::<_, std::convert::Infallible>(layer(config(50))));
+
+        assert_eq!(send(&app, request("/reports", None)).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, request("/login", None)).await.status(), StatusCode::OK);
+        assert_eq!(logs.count("Stacked BarnacleLayers have conflicting configs"), 0);
+    }
+}
+
+mod pre_check {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pre_check_rejects_suspended_key_without_counting() {
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(10))
+            .with_pre_check(|context: &BarnacleContext| {
+                let suspended = context.key == BarnacleKey::ApiKey("suspended".into());
+                async move {
+                    if suspended {
+                        Err(BarnacleError::custom("API key suspended", Some(StatusCode::FORBIDDEN)))
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .build()
+            .unwrap();
+        let app = Router::new().route("/reports", get(ok_handler)).layer(layer);
+
+        let response = send(&app, request("/reports", Some("suspended"))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(store.calls.load(Ordering::SeqCst), 0);
+
+        let response = send(&app, request("/reports", Some("active"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.calls.load(Ordering::SeqCst), 1);
+    }
+}
+
+mod optional_api_key {
+    use super::*;
+    use axum::routing::post;
+
+    #[derive(serde::Deserialize)]
+    struct LoginPayload {
+        email: String,
+    }
+
+    impl KeyExtractable for LoginPayload {
+        fn extract_key(&self, _parts: &Parts) -> BarnacleKey {
+            BarnacleKey::Email(self.email.clone())
+        }
+    }
+
+    async fn optional_api_key(_api_key: String, _config: ApiKeyConfig, _parts: Arc<Parts>, _state: ()) -> Result<(), BarnacleError> {
+        Ok(())
+    }
+
+    fn login(email: &str, ip: &str, api_key: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder()
+            .uri("/login")
+            .method("POST")
+            .header("content-type", "application/json")
+            .header("x-forwarded-for", ip);
+        if let Some(key) = api_key {
+            builder = builder.header("x-api-key", key);
+        }
+        builder.body(Body::from(format!(r#"{{"email":"{}"}}"#, email))).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_requests_are_ip_limited() {
+        let store = MockStore::default();
+        let layer: BarnacleLayer<LoginPayload, MockStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(2))
+            .with_api_key_validator(optional_api_key)
+            .with_state(())
+            .build()
+            .unwrap();
+        let app = Router::new().route("/login", post(ok_handler)).layer(layer);
+
+        // Rotating the payload key does not escape the per-IP limit
+        for email in ["a@example.com", "b@example.com"] {
+            let response = send(&app, login(email, "203.0.113.7", None)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = send(&app, login("c@example.com", "203.0.113.7", None)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(store.count(BarnacleKey::Ip("203.0.113.7".into()), "/login", "POST"), 2);
+
+        // Other clients and keyed requests have their own buckets
+        let response = send(&app, login("c@example.com", "198.51.100.1", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, login("c@example.com", "203.0.113.7", Some("partner"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+mod hidden_retry_after {
+    use super::*;
+
+    fn from_agent(user_agent: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/search")
+            .header("user-agent", user_agent)
+            .header("x-forwarded-for", "203.0.113.9")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_bot_rejections_omit_reset_headers() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(1))
+            .with_hidden_retry_after(|parts: &Parts| {
+                parts
+                    .headers
+                    .get("user-agent")
+                    .and_then(|h| h.to_str().ok())
+                    .is_some_and(|ua| ua.to_ascii_lowercase().contains("bot"))
+            })
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+
+        // Both agents share the IP bucket
+        let response = send(&app, from_agent("Mozilla/5.0")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = send(&app, from_agent("CrawlBot/2.1")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(header(&response, "Retry-After").is_none());
+        assert!(header(&response, "X-RateLimit-Reset").is_none());
+        assert!(header(&response, "X-RateLimit-Reset-At").is_none());
+
+        let response = send(&app, from_agent("Mozilla/5.0")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "Retry-After").as_deref(), Some("60"));
+        assert!(header(&response, "X-RateLimit-Reset-At").is_some());
+    }
+}
+
+mod grace_requests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_grace_requests_warn_then_enforce() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(2))
+            .with_grace_requests(2)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+
+        for remaining in ["1", "0"] {
+            let response = send(&app, request("/search", None)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some(remaining));
+            assert!(header(&response, "X-RateLimit-Warning").is_none());
+        }
+        for _ in 0..2 {
+            let response = send(&app, request("/search", None)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(header(&response, "X-RateLimit-Warning").as_deref(), Some("over-limit"));
+            assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some("0"));
+            assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("2"));
+        }
+
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("2"));
+    }
+}
+
+mod failure_limit {
+    use super::*;
+
+    async fn login_handler(req: Request<Body>) -> StatusCode {
+        match req.headers().get("x-password").and_then(|h| h.to_str().ok()) {
+            Some("correct") => StatusCode::OK,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    fn login(password: &str) -> Request<Body> {
+        Request::builder().uri("/login").header("x-password", password).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_failures_trip_failure_limit_but_successes_do_not() {
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(20))
+            .with_failure_config(config(5))
+            .build()
+            .unwrap();
+        let app = Router::new().route("/login", get(login_handler)).layer(layer);
+
+        // Successes only count against the total budget
+        for _ in 0..6 {
+            let response = send(&app, login("correct")).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        for _ in 0..5 {
+            let response = send(&app, login("wrong")).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let response = send(&app, login("correct")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("5"));
+        let key = BarnacleKey::Ip("local:GET:/login".into());
+        assert_eq!(store.count(key.clone(), "/login#failures", "GET"), 5);
+        // The rejected attempt did not consume the total budget
+        assert_eq!(store.count(key, "/login", "GET"), 11);
+    }
+}
+
+mod layer_config {
+    use super::*;
+
+    const CONFIG: &str = r#"{
+        "limit": { "max_requests": 2, "window": "1m", "reset_on_success": "Not" },
+        "api_key": { "header_name": "x-client-key", "cache_ttl_seconds": 600 },
+        "api_key_global_limit": { "max_requests": 100, "window": "1h" },
+        "failure_limit": { "max_requests": 5, "window": "15m" },
+        "request_id": { "header_name": "x-correlation-id", "include_in_error_body": true },
+        "concurrency": { "max_in_flight": 4, "safety_ttl": "5m" },
+        "idempotency": { "header_name": "idempotency-key", "ttl": "1h30m" },
+        "reset_on_success_header": { "name": "x-auth-result", "success_value": "ok" },
+        "grace_requests": 0,
+        "retry_after_jitter": 0.1,
+        "response_cost": true,
+        "refund_on_panic": true
+    }"#;
+
+    #[test]
+    fn test_layer_config_round_trips() {
+        let layer_config: BarnacleLayerConfig = serde_json::from_str(CONFIG).unwrap();
+        assert_eq!(layer_config.limit.window, Duration::from_secs(60));
+        assert_eq!(layer_config.idempotency.as_ref().unwrap().ttl, Duration::from_secs(90 * 60));
+        assert_eq!(layer_config.api_key.as_ref().unwrap().header_name, "x-client-key");
+
+        let json = serde_json::to_value(&layer_config).unwrap();
+        assert_eq!(json["limit"]["window"], "1m");
+        assert_eq!(json["idempotency"]["ttl"], "90m");
+        let reloaded: BarnacleLayerConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(reloaded.failure_limit.unwrap().window, Duration::from_secs(15 * 60));
+
+        // Absent sections and fields fall back to defaults
+        let minimal: BarnacleLayerConfig = serde_json::from_str(r#"{ "limit": { "window": "30s" } }"#).unwrap();
+        assert_eq!(minimal.limit.max_requests, BarnacleConfig::default().max_requests);
+        assert!(minimal.concurrency.is_none());
+        assert!(serde_json::from_str::<BarnacleConfig>(r#"{ "window": "soon" }"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_layer_built_from_config_document() {
+        let layer_config: BarnacleLayerConfig = serde_json::from_str(CONFIG).unwrap();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_layer_config(layer_config)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/reports", get(ok_handler)).layer(layer);
+
+        let response = send(&app, request("/reports", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("2"));
+        send(&app, request("/reports", None)).await;
+
+        let rejected = Request::builder()
+            .uri("/reports")
+            .header("x-correlation-id", "req-9")
+            .body(Body::empty())
+            .unwrap();
+        let response = send(&app, rejected).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["request_id"], "req-9");
+    }
+}
+
+mod scope_header {
+    use super::*;
+
+    async fn optional_api_key(_api_key: String, _config: ApiKeyConfig, _parts: Arc<Parts>, _state: ()) -> Result<(), BarnacleError> {
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scope_header_matches_key() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(1))
+            .with_api_key_validator(optional_api_key)
+            .with_state(())
+            .with_scope_header(true)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+
+        let response = send(&app, request("/search", Some("partner"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "X-RateLimit-Scope").as_deref(), Some("api-key"));
+
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "X-RateLimit-Scope").as_deref(), Some("ip"));
+
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "X-RateLimit-Scope").as_deref(), Some("ip"));
+    }
+
+    #[tokio::test]
+    async fn test_scope_header_off_by_default() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(1))
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+
+        let response = send(&app, request("/search", None)).await;
+        assert!(header(&response, "X-RateLimit-Scope").is_none());
+    }
+}
+
+mod header_style {
+    use super::*;
+
+    fn app(header_style: Option<HeaderStyle>) -> Router {
+        let mut builder = BarnacleLayer::<(), MockStore, (), BarnacleError, ()>::builder()
+            .with_store(MockStore::default())
+            .with_config(config(2));
+        if let Some(header_style) = header_style {
+            builder = builder.with_header_style(header_style);
+        }
+        Router::new().route("/search", get(ok_handler)).layer(builder.build().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_legacy_headers_by_default() {
+        let app = app(None);
+
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("2"));
+        assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some("1"));
+        assert!(header(&response, "RateLimit-Limit").is_none());
+        assert!(header(&response, "RateLimit").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ietf_draft_headers_replace_legacy() {
+        let app = app(Some(HeaderStyle::IetfDraft));
+
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(header(&response, "RateLimit-Limit").as_deref(), Some("2"));
+        assert_eq!(header(&response, "RateLimit-Remaining").as_deref(), Some("1"));
+        assert_eq!(header(&response, "RateLimit").as_deref(), Some("limit=2, remaining=1"));
+        assert!(header(&response, "X-RateLimit-Limit").is_none());
+        assert!(header(&response, "X-RateLimit-Remaining").is_none());
+
+        send(&app, request("/search", None)).await;
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "RateLimit-Remaining").as_deref(), Some("0"));
+        assert_eq!(header(&response, "RateLimit-Reset").as_deref(), Some("60"));
+        assert_eq!(header(&response, "RateLimit").as_deref(), Some("limit=2, remaining=0, reset=60"));
+        assert_eq!(header(&response, "Retry-After").as_deref(), Some("60"));
+        assert!(header(&response, "X-RateLimit-Reset").is_none());
+        assert!(header(&response, "X-RateLimit-Reset-At").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_both_header_styles() {
+        let app = app(Some(HeaderStyle::Both));
+
+        send(&app, request("/search", None)).await;
+        send(&app, request("/search", None)).await;
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        for (name, value) in [
+            ("X-RateLimit-Limit", "2"),
+            ("X-RateLimit-Remaining", "0"),
+            ("X-RateLimit-Reset", "60"),
+            ("RateLimit-Limit", "2"),
+            ("RateLimit-Remaining", "0"),
+            ("RateLimit-Reset", "60"),
+            ("RateLimit", "limit=2, remaining=0, reset=60"),
+        ] {
+            assert_eq!(header(&response, name).as_deref(), Some(value), "{}", name);
+        }
+        assert!(header(&response, "X-RateLimit-Reset-At").is_some());
+    }
+}
+
+mod retry_after_on_success {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn app(enabled: bool) -> Router {
+        let store = InMemoryBarnacleStore::new().with_clock(FixedClock(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)));
+        let layer: BarnacleLayer<(), InMemoryBarnacleStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(5))
+            .with_retry_after_on_success(enabled)
+            .build()
+            .unwrap();
+        Router::new().route("/search", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_sent_on_allowed_response() {
+        let app = app(true);
+
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "Retry-After").as_deref(), Some("60"));
+        assert_eq!(header(&response, "X-RateLimit-Reset").as_deref(), Some("60"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_off_by_default() {
+        let app = app(false);
+
+        let response = send(&app, request("/search", None)).await;
+        assert!(header(&response, "Retry-After").is_none());
+        assert_eq!(header(&response, "X-RateLimit-Reset").as_deref(), Some("60"));
+    }
+}
+
+mod manual_response {
+    use super::*;
+    use axum::response::IntoResponse;
+
+    // The rate limit headers of a response, with the reset timestamp dropped since the
+    // two paths may straddle a second boundary
+    fn rate_limit_headers(response: &Response) -> Vec<(String, String)> {
+        let mut headers: Vec<_> = response
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.as_str().to_string(), value.to_str().unwrap().to_string()))
+            .filter(|(name, _)| {
+                (name.starts_with("x-ratelimit") || name == "retry-after" || name == "x-barnacle-error")
+                    && name != "x-ratelimit-reset-at"
+            })
+            .collect();
+        headers.sort();
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_manual_and_middleware_headers_match() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(1))
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+
+        let store = MockStore::default();
+        let context = BarnacleContext {
+            key: BarnacleKey::Ip("127.0.0.1".to_string()),
+            path: "/search".to_string(),
+            method: "GET".to_string(),
+        };
+
+        for expected_status in [StatusCode::OK, StatusCode::TOO_MANY_REQUESTS] {
+            let via_middleware = send(&app, request("/search", None)).await;
+            let outcome = store.increment(&context, &config(1)).await;
+            let manual = rate_limit_response(outcome, &config(1), "ok".into_response());
+
+            assert_eq!(via_middleware.status(), expected_status);
+            assert_eq!(manual.status(), expected_status);
+            assert_eq!(rate_limit_headers(&manual), rate_limit_headers(&via_middleware));
+            assert!(!rate_limit_headers(&manual).is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_manual_rejection_body_matches_middleware() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(0))
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+
+        let via_middleware = send(&app, request("/search", None)).await;
+        let outcome = Err(BarnacleError::rate_limit_exceeded(0, 60, 0));
+        let manual = rate_limit_response(outcome, &config(0), "ok".into_response());
+
+        assert_eq!(manual.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(body_json(manual).await, body_json(via_middleware).await);
+    }
+}
+
+mod key_extractor {
+    use super::*;
+
+    fn tenant_request(tenant: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri("/search").method("GET").header("x-forwarded-for", "10.0.0.1");
+        if let Some(tenant) = tenant {
+            builder = builder.header("x-tenant-id", tenant);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requests_keyed_by_header() {
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(1))
+            .with_key_extractor(|parts: &Parts| {
+                parts
+                    .headers
+                    .get("x-tenant-id")
+                    .and_then(|value| value.to_str().ok())
+                    .map(|tenant| BarnacleKey::Custom(format!("tenant:{}", tenant)))
+            })
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+
+        assert_eq!(send(&app, tenant_request(Some("acme"))).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, tenant_request(Some("acme"))).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        // Another tenant behind the same IP has its own budget
+        assert_eq!(send(&app, tenant_request(Some("globex"))).await.status(), StatusCode::OK);
+        // Without the header the request falls back to the IP key
+        assert_eq!(send(&app, tenant_request(None)).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, tenant_request(None)).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(store.count(BarnacleKey::Custom("tenant:acme".to_string()), "/search", "GET"), 1);
+        assert_eq!(store.count(BarnacleKey::Ip("10.0.0.1".to_string()), "/search", "GET"), 1);
+    }
+}
+
+mod background_reset {
+    use super::*;
+    use tokio::sync::Semaphore;
+
+    // Resets wait for the test to open the gate, and the first one fails
+    #[derive(Clone)]
+    struct GatedResetStore {
+        inner: MockStore,
+        gate: Arc<Semaphore>,
+        failures_left: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl BarnacleStore for GatedResetStore {
+        async fn increment(&self, context: &BarnacleContext, config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+            self.inner.increment(context, config).await
+        }
+        async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+            self.gate.acquire().await.unwrap().forget();
+            if self.failures_left.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+                self.gate.add_permits(1);
+                return Err(BarnacleError::store_error("transient failure"));
+            }
+            self.inner.reset(context).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_returns_before_reset_completes() {
+        let store = GatedResetStore {
+            inner: MockStore::default(),
+            gate: Arc::new(Semaphore::new(0)),
+            failures_left: Arc::new(AtomicUsize::new(1)),
+        };
+        let layer: BarnacleLayer<(), GatedResetStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(BarnacleConfig { reset_on_success: ResetOnSuccess::Yes(None), ..config(5) })
+            .with_background_reset(16)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/login", get(ok_handler)).layer(layer);
+
+        let response = tokio::time::timeout(Duration::from_secs(1), send(&app, request("/login", None)))
+            .await
+            .expect("response must not wait for the reset");
+        assert_eq!(response.status(), StatusCode::OK);
+        let key = BarnacleKey::Ip("local:GET:/login".to_string());
+        assert_eq!(store.inner.count(key.clone(), "/login", "GET"), 1);
+
+        // The reset runs once released, retrying after the transient failure
+        store.gate.add_permits(1);
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while store.inner.count(key.clone(), "/login", "GET") != 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("reset should eventually run");
+    }
+}
+
+mod trusted_proxies {
+    use super::*;
+    use axum::extract::ConnectInfo;
+    use std::net::SocketAddr;
+
+    fn proxied_request(peer: &str, forwarded_for: &str) -> Request<Body> {
+        let mut req = request("/search", None);
+        req.headers_mut().insert("x-forwarded-for", forwarded_for.parse().unwrap());
+        req.extensions_mut().insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+        req
+    }
+
+    async fn key_used(trusted_proxies: TrustedProxyConfig, req: Request<Body>) -> BarnacleKey {
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(5))
+            .with_trusted_proxies(trusted_proxies)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+        send(&app, req).await;
+
+        let counters = store.counters.lock().unwrap();
+        assert_eq!(counters.len(), 1);
+        counters.keys().next().unwrap().0.clone()
+    }
+
+    #[tokio::test]
+    async fn test_multi_hop_chain() {
+        let req = proxied_request("10.0.0.2:443", "6.6.6.6, 203.0.113.9, 10.0.0.5");
+        assert_eq!(key_used(TrustedProxyConfig::hops(2), req).await, BarnacleKey::Ip("203.0.113.9".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_peer_falls_back_to_connect_info() {
+        let config = TrustedProxyConfig::trusted_cidrs(vec!["10.0.0.0/8".parse().unwrap()]);
+
+        let req = proxied_request("10.0.0.2:443", "6.6.6.6, 203.0.113.9");
+        assert_eq!(key_used(config.clone(), req).await, BarnacleKey::Ip("203.0.113.9".to_string()));
+
+        // Connected directly from outside the trusted network with a forged header
+        let req = proxied_request("198.51.100.1:51000", "6.6.6.6");
+        assert_eq!(key_used(config, req).await, BarnacleKey::Ip("198.51.100.1".to_string()));
+    }
+}
+
+mod ip_key_prefix {
+    use super::*;
+
+    async fn keys_used(requests: Vec<Request<Body>>) -> Vec<BarnacleKey> {
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(5))
+            .with_ip_key_prefix(IpKeyPrefix::new(24, 64))
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+        for req in requests {
+            send(&app, req).await;
+        }
+
+        let counters = store.counters.lock().unwrap();
+        let mut keys: Vec<BarnacleKey> = counters.keys().map(|(key, _, _)| key.clone()).collect();
+        keys.sort_by_key(|key| format!("{:?}", key));
+        keys
+    }
+
+    fn forwarded_for(ip: &str) -> Request<Body> {
+        let mut req = request("/search", None);
+        req.headers_mut().insert("x-forwarded-for", ip.parse().unwrap());
+        req
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_addresses_share_their_network() {
+        let keys = keys_used(vec![
+            forwarded_for("2001:db8:1:2::1"),
+            forwarded_for("2001:db8:1:2:ffff::9"),
+            forwarded_for("2001:db8:1:3::1"),
+        ])
+        .await;
+        assert_eq!(
+            keys,
+            vec![BarnacleKey::Ip("2001:db8:1:2::".to_string()), BarnacleKey::Ip("2001:db8:1:3::".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ipv4_addresses_share_their_network() {
+        let keys = keys_used(vec![forwarded_for("198.51.100.1"), forwarded_for("198.51.100.200")]).await;
+        assert_eq!(keys, vec![BarnacleKey::Ip("198.51.100.0".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_ip_is_used_raw() {
+        let mut req = request("/search", None);
+        req.headers_mut().insert("x-real-ip", "proxy-7".parse().unwrap());
+        let keys = keys_used(vec![req]).await;
+        assert_eq!(keys, vec![BarnacleKey::Ip("proxy-7".to_string())]);
+    }
+}
+
+mod trusted_identity_header {
+    use super::*;
+
+    fn app(store: MockStore) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(5))
+            .with_api_key_validator(require_api_key)
+            .with_state(())
+            .with_trusted_identity_header("x-authenticated-user")
+            .build()
+            .unwrap();
+        Router::new().route("/reports", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_keys_on_trusted_header_without_api_key() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+
+        let mut req = request("/reports", None);
+        req.headers_mut().insert("x-authenticated-user", "alice".parse().unwrap());
+        let response = send(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.count(BarnacleKey::Custom("alice".to_string()), "/reports", "GET"), 1);
+
+        // Without the header the API key is still required
+        let response = send(&app, request("/reports", None)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
+mod store_error_policy {
+    use super::*;
+
+    // Store that can't count anything
+    #[derive(Clone, Default)]
+    struct BrokenStore;
+
+    #[async_trait::async_trait]
+    impl BarnacleStore for BrokenStore {
+        async fn increment(&self, _context: &BarnacleContext, _config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+            Err(BarnacleError::store_error("store offline"))
+        }
+        async fn reset(&self, _context: &BarnacleContext) -> Result<(), BarnacleError> {
+            Err(BarnacleError::store_error("store offline"))
+        }
+    }
+
+    fn app(policy: StoreErrorPolicy) -> Router {
+        let layer: BarnacleLayer<(), BrokenStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(BrokenStore)
+            .with_config(config(10))
+            .with_store_error_policy(policy)
+            .build()
+            .unwrap();
+        Router::new().route("/search", get(ok_handler)).layer(layer)
+    }
+
+    fn api_key_app(policy: StoreErrorPolicy) -> Router {
+        let layer: BarnacleLayer<(), BrokenStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(BrokenStore)
+            .with_config(config(10))
+            .with_api_key_global_config(config(100))
+            .with_api_key_validator(require_api_key)
+            .with_state(())
+            .with_store_error_policy(policy)
+            .build()
+            .unwrap();
+        Router::new().route("/search", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_fail_closed_rejects() {
+        let response = send(&app(StoreErrorPolicy::FailClosed), request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = send(&api_key_app(StoreErrorPolicy::FailClosed), request("/search", Some("key-1"))).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_fail_open_passes_without_headers() {
+        let response = send(&app(StoreErrorPolicy::FailOpen), request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "x-ratelimit-limit"), None);
+
+        let response = send(&api_key_app(StoreErrorPolicy::FailOpen), request("/search", Some("key-1"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "x-ratelimit-remaining"), None);
+    }
+
+    #[tokio::test]
+    async fn test_fail_open_still_enforces_limits() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(1))
+            .with_store_error_policy(StoreErrorPolicy::FailOpen)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+
+        assert_eq!(send(&app, request("/search", None)).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, request("/search", None)).await.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
+
+mod observer {
+    use super::*;
+
+    // Store whose increments fail
+    #[derive(Clone, Default)]
+    struct FailingStore;
+
+    #[async_trait::async_trait]
+    impl BarnacleStore for FailingStore {
+        async fn increment(&self, _context: &BarnacleContext, _config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+            Err(BarnacleError::store_error("store offline"))
+        }
+        async fn reset(&self, _context: &BarnacleContext) -> Result<(), BarnacleError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_allowed_and_blocked_are_reported() {
+        let observer = Arc::new(CountingObserver::new());
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(2))
+            .with_observer(observer.clone())
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+
+        for _ in 0..3 {
+            send(&app, request("/search", None)).await;
+        }
+        assert_eq!(observer.allowed(), 2);
+        assert_eq!(observer.blocked(), 1);
+        assert_eq!(observer.store_errors(), 0);
+        assert_eq!(observer.store_calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_store_errors_are_reported() {
+        let observer = Arc::new(CountingObserver::new());
+        let layer: BarnacleLayer<(), FailingStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(FailingStore)
+            .with_config(config(2))
+            .with_observer(observer.clone())
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!((observer.allowed(), observer.blocked(), observer.store_errors()), (0, 0, 1));
+    }
+}
+
+mod json_key_path {
+    use super::*;
+    use axum::routing::post;
+
+    fn json_request(body: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/orders")
+            .method("POST")
+            .header("content-type", "application/json")
+            .header("x-forwarded-for", "203.0.113.7")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_keys_on_nested_field() {
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(5))
+            .with_json_key_path("$.user.id".parse().unwrap(), 1024)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/orders", post(ok_handler)).layer(layer);
+
+        send(&app, json_request(r#"{"user": {"id": "u-42"}, "items": []}"#)).await;
+        send(&app, json_request(r#"{"user": {"id": 42}}"#)).await;
+        // No such field, not JSON, or over the size cap: keyed by IP
+        send(&app, json_request(r#"{"user": {}}"#)).await;
+        send(&app, json_request("not json")).await;
+        send(&app, json_request(&format!(r#"{{"user": {{"id": "u-42"}}, "pad": "{}"}}"#, "x".repeat(2048)))).await;
+
+        assert_eq!(store.count(BarnacleKey::Custom("u-42".to_string()), "/orders", "POST"), 1);
+        assert_eq!(store.count(BarnacleKey::Custom("42".to_string()), "/orders", "POST"), 1);
+        assert_eq!(store.count(BarnacleKey::Ip("203.0.113.7".to_string()), "/orders", "POST"), 3);
+    }
+
+    #[test]
+    fn test_path_parsing() {
+        let body = serde_json::json!({"items": [{"sku": "A-1"}], "user name": "ann", "nested": {"list": []}});
+        let extract = |path: &str| path.parse::<JsonKeyPath>().unwrap().extract(&body);
+        assert_eq!(extract("$.items[0].sku"), Some("A-1".to_string()));
+        assert_eq!(extract("$['user name']"), Some("ann".to_string()));
+        assert_eq!(extract("$.items[1].sku"), None);
+        assert_eq!(extract("$.nested.list"), None);
+
+        for invalid in ["user.id", "$", "$.", "$.items[", "$.items[x]", "$..id"] {
+            assert!(invalid.parse::<JsonKeyPath>().is_err(), "{}", invalid);
+        }
+    }
+}
+
+mod payload_key_required {
+    use super::*;
+    use axum::routing::post;
+
+    #[derive(serde::Deserialize)]
+    struct LoginPayload {
+        email: String,
+    }
+
+    impl KeyExtractable for LoginPayload {
+        fn extract_key(&self, _parts: &Parts) -> BarnacleKey {
+            BarnacleKey::Email(self.email.clone())
+        }
+    }
+
+    fn login(body: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/login")
+            .method("POST")
+            .header("content-type", "application/json")
+            .header("x-forwarded-for", "203.0.113.7")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    // Body whose stream fails, as when the client disconnects mid-upload
+    fn unreadable_login() -> Request<Body> {
+        let stream = futures::stream::once(async { Err::<axum::body::Bytes, _>(std::io::Error::new(std::io::ErrorKind::Other, "connection reset")) });
+        Request::builder()
+            .uri("/login")
+            .method("POST")
+            .header("content-type", "application/json")
+            .header("x-forwarded-for", "203.0.113.7")
+            .body(Body::from_stream(stream))
+            .unwrap()
+    }
+
+    fn app(store: MockStore, required: bool) -> Router {
+        let layer: BarnacleLayer<LoginPayload, MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(5))
+            .with_payload_key_required(required)
+            .build()
+            .unwrap();
+        Router::new().route("/login", post(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_non_json_body_is_rejected() {
+        let store = MockStore::default();
+        let response = send(&app(store.clone(), true), login("email=a@example.com")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "REQUEST_PARSING_ERROR");
+        assert!(body["error"]["message"].as_str().unwrap().contains("not valid JSON"));
+        assert_eq!(store.count(BarnacleKey::Ip("203.0.113.7".into()), "/login", "POST"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_json_without_key_field_is_rejected() {
+        let store = MockStore::default();
+        let response = send(&app(store.clone(), true), login(r#"{"username": "ann"}"#)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = body_json(response).await;
+        let message = body["error"]["message"].as_str().unwrap();
+        assert!(message.contains("missing the rate limit key"), "{}", message);
+        assert!(message.contains("email"), "{}", message);
+
+        let response = send(&app(store.clone(), true), login(r#"{"email": "ann@example.com"}"#)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.count(BarnacleKey::Email("ann@example.com".into()), "/login", "POST"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_body_is_rejected() {
+        let store = MockStore::default();
+        let response = send(&app(store.clone(), true), unreadable_login()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = body_json(response).await;
+        assert!(body["error"]["message"].as_str().unwrap().contains("Failed to read request body"));
+        assert_eq!(store.count(BarnacleKey::Ip("203.0.113.7".into()), "/login", "POST"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_parse_and_read_failures_fall_back_alike() {
+        let store = MockStore::default();
+        let app = app(store.clone(), false);
+
+        let response = send(&app, login(r#"{"email": "ann@example.com"}"#)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, login("not json")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, unreadable_login()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(store.count(BarnacleKey::Email("ann@example.com".into()), "/login", "POST"), 1);
+        assert_eq!(store.count(BarnacleKey::Ip("203.0.113.7".into()), "/login", "POST"), 2);
+    }
+}
+
+mod config_resolver {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_paths_under_one_layer_get_their_own_limits() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config_resolver(|context: &BarnacleContext| match context.path.as_str() {
+                "/search" => config(1),
+                _ => config(3),
+            })
+            .build()
+            .unwrap();
+        let app = Router::new()
+            .route("/search", get(ok_handler))
+            .route("/reports", get(ok_handler))
+            .layer(layer);
+
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("1"));
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        for _ in 0..3 {
+            let response = send(&app, request("/reports", None)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("3"));
+        }
+        let response = send(&app, request("/reports", None)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_static_resolver_matches_with_config() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config_resolver(StaticConfigResolver::new(config(1)))
+            .build()
+            .unwrap();
+        let app = Router::new().route("/search", get(ok_handler)).layer(layer);
+
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, request("/search", None)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
+
+mod no_count_statuses {
+    use super::*;
+    use axum::http::HeaderMap;
+
+    async fn feed_handler(headers: HeaderMap) -> StatusCode {
+        if headers.contains_key("if-none-match") {
+            StatusCode::NOT_MODIFIED
+        } else {
+            StatusCode::OK
+        }
+    }
+
+    fn feed(cached: bool) -> Request<Body> {
+        let mut builder = Request::builder().uri("/feed").header("x-api-key", "reader");
+        if cached {
+            builder = builder.header("if-none-match", "\"v1\"");
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_responses_do_not_use_quota() {
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(2))
+            .with_api_key_validator(require_api_key)
+            .with_state(())
+            .with_no_count_statuses(vec![304])
+            .build()
+            .unwrap();
+        let app = Router::new().route("/feed", get(feed_handler)).layer(layer);
+        let key = || BarnacleKey::ApiKey("reader".into());
+
+        for _ in 0..5 {
+            let response = send(&app, feed(true)).await;
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some("2"));
+        }
+        assert_eq!(store.count(key(), "/feed", "GET"), 0);
+
+        for _ in 0..2 {
+            let response = send(&app, feed(false)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert_eq!(store.count(key(), "/feed", "GET"), 2);
+        let response = send(&app, feed(false)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
+
+mod request_cost {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_costly_route_uses_more_quota() {
+        let layer: BarnacleLayer<(), InMemoryBarnacleStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(InMemoryBarnacleStore::new())
+            .with_config(config(10))
+            .with_api_key_validator(require_api_key)
+            .with_state(())
+            .with_request_cost(|parts: &Parts| if parts.uri.path() == "/export" { 5 } else { 1 })
+            .build()
+            .unwrap();
+        let app = Router::new()
+            .route("/export", get(ok_handler))
+            .route("/search", get(ok_handler))
+            .layer(layer);
+
+        for remaining in ["5", "0"] {
+            let response = send(&app, request("/export", Some("exporter"))).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some(remaining));
+        }
+        let response = send(&app, request("/export", Some("exporter"))).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let response = send(&app, request("/search", Some("exporter"))).await;
+        assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some("9"));
+    }
+
+    #[tokio::test]
+    async fn test_cost_counts_against_api_key_global_limit() {
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(100))
+            .with_api_key_global_config(config(8))
+            .with_api_key_validator(require_api_key)
+            .with_state(())
+            .with_request_cost(|parts: &Parts| if parts.uri.path() == "/export" { 5 } else { 1 })
+            .build()
+            .unwrap();
+        let app = Router::new().route("/export", get(ok_handler)).layer(layer);
+
+        let response = send(&app, request("/export", Some("exporter"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.count(BarnacleKey::ApiKey("exporter".into()), "*", "*"), 5);
+
+        // Only 3 units are left across all endpoints; the rejection is refunded whole
+        let response = send(&app, request("/export", Some("exporter"))).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(store.count(BarnacleKey::ApiKey("exporter".into()), "*", "*"), 5);
+        assert_eq!(store.count(BarnacleKey::ApiKey("exporter".into()), "/export", "GET"), 5);
+    }
+}
+
+mod max_body_bytes {
+    use super::*;
+    use axum::routing::post;
+
+    fn upload(size: usize) -> Request<Body> {
+        Request::builder()
+            .uri("/upload")
+            .method("POST")
+            .header("x-forwarded-for", "203.0.113.7")
+            .body(Body::from(vec![b'x'; size]))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body_is_rejected_before_the_handler() {
+        let store = MockStore::default();
+        let handled = Arc::new(AtomicUsize::new(0));
+        let counter = handled.clone();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(5))
+            .with_max_body_bytes(1024)
+            .build()
+            .unwrap();
+        let app = Router::new()
+            .route(
+                "/upload",
+                post(move || {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async { "ok" }
+                }),
+            )
+            .layer(layer);
+
+        let response = send(&app, upload(4096)).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "PAYLOAD_TOO_LARGE");
+        assert_eq!(handled.load(Ordering::SeqCst), 0);
+        assert_eq!(store.calls.load(Ordering::SeqCst), 0);
+
+        let response = send(&app, upload(1024)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+    }
+}
+
+mod store_outcomes {
+    use super::*;
+
+    // Store reporting rejections as `allowed: false` rather than an error, or failing outright
+    #[derive(Clone, Default)]
+    struct FlagStore {
+        inner: MockStore,
+        offline: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl BarnacleStore for FlagStore {
+        async fn increment(&self, context: &BarnacleContext, config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+            if self.offline {
+                return Err(BarnacleError::store_error("store offline"));
+            }
+            match self.inner.increment(context, config).await {
+                Err(BarnacleError::RateLimitExceeded { retry_after, .. }) => Ok(BarnacleResult {
+                    allowed: false,
+                    remaining: 0,
+                    retry_after: Some(Duration::from_secs(retry_after)),
+                    reset_after: None,
+                    first_seen: None,
+                    window_reset: None,
+                }),
+                outcome => outcome,
+            }
+        }
+        async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+            self.inner.reset(context).await
+        }
+    }
+
+    fn app(store: FlagStore) -> Router {
+        let layer: BarnacleLayer<(), FlagStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(1))
+            .with_api_key_validator(require_api_key)
+            .with_state(())
+            .build()
+            .unwrap();
+        Router::new().route("/reports", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_over_limit_key_gets_429() {
+        let app = app(FlagStore::default());
+        let response = send(&app, request("/reports", Some("key-1"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = send(&app, request("/reports", Some("key-1"))).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "Retry-After").as_deref(), Some("60"));
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "RATE_LIMIT_EXCEEDED");
+    }
+
+    #[tokio::test]
+    async fn test_store_failure_gets_503() {
+        let app = app(FlagStore { offline: true, ..FlagStore::default() });
+        let response = send(&app, request("/reports", Some("key-1"))).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "STORE_ERROR");
+    }
+}
+
+mod required_scope {
+    use super::*;
+    use barnacle_rs::{ApiKeyStore, StaticApiKeyConfig, StaticApiKeyStore};
+
+    async fn static_validator(api_key: String, _config: ApiKeyConfig, _parts: Arc<Parts>, _state: ()) -> Result<ApiKeyValidationResult, BarnacleError> {
+        let keys = StaticApiKeyConfig::new(config(10))
+            .with_key_config("writer".to_string(), config(10))
+            .with_key_scopes("writer", ["reports:read", "reports:write"])
+            .with_key_config("reader".to_string(), config(10))
+            .with_key_scopes("reader", ["reports:read"]);
+        Ok(StaticApiKeyStore::new(keys).validate_key(&api_key).await)
+    }
+
+    fn app(store: MockStore) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(10))
+            .with_api_key_validator(static_validator)
+            .with_required_scope("reports:write")
+            .with_state(())
+            .build()
+            .unwrap();
+        Router::new().route("/reports", get(|| async { "ok" })).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_key_with_scope_is_allowed() {
+        let store = MockStore::default();
+        let response = send(&app(store.clone()), request("/reports", Some("writer"))).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.count(BarnacleKey::ApiKey("writer".to_string()), "/reports", "GET"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_key_without_scope_is_denied_before_counting() {
+        let store = MockStore::default();
+        let response = send(&app(store.clone()), request("/reports", Some("reader"))).await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "PERMISSION_DENIED");
+        assert_eq!(body["error"]["details"]["required_scope"], "reports:write");
+        assert_eq!(store.count(BarnacleKey::ApiKey("reader".to_string()), "/reports", "GET"), 0);
+    }
+}
+
+mod rate_limit_snapshot {
+    use super::*;
+    use axum::Extension;
+    use barnacle_rs::RateLimitSnapshot;
+
+    async fn echo_snapshot(snapshot: Option<Extension<RateLimitSnapshot>>) -> String {
+        match snapshot {
+            Some(Extension(snapshot)) => format!("{}/{}/{:?}", snapshot.remaining, snapshot.limit, snapshot.reset_after),
+            None => "none".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handler_reads_snapshot_from_extension() {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(3))
+            .with_api_key_validator(require_api_key)
+            .with_state(())
+            .build()
+            .unwrap();
+        let app = Router::new().route("/quota", get(echo_snapshot)).layer(layer);
+
+        send(&app, request("/quota", Some("key"))).await;
+        let response = send(&app, request("/quota", Some("key"))).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let remaining = header(&response, "X-RateLimit-Remaining").unwrap();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let echoed = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(echoed.starts_with(&format!("{}/3/", remaining)), "{}", echoed);
+        assert!(echoed.starts_with("1/3/"), "{}", echoed);
+    }
+}
+
+mod per_key_config {
+    use super::*;
+    use axum::routing::post;
+
+    #[derive(serde::Deserialize)]
+    struct LoginPayload {
+        email: String,
+    }
+
+    impl KeyExtractable for LoginPayload {
+        fn extract_key(&self, _parts: &Parts) -> BarnacleKey {
+            BarnacleKey::Email(self.email.clone())
+        }
+    }
+
+    fn login(email: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/login")
+            .method("POST")
+            .header("content-type", "application/json")
+            .body(Body::from(format!(r#"{{"email":"{}"}}"#, email)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_payload_keys_select_their_own_limits() {
+        let layer: BarnacleLayer<LoginPayload, MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(1))
+            .with_per_key_config(|key: &BarnacleKey| match key {
+                BarnacleKey::Email(email) if email == "vip@example.com" => Some(config(3)),
+                _ => None,
+            })
+            .build()
+            .unwrap();
+        let app = Router::new().route("/login", post(ok_handler)).layer(layer);
+
+        for _ in 0..3 {
+            let response = send(&app, login("vip@example.com")).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("3"));
+        }
+        let response = send(&app, login("vip@example.com")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Keys without an override get the layer's config
+        let response = send(&app, login("user@example.com")).await;
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("1"));
+        let response = send(&app, login("user@example.com")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
+
+mod reset_if_unchanged {
+    use super::*;
+
+    fn context() -> BarnacleContext {
+        BarnacleContext {
+            key: BarnacleKey::Custom("racer".to_string()),
+            path: "/login".to_string(),
+            method: "GET".to_string(),
+        }
+    }
+
+    // The handler counts a concurrent request for the same key before succeeding
+    fn app(store: InMemoryBarnacleStore, conditional: bool) -> Router {
+        let layer: BarnacleLayer<(), InMemoryBarnacleStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(BarnacleConfig {
+                max_requests: 5,
+                window: Duration::from_secs(60),
+                reset_on_success: ResetOnSuccess::Yes(None),
+            })
+            .with_key_extractor(|_parts: &Parts| Some(BarnacleKey::Custom("racer".to_string())))
+            .with_reset_if_unchanged(conditional)
+            .build()
+            .unwrap();
+        let handler = move || {
+            let store = store.clone();
+            async move {
+                store.increment(&context(), &config(5)).await.unwrap();
+                "ok"
+            }
+        };
+        Router::new().route("/login", get(handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_count_survives_conditional_reset() {
+        let store = InMemoryBarnacleStore::new();
+        let response = send(&app(store.clone(), true), request("/login", None)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.usage_for_key(&context()).await.unwrap().count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_plain_reset_clobbers_concurrent_count() {
+        let store = InMemoryBarnacleStore::new();
+        let response = send(&app(store.clone(), false), request("/login", None)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.usage_for_key(&context()).await.unwrap().count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_counter_is_reset() {
+        let store = InMemoryBarnacleStore::new();
+        let layer: BarnacleLayer<(), InMemoryBarnacleStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(BarnacleConfig {
+                max_requests: 5,
+                window: Duration::from_secs(60),
+                reset_on_success: ResetOnSuccess::Yes(None),
+            })
+            .with_key_extractor(|_parts: &Parts| Some(BarnacleKey::Custom("racer".to_string())))
+            .with_reset_if_unchanged(true)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/login", get(ok_handler)).layer(layer);
+
+        send(&app, request("/login", None)).await;
+        assert_eq!(store.usage_for_key(&context()).await.unwrap().count, 0);
+    }
+}
+
+mod success_predicate {
+    use super::*;
+    use axum::routing::post;
+
+    fn app(store: MockStore) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(BarnacleConfig {
+                max_requests: 5,
+                window: Duration::from_secs(60),
+                reset_on_success: ResetOnSuccess::Yes(None),
+            })
+            .with_key_extractor(|_parts: &Parts| Some(BarnacleKey::Custom("client".to_string())))
+            .with_success_predicate(|_parts, body| {
+                serde_json::from_slice::<serde_json::Value>(body).is_ok_and(|json| json["ok"] == true)
+            })
+            .build()
+            .unwrap();
+        Router::new()
+            .route("/ok", post(|| async { r#"{"ok":true}"# }))
+            .route("/soft-fail", post(|| async { r#"{"ok":false}"# }))
+            .layer(layer)
+    }
+
+    fn post_to(path: &str) -> Request<Body> {
+        Request::builder().uri(path).method("POST").body(Body::empty()).unwrap()
+    }
+
+    fn key() -> BarnacleKey {
+        BarnacleKey::Custom("client".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_body_reporting_failure_keeps_quota_used() {
+        let store = MockStore::default();
+        let response = send(&app(store.clone()), post_to("/soft-fail")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&bytes[..], br#"{"ok":false}"#);
+        assert_eq!(store.count(key(), "/soft-fail", "POST"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_body_reporting_success_resets_quota() {
+        let store = MockStore::default();
+        let response = send(&app(store.clone()), post_to("/ok")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&bytes[..], br#"{"ok":true}"#);
+        assert_eq!(store.count(key(), "/ok", "POST"), 0);
+    }
+}
+
+mod tracing_span {
+    use super::*;
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    // Writes one line per closed span, with every field recorded on it
+    fn capture_spans(logs: &CapturedLogs) -> tracing::subscriber::DefaultGuard {
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::INFO)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::set_default(subscriber)
+    }
+
+    fn app(max_requests: u32) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(max_requests))
+            .build()
+            .unwrap();
+        Router::new().route("/reports", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_allowed_request_records_decision_fields() {
+        let logs = CapturedLogs::default();
+        let _guard = capture_spans(&logs);
+
+        let response = send(&app(10), request("/reports", Some("traced"))).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(logs.count("barnacle.ratelimit{"), 1);
+        assert_eq!(logs.count("traced"), 1);
+        assert_eq!(logs.count("/reports"), 1);
+        assert_eq!(logs.count("method=GET"), 1);
+        assert_eq!(logs.count("allowed=true"), 1);
+        assert_eq!(logs.count("remaining=9"), 1);
+        assert_eq!(logs.count("store_latency_ms="), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_request_records_not_allowed() {
+        let logs = CapturedLogs::default();
+        let _guard = capture_spans(&logs);
+        let app = app(1);
+
+        assert_eq!(send(&app, request("/reports", Some("traced"))).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, request("/reports", Some("traced"))).await.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        assert_eq!(logs.count("barnacle.ratelimit{"), 2);
+        assert_eq!(logs.count("allowed=true"), 1);
+        assert_eq!(logs.count("allowed=false"), 1);
+    }
+}
+
+mod response_builder {
+    use super::*;
+    use axum::response::IntoResponse;
+
+    fn envelope(error: &BarnacleError) -> Response {
+        let body = serde_json::json!({
+            "success": false,
+            "errors": [{ "code": error.error_code(), "message": error.to_string() }],
+        });
+        (error.status_code(), axum::Json(body)).into_response()
+    }
+
+    fn app(custom: bool) -> Router {
+        let mut builder = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(1))
+            .with_api_key_validator(require_api_key)
+            .with_state(());
+        if custom {
+            builder = builder.with_response_builder(envelope);
+        }
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, _> = builder.build().unwrap();
+        Router::new().route("/reports", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_custom_envelope_keeps_rate_limit_headers() {
+        let app = app(true);
+        assert_eq!(send(&app, request("/reports", Some("enveloped"))).await.status(), StatusCode::OK);
+
+        let response = send(&app, request("/reports", Some("enveloped"))).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("1"));
+        assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some("0"));
+        assert!(header(&response, "Retry-After").is_some());
+        let body = body_json(response).await;
+        assert_eq!(body["success"], false);
+        assert_eq!(body["errors"][0]["code"], "RATE_LIMIT_EXCEEDED");
+    }
+
+    #[tokio::test]
+    async fn test_custom_envelope_for_missing_api_key() {
+        let response = send(&app(true), request("/reports", None)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body = body_json(response).await;
+        assert_eq!(body["success"], false);
+        assert!(body["errors"][0]["code"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_default_body_without_builder() {
+        let app = app(false);
+        assert_eq!(send(&app, request("/reports", Some("plain"))).await.status(), StatusCode::OK);
+
+        let response = send(&app, request("/reports", Some("plain"))).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("1"));
+        let body = body_json(response).await;
+        assert!(body.get("success").is_none());
+        assert_eq!(body["error"]["code"], "RATE_LIMIT_EXCEEDED");
+    }
+}
+
+mod error_format {
+    use super::*;
+
+    async fn rejected(error_format: ErrorFormat) -> Response {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(MockStore::default())
+            .with_config(config(1))
+            .with_error_format(error_format)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/reports", get(ok_handler)).layer(layer);
+        assert_eq!(send(&app, request("/reports", Some("formatted"))).await.status(), StatusCode::OK);
+        let response = send(&app, request("/reports", Some("formatted"))).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("1"));
+        response
+    }
+
+    #[tokio::test]
+    async fn test_json_is_the_default_envelope() {
+        let response = rejected(ErrorFormat::Json).await;
+        assert_eq!(header(&response, "content-type").as_deref(), Some("application/json"));
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "RATE_LIMIT_EXCEEDED");
+        assert_eq!(body["error"]["details"]["limit"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_problem_json() {
+        let response = rejected(ErrorFormat::ProblemJson).await;
+        assert_eq!(header(&response, "content-type").as_deref(), Some("application/problem+json"));
+        let body = body_json(response).await;
+        assert_eq!(body["type"], "RATE_LIMIT_EXCEEDED");
+        assert_eq!(body["title"], "Too Many Requests");
+        assert_eq!(body["status"], 429);
+        assert!(body["detail"].is_string());
+        assert_eq!(body["category"], BarnacleError::rate_limit_exceeded(0, 1, 1).error_type());
+        assert_eq!(body["limit"], 1);
+        assert!(body.get("error").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_plain_text() {
+        let response = rejected(ErrorFormat::PlainText).await;
+        assert!(header(&response, "content-type").unwrap().starts_with("text/plain"));
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(text.starts_with("Rate limit exceeded"), "{}", text);
+    }
+}
+
+mod access_control {
+    use super::*;
+
+    fn app(store: MockStore) -> Router {
+        let access_control = AccessControl::new()
+            .allow(BarnacleKey::ApiKey("internal".to_string()))
+            .deny(BarnacleKey::ApiKey("banned".to_string()));
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(1))
+            .with_access_control(access_control)
+            .build()
+            .unwrap();
+        Router::new().route("/reports", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_allowlisted_key_bypasses_limit() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+
+        for _ in 0..3 {
+            let response = send(&app, request("/reports", Some("internal"))).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(header(&response, "X-RateLimit-Remaining").is_none());
+        }
+        assert_eq!(store.count(BarnacleKey::ApiKey("internal".into()), "/reports", "GET"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_denylisted_key_is_forbidden() {
+        let store = MockStore::default();
+        let response = send(&app(store.clone()), request("/reports", Some("banned"))).await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "PERMISSION_DENIED");
+        assert_eq!(store.count(BarnacleKey::ApiKey("banned".into()), "/reports", "GET"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_other_keys_are_limited() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+
+        assert_eq!(send(&app, request("/reports", Some("regular"))).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, request("/reports", Some("regular"))).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(store.count(BarnacleKey::ApiKey("regular".into()), "/reports", "GET"), 1);
+    }
+
+    #[test]
+    fn test_denylist_wins_over_allowlist() {
+        let key = BarnacleKey::ApiKey("both".to_string());
+        let access_control = AccessControl::new().allow(key.clone()).deny(key.clone());
+        assert!(access_control.is_denylisted(&key));
+        assert!(!access_control.is_allowlisted(&key));
+    }
+}
+
+mod global_limit {
+    use super::*;
+
+    fn app(store: MockStore) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(10))
+            .with_global_config(config(2))
+            .build()
+            .unwrap();
+        Router::new().route("/reports", get(ok_handler)).route("/other", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_global_cap_trips_before_any_key() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+
+        assert_eq!(send(&app, request("/reports", Some("first"))).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, request("/reports", Some("second"))).await.status(), StatusCode::OK);
+
+        let response = send(&app, request("/reports", Some("third"))).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "X-RateLimit-Scope").as_deref(), Some("global"));
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("2"));
+
+        // The rejected key is refunded; the endpoint counter holds every allowed request
+        assert_eq!(store.count(BarnacleKey::ApiKey("third".into()), "/reports", "GET"), 0);
+        assert_eq!(store.count(BarnacleKey::Custom("global:/reports".into()), "/reports", "GET"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_global_cap_is_per_endpoint() {
+        let app = app(MockStore::default());
+
+        assert_eq!(send(&app, request("/reports", Some("first"))).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, request("/reports", Some("second"))).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, request("/other", Some("first"))).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_reports_tighter_remaining() {
+        let response = send(&app(MockStore::default()), request("/reports", Some("first"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "X-RateLimit-Limit").as_deref(), Some("2"));
+        assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some("1"));
+    }
+
+    fn global_count(store: &MockStore, path: &str) -> u32 {
+        store.count(BarnacleKey::Custom(format!("global:{}", path)), path, "GET")
+    }
+
+    #[tokio::test]
+    async fn test_uncounted_status_refunds_global_cap() {
+        async fn unavailable_handler() -> StatusCode {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(10))
+            .with_global_config(config(2))
+            .with_no_count_statuses(vec![503])
+            .build()
+            .unwrap();
+        let app = Router::new().route("/flaky", get(unavailable_handler)).layer(layer);
+
+        for _ in 0..3 {
+            let response = send(&app, request("/flaky", Some("first"))).await;
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+        assert_eq!(global_count(&store, "/flaky"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_panic_refunds_global_cap() {
+        async fn panic_handler() -> &'static str {
+            panic!("handler failure")
+        }
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(10))
+            .with_global_config(config(2))
+            .with_refund_on_panic(true)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/panic", get(panic_handler)).layer(layer);
+
+        for _ in 0..3 {
+            let app = app.clone();
+            let panicking = tokio::spawn(async move { send(&app, request("/panic", Some("first"))).await });
+            assert!(panicking.await.unwrap_err().is_panic());
+        }
+        assert_eq!(global_count(&store, "/panic"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_response_cost_reaches_global_cap() {
+        async fn costly_handler() -> Response {
+            let mut response = Response::new(Body::empty());
+            response.extensions_mut().insert(ResponseCost(3));
+            response
+        }
+        let store = MockStore::default();
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+            .with_store(store.clone())
+            .with_config(config(10))
+            .with_global_config(config(5))
+            .with_response_cost(true)
+            .build()
+            .unwrap();
+        let app = Router::new().route("/export", get(costly_handler)).layer(layer);
+
+        let response = send(&app, request("/export", Some("first"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(global_count(&store, "/export"), 3);
+        assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some("2"));
+    }
+}
+
+mod api_key_rate_limit_layer {
+    use super::*;
+
+    fn app(store: MockStore) -> Router {
+        let keys = StaticApiKeyStore::new(StaticApiKeyConfig::new(config(2)).with_key_config("good".to_string(), config(2)));
+        let layer: ApiKeyRateLimitLayer<(), MockStore, StaticApiKeyStore> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(2))
+            .with_api_key_store(keys)
+            .build()
+            .unwrap();
+        Router::new().route("/reports", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_validation_failures_are_not_counted() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+
+        let response = send(&app, request("/reports", Some("bad"))).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(header(&response, "X-RateLimit-Limit").is_none());
+        assert_eq!(body_json(response).await["error"]["code"], "INVALID_API_KEY");
+
+        let response = send(&app, request("/reports", None)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(body_json(response).await["error"]["code"], "API_KEY_MISSING");
+
+        assert_eq!(store.count(BarnacleKey::ApiKey("bad".into()), "/reports", "GET"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_valid_key_is_limited_with_one_set_of_headers() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+
+        for remaining in ["1", "0"] {
+            let response = send(&app, request("/reports", Some("good"))).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers().get_all("X-RateLimit-Remaining").iter().count(), 1);
+            assert_eq!(header(&response, "X-RateLimit-Remaining").as_deref(), Some(remaining));
+        }
+
+        let response = send(&app, request("/reports", Some("good"))).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get_all("X-RateLimit-Limit").iter().count(), 1);
+        assert_eq!(store.count(BarnacleKey::ApiKey("good".into()), "/reports", "GET"), 2);
+    }
+}
+
+mod matched_path {
+    use super::*;
+
+    fn app(store: MockStore, use_matched_path: Option<bool>) -> Router {
+        let mut builder = BarnacleLayer::builder().with_store(store).with_config(config(2));
+        if let Some(enabled) = use_matched_path {
+            builder = builder.with_use_matched_path(enabled);
+        }
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, ()> = builder.build().unwrap();
+        Router::new().route("/users/{id}", get(ok_handler)).layer(layer)
+    }
+
+    fn key() -> BarnacleKey {
+        BarnacleKey::ApiKey("client".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_parameterized_route_shares_one_bucket() {
+        let store = MockStore::default();
+        let app = app(store.clone(), None);
+
+        assert_eq!(send(&app, request("/users/1", Some("client"))).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, request("/users/2", Some("client"))).await.status(), StatusCode::OK);
+        assert_eq!(send(&app, request("/users/3", Some("client"))).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(store.count(key(), "/users/{id}", "GET"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_raw_paths_when_disabled() {
+        let store = MockStore::default();
+        let app = app(store.clone(), Some(false));
+
+        for id in 1..=3 {
+            let response = send(&app, request(&format!("/users/{}", id), Some("client"))).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert_eq!(store.count(key(), "/users/1", "GET"), 1);
+        assert_eq!(store.count(key(), "/users/{id}", "GET"), 0);
+    }
+}
+
+mod query_api_key {
+    use super::*;
+
+    fn app(store: MockStore) -> Router {
+        let layer: BarnacleLayer<(), MockStore, (), BarnacleError, _> = BarnacleLayer::builder()
+            .with_store(store)
+            .with_config(config(10))
+            .with_api_key_validator(require_api_key)
+            .with_state(())
+            .with_api_key_query_param("api_key")
+            .build()
+            .unwrap();
+        Router::new().route("/items", get(ok_handler)).layer(layer)
+    }
+
+    #[tokio::test]
+    async fn test_header_takes_precedence_over_query() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+
+        let response = send(&app, request("/items?api_key=from-query", Some("from-header"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.count(BarnacleKey::ApiKey("from-header".into()), "/items", "GET"), 1);
+        assert_eq!(store.count(BarnacleKey::ApiKey("from-query".into()), "/items", "GET"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_query_fallback_warns() {
+        let logs = CapturedLogs::default();
+        let _guard = logs.capture();
+        let store = MockStore::default();
+        let app = app(store.clone());
+
+        let response = send(&app, request("/items?page=2&api_key=legacy%20client", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.count(BarnacleKey::ApiKey("legacy client".into()), "/items", "GET"), 1);
+        assert_eq!(logs.count("API key read from the `api_key` query parameter"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_missing_from_header_and_query() {
+        let store = MockStore::default();
+        let app = app(store.clone());
+
+        assert_eq!(send(&app, request("/items", None)).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(send(&app, request("/items?api_key=", None)).await.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "metrics")]
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use barnacle_rs::prometheus::proto::MetricType;
+use barnacle_rs::prometheus::Registry;
+use barnacle_rs::{BarnacleConfig, BarnacleError, BarnacleLayer, InMemoryBarnacleStore, PrometheusObserver, ResetOnSuccess};
+use std::sync::Arc;
+use std::time::Duration;
+use tower::ServiceExt;
+
+fn metric_total(registry: &Registry, name: &str, label: Option<(&str, &str)>) -> f64 {
+    registry
+        .gather()
+        .iter()
+        .filter(|family| family.get_name() == name)
+        .flat_map(|family| {
+            let histogram = family.get_field_type() == MetricType::HISTOGRAM;
+            family.get_metric().iter().map(move |metric| (histogram, metric))
+        })
+        .filter(|(_, metric)| {
+            label.map_or(true, |(label_name, value)| {
+                metric.get_label().iter().any(|pair| pair.get_name() == label_name && pair.get_value() == value)
+            })
+        })
+        .map(|(histogram, metric)| {
+            if histogram {
+                metric.get_histogram().get_sample_count() as f64
+            } else {
+                metric.get_counter().get_value()
+            }
+        })
+        .sum()
+}
+
+#[tokio::test]
+async fn test_requests_are_counted_in_registry() {
+    let registry = Registry::new();
+    let observer = PrometheusObserver::new(&registry).unwrap();
+    let layer: BarnacleLayer<(), InMemoryBarnacleStore, (), BarnacleError, ()> = BarnacleLayer::builder()
+        .with_store(InMemoryBarnacleStore::new())
+        .with_config(BarnacleConfig { max_requests: 2, window: Duration::from_secs(60), reset_on_success: ResetOnSuccess::Not })
+        .with_observer(Arc::new(observer))
+        .build()
+        .unwrap();
+    let app = Router::new().route("/search", get(|| async { "ok" })).layer(layer);
+
+    for _ in 0..3 {
+        let req = Request::builder()
+            .uri("/search")
+            .header("x-forwarded-for", "203.0.113.7")
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(req).await.unwrap();
+    }
+
+    assert_eq!(metric_total(&registry, "barnacle_requests_total", Some(("result", "allowed"))), 2.0);
+    assert_eq!(metric_total(&registry, "barnacle_requests_total", Some(("result", "blocked"))), 1.0);
+    assert_eq!(metric_total(&registry, "barnacle_blocked_total", Some(("key_type", "ip"))), 1.0);
+    assert_eq!(metric_total(&registry, "barnacle_store_latency_seconds", None), 3.0);
+}
+
+#[test]
+fn test_registering_twice_fails() {
+    let registry = Registry::new();
+    PrometheusObserver::new(&registry).unwrap();
+    assert!(PrometheusObserver::new(&registry).is_err());
+}
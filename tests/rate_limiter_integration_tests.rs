@@ -7,7 +7,7 @@ use axum::{
     routing::{get, post},
     Router,
 };
-use barnacle_rs::{BarnacleConfig, BarnacleContext, BarnacleKey, BarnacleLayer, BarnacleStore, KeyExtractable, RedisBarnacleStore, ResetOnSuccess};
+use barnacle_rs::{BarnacleConfig, BarnacleContext, BarnacleKey, BarnacleLayer, BarnacleStore, GcraStore, KeyExtractable, RedisBarnacleStore, ResetOnSuccess, SlidingWindowStore, TokenBucketConfig, TokenBucketStore};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tokio::time::sleep;
@@ -365,3 +365,828 @@ mod rate_limit {
         );
     }
 }
+
+mod in_flight {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_redis_in_flight_release_floors_at_zero() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let context = BarnacleContext {
+            key: BarnacleKey::Custom(format!("in-flight-{}", uuid::Uuid::new_v4())),
+            path: "/api/slow".to_string(),
+            method: "GET".to_string(),
+        };
+        let ttl = Duration::from_secs(30);
+
+        assert!(store.acquire_in_flight(&context, 1, ttl).await.unwrap());
+        assert!(!store.acquire_in_flight(&context, 1, ttl).await.unwrap());
+
+        // Releasing more often than acquired must not leave spare slots behind
+        store.release_in_flight(&context).await.unwrap();
+        store.release_in_flight(&context).await.unwrap();
+        assert!(store.acquire_in_flight(&context, 1, ttl).await.unwrap());
+        assert!(!store.acquire_in_flight(&context, 1, ttl).await.unwrap());
+
+        store.release_in_flight(&context).await.unwrap();
+    }
+}
+
+mod key_hashing {
+    use super::*;
+
+    fn context(id: &str) -> BarnacleContext {
+        BarnacleContext {
+            key: BarnacleKey::Custom(id.to_string()),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hashed_keys_are_fixed_length_and_stable() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing")
+            .with_key_hashing(true);
+
+        let short = store.key_for(&context("a"));
+        let long = store.key_for(&context(&"tenant:region:user:".repeat(200)));
+        assert!(short.starts_with("barnacle:custom:"));
+        assert_eq!(short.len(), "barnacle:custom:".len() + 64);
+        assert_eq!(short.len(), long.len());
+        assert_eq!(short, store.key_for(&context("a")));
+        assert_ne!(short, long);
+    }
+
+    #[tokio::test]
+    async fn test_increment_and_reset_agree_on_hashed_key() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing")
+            .with_key_hashing(true);
+        let context = context(&format!("hashing-{}", uuid::Uuid::new_v4()));
+        let config = BarnacleConfig {
+            max_requests: 2,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+
+        store.increment(&context, &config).await.unwrap();
+        store.increment(&context, &config).await.unwrap();
+        assert!(store.increment(&context, &config).await.is_err());
+
+        store.reset(&context).await.unwrap();
+        let result = store.increment(&context, &config).await.unwrap();
+        assert_eq!(result.remaining, 1);
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod composite_keys {
+    use super::*;
+
+    fn context(key: BarnacleKey) -> BarnacleContext {
+        BarnacleContext {
+            key,
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        }
+    }
+
+    fn store() -> RedisBarnacleStore {
+        RedisBarnacleStore::from_url("redis://127.0.0.1:6379").expect("Failed to create Redis store for testing")
+    }
+
+    #[tokio::test]
+    async fn test_composite_key_is_sorted_and_stable() {
+        let store = store();
+        let key = store.key_for(&context(BarnacleKey::composite([("user", "456"), ("tenant", "123")])));
+        assert_eq!(key, "barnacle:composite:tenant=123:user=456:GET:/api/search");
+        assert_eq!(key, store.key_for(&context(BarnacleKey::composite([("tenant", "123"), ("user", "456")]))));
+        assert_ne!(key, store.key_for(&context(BarnacleKey::Custom("tenant=123:user=456".to_string()))));
+    }
+
+    #[tokio::test]
+    async fn test_composite_key_escapes_separators() {
+        let store = store();
+        let key = store.key_for(&context(BarnacleKey::composite([("tenant", "a:b"), ("user", "c=d\\e")])));
+        assert_eq!(key, "barnacle:composite:tenant=a\\:b:user=c\\=d\\\\e:GET:/api/search");
+
+        // A colon inside a value can't pose as a separator between parts
+        let joined = store.key_for(&context(BarnacleKey::composite([("tenant", "1:user=2")])));
+        let split = store.key_for(&context(BarnacleKey::composite([("tenant", "1"), ("user", "2")])));
+        assert_ne!(joined, split);
+    }
+}
+
+mod key_prefix {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_custom_prefix_namespaces_keys() {
+        let context = BarnacleContext {
+            key: BarnacleKey::Email("user@example.com".to_string()),
+            path: "/auth/login".to_string(),
+            method: "POST".to_string(),
+        };
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379").expect("Failed to create Redis store for testing");
+        assert_eq!(store.key_for(&context), "barnacle:email:user@example.com:POST:/auth/login");
+
+        let store = store.with_key_prefix("tenant-a".to_string());
+        assert_eq!(store.key_for(&context), "tenant-a:email:user@example.com:POST:/auth/login");
+
+        let store = store.with_key_hashing(true);
+        assert!(store.key_for(&context).starts_with("tenant-a:email:"));
+    }
+}
+
+mod window_validation {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_out_of_range_window_is_a_configuration_error() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let context = BarnacleContext {
+            key: BarnacleKey::Custom(format!("window-{}", uuid::Uuid::new_v4())),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        };
+
+        for window in [Duration::from_secs(u64::MAX), Duration::from_millis(500)] {
+            let config = BarnacleConfig {
+                max_requests: 5,
+                window,
+                reset_on_success: ResetOnSuccess::Not,
+            };
+            match store.increment(&context, &config).await {
+                Err(BarnacleError::Configuration { message }) => {
+                    assert!(message.contains("EXPIRE"), "unexpected message: {}", message)
+                }
+                other => panic!("expected a configuration error, got {:?}", other),
+            }
+        }
+    }
+}
+
+mod expiry_race {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_counter_without_ttl_gets_expiry_on_next_increment() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let pool = deadpool_redis::Config::from_url("redis://127.0.0.1:6379")
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+            .expect("Failed to create Redis pool");
+        let mut conn = pool.get().await.expect("Failed to get Redis connection");
+        let context = BarnacleContext {
+            key: BarnacleKey::Custom(format!("expiry-race-{}", uuid::Uuid::new_v4())),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        };
+        let config = BarnacleConfig {
+            max_requests: 5,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+        let redis_key = store.key_for(&context);
+
+        // The first request's connection died after INCR, before its EXPIRE landed
+        let _: i64 = deadpool_redis::redis::cmd("INCR")
+            .arg(&redis_key)
+            .query_async(&mut conn)
+            .await
+            .unwrap();
+        let ttl: i64 = deadpool_redis::redis::cmd("TTL").arg(&redis_key).query_async(&mut conn).await.unwrap();
+        assert_eq!(ttl, -1);
+
+        // The second request sees a count above one but must still leave a TTL behind
+        let result = store.increment(&context, &config).await.unwrap();
+        assert_eq!(result.remaining, 3);
+        let ttl: i64 = deadpool_redis::redis::cmd("TTL").arg(&redis_key).query_async(&mut conn).await.unwrap();
+        assert!(ttl > 0 && ttl <= 60, "expected a TTL to be set, got {}", ttl);
+
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod decrement {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_decrement_returns_quota_without_extending_window() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let pool = deadpool_redis::Config::from_url("redis://127.0.0.1:6379")
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+            .expect("Failed to create Redis pool");
+        let mut conn = pool.get().await.expect("Failed to get Redis connection");
+        let context = BarnacleContext {
+            key: BarnacleKey::Custom(format!("decrement-{}", uuid::Uuid::new_v4())),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        };
+        let config = BarnacleConfig {
+            max_requests: 3,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+        let redis_key = store.key_for(&context);
+
+        for _ in 0..3 {
+            store.increment(&context, &config).await.unwrap();
+        }
+        assert!(store.increment(&context, &config).await.is_err());
+
+        // Shorten the window so an extension back to 60s would be visible
+        let _: () = deadpool_redis::redis::cmd("EXPIRE").arg(&redis_key).arg(30).query_async(&mut conn).await.unwrap();
+        store.decrement(&context, 1).await.unwrap();
+        let ttl: i64 = deadpool_redis::redis::cmd("TTL").arg(&redis_key).query_async(&mut conn).await.unwrap();
+        assert!(ttl > 0 && ttl <= 30, "decrement must keep the TTL, got {}", ttl);
+
+        let result = store.increment(&context, &config).await.unwrap();
+        assert_eq!(result.remaining, 0);
+
+        // Floored at zero
+        store.decrement(&context, 10).await.unwrap();
+        let count: i64 = deadpool_redis::redis::cmd("GET").arg(&redis_key).query_async(&mut conn).await.unwrap();
+        assert_eq!(count, 0);
+
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod window_reset {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_allowed_requests_report_window_reset() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let context = BarnacleContext {
+            key: BarnacleKey::Custom(format!("window-reset-{}", uuid::Uuid::new_v4())),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        };
+        let config = BarnacleConfig {
+            max_requests: 3,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+
+        let first = store.increment(&context, &config).await.unwrap();
+        let reset_after = first.reset_after.expect("allowed requests should report the window reset");
+        assert!(reset_after.as_secs() > 0 && reset_after.as_secs() <= 60);
+
+        let second = store.increment(&context, &config).await.unwrap();
+        assert!(second.reset_after.unwrap() <= reset_after);
+
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod metadata {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_metadata_round_trips_without_affecting_count() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let context = BarnacleContext {
+            key: BarnacleKey::ApiKey(format!("metadata-{}", uuid::Uuid::new_v4())),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        };
+        let config = BarnacleConfig {
+            max_requests: 5,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+        let metadata = HashMap::from([("plan".to_string(), "pro".to_string())]);
+
+        let result = store.increment_with_metadata(&context, &config, &metadata).await.unwrap();
+        assert_eq!(result.remaining, 4);
+        let result = store.increment(&context, &config).await.unwrap();
+        assert_eq!(result.remaining, 3);
+
+        let usage = store.usage_for_key(&context).await.unwrap();
+        assert_eq!(usage.count, 2);
+        assert_eq!(usage.metadata.get("plan").map(String::as_str), Some("pro"));
+        assert!(usage.retry_after.is_some_and(|ttl| ttl <= Duration::from_secs(60)));
+
+        store.reset(&context).await.unwrap();
+        let usage = store.usage_for_key(&context).await.unwrap();
+        assert_eq!(usage.count, 0);
+        assert!(usage.metadata.is_empty());
+    }
+}
+
+mod sliding_window {
+    use super::*;
+
+    // One request opens the window, three more arrive just before it ends, then a
+    // burst of four right after the boundary. Returns how many of the burst got through.
+    async fn boundary_burst<S: BarnacleStore>(store: &S, context: &BarnacleContext) -> usize {
+        let config = BarnacleConfig {
+            max_requests: 4,
+            window: Duration::from_secs(2),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+        store.increment(context, &config).await.unwrap();
+        sleep(Duration::from_millis(1500)).await;
+        for _ in 0..3 {
+            store.increment(context, &config).await.unwrap();
+        }
+        sleep(Duration::from_millis(700)).await;
+
+        let mut allowed = 0;
+        for _ in 0..4 {
+            if store.increment(context, &config).await.is_ok() {
+                allowed += 1;
+            }
+        }
+        store.reset(context).await.unwrap();
+        allowed
+    }
+
+    fn context(name: &str) -> BarnacleContext {
+        BarnacleContext {
+            key: BarnacleKey::Custom(format!("{}-{}", name, uuid::Uuid::new_v4())),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_burst_across_window_boundary() {
+        let fixed = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let sliding = SlidingWindowStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+
+        // The fixed window starts over at the boundary and lets 8 requests through in ~0.7s
+        assert_eq!(boundary_burst(&fixed, &context("fixed-burst")).await, 4);
+        // The sliding window only frees the slot of the request that left the trailing window
+        assert_eq!(boundary_burst(&sliding, &context("sliding-burst")).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_is_oldest_entry_expiry() {
+        let store = SlidingWindowStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let context = context("sliding-retry");
+        let config = BarnacleConfig {
+            max_requests: 2,
+            window: Duration::from_secs(10),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+
+        let first = store.increment(&context, &config).await.unwrap();
+        assert_eq!(first.remaining, 1);
+        sleep(Duration::from_millis(1100)).await;
+        store.increment(&context, &config).await.unwrap();
+
+        match store.increment(&context, &config).await {
+            Err(BarnacleError::RateLimitExceeded { retry_after, .. }) => {
+                assert!((8..=9).contains(&retry_after), "retry_after was {}", retry_after)
+            }
+            other => panic!("expected rate limit error, got {:?}", other.map(|r| r.remaining)),
+        }
+
+        // A refunded request frees its slot
+        store.decrement(&context, 1).await.unwrap();
+        assert!(store.increment(&context, &config).await.is_ok());
+        store.reset(&context).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_remaining_counts_trailing_window() {
+        let store = SlidingWindowStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let context = context("sliding-remaining");
+        let config = BarnacleConfig {
+            max_requests: 3,
+            window: Duration::from_secs(2),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+
+        assert_eq!(store.peek(&context, &config).await.unwrap().remaining, 3);
+        assert_eq!(store.increment(&context, &config).await.unwrap().remaining, 2);
+        sleep(Duration::from_millis(1200)).await;
+        assert_eq!(store.increment(&context, &config).await.unwrap().remaining, 1);
+        assert_eq!(store.peek(&context, &config).await.unwrap().remaining, 1);
+
+        // The first request left the trailing window, the second is still in it
+        sleep(Duration::from_millis(1000)).await;
+        assert_eq!(store.peek(&context, &config).await.unwrap().remaining, 2);
+        assert_eq!(store.increment(&context, &config).await.unwrap().remaining, 1);
+
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod increment_by {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cost_five_exhausts_limit_of_ten_in_two_calls() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let context = BarnacleContext {
+            key: BarnacleKey::ApiKey(format!("increment-by-{}", uuid::Uuid::new_v4())),
+            path: "/api/export".to_string(),
+            method: "GET".to_string(),
+        };
+        let config = BarnacleConfig {
+            max_requests: 10,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+
+        assert_eq!(store.increment_by(&context, &config, 5).await.unwrap().remaining, 5);
+        assert_eq!(store.increment_by(&context, &config, 5).await.unwrap().remaining, 0);
+        match store.increment_by(&context, &config, 5).await {
+            Err(BarnacleError::RateLimitExceeded { remaining, limit, .. }) => assert_eq!((remaining, limit), (0, 10)),
+            other => panic!("expected rate limit error, got {:?}", other.map(|r| r.remaining)),
+        }
+
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod reset_many {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_all_listed_contexts_are_cleared() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let email = format!("reset-many-{}@example.com", uuid::Uuid::new_v4());
+        let context = |path: &str| BarnacleContext {
+            key: BarnacleKey::Email(email.clone()),
+            path: path.to_string(),
+            method: "POST".to_string(),
+        };
+        let config = BarnacleConfig {
+            max_requests: 10,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+        let cleared = [context("/auth/login"), context("/auth/start-reset"), context("/auth/verify")];
+        let kept = context("/auth/register");
+
+        for context in cleared.iter().chain([&kept]) {
+            store.increment(context, &config).await.unwrap();
+            store.increment(context, &config).await.unwrap();
+        }
+        store.reset_many(&cleared).await.unwrap();
+
+        for context in &cleared {
+            assert_eq!(store.increment(context, &config).await.unwrap().remaining, 9);
+        }
+        assert_eq!(store.increment(&kept, &config).await.unwrap().remaining, 7);
+
+        store.reset_many(&[]).await.unwrap();
+        store.reset_many(&cleared).await.unwrap();
+        store.reset(&kept).await.unwrap();
+    }
+}
+
+mod reset_if_below {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reset_only_within_threshold() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let context = BarnacleContext {
+            key: BarnacleKey::Email(format!("reset-if-below-{}@example.com", uuid::Uuid::new_v4())),
+            path: "/api/login".to_string(),
+            method: "POST".to_string(),
+        };
+        let config = BarnacleConfig {
+            max_requests: 10,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+
+        for _ in 0..3 {
+            store.increment(&context, &config).await.unwrap();
+        }
+        assert!(!store.reset_if_below(&context, 2).await.unwrap());
+        assert_eq!(store.increment(&context, &config).await.unwrap().remaining, 6);
+
+        assert!(store.reset_if_below(&context, 4).await.unwrap());
+        assert_eq!(store.increment(&context, &config).await.unwrap().remaining, 9);
+
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod token_bucket {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_redis_bucket_bursts_then_refills() {
+        let store = TokenBucketStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing")
+            .with_bucket_config(TokenBucketConfig::new(5.0, 3));
+        let context = BarnacleContext {
+            key: BarnacleKey::Custom(format!("token-bucket-{}", uuid::Uuid::new_v4())),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        };
+        let config = BarnacleConfig::default();
+
+        for remaining in [2, 1, 0] {
+            assert_eq!(store.increment(&context, &config).await.unwrap().remaining, remaining);
+        }
+        assert!(store.increment(&context, &config).await.is_err());
+
+        // 5 tokens/s: a token is back after 200ms
+        sleep(Duration::from_millis(250)).await;
+        assert_eq!(store.peek(&context, &config).await.unwrap().remaining, 1);
+        assert!(store.increment(&context, &config).await.is_ok());
+        assert!(!store.peek(&context, &config).await.unwrap().allowed);
+
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod gcra {
+    use super::*;
+
+    fn context(name: &str) -> BarnacleContext {
+        BarnacleContext {
+            key: BarnacleKey::Custom(format!("{}-{}", name, uuid::Uuid::new_v4())),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        }
+    }
+
+    // 10 requests per second: one every 100ms
+    fn config() -> BarnacleConfig {
+        BarnacleConfig {
+            max_requests: 10,
+            window: Duration::from_secs(1),
+            reset_on_success: ResetOnSuccess::Not,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_burst_rejected_beyond_tolerance() {
+        let store = GcraStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing")
+            .with_burst_tolerance(2);
+        let context = context("gcra-burst");
+
+        for remaining in [2, 1, 0] {
+            assert_eq!(store.increment(&context, &config()).await.unwrap().remaining, remaining);
+        }
+        match store.increment(&context, &config()).await {
+            Err(BarnacleError::RateLimitExceeded { retry_after, .. }) => assert_eq!(retry_after, 1),
+            other => panic!("expected rate limit error, got {:?}", other.map(|r| r.remaining)),
+        }
+
+        store.reset(&context).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_steady_rate_is_conformant() {
+        let store = GcraStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing")
+            .with_burst_tolerance(0);
+        let context = context("gcra-steady");
+
+        // Strict spacing: a request every emission interval always passes...
+        for _ in 0..5 {
+            assert!(store.increment(&context, &config()).await.is_ok());
+            sleep(Duration::from_millis(110)).await;
+        }
+        // ...but one right behind another does not
+        assert!(store.increment(&context, &config()).await.is_ok());
+        assert!(store.increment(&context, &config()).await.is_err());
+
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod reservation {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancelled_reservation_returns_quota() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let context = BarnacleContext {
+            key: BarnacleKey::ApiKey(format!("reservation-{}", uuid::Uuid::new_v4())),
+            path: "/api/export".to_string(),
+            method: "POST".to_string(),
+        };
+        let config = BarnacleConfig {
+            max_requests: 2,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+
+        let committed = store.reserve(&context, &config).await.unwrap();
+        assert_eq!(committed.result().remaining, 1);
+        store.commit(committed).await.unwrap();
+
+        // The last slot is held while the reservation is open...
+        let held = store.reserve(&context, &config).await.unwrap();
+        assert!(store.reserve(&context, &config).await.is_err());
+
+        // ...and returned when it is cancelled
+        store.cancel(held).await.unwrap();
+        let retried = store.reserve(&context, &config).await.unwrap();
+        assert_eq!(retried.result().remaining, 0);
+        store.commit(retried).await.unwrap();
+
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod peek {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_peek_does_not_consume_quota() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let context = BarnacleContext {
+            key: BarnacleKey::ApiKey(format!("peek-{}", uuid::Uuid::new_v4())),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        };
+        let config = BarnacleConfig {
+            max_requests: 2,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+
+        let fresh = store.peek(&context, &config).await.unwrap();
+        assert!(fresh.allowed);
+        assert_eq!(fresh.remaining, 2);
+        assert!(fresh.reset_after.is_none());
+
+        store.increment(&context, &config).await.unwrap();
+        for _ in 0..3 {
+            let status = store.peek(&context, &config).await.unwrap();
+            assert_eq!(status.remaining, 1);
+            assert!(status.reset_after.unwrap() <= Duration::from_secs(60));
+        }
+
+        store.increment(&context, &config).await.unwrap();
+        assert!(!store.peek(&context, &config).await.unwrap().allowed);
+
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod non_numeric_counter {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_non_numeric_counter_is_reinitialized() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing");
+        let pool = deadpool_redis::Config::from_url("redis://127.0.0.1:6379")
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+            .expect("Failed to create Redis pool");
+        let mut conn = pool.get().await.expect("Failed to get Redis connection");
+        let context = BarnacleContext {
+            key: BarnacleKey::Custom(format!("seeded-{}", uuid::Uuid::new_v4())),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        };
+        let config = BarnacleConfig {
+            max_requests: 5,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+        let redis_key = store.key_for(&context);
+
+        // Seeded by hand with something that isn't a count
+        let _: () = deadpool_redis::redis::cmd("SET")
+            .arg(&redis_key)
+            .arg("not-a-number")
+            .query_async(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(store.peek(&context, &config).await.unwrap().remaining, 5);
+
+        let _: () = deadpool_redis::redis::cmd("SET")
+            .arg(&redis_key)
+            .arg("1.5")
+            .query_async(&mut conn)
+            .await
+            .unwrap();
+        let result = store.increment(&context, &config).await.unwrap();
+        assert_eq!(result.remaining, 4);
+        let ttl: i64 = deadpool_redis::redis::cmd("TTL").arg(&redis_key).query_async(&mut conn).await.unwrap();
+        assert!(ttl > 0 && ttl <= 60, "expected a fresh window, got TTL {}", ttl);
+
+        store.reset(&context).await.unwrap();
+    }
+}
+
+mod violation_backoff {
+    use super::*;
+    use barnacle_rs::Backoff;
+
+    #[tokio::test]
+    async fn test_repeated_violations_escalate_retry_after() {
+        let store = RedisBarnacleStore::from_url("redis://127.0.0.1:6379")
+            .expect("Failed to create Redis store for testing")
+            .with_violation_backoff(Backoff::new(Duration::from_secs(1), Duration::from_secs(60)).with_multiplier(4.0));
+        let context = BarnacleContext {
+            key: BarnacleKey::Custom(format!("violations-{}", uuid::Uuid::new_v4())),
+            path: "/api/search".to_string(),
+            method: "GET".to_string(),
+        };
+        let config = BarnacleConfig {
+            max_requests: 1,
+            window: Duration::from_secs(5),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+        store.increment(&context, &config).await.unwrap();
+
+        // Backoffs of 1s, 4s, 16s, 60s: the window's TTL wins until the backoff outgrows it
+        let mut retry_afters = Vec::new();
+        for _ in 0..4 {
+            match store.increment(&context, &config).await {
+                Err(BarnacleError::RateLimitExceeded { retry_after, .. }) => retry_afters.push(retry_after),
+                other => panic!("expected a rate limit error, got {:?}", other),
+            }
+        }
+        assert!(retry_afters[0] <= 5 && retry_afters[1] <= 5, "{:?}", retry_afters);
+        assert_eq!(&retry_afters[2..], &[16, 60]);
+
+        // The counter stays blocked for the backoff, and a reset clears the violations
+        assert!(store.usage_for_key(&context).await.unwrap().retry_after.unwrap() > Duration::from_secs(30));
+        store.reset(&context).await.unwrap();
+        store.increment(&context, &config).await.unwrap();
+        match store.increment(&context, &config).await {
+            Err(BarnacleError::RateLimitExceeded { retry_after, .. }) => assert!(retry_after <= 5),
+            other => panic!("expected a rate limit error, got {:?}", other),
+        }
+    }
+}
+
+mod store_builder {
+    use super::*;
+
+    fn context() -> BarnacleContext {
+        BarnacleContext {
+            key: BarnacleKey::Email("user@example.com".to_string()),
+            path: "/auth/login".to_string(),
+            method: "POST".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_builder_needs_exactly_one_connection_source() {
+        let pool = deadpool_redis::Config::from_url("redis://127.0.0.1:6379")
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+            .unwrap();
+
+        for builder in [
+            RedisBarnacleStore::builder(),
+            RedisBarnacleStore::builder().url("redis://127.0.0.1:6379").pool(pool.clone()),
+            RedisBarnacleStore::builder().pool(pool.clone()).max_size(4),
+        ] {
+            assert!(matches!(builder.build(), Err(BarnacleError::Configuration { .. })));
+        }
+        assert!(RedisBarnacleStore::builder().pool(pool).build().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_builder_applies_key_options() {
+        let store = RedisBarnacleStore::builder()
+            .url("redis://127.0.0.1:6379")
+            .max_size(4)
+            .key_prefix("tenant-a")
+            .build()
+            .unwrap();
+        assert_eq!(store.key_for(&context()), "tenant-a:email:user@example.com:POST:/auth/login");
+    }
+
+    #[tokio::test]
+    async fn test_fail_open_allows_requests_without_redis() {
+        let config = BarnacleConfig {
+            max_requests: 5,
+            window: Duration::from_secs(60),
+            reset_on_success: ResetOnSuccess::Not,
+        };
+        let store = RedisBarnacleStore::builder().url("redis://127.0.0.1:1").build().unwrap();
+        assert!(store.increment(&context(), &config).await.is_err());
+
+        let store = RedisBarnacleStore::builder().url("redis://127.0.0.1:1").fail_open().build().unwrap();
+        let result = store.increment(&context(), &config).await.unwrap();
+        assert!(result.allowed);
+        assert_eq!(result.remaining, 5);
+    }
+}
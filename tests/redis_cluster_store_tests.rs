@@ -0,0 +1,48 @@
+#![cfg(feature = "cluster")]
+
+use barnacle_rs::{BarnacleConfig, BarnacleContext, BarnacleError, BarnacleKey, BarnacleStore, RedisClusterBarnacleStore, ResetOnSuccess};
+use std::time::Duration;
+
+fn context(key: &str) -> BarnacleContext {
+    BarnacleContext { key: BarnacleKey::Email(key.into()), path: "/login".into(), method: "POST".into() }
+}
+
+#[cfg(test)]
+mod redis_cluster_store_tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_carry_a_hash_tag() {
+        let store = RedisClusterBarnacleStore::from_urls(&["redis://127.0.0.1:7000"]).unwrap();
+        assert_eq!(store.key_for(&context("user@example.com")), "barnacle:email:{user@example.com:POST:/login}");
+
+        let store = store.with_key_prefix("tenant-a".to_string());
+        assert_eq!(store.key_for(&context("user@example.com")), "tenant-a:email:{user@example.com:POST:/login}");
+    }
+
+    #[test]
+    fn test_invalid_url_is_rejected() {
+        assert!(RedisClusterBarnacleStore::from_urls(&["not a url"]).is_err());
+    }
+
+    // Needs a cluster on REDIS_CLUSTER_URL, e.g. redis://127.0.0.1:7000
+    #[tokio::test]
+    async fn test_increment_and_reset_across_the_cluster() {
+        let Ok(url) = std::env::var("REDIS_CLUSTER_URL") else {
+            return;
+        };
+        let store = RedisClusterBarnacleStore::from_urls(&[url.as_str()]).unwrap();
+        let config = BarnacleConfig { max_requests: 2, window: Duration::from_secs(60), reset_on_success: ResetOnSuccess::Not };
+
+        // Keys spread over different slots
+        for user in 0..10 {
+            let context = context(&format!("user-{}-{}@example.com", user, uuid::Uuid::new_v4()));
+            assert_eq!(store.increment(&context, &config).await.unwrap().remaining, 1);
+            assert_eq!(store.increment(&context, &config).await.unwrap().remaining, 0);
+            assert!(matches!(store.increment(&context, &config).await, Err(BarnacleError::RateLimitExceeded { .. })));
+
+            store.reset(&context).await.unwrap();
+            assert_eq!(store.increment(&context, &config).await.unwrap().remaining, 1);
+        }
+    }
+}
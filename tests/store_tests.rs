@@ -1,26 +1,46 @@
-use barnacle_rs::{BarnacleConfig, BarnacleKey, BarnacleContext, ResetOnSuccess, BarnacleResult, BarnacleError, BarnacleStore};
+use barnacle_rs::{BarnacleConfig, BarnacleKey, BarnacleContext, ResetOnSuccess, BarnacleResult, BarnacleError, BarnacleStore, FallbackStore, GlobalCeilingStore, InMemoryBarnacleStore, TimeoutStore, RateLimitDecision, RateLimiter};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+// (key, path, method) -> count
+type Counters = Arc<Mutex<HashMap<(BarnacleKey, String, String), u32>>>;
+// context -> start of its current window
+type Windows = Arc<Mutex<HashMap<BarnacleContext, Instant>>>;
 
 // Mock store for in-memory rate limiting
 #[derive(Clone, Default)]
 struct MockStore {
-    // (key, path, method) -> (count, expiry)
-    counters: Arc<Mutex<HashMap<(BarnacleKey, String, String), u32>>>,
+    counters: Counters,
+    windows: Windows,
 }
 
 #[async_trait::async_trait]
 impl BarnacleStore for MockStore {
     async fn increment(&self, context: &BarnacleContext, config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
         let mut counters = self.counters.lock().unwrap();
+        let mut windows = self.windows.lock().unwrap();
         let k = (context.key.clone(), context.path.clone(), context.method.clone());
+        let first_seen = !windows.contains_key(context);
+        let window_start = windows.entry(context.clone()).or_insert_with(Instant::now);
+        let window_reset = !first_seen && window_start.elapsed() >= config.window;
+        if window_reset {
+            *window_start = Instant::now();
+            counters.remove(&k);
+        }
         let count = counters.entry(k).or_insert(0);
         if *count >= config.max_requests {
             return Err(BarnacleError::rate_limit_exceeded(0, config.window.as_secs(), config.max_requests));
         }
         *count += 1;
-        Ok(BarnacleResult { allowed: true, remaining: config.max_requests - *count, retry_after: None })
+        Ok(BarnacleResult {
+            allowed: true,
+            remaining: config.max_requests - *count,
+            retry_after: None,
+            reset_after: None,
+            first_seen: Some(first_seen),
+            window_reset: Some(window_reset),
+        })
     }
     async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
         let mut counters = self.counters.lock().unwrap();
@@ -38,6 +58,24 @@ fn config() -> BarnacleConfig {
 mod adv_unit_tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_first_seen_and_window_reset() {
+        let store = MockStore::default();
+        let c = BarnacleConfig { max_requests: 2, window: Duration::from_millis(50), reset_on_success: ResetOnSuccess::Not };
+        let ctx = BarnacleContext { key: BarnacleKey::Ip("5.6.7.8".into()), path: "/seen".into(), method: "GET".into() };
+
+        let fresh = store.increment(&ctx, &c).await.unwrap();
+        assert_eq!((fresh.first_seen, fresh.window_reset), (Some(true), Some(false)));
+        let same_window = store.increment(&ctx, &c).await.unwrap();
+        assert_eq!((same_window.first_seen, same_window.window_reset), (Some(false), Some(false)));
+
+        // A returning key after its window expired is not reported as new
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let returning = store.increment(&ctx, &c).await.unwrap();
+        assert_eq!((returning.first_seen, returning.window_reset), (Some(false), Some(true)));
+        assert_eq!(returning.remaining, 1);
+    }
+
     #[tokio::test]
     async fn test_api_key_and_ip_isolation() {
         // Different API keys should not interfere
@@ -270,4 +308,314 @@ mod adv_unit_tests {
         assert!(store.reset(&ctx2).await.is_ok());
         assert!(store.increment(&ctx1, &c).await.is_err());
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod global_ceiling_tests {
+    use super::*;
+
+    fn context(ip: &str) -> BarnacleContext {
+        BarnacleContext { key: BarnacleKey::Ip(ip.to_string()), path: "/api/export".to_string(), method: "POST".to_string() }
+    }
+
+    fn config(max_requests: u32) -> BarnacleConfig {
+        BarnacleConfig { max_requests, window: Duration::from_secs(60), reset_on_success: ResetOnSuccess::Not }
+    }
+
+    #[tokio::test]
+    async fn test_global_ceiling_trips_while_keys_are_under_budget() {
+        let inner = InMemoryBarnacleStore::new();
+        let store = GlobalCeilingStore::new(inner.clone(), config(3));
+        let per_key = config(10);
+
+        for ip in ["10.0.0.1", "10.0.0.2", "10.0.0.3"] {
+            store.increment(&context(ip), &per_key).await.unwrap();
+        }
+        match store.increment(&context("10.0.0.4"), &per_key).await {
+            Err(BarnacleError::RateLimitExceeded { limit, .. }) => assert_eq!(limit, 3),
+            other => panic!("expected the global ceiling to reject, got {:?}", other.map(|r| r.remaining)),
+        }
+        assert!(store.increment(&context("10.0.0.1"), &per_key).await.is_err());
+
+        // The rejected requests were refunded to their keys
+        assert_eq!(inner.usage_for_key(&context("10.0.0.1")).await.unwrap().count, 1);
+        assert_eq!(inner.usage_for_key(&context("10.0.0.4")).await.unwrap().count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_per_key_rejection_does_not_use_global_budget() {
+        let inner = InMemoryBarnacleStore::new();
+        let store = GlobalCeilingStore::new(inner.clone(), config(5));
+
+        store.increment(&context("10.0.0.1"), &config(1)).await.unwrap();
+        assert!(store.increment(&context("10.0.0.1"), &config(1)).await.is_err());
+        let global = inner.usage_for_key(&GlobalCeilingStore::<InMemoryBarnacleStore>::global_context()).await.unwrap();
+        assert_eq!(global.count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_weighted_increment_charges_the_ceiling_its_cost() {
+        let inner = InMemoryBarnacleStore::new();
+        let store = GlobalCeilingStore::new(inner.clone(), config(8));
+        let per_key = config(10);
+
+        let result = store.increment_by(&context("10.0.0.1"), &per_key, 5).await.unwrap();
+        assert_eq!(result.remaining, 3);
+        let global = inner.usage_for_key(&GlobalCeilingStore::<InMemoryBarnacleStore>::global_context()).await.unwrap();
+        assert_eq!(global.count, 5);
+
+        // The ceiling has 3 units left, so a cost-5 request is rejected and refunded whole
+        assert!(store.increment_by(&context("10.0.0.2"), &per_key, 5).await.is_err());
+        assert_eq!(inner.usage_for_key(&context("10.0.0.2")).await.unwrap().count, 0);
+    }
+}
+
+#[cfg(test)]
+mod timeout_store_tests {
+    use super::*;
+
+    // Store taking `delay` for every increment and reset
+    #[derive(Clone)]
+    struct SlowStore {
+        inner: InMemoryBarnacleStore,
+        delay: Duration,
+    }
+
+    #[async_trait::async_trait]
+    impl BarnacleStore for SlowStore {
+        async fn increment(&self, context: &BarnacleContext, config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.increment(context, config).await
+        }
+
+        async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.reset(context).await
+        }
+    }
+
+    fn slow(delay: Duration) -> SlowStore {
+        SlowStore { inner: InMemoryBarnacleStore::new(), delay }
+    }
+
+    fn context() -> BarnacleContext {
+        BarnacleContext { key: BarnacleKey::Ip("10.0.0.1".to_string()), path: "/api/search".to_string(), method: "GET".to_string() }
+    }
+
+    fn config() -> BarnacleConfig {
+        BarnacleConfig { max_requests: 5, window: Duration::from_secs(60), reset_on_success: ResetOnSuccess::Not }
+    }
+
+    #[tokio::test]
+    async fn test_fast_store_is_unaffected() {
+        let store = TimeoutStore::new(slow(Duration::ZERO), Duration::from_millis(200));
+        assert_eq!(store.increment(&context(), &config()).await.unwrap().remaining, 4);
+        store.reset(&context()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timeout_fails_closed_by_default() {
+        let store = TimeoutStore::new(slow(Duration::from_millis(500)), Duration::from_millis(20));
+
+        let started = Instant::now();
+        match store.increment(&context(), &config()).await {
+            Err(BarnacleError::StoreError { .. }) => {}
+            other => panic!("expected a store error, got {:?}", other.map(|r| r.remaining)),
+        }
+        assert!(matches!(store.reset(&context()).await, Err(BarnacleError::StoreError { .. })));
+        assert!(started.elapsed() < Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_fails_open() {
+        let store = TimeoutStore::new(slow(Duration::from_millis(500)), Duration::from_millis(20)).with_fail_open(true);
+
+        let result = store.increment(&context(), &config()).await.unwrap();
+        assert!(result.allowed);
+        // The count was abandoned along with the timed out call
+        assert_eq!(store.inner().inner.usage_for_key(&context()).await.unwrap().count, 0);
+        assert!(store.reset(&context()).await.is_ok());
+    }
+}
+
+mod rate_limiter_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_evaluate_returns_allowed_then_throttled() {
+        let limiter = RateLimiter::new(InMemoryBarnacleStore::new());
+        let context = BarnacleContext { key: BarnacleKey::Custom("job-runner".to_string()), path: "/jobs".to_string(), method: "POST".to_string() };
+        let config = BarnacleConfig { max_requests: 2, window: Duration::from_secs(60), reset_on_success: ResetOnSuccess::Not };
+
+        for expected_remaining in [1, 0] {
+            match limiter.evaluate(&context, &config).await.unwrap() {
+                RateLimitDecision::Allowed { remaining, reset } => {
+                    assert_eq!(remaining, expected_remaining);
+                    assert!(reset.is_some_and(|reset| reset <= config.window));
+                }
+                decision => panic!("expected an allowed decision, got {:?}", decision),
+            }
+        }
+
+        match limiter.evaluate(&context, &config).await.unwrap() {
+            RateLimitDecision::Throttled { retry_after, limit } => {
+                assert_eq!(limit, 2);
+                assert!(retry_after > Duration::ZERO && retry_after <= config.window);
+            }
+            decision => panic!("expected a throttled decision, got {:?}", decision),
+        }
+    }
+}
+
+#[cfg(test)]
+mod fallback_store_tests {
+    use super::*;
+
+    // Primary that is always down
+    #[derive(Clone)]
+    struct DownStore;
+
+    #[async_trait::async_trait]
+    impl BarnacleStore for DownStore {
+        async fn increment(&self, _context: &BarnacleContext, _config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+            Err(BarnacleError::store_error("connection refused"))
+        }
+
+        async fn reset(&self, _context: &BarnacleContext) -> Result<(), BarnacleError> {
+            Err(BarnacleError::store_error("connection refused"))
+        }
+    }
+
+    fn context() -> BarnacleContext {
+        BarnacleContext { key: BarnacleKey::Email("user@example.com".into()), path: "/login".into(), method: "POST".into() }
+    }
+
+    #[tokio::test]
+    async fn test_secondary_enforces_limits_while_primary_is_down() {
+        let store = FallbackStore::new(DownStore, InMemoryBarnacleStore::new());
+
+        assert_eq!(store.increment(&context(), &config()).await.unwrap().remaining, 1);
+        assert_eq!(store.increment(&context(), &config()).await.unwrap().remaining, 0);
+        assert!(matches!(
+            store.increment(&context(), &config()).await,
+            Err(BarnacleError::RateLimitExceeded { .. })
+        ));
+
+        store.reset(&context()).await.unwrap();
+        assert!(store.increment(&context(), &config()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_primary_rejections_do_not_fall_back() {
+        let primary = MockStore::default();
+        let secondary = InMemoryBarnacleStore::new();
+        let store = FallbackStore::new(primary, secondary.clone());
+
+        store.increment(&context(), &config()).await.unwrap();
+        store.increment(&context(), &config()).await.unwrap();
+        assert!(matches!(
+            store.increment(&context(), &config()).await,
+            Err(BarnacleError::RateLimitExceeded { .. })
+        ));
+        assert_eq!(secondary.usage_for_key(&context()).await.unwrap().count, 0);
+    }
+}
+
+mod permit_tests {
+    use super::*;
+    use barnacle_rs::{ConcurrencyConfig, InFlightGuard, RateLimitPermit};
+
+    fn context() -> BarnacleContext {
+        BarnacleContext {
+            key: BarnacleKey::Custom("tenant".to_string()),
+            path: "export".to_string(),
+            method: "JOB".to_string(),
+        }
+    }
+
+    fn config(max_requests: u32) -> BarnacleConfig {
+        BarnacleConfig { max_requests, window: Duration::from_secs(60), reset_on_success: ResetOnSuccess::Not }
+    }
+
+    // Treats `max_requests` as a cap on simultaneous sections
+    #[derive(Clone, Default)]
+    struct SlotStore(InMemoryBarnacleStore);
+
+    #[async_trait::async_trait]
+    impl BarnacleStore for SlotStore {
+        async fn increment(&self, context: &BarnacleContext, config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+            self.0.increment(context, config).await
+        }
+        async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+            self.0.reset(context).await
+        }
+        async fn acquire_in_flight(&self, context: &BarnacleContext, max_in_flight: u32, ttl: Duration) -> Result<bool, BarnacleError> {
+            self.0.acquire_in_flight(context, max_in_flight, ttl).await
+        }
+        async fn release_in_flight(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+            self.0.release_in_flight(context).await
+        }
+        async fn acquire(&self, context: &BarnacleContext, config: &BarnacleConfig) -> Result<RateLimitPermit<Self>, BarnacleError> {
+            let concurrency = ConcurrencyConfig { max_in_flight: config.max_requests, safety_ttl: config.window };
+            match InFlightGuard::acquire(self, context, &concurrency).await? {
+                Some(guard) => Ok(RateLimitPermit::in_flight(guard)),
+                None => Err(BarnacleError::concurrency_limit_exceeded(config.max_requests)),
+            }
+        }
+    }
+
+    async fn failing_section(store: &SlotStore) -> Result<(), BarnacleError> {
+        let _permit = store.acquire(&context(), &config(1)).await?;
+        tokio::task::yield_now().await;
+        Err(BarnacleError::internal_error("job failed"))
+    }
+
+    async fn acquire_eventually(store: &SlotStore) -> Option<RateLimitPermit<SlotStore>> {
+        for _ in 0..50 {
+            if let Ok(permit) = store.acquire(&context(), &config(1)).await {
+                return Some(permit);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        None
+    }
+
+    #[tokio::test]
+    async fn test_fixed_window_permit_counts_and_holds_nothing() {
+        let store = InMemoryBarnacleStore::new();
+
+        let permit = store.acquire(&context(), &config(2)).await.unwrap();
+        assert!(!permit.holds_slot());
+        assert_eq!(permit.result().unwrap().remaining, 1);
+        drop(permit);
+
+        let _second = store.acquire(&context(), &config(2)).await.unwrap();
+        // Dropping permits gives nothing back in a window
+        assert!(matches!(
+            store.acquire(&context(), &config(2)).await,
+            Err(BarnacleError::RateLimitExceeded { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_slot_held_while_permit_lives() {
+        let store = SlotStore::default();
+
+        let permit = store.acquire(&context(), &config(1)).await.unwrap();
+        assert!(permit.holds_slot());
+        assert!(matches!(
+            store.acquire(&context(), &config(1)).await,
+            Err(BarnacleError::ConcurrencyLimitExceeded { max_in_flight: 1 })
+        ));
+
+        permit.release().await.unwrap();
+        assert!(store.acquire(&context(), &config(1)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_slot_released_when_section_errors() {
+        let store = SlotStore::default();
+
+        assert!(failing_section(&store).await.is_err());
+        assert!(acquire_eventually(&store).await.is_some(), "permit must be released on the error path");
+    }
+}
@@ -0,0 +1,130 @@
+use barnacle_rs::{
+    BarnacleConfig, BarnacleContext, BarnacleError, BarnacleKey, BarnacleStore, Clock,
+    InMemoryTokenBucketStore, ResetOnSuccess, TokenBucketConfig,
+};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+// Clock that only moves when the test advances it
+#[derive(Clone)]
+struct ManualClock(Arc<Mutex<SystemTime>>);
+
+impl ManualClock {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))))
+    }
+
+    fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+fn context() -> BarnacleContext {
+    BarnacleContext {
+        key: BarnacleKey::Ip("10.0.0.1".to_string()),
+        path: "/api/search".to_string(),
+        method: "GET".to_string(),
+    }
+}
+
+fn config() -> BarnacleConfig {
+    BarnacleConfig { max_requests: 10, window: Duration::from_secs(10), reset_on_success: ResetOnSuccess::Not }
+}
+
+#[cfg(test)]
+mod token_bucket_store_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_burst_then_fractional_refill() {
+        let clock = ManualClock::new();
+        let store = InMemoryTokenBucketStore::new()
+            .with_bucket_config(TokenBucketConfig::new(2.0, 3))
+            .with_clock(clock.clone());
+
+        for remaining in [2, 1, 0] {
+            assert_eq!(store.increment(&context(), &config()).await.unwrap().remaining, remaining);
+        }
+
+        // Half a token after 250ms at 2 tokens/s: still rejected, one more token in 250ms
+        clock.advance(Duration::from_millis(250));
+        match store.increment(&context(), &config()).await {
+            Err(BarnacleError::RateLimitExceeded { retry_after, limit, .. }) => {
+                assert_eq!(retry_after, 1);
+                assert_eq!(limit, 3);
+            }
+            other => panic!("expected rate limit error, got {:?}", other.map(|r| r.remaining)),
+        }
+
+        clock.advance(Duration::from_millis(250));
+        let result = store.increment(&context(), &config()).await.unwrap();
+        assert_eq!(result.remaining, 0);
+        assert_eq!(result.retry_after, Some(Duration::from_millis(500)));
+        assert_eq!(result.reset_after, Some(Duration::from_millis(1500)));
+
+        // 1.75s refills 3.5 tokens, capped at the burst capacity of 3
+        clock.advance(Duration::from_millis(1750));
+        assert_eq!(store.increment(&context(), &config()).await.unwrap().remaining, 2);
+    }
+
+    #[tokio::test]
+    async fn test_bucket_derived_from_barnacle_config() {
+        let clock = ManualClock::new();
+        let store = InMemoryTokenBucketStore::new().with_clock(clock.clone());
+        // 10 requests per 10s: bursts of 10, one token per second
+        let config = config();
+
+        for _ in 0..10 {
+            store.increment(&context(), &config).await.unwrap();
+        }
+        assert!(store.increment(&context(), &config).await.is_err());
+
+        clock.advance(Duration::from_millis(1500));
+        let result = store.increment(&context(), &config).await.unwrap();
+        assert_eq!(result.remaining, 0);
+        assert_eq!(result.retry_after, Some(Duration::from_millis(500)));
+
+        store.reset(&context()).await.unwrap();
+        assert_eq!(store.increment(&context(), &config).await.unwrap().remaining, 9);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_bucket_config_rejected() {
+        let store = InMemoryTokenBucketStore::new().with_bucket_config(TokenBucketConfig::new(0.0, 5));
+        assert!(matches!(
+            store.increment(&context(), &config()).await,
+            Err(BarnacleError::Configuration { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_remaining_is_whole_tokens() {
+        let clock = ManualClock::new();
+        let store = InMemoryTokenBucketStore::new()
+            .with_bucket_config(TokenBucketConfig::new(2.0, 5))
+            .with_clock(clock.clone());
+
+        assert_eq!(store.peek(&context(), &config()).await.unwrap().remaining, 5);
+        for _ in 0..5 {
+            store.increment(&context(), &config()).await.unwrap();
+        }
+
+        // 1.75 tokens after 875ms: one whole token, not the fixed-window 10 - 5
+        clock.advance(Duration::from_millis(875));
+        let status = store.peek(&context(), &config()).await.unwrap();
+        assert!(status.allowed);
+        assert_eq!(status.remaining, 1);
+        assert_eq!(status.reset_after, Some(Duration::from_millis(1625)));
+
+        // Peeking takes nothing: the token is still there, and 0.75 are left after taking it
+        let result = store.increment(&context(), &config()).await.unwrap();
+        assert_eq!(result.remaining, 0);
+        assert!(!store.peek(&context(), &config()).await.unwrap().allowed);
+    }
+}
@@ -0,0 +1,45 @@
+// Minimal custom store wired into a router; must keep compiling with the documented bounds
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::{routing::get, Router};
+use barnacle_rs::{BarnacleConfig, BarnacleContext, BarnacleError, BarnacleLayer, BarnacleResult, BarnacleStore};
+
+#[derive(Clone, Default)]
+struct CountingStore {
+    counts: Arc<Mutex<HashMap<BarnacleContext, u32>>>,
+}
+
+#[async_trait::async_trait]
+impl BarnacleStore for CountingStore {
+    async fn increment(&self, context: &BarnacleContext, config: &BarnacleConfig) -> Result<BarnacleResult, BarnacleError> {
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(context.clone()).or_insert(0);
+        if *count >= config.max_requests {
+            return Err(BarnacleError::rate_limit_exceeded(0, config.window.as_secs(), config.max_requests));
+        }
+        *count += 1;
+        Ok(BarnacleResult {
+            allowed: true,
+            remaining: config.max_requests - *count,
+            retry_after: None,
+            reset_after: None,
+            first_seen: None,
+            window_reset: None,
+        })
+    }
+
+    async fn reset(&self, context: &BarnacleContext) -> Result<(), BarnacleError> {
+        self.counts.lock().unwrap().remove(context);
+        Ok(())
+    }
+}
+
+fn main() {
+    let layer: BarnacleLayer<(), CountingStore> = BarnacleLayer::builder()
+        .with_store(CountingStore::default())
+        .with_config(BarnacleConfig::default())
+        .build()
+        .unwrap();
+    let _app: Router = Router::new().route("/", get(|| async { "ok" })).layer(layer);
+}
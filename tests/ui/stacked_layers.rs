@@ -0,0 +1,42 @@
+// An API key layer and a payload/IP layer stacked on one router, and on a bare tower stack
+// with a non-axum body; must keep compiling without extra body bounds
+use std::convert::Infallible;
+
+use axum::{body::Body, http::Request, http::Response, routing::post, Router};
+use barnacle_rs::{
+    ApiKeyRateLimitLayer, BarnacleConfig, BarnacleLayer, InMemoryBarnacleStore, StaticApiKeyConfig, StaticApiKeyStore,
+};
+use tower::ServiceBuilder;
+
+fn key_layer() -> ApiKeyRateLimitLayer<(), InMemoryBarnacleStore, StaticApiKeyStore> {
+    BarnacleLayer::builder()
+        .with_store(InMemoryBarnacleStore::new())
+        .with_config(BarnacleConfig::default())
+        .with_api_key_store(StaticApiKeyStore::new(StaticApiKeyConfig::new(BarnacleConfig::default())))
+        .build()
+        .unwrap()
+}
+
+fn ip_layer() -> BarnacleLayer<(), InMemoryBarnacleStore> {
+    BarnacleLayer::builder()
+        .with_store(InMemoryBarnacleStore::new())
+        .with_config(BarnacleConfig::default())
+        .build()
+        .unwrap()
+}
+
+fn accepts_string_body<Svc: tower::Service<Request<String>, Response = Response<Body>>>(_service: &Svc) {}
+
+fn main() {
+    // The last `layer` runs first: reject bad keys before anything is counted
+    let _app: Router = Router::new()
+        .route("/", post(|| async { "ok" }))
+        .layer(ip_layer())
+        .layer(key_layer());
+
+    let service = ServiceBuilder::new()
+        .layer(key_layer())
+        .layer(ip_layer())
+        .service_fn(|_req: Request<Body>| async { Ok::<_, Infallible>(Response::new(Body::from("ok"))) });
+    accepts_string_body(&service);
+}
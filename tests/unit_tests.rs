@@ -1,4 +1,4 @@
-use barnacle_rs::{BarnacleConfig, BarnacleKey, ResetOnSuccess};
+use barnacle_rs::{BarnacleConfig, BarnacleContext, BarnacleKey, IpCidr, IpKeyPrefix, ResetOnSuccess, StaticApiKeyConfig, TrustedProxyConfig};
 use std::time::Duration;
 
 #[cfg(test)]
@@ -31,6 +31,24 @@ mod basic_unit_tests {
         assert!(matches!(config.reset_on_success, ResetOnSuccess::Yes(None)));
     }
 
+    #[test]
+    fn test_barnacle_config_presets() {
+        let login = BarnacleConfig::login_defaults();
+        assert_eq!(login.max_requests, 5);
+        assert_eq!(login.window, Duration::from_secs(900));
+        assert_eq!(login.reset_on_success, ResetOnSuccess::Yes(None));
+
+        let public_api = BarnacleConfig::public_api_defaults();
+        assert_eq!(public_api.max_requests, 100);
+        assert_eq!(public_api.window, Duration::from_secs(60));
+        assert_eq!(public_api.reset_on_success, ResetOnSuccess::Not);
+
+        let strict = BarnacleConfig::strict();
+        assert_eq!(strict.max_requests, 3);
+        assert_eq!(strict.window, Duration::from_secs(3600));
+        assert_eq!(strict.reset_on_success, ResetOnSuccess::Not);
+    }
+
     #[test]
     fn test_barnacle_key_variants() {
         let email_key = BarnacleKey::Email("test@example.com".to_string());
@@ -66,6 +84,26 @@ mod basic_unit_tests {
         assert_eq!(format!("{:?}", api_key), "ApiKey(\"secret_key\")");
     }
 
+    #[test]
+    fn test_barnacle_context_as_map_key() {
+        use std::collections::HashMap;
+
+        let context = |key: BarnacleKey, method: &str| BarnacleContext {
+            key,
+            path: "/api/search".to_string(),
+            method: method.to_string(),
+        };
+        let mut counters: HashMap<BarnacleContext, u32> = HashMap::new();
+
+        *counters.entry(context(BarnacleKey::Ip("10.0.0.1".to_string()), "GET")).or_default() += 1;
+        *counters.entry(context(BarnacleKey::Ip("10.0.0.1".to_string()), "GET")).or_default() += 1;
+        *counters.entry(context(BarnacleKey::Ip("10.0.0.1".to_string()), "POST")).or_default() += 1;
+        *counters.entry(context(BarnacleKey::ApiKey("10.0.0.1".to_string()), "GET")).or_default() += 1;
+
+        assert_eq!(counters.len(), 3);
+        assert_eq!(counters[&context(BarnacleKey::Ip("10.0.0.1".to_string()), "GET")], 2);
+    }
+
     #[test]
     fn test_reset_on_success_variants() {
         let no_reset = ResetOnSuccess::Not;
@@ -120,6 +158,32 @@ mod basic_unit_tests {
             ResetOnSuccess::Yes(Some(_))
         ));
     }
+
+    #[test]
+    fn test_barnacle_config_from_rate_string() {
+        for (rate, max_requests, window) in [
+            ("1000/hour", 1000, 3_600),
+            ("10/s", 10, 1),
+            ("60/m", 60, 60),
+            ("5/15m", 5, 900),
+            ("100000/d", 100_000, 86_400),
+            (" 3 / minute ", 3, 60),
+        ] {
+            let config: BarnacleConfig = rate.parse().unwrap();
+            assert_eq!(config.max_requests, max_requests, "{}", rate);
+            assert_eq!(config.window, Duration::from_secs(window), "{}", rate);
+            assert_eq!(config.reset_on_success, ResetOnSuccess::Not);
+        }
+
+        for rate in ["1000", "abc/h", "10/fortnight", "10/0s", "-1/h"] {
+            assert!(rate.parse::<BarnacleConfig>().is_err(), "{}", rate);
+        }
+
+        let static_config = StaticApiKeyConfig::new(BarnacleConfig::default())
+            .with_key_config_str("pro_key", "1000/hour")
+            .unwrap();
+        assert_eq!(static_config.get_config_for_key("pro_key").max_requests, 1000);
+    }
 }
 
 #[cfg(test)]
@@ -142,7 +206,9 @@ mod barnacle_layer_unit_tests {
         Arc::new(parts)
     }
 
-    fn api_key_validator() -> impl Fn(String, ApiKeyConfig, Arc<Parts>, State) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), BarnacleError>> + Send>> + Clone {
+    type ValidatorFuture = std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), BarnacleError>> + Send>>;
+
+    fn api_key_validator() -> impl Fn(String, ApiKeyConfig, Arc<Parts>, State) -> ValidatorFuture + Clone {
         |api_key: String, _api_key_config: ApiKeyConfig, _parts: Arc<Parts>, state: State| {
             Box::pin(async move {
                 if state.allowed == api_key {
@@ -167,3 +233,245 @@ mod barnacle_layer_unit_tests {
         assert!(result.is_err());
     }
 }
+
+#[cfg(test)]
+mod trusted_proxy_unit_tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let private: IpCidr = "10.0.0.0/8".parse().unwrap();
+        assert!(private.contains(ip("10.20.30.40")));
+        assert!(!private.contains(ip("11.0.0.1")));
+        assert!(!private.contains(ip("::ffff:10.0.0.1")));
+
+        let v6: IpCidr = "fd00::/8".parse().unwrap();
+        assert!(v6.contains(ip("fd12::1")));
+        assert!(!v6.contains(ip("fe80::1")));
+
+        let host: IpCidr = "192.168.1.7".parse().unwrap();
+        assert!(host.contains(ip("192.168.1.7")));
+        assert!(!host.contains(ip("192.168.1.8")));
+
+        for invalid in ["10.0.0.0/33", "fd00::/129", "not-an-ip/8", "10.0.0.0/x"] {
+            assert!(invalid.parse::<IpCidr>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_client_ip_by_hops() {
+        let chain = "1.1.1.1, 2.2.2.2, 3.3.3.3";
+        assert_eq!(TrustedProxyConfig::hops(1).client_ip(chain, None), Some(ip("3.3.3.3")));
+        assert_eq!(TrustedProxyConfig::hops(2).client_ip(chain, None), Some(ip("2.2.2.2")));
+        assert_eq!(TrustedProxyConfig::hops(3).client_ip(chain, None), Some(ip("1.1.1.1")));
+        // Fewer entries than proxies, or garbage: the header can't be trusted
+        assert_eq!(TrustedProxyConfig::hops(4).client_ip(chain, None), None);
+        assert_eq!(TrustedProxyConfig::hops(1).client_ip("1.1.1.1, unknown", None), None);
+    }
+
+    #[test]
+    fn test_client_ip_by_trusted_cidrs() {
+        let config = TrustedProxyConfig::trusted_cidrs(vec!["10.0.0.0/8".parse().unwrap()]);
+        // A spoofed leftmost entry is skipped over; the rightmost untrusted entry is the client
+        let chain = "6.6.6.6, 203.0.113.9, 10.0.0.5";
+        assert_eq!(config.client_ip(chain, Some(ip("10.0.0.1"))), Some(ip("203.0.113.9")));
+        // Only proxies in the chain
+        assert_eq!(config.client_ip("10.0.0.7, 10.0.0.5", Some(ip("10.0.0.1"))), None);
+        // The request didn't come through a trusted proxy
+        assert_eq!(config.client_ip(chain, Some(ip("198.51.100.1"))), None);
+    }
+
+    #[test]
+    fn test_ip_key_prefix_mask() {
+        let prefix = IpKeyPrefix::new(24, 64);
+        assert_eq!(prefix.mask(ip("203.0.113.77")), ip("203.0.113.0"));
+        assert_eq!(prefix.mask(ip("2001:db8:1:2:aaaa:bbbb:cccc:dddd")), ip("2001:db8:1:2::"));
+        // IPv4-mapped addresses are masked as IPv4
+        assert_eq!(prefix.mask(ip("::ffff:203.0.113.77")), ip("203.0.113.0"));
+
+        let whole = IpKeyPrefix::new(32, 128);
+        assert_eq!(whole.mask(ip("203.0.113.77")), ip("203.0.113.77"));
+        assert_eq!(whole.mask(ip("2001:db8::1")), ip("2001:db8::1"));
+        assert_eq!(IpKeyPrefix::new(0, 0).mask(ip("2001:db8::1")), ip("::"));
+    }
+}
+
+mod store_contract_unit_tests {
+    use super::*;
+    use barnacle_rs::{BarnacleError, BarnacleResult};
+
+    fn result(allowed: bool, retry_after: Option<Duration>) -> BarnacleResult {
+        BarnacleResult { allowed, remaining: 0, retry_after, reset_after: None, first_seen: None, window_reset: None }
+    }
+
+    #[test]
+    fn test_disallowed_results_become_rate_limit_errors() {
+        let config = BarnacleConfig { max_requests: 10, window: Duration::from_secs(60), reset_on_success: ResetOnSuccess::Not };
+
+        assert!(result(true, None).reject_if_disallowed(&config).is_ok());
+        match result(false, Some(Duration::from_millis(1500))).reject_if_disallowed(&config) {
+            Err(BarnacleError::RateLimitExceeded { retry_after, limit, .. }) => assert_eq!((retry_after, limit), (2, 10)),
+            other => panic!("expected rate limit error, got {:?}", other.map(|r| r.allowed)),
+        }
+        // Without timing from the store, clients wait out the window
+        match result(false, None).reject_if_disallowed(&config) {
+            Err(BarnacleError::RateLimitExceeded { retry_after, .. }) => assert_eq!(retry_after, 60),
+            other => panic!("expected rate limit error, got {:?}", other.map(|r| r.allowed)),
+        }
+    }
+}
+
+mod backoff_unit_tests {
+    use barnacle_rs::{next_backoff, next_backoff_jittered, Backoff, EQUAL_JITTER, FULL_JITTER};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::time::Duration;
+
+    fn backoff() -> Backoff {
+        Backoff::new(Duration::from_millis(100), Duration::from_secs(5))
+    }
+
+    #[test]
+    fn test_next_backoff_doubles_up_to_max() {
+        assert_eq!(next_backoff(0, &backoff()), Duration::from_millis(100));
+        assert_eq!(next_backoff(3, &backoff()), Duration::from_millis(800));
+        assert_eq!(next_backoff(10, &backoff()), Duration::from_secs(5));
+        assert_eq!(next_backoff(u32::MAX, &backoff()), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_jittered_backoff_stays_within_bounds() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for attempt in 0..8 {
+            let base = next_backoff(attempt, &backoff());
+            for jitter in [EQUAL_JITTER, FULL_JITTER, 0.2] {
+                let delay = next_backoff_jittered(attempt, &backoff(), jitter, &mut rng);
+                assert!(delay >= base.mul_f64(1.0 - jitter), "{:?} below bounds of {:?}", delay, base);
+                assert!(delay <= base, "{:?} above {:?}", delay, base);
+            }
+        }
+    }
+
+    #[test]
+    fn test_jittered_backoff_is_deterministic_with_a_seed() {
+        let delays = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..5).map(|attempt| next_backoff_jittered(attempt, &backoff(), FULL_JITTER, &mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(delays(42), delays(42));
+        assert_eq!(next_backoff_jittered(2, &backoff(), 0.0, &mut StdRng::seed_from_u64(1)), Duration::from_millis(400));
+    }
+}
+
+mod retry_unit_tests {
+    use barnacle_rs::{retry_with_backoff, Backoff};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    fn backoff() -> Backoff {
+        Backoff::new(Duration::from_millis(1), Duration::from_millis(5))
+    }
+
+    // Stands in for a pool whose first `failures` checkouts fail
+    async fn flaky(attempts: &AtomicU32, failures: u32) -> Result<&'static str, String> {
+        let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+        if attempt < failures {
+            Err(format!("connection refused ({})", attempt))
+        } else {
+            Ok("connection")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_succeeds_after_transient_failures() {
+        let attempts = AtomicU32::new(0);
+        let result = retry_with_backoff(3, &backoff(), || flaky(&attempts, 2)).await;
+
+        assert_eq!(result, Ok("connection"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_retries() {
+        let attempts = AtomicU32::new(0);
+        let result = retry_with_backoff(2, &backoff(), || flaky(&attempts, 5)).await;
+
+        assert_eq!(result, Err("connection refused (2)".to_string()));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+}
+
+mod core_unit_tests {
+    use super::*;
+    use barnacle_rs::core::{check_rate_limit, check_rate_limit_by};
+    use barnacle_rs::{BarnacleError, InMemoryBarnacleStore};
+
+    fn context() -> BarnacleContext {
+        BarnacleContext {
+            key: BarnacleKey::Custom("grpc-client".to_string()),
+            path: "/orders.OrderService/Create".to_string(),
+            method: "POST".to_string(),
+        }
+    }
+
+    fn config(max_requests: u32) -> BarnacleConfig {
+        BarnacleConfig { max_requests, window: Duration::from_secs(60), reset_on_success: ResetOnSuccess::Not }
+    }
+
+    #[tokio::test]
+    async fn test_check_rate_limit_counts_until_exceeded() {
+        let store = InMemoryBarnacleStore::new();
+
+        let first = check_rate_limit(&store, &context(), &config(2)).await.unwrap();
+        assert_eq!(first.remaining, 1);
+        let second = check_rate_limit(&store, &context(), &config(2)).await.unwrap();
+        assert_eq!(second.remaining, 0);
+
+        let third = check_rate_limit(&store, &context(), &config(2)).await;
+        assert!(matches!(third, Err(BarnacleError::RateLimitExceeded { limit: 2, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_check_rate_limit_by_charges_cost() {
+        let store = InMemoryBarnacleStore::new();
+
+        let result = check_rate_limit_by(&store, &context(), &config(5), 3).await.unwrap();
+        assert_eq!(result.remaining, 2);
+        assert!(check_rate_limit_by(&store, &context(), &config(5), 3).await.is_err());
+    }
+}
+
+mod reset_on_success_unit_tests {
+    use super::*;
+    use barnacle_rs::BarnacleError;
+
+    #[test]
+    fn test_accepts_valid_status_codes() {
+        let reset = ResetOnSuccess::on_status_codes([200, 201, 304]).unwrap();
+        assert_eq!(reset, ResetOnSuccess::Yes(Some(vec![200, 201, 304])));
+        assert!(ResetOnSuccess::on_status_codes([100, 599]).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_codes_outside_http_range() {
+        for code in [0, 99, 600, 999] {
+            let result = ResetOnSuccess::on_status_codes([200, code]);
+            assert!(matches!(result, Err(BarnacleError::Configuration { .. })), "{} should be rejected", code);
+        }
+    }
+
+    #[test]
+    fn test_rejects_empty_set() {
+        assert!(ResetOnSuccess::on_status_codes(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_success_2xx_resets_on_any_2xx() {
+        assert_eq!(ResetOnSuccess::success_2xx(), ResetOnSuccess::Yes(None));
+    }
+}